@@ -0,0 +1,102 @@
+//! GET/SET throughput against a shared `Db` as the number of threads grows.
+//!
+//! Each thread works on its own keys, spread over every shard, and runs its
+//! commands through `handle_command` as a connection would. With per-shard
+//! locking, throughput should grow with the thread count up to the number
+//! of cores, with or without the append-only file.
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use eoncache::command::handle_command;
+use eoncache::{AppendFsync, Config, Db, Frame, Parse, Session};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Keys each thread cycles through.
+const KEYS_PER_THREAD: usize = 1024;
+
+/// Thread counts to measure: powers of two up to the number of cores.
+fn thread_counts() -> Vec<usize> {
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    let mut counts: Vec<usize> = (0..).map(|shift| 1 << shift).take_while(|&count| count < cores).collect();
+    counts.push(cores);
+    counts
+}
+
+fn command(args: &[&Bytes]) -> Parse {
+    Parse::new(Frame::Array(args.iter().map(|&arg| Frame::Bulk(arg.clone())).collect())).unwrap()
+}
+
+/// Run `ops` operations split over `threads` threads, each alternating a
+/// SET and a GET on its own keys, and return the time taken.
+fn run(db: &Arc<Db>, threads: usize, ops: u64) -> Duration {
+    let barrier = Arc::new(Barrier::new(threads + 1));
+    let handles: Vec<_> = (0..threads)
+        .map(|thread| {
+            let mut session = Session::new(db.clone());
+            let barrier = barrier.clone();
+            let keys: Vec<Bytes> = (0..KEYS_PER_THREAD).map(|key| Bytes::from(format!("thread:{}:key:{}", thread, key))).collect();
+            let (set, get, value) = (Bytes::from_static(b"SET"), Bytes::from_static(b"GET"), Bytes::from_static(b"value"));
+
+            thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+                barrier.wait();
+                runtime.block_on(async {
+                    for op in 0..ops / threads as u64 {
+                        let key = &keys[op as usize % KEYS_PER_THREAD];
+                        let mut parse = if op % 2 == 0 { command(&[&set, key, &value]) } else { command(&[&get, key]) };
+                        handle_command(&mut parse, &mut session).await.unwrap();
+                    }
+                });
+            })
+        })
+        .collect();
+
+    barrier.wait();
+    let start = Instant::now();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    start.elapsed()
+}
+
+/// Measure throughput for every thread count, each against a `Db` made by
+/// `db` for it.
+fn bench(c: &mut Criterion, name: &str, db: impl Fn(usize) -> Db) {
+    let mut group = c.benchmark_group(name);
+    // Operations per measured iteration, whatever the thread count.
+    let ops = 100_000;
+    group.throughput(Throughput::Elements(ops));
+
+    for threads in thread_counts() {
+        let db = Arc::new(db(threads));
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| (0..iters).map(|_| run(&db, threads, ops)).sum());
+        });
+    }
+    group.finish();
+}
+
+fn get_set(c: &mut Criterion) {
+    bench(c, "get_set", |_| Db::new());
+}
+
+/// The same with every SET logged to the append-only file, flushed to disk
+/// by the operating system.
+fn get_set_aof(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    bench(c, "get_set_aof", |threads| {
+        let config = Config {
+            dbfilename: dir.path().join(format!("{}.ecs", threads)),
+            appendonly: true,
+            appendfilename: dir.path().join(format!("{}.aof", threads)),
+            appendfsync: AppendFsync::No,
+            ..Config::default()
+        };
+        Db::open(config).unwrap()
+    });
+}
+
+criterion_group!(benches, get_set, get_set_aof);
+criterion_main!(benches);
@@ -0,0 +1,568 @@
+//! Append-only file: a log of every write, replayed at startup.
+//!
+//! Write commands are appended in RESP form once they have run, preceded by
+//! a `SELECT` whenever their namespace differs from that of the previous
+//! entry. Commands whose effect depends on when or how they ran are logged
+//! in a form that replays to the same result; see `command::propagate`.
+//!
+//! Writes run concurrently, and are logged in the order they took effect: a
+//! write takes its place in the log while it still holds the locks of what
+//! it changed, and fills it in once it has run. The log itself is only
+//! locked to write the entries whose turn has come.
+//!
+//! `BGREWRITEAOF` replaces the log with a snapshot of the data, embedded as
+//! a single bulk string, followed by the writes made while the snapshot was
+//! being written. A log that ends in the middle of a command, as after a
+//! crash, is trimmed back to the last complete command when loaded.
+//!
+//! Transactions are logged as a whole between `MULTI` and `EXEC`; one that
+//! was cut short is trimmed along with its `MULTI`.
+
+use crate::command::handle_command;
+use crate::frame::{self, Frame};
+use crate::snapshot::{self, Record};
+use crate::{Db, Parse, Session};
+use bytes::Bytes;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use tokio::sync::Notify;
+
+/// A command to log and the namespace it ran in.
+type Entry = (usize, Vec<Bytes>);
+
+thread_local! {
+    /// What the write running on this thread logs, while it runs.
+    static SCOPE: RefCell<Option<Logged>> = const { RefCell::new(None) };
+}
+
+/// When the log is flushed to disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AppendFsync {
+    /// After every write, before replying.
+    Always,
+    /// Once per second in the background.
+    #[default]
+    EverySec,
+    /// Whenever the operating system sees fit.
+    No,
+}
+
+impl AppendFsync {
+    /// Name of the policy, as used by `appendfsync`.
+    pub fn name(self) -> &'static str {
+        match self {
+            AppendFsync::Always => "always",
+            AppendFsync::EverySec => "everysec",
+            AppendFsync::No => "no",
+        }
+    }
+}
+
+impl FromStr for AppendFsync {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let policy = match name.to_lowercase().as_str() {
+            "always" => AppendFsync::Always,
+            "everysec" => AppendFsync::EverySec,
+            "no" => AppendFsync::No,
+            _ => return Err(format!("invalid fsync policy '{}'", name)),
+        };
+        Ok(policy)
+    }
+}
+
+impl fmt::Display for AppendFsync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The append-only file of a `Db`, along with its settings and the outcome
+/// of the last write and rewrite.
+#[derive(Debug)]
+pub(crate) struct AppendOnly {
+    pub(crate) path: PathBuf,
+    pub(crate) fsync: Mutex<AppendFsync>,
+
+    /// The open log; set once it has been loaded, and never if the log is
+    /// disabled.
+    log: OnceLock<tokio::sync::Mutex<Log>>,
+
+    /// Writes that took effect and are not logged yet.
+    pending: Mutex<Pending>,
+
+    /// Notified whenever a write fills its slot in `pending`.
+    filled: Notify,
+
+    /// Size of the log in bytes.
+    pub(crate) size: AtomicU64,
+    pub(crate) rewriting: AtomicBool,
+    pub(crate) last_rewrite_ok: AtomicBool,
+    pub(crate) last_write_ok: AtomicBool,
+}
+
+#[derive(Debug)]
+struct Log {
+    file: File,
+
+    /// Namespace of the last command logged, if known.
+    namespace: Option<usize>,
+
+    /// Writes made since a rewrite started, appended to the rewritten log
+    /// once its snapshot is written.
+    rewrite: Option<Vec<u8>>,
+
+    /// Whether anything was written since the last fsync.
+    unsynced: bool,
+}
+
+/// Exclusive access to the log, held while entries are written to it.
+pub(crate) struct Writer<'a> {
+    aof: &'a AppendOnly,
+    log: tokio::sync::MutexGuard<'a, Log>,
+}
+
+/// Writes waiting for their turn in the log, each in a slot numbered in the
+/// order they took effect. A slot is empty until its write has run.
+#[derive(Debug, Default)]
+struct Pending {
+    /// Number of the first slot in `slots`; every slot before it is logged.
+    first: u64,
+    slots: VecDeque<Option<Vec<Entry>>>,
+}
+
+impl Pending {
+    fn reserve(&mut self) -> u64 {
+        self.slots.push_back(None);
+        self.first + self.slots.len() as u64 - 1
+    }
+
+    fn fill(&mut self, slot: u64, entries: Vec<Entry>) {
+        self.slots[(slot - self.first) as usize] = Some(entries);
+    }
+
+    /// Take the entries of the filled slots at the front.
+    fn take_ready(&mut self) -> Vec<Entry> {
+        let mut ready = Vec::new();
+        while let Some(Some(_)) = self.slots.front() {
+            ready.extend(self.slots.pop_front().flatten().unwrap_or_default());
+            self.first += 1;
+        }
+        ready
+    }
+}
+
+/// What a write logs: the slot it took, once it changed something, and the
+/// writes made on its behalf, such as pops served to blocked clients.
+#[derive(Debug, Default)]
+struct Logged {
+    slot: Option<u64>,
+    recorded: Vec<Entry>,
+}
+
+/// Collects what the write command running on the current thread logs.
+/// The command must run without yielding while the scope is open.
+///
+/// Dropping the scope without logging anything frees the slot it took.
+pub(crate) struct Scope<'a> {
+    aof: &'a AppendOnly,
+}
+
+impl AppendOnly {
+    pub(crate) fn new(path: PathBuf, fsync: AppendFsync) -> AppendOnly {
+        AppendOnly {
+            path,
+            fsync: Mutex::new(fsync),
+            log: OnceLock::new(),
+            pending: Mutex::new(Pending::default()),
+            filled: Notify::new(),
+            size: AtomicU64::new(0),
+            rewriting: AtomicBool::new(false),
+            last_rewrite_ok: AtomicBool::new(true),
+            last_write_ok: AtomicBool::new(true),
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.log.get().is_some()
+    }
+
+    /// Start logging to `file`, which already holds `size` bytes.
+    fn enable(&self, file: File, size: u64) {
+        let log = Log { file, namespace: None, rewrite: None, unsynced: false };
+        self.log.set(tokio::sync::Mutex::new(log)).expect("the log is enabled only once");
+        self.size.store(size, Ordering::Relaxed);
+    }
+
+    /// Lock the log, or `None` if it is disabled.
+    pub(crate) async fn lock(&self) -> Option<Writer<'_>> {
+        Some(Writer { aof: self, log: self.log.get()?.lock().await })
+    }
+
+    /// Start collecting what the write command about to run on this thread
+    /// logs, or `None` if the log is disabled.
+    pub(crate) fn scope(&self) -> Option<Scope<'_>> {
+        if !self.enabled() {
+            return None;
+        }
+        SCOPE.with_borrow_mut(|scope| {
+            debug_assert!(scope.is_none(), "scopes do not nest");
+            *scope = Some(Logged::default());
+        });
+        Some(Scope { aof: self })
+    }
+
+    /// Record that the running write changed something. Called while the
+    /// locks of what changed are still held, so that the write takes its
+    /// place in the log ahead of any later write to the same keys.
+    pub(crate) fn changed(&self) {
+        SCOPE.with_borrow_mut(|scope| {
+            if let Some(logged) = scope.as_mut().filter(|logged| logged.slot.is_none()) {
+                logged.slot = Some(self.pending.lock().unwrap().reserve());
+            }
+        });
+    }
+
+    /// Record `command`, run in namespace `index` on behalf of the write
+    /// running on this thread if there is one: a pop served to a blocked
+    /// client, or a key evicted to make room. Called while the locks of what
+    /// it changed are still held.
+    pub(crate) fn record(&self, index: usize, command: Vec<Bytes>) {
+        if !self.enabled() {
+            return;
+        }
+        let command = SCOPE.with_borrow_mut(|scope| match scope {
+            Some(logged) => {
+                logged.recorded.push((index, command));
+                None
+            }
+            None => Some(command),
+        });
+        if let Some(command) = command {
+            self.log_now(vec![(index, command)]);
+        }
+    }
+
+    /// Log `entries` in a slot of their own, taken now.
+    pub(crate) fn log_now(&self, entries: Vec<Entry>) -> u64 {
+        let mut pending = self.pending.lock().unwrap();
+        let slot = pending.reserve();
+        pending.fill(slot, entries);
+        drop(pending);
+        self.filled.notify_waiters();
+        slot
+    }
+
+    /// Write every entry logged so far, once the writes still filling their
+    /// slots are done.
+    pub(crate) async fn write_pending(&self) {
+        let last = {
+            let pending = self.pending.lock().unwrap();
+            (pending.first + pending.slots.len() as u64).checked_sub(1)
+        };
+        if let Some(last) = last {
+            self.write_through(last).await;
+        }
+    }
+
+    /// Write every entry up to and including those of `slot` to the log.
+    /// Slots before it that are still empty belong to writes running right
+    /// now, which fill them without yielding.
+    pub(crate) async fn write_through(&self, slot: u64) {
+        loop {
+            let mut filled = pin!(self.filled.notified());
+            filled.as_mut().enable();
+            match self.lock().await {
+                Some(mut writer) => writer.flush(),
+                None => return,
+            }
+            if self.pending.lock().unwrap().first > slot {
+                return;
+            }
+            filled.await;
+        }
+    }
+
+    /// Flush the log to disk if the policy is `everysec` and anything was
+    /// written since the last time.
+    ///
+    /// Entries no command wrote itself, such as values put back for blocked
+    /// clients that went away, are written first, whatever the policy.
+    pub(crate) async fn sync(&self) {
+        let file = match self.lock().await {
+            Some(mut writer) => {
+                writer.flush();
+                if *self.fsync.lock().unwrap() != AppendFsync::EverySec || !std::mem::take(&mut writer.log.unsynced) {
+                    return;
+                }
+                writer.log.file.try_clone()
+            }
+            None => return,
+        };
+
+        let result = match file {
+            Ok(file) => tokio::task::spawn_blocking(move || file.sync_data()).await.unwrap_or_else(|e| Err(io::Error::other(e))),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::error!("Can't fsync the append-only file: {}", e);
+        }
+    }
+
+    /// Replace the log with `namespaces`, a copy of the data taken when
+    /// `Writer::start_rewrite` was called, followed by the writes made since.
+    pub(crate) fn rewrite(&self, namespaces: &[Vec<Record>]) {
+        let log = self.log.get().expect("only an enabled log is rewritten");
+        let result = write_base(&self.path, namespaces).and_then(|(mut file, base)| {
+            let mut log = log.blocking_lock();
+            let tail = log.rewrite.take().unwrap_or_default();
+            file.write_all(&tail)?;
+            file.sync_all()?;
+            fs::rename(temporary(&self.path), &self.path)?;
+
+            log.file = OpenOptions::new().append(true).open(&self.path)?;
+            log.unsynced = false;
+            self.size.store((base + tail.len()) as u64, Ordering::Relaxed);
+            Ok(())
+        });
+
+        if let Err(e) = &result {
+            tracing::error!("Append-only file rewrite failed: {}", e);
+            log.blocking_lock().rewrite = None;
+        }
+        self.last_rewrite_ok.store(result.is_ok(), Ordering::Relaxed);
+        self.rewriting.store(false, Ordering::Release);
+    }
+}
+
+impl Scope<'_> {
+    /// Log `commands`, run in namespace `namespace`, followed by the pops of
+    /// blocked clients they served. Returns the slot they take, or `None`
+    /// if nothing changed.
+    pub(crate) fn log(self, namespace: usize, commands: Vec<Vec<Bytes>>) -> Option<u64> {
+        self.finish(commands.into_iter().map(|command| (namespace, command)).collect(), false)
+    }
+
+    /// Log the commands of a transaction, each with the namespace it ran in,
+    /// and the pops of blocked clients they served, between `MULTI` and
+    /// `EXEC`. A log cut short in between replays none of them.
+    pub(crate) fn log_transaction(self, commands: Vec<Entry>) -> Option<u64> {
+        self.finish(commands, true)
+    }
+
+    fn finish(self, mut entries: Vec<Entry>, transaction: bool) -> Option<u64> {
+        let logged = SCOPE.take().unwrap_or_default();
+        entries.extend(logged.recorded);
+        // A write that changed nothing has nothing to replay.
+        let slot = logged.slot?;
+
+        if transaction {
+            if let (Some(&(first, _)), Some(&(last, _))) = (entries.first(), entries.last()) {
+                entries.insert(0, (first, vec![Bytes::from_static(b"MULTI")]));
+                entries.push((last, vec![Bytes::from_static(b"EXEC")]));
+            }
+        }
+        self.aof.pending.lock().unwrap().fill(slot, entries);
+        self.aof.filled.notify_waiters();
+        Some(slot)
+    }
+}
+
+impl Drop for Scope<'_> {
+    fn drop(&mut self) {
+        if let Some(slot) = SCOPE.take().and_then(|logged| logged.slot) {
+            self.aof.pending.lock().unwrap().fill(slot, Vec::new());
+            self.aof.filled.notify_waiters();
+        }
+    }
+}
+
+impl Writer<'_> {
+    /// Write the entries of the writes whose turn has come.
+    pub(crate) fn flush(&mut self) {
+        let entries = self.aof.pending.lock().unwrap().take_ready();
+        self.write(&entries);
+    }
+
+    /// Write `entries`, each a command and the namespace it ran in.
+    fn write(&mut self, entries: &[Entry]) {
+        if entries.is_empty() {
+            return;
+        }
+
+        let mut data = Vec::new();
+        for (namespace, command) in entries {
+            if self.log.namespace != Some(*namespace) {
+                encode(&mut data, &[Bytes::from_static(b"SELECT"), Bytes::from(namespace.to_string())]);
+                self.log.namespace = Some(*namespace);
+            }
+            encode(&mut data, command);
+        }
+
+        if let Some(rewrite) = &mut self.log.rewrite {
+            rewrite.extend_from_slice(&data);
+        }
+        let mut result = self.log.file.write_all(&data);
+        if result.is_ok() && *self.aof.fsync.lock().unwrap() == AppendFsync::Always {
+            result = self.log.file.sync_data();
+        }
+
+        match result {
+            Ok(()) => {
+                self.log.unsynced = true;
+                self.aof.size.fetch_add(data.len() as u64, Ordering::Relaxed);
+                self.aof.last_write_ok.store(true, Ordering::Relaxed);
+            }
+            Err(e) => {
+                tracing::error!("Can't write to the append-only file: {}", e);
+                self.aof.last_write_ok.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Start buffering writes for a rewrite. Returns `false` if a rewrite is
+    /// already running.
+    pub(crate) fn start_rewrite(&mut self) -> bool {
+        if self.log.rewrite.is_some() {
+            return false;
+        }
+        self.log.rewrite = Some(Vec::new());
+        // The rewritten log starts with the snapshot, so its first command
+        // must select its namespace.
+        self.log.namespace = None;
+        self.aof.rewriting.store(true, Ordering::Release);
+        true
+    }
+}
+
+/// Append `command` to `out` as a RESP array of bulk strings.
+fn encode(out: &mut Vec<u8>, command: &[Bytes]) {
+    out.extend_from_slice(format!("*{}\r\n", command.len()).as_bytes());
+    for arg in command {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+fn temporary(path: &Path) -> PathBuf {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    temporary.into()
+}
+
+/// Write `namespaces` as the snapshot a log starts with to the temporary
+/// file next to `path`. Returns the file, open for more writes, and the size
+/// of the snapshot.
+fn write_base(path: &Path, namespaces: &[Vec<Record>]) -> io::Result<(File, usize)> {
+    let data = snapshot::encode(namespaces);
+    let mut base = format!("${}\r\n", data.len()).into_bytes();
+    base.extend_from_slice(&data);
+    base.extend_from_slice(b"\r\n");
+
+    let mut file = File::create(temporary(path))?;
+    file.write_all(&base)?;
+    Ok((file, base.len()))
+}
+
+/// Load the log of `db`, or create it from the snapshot if there is no log
+/// yet, and start logging writes.
+pub(crate) fn open(db: Db) -> crate::Result<Db> {
+    let path = db.aof().path.clone();
+    let (db, file, size) = match snapshot::read_file(&path)? {
+        Some(data) => {
+            let db = Arc::new(db);
+            let valid = replay(&db, &data).map_err(|e| format!("can't load append-only file '{}': {}", path.display(), e))?;
+            let file = OpenOptions::new().append(true).open(&path)?;
+            if valid < data.len() {
+                tracing::warn!(
+                    "Append-only file '{}' ends with an incomplete command or transaction; trimming {} bytes",
+                    path.display(),
+                    data.len() - valid
+                );
+                file.set_len(valid as u64)?;
+                file.sync_all()?;
+            }
+
+            let db = Arc::into_inner(db).expect("nothing outlives the replay");
+            (db, file, valid)
+        }
+        None => {
+            db.load_snapshot()?;
+            let (namespaces, _) = db.records();
+            let (file, size) = write_base(&path, &namespaces)?;
+            file.sync_all()?;
+            fs::rename(temporary(&path), &path)?;
+            (db, OpenOptions::new().append(true).open(&path)?, size)
+        }
+    };
+
+    db.aof().enable(file, size as u64);
+    Ok(db)
+}
+
+/// Run the commands logged in `data` against `db`. Returns the length of the
+/// part of `data` that holds complete commands and transactions; the rest
+/// was cut short.
+fn replay(db: &Arc<Db>, data: &[u8]) -> Result<usize, String> {
+    let mut session = Session::new(db.clone());
+    let mut cursor = Cursor::new(data);
+    let mut valid = 0;
+    let mut committed = 0;
+
+    while valid < data.len() {
+        match Frame::check(&mut cursor) {
+            Ok(()) => {}
+            Err(frame::Error::Incomplete) => break,
+            Err(frame::Error::Other(e)) => return Err(format!("invalid entry at offset {}: {}", valid, e)),
+        }
+        cursor.set_position(valid as u64);
+        let entry = Frame::parse(&mut cursor).map_err(|e| format!("invalid entry at offset {}: {:?}", valid, e))?;
+
+        match entry {
+            Frame::Bulk(base) if valid == 0 => {
+                let namespaces = snapshot::decode(base).map_err(|e| format!("invalid snapshot: {}", e))?;
+                db.install(namespaces)?;
+            }
+            Frame::Array(_) => {
+                let mut parse = Parse::new(entry).map_err(|e| e.to_string())?;
+                // Logged commands never block, so they complete as soon as
+                // they are polled.
+                match poll_once(handle_command(&mut parse, &mut session)) {
+                    Poll::Ready(Ok(_)) => {}
+                    Poll::Ready(Err(e)) => return Err(format!("command at offset {} failed: {}", valid, e)),
+                    Poll::Pending => return Err(format!("command at offset {} blocked", valid)),
+                }
+            }
+            _ => return Err(format!("unexpected entry at offset {}", valid)),
+        }
+        valid = cursor.position() as usize;
+        if !session.in_transaction() {
+            committed = valid;
+        }
+    }
+
+    Ok(committed)
+}
+
+/// Take what was recorded on behalf of the write running on this thread so
+/// far, for a transaction to log it between its commands.
+pub(crate) fn take_recorded() -> Vec<(usize, Vec<Bytes>)> {
+    SCOPE.with_borrow_mut(|scope| scope.as_mut().map(|logged| std::mem::take(&mut logged.recorded)).unwrap_or_default())
+}
+
+/// Poll `future` once, without arranging to be woken.
+pub(crate) fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+    pin!(future).poll(&mut Context::from_waker(Waker::noop()))
+}
@@ -36,22 +36,19 @@ async fn main() -> Result<(), Error> {
                 if select_used {
                     println!("Error: SELECT can only be called at the start of the session");
                 }
-                else if let Some(index_str) = parts.get(1) {
-                    if let Ok(index) = index_str.parse::<usize>() {
-                        let client = client.clone();
-                        tokio::spawn(async move {
-                            let res = client.lock().await.select(index).await;
-                            match res {
-                                Ok(_) => println!("Database {} selected", index),
-                                Err(e) => println!("Error: {}", e),
-                            }
-                        });
-                        select_used = true;
-                    } else {
-                        println!("Invalid database index.");
-                    }
+                else if let Some(name) = parts.get(1) {
+                    let name = name.to_string();
+                    let client = client.clone();
+                    tokio::spawn(async move {
+                        let res = client.lock().await.select_named(&name).await;
+                        match res {
+                            Ok(_) => println!("Database {} selected", name),
+                            Err(e) => println!("Error: {}", e),
+                        }
+                    });
+                    select_used = true;
                 } else {
-                    println!("Usage: SELECT <index> (0-15) default 0");
+                    println!("Usage: SELECT <index|alias> default 0");
                 }
             },
             "get" => {
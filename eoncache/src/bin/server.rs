@@ -1,13 +1,105 @@
-use eoncache::{Db, Shutdown, run_server};
+use eoncache::{AppendFsync, Config, Db, EvictionPolicy, KeyspaceEvents, Shutdown, run_server};
+use eoncache::config::{parse_memory, parse_save, SaveRule};
+use std::path::PathBuf;
+use structopt::StructOpt;
 use tokio::net::TcpListener;
 use std::sync::Arc;
 
+#[derive(StructOpt, Debug)]
+#[structopt(name = "eoncache-server")]
+struct Cli {
+    /// Number of namespaces
+    #[structopt(long, default_value = "16")]
+    databases: usize,
+
+    /// Name a namespace, as in `--alias sessions=1`, so that clients can
+    /// `SELECT sessions`; may be repeated
+    #[structopt(long = "alias", parse(try_from_str = parse_alias))]
+    aliases: Vec<(String, usize)>,
+
+    /// Memory limit, such as `100mb`; 0 means unlimited
+    #[structopt(long, default_value = "0", parse(try_from_str = parse_maxmemory))]
+    maxmemory: usize,
+
+    /// What to do once the memory limit is reached: noeviction, allkeys-lru,
+    /// allkeys-lfu, allkeys-random, volatile-lru, volatile-lfu,
+    /// volatile-random or volatile-ttl
+    #[structopt(long, default_value = "noeviction")]
+    maxmemory_policy: EvictionPolicy,
+
+    /// File snapshots are saved to and loaded from at startup
+    #[structopt(long, default_value = "dump.ecs", parse(from_os_str))]
+    dbfilename: PathBuf,
+
+    /// When to save a snapshot in the background, as pairs of seconds and
+    /// changes; an empty string disables saving
+    #[structopt(long, default_value = "3600 1 300 100 60 10000", parse(try_from_str = parse_save_rules))]
+    save: SaveRules,
+
+    /// Log every write to the append-only file, and load it at startup
+    /// instead of the snapshot
+    #[structopt(long)]
+    appendonly: bool,
+
+    /// File writes are logged to
+    #[structopt(long, default_value = "appendonly.aof", parse(from_os_str))]
+    appendfilename: PathBuf,
+
+    /// When the append-only file is flushed to disk: always, everysec or no
+    #[structopt(long, default_value = "everysec")]
+    appendfsync: AppendFsync,
+
+    /// Messages a subscriber may have waiting before it is disconnected; 0
+    /// means unlimited
+    #[structopt(long, default_value = "10000")]
+    pubsub_queue_limit: usize,
+
+    /// Keyspace events to publish, as letters: K for keyspace channels, E
+    /// for keyevent channels, then g, $, l, s, h, z, x, e, t or A for the
+    /// classes of events
+    #[structopt(long, default_value = "")]
+    notify_keyspace_events: KeyspaceEvents,
+}
+
+// Under its own name so that structopt takes `--save` as a single value
+// rather than one rule per occurrence.
+type SaveRules = Vec<SaveRule>;
+
+fn parse_alias(value: &str) -> Result<(String, usize), String> {
+    let invalid = || format!("invalid alias '{}', expected name=index", value);
+    let (name, index) = value.split_once('=').ok_or_else(invalid)?;
+    Ok((name.to_string(), index.parse().map_err(|_| invalid())?))
+}
+
+fn parse_maxmemory(value: &str) -> Result<usize, String> {
+    parse_memory(value).ok_or_else(|| format!("invalid memory value '{}'", value))
+}
+
+fn parse_save_rules(value: &str) -> Result<SaveRules, String> {
+    parse_save(value).ok_or_else(|| format!("invalid save rules '{}'", value))
+}
+
 #[tokio::main]
 
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> eoncache::Result<()> {
+    let cli = Cli::from_args();
+    let config = Config {
+        databases: cli.databases,
+        aliases: cli.aliases.into_iter().collect(),
+        maxmemory: cli.maxmemory,
+        maxmemory_policy: cli.maxmemory_policy,
+        dbfilename: cli.dbfilename,
+        save: cli.save,
+        appendonly: cli.appendonly,
+        appendfilename: cli.appendfilename,
+        appendfsync: cli.appendfsync,
+        pubsub_queue_limit: cli.pubsub_queue_limit,
+        notify_keyspace_events: cli.notify_keyspace_events,
+    };
+    config.validate()?;
 
     // Create the shared database instance=
-    let db = Arc::new(Db::new()); 
+    let db = Arc::new(Db::open(config)?);
 
     // Set up the TCP listener
     let listener = TcpListener::bind("127.0.0.1:6379").await?;
@@ -20,7 +112,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         shutdown_clone.listen_for_ctrl_c().await;
     });
     // Run the server
-    let _ = run_server(listener, db, shutdown).await;
+    let _ = run_server(listener, db.clone(), shutdown).await;
+
+    // Save on the way out so that nothing since the last snapshot is lost.
+    if !db.save_rules().is_empty() {
+        db.save()?;
+    }
     println!("Server has shut down");
     Ok(())
 }
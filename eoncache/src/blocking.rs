@@ -0,0 +1,293 @@
+//! Registry of clients blocked on empty keys.
+//!
+//! A client that blocks (for instance with `BLPOP` or `BZPOPMIN`) registers a
+//! `Waiter` under every key it is waiting on. Writers that make data available
+//! on a key hand it directly to the oldest registered waiter, so blocked
+//! clients are served in FIFO order without polling.
+//!
+//! Clients blocked on a stream (`XREAD BLOCK`) do not consume what they read,
+//! so every one of them must see a new entry. They register as `Watchers`
+//! instead and are all woken whenever an entry is appended.
+
+use crate::db::format_float;
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{oneshot, Notify};
+
+/// One end of a list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum End {
+    Front,
+    Back,
+}
+
+/// What a blocked client pops once its key holds data.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Pop {
+    /// An element from one end of a list (`BLPOP`, `BRPOP`).
+    List(End),
+    /// An element from one end of a list, pushed onto one end of the list at
+    /// `destination` in the same step (`BLMOVE`).
+    Move { from: End, to: End, destination: Bytes },
+    /// The member with the lowest score of a sorted set (`BZPOPMIN`).
+    Min,
+    /// The member with the highest score of a sorted set (`BZPOPMAX`).
+    Max,
+}
+
+impl Pop {
+    /// Whether this pop applies to values of the given type.
+    pub(crate) fn accepts(&self, type_name: &str) -> bool {
+        match self {
+            Pop::List(_) | Pop::Move { .. } => type_name == "list",
+            Pop::Min | Pop::Max => type_name == "zset",
+        }
+    }
+
+    /// The command that pops the same way from `key` without blocking, as
+    /// logged to the append-only file.
+    pub(crate) fn command(&self, key: &Bytes) -> Vec<Bytes> {
+        let end = |end: &End| Bytes::from_static(if *end == End::Front { b"LEFT" } else { b"RIGHT" });
+        match self {
+            Pop::List(End::Front) => vec![Bytes::from_static(b"LPOP"), key.clone()],
+            Pop::List(End::Back) => vec![Bytes::from_static(b"RPOP"), key.clone()],
+            Pop::Move { from, to, destination } => {
+                vec![Bytes::from_static(b"LMOVE"), key.clone(), destination.clone(), end(from), end(to)]
+            }
+            Pop::Min => vec![Bytes::from_static(b"ZPOPMIN"), key.clone()],
+            Pop::Max => vec![Bytes::from_static(b"ZPOPMAX"), key.clone()],
+        }
+    }
+
+    /// The command that puts `handoff` back where this pop took it from, as
+    /// logged to the append-only file, or `None` for a move, whose element
+    /// stays in its destination.
+    pub(crate) fn undo_command(&self, handoff: &Handoff) -> Option<Vec<Bytes>> {
+        let push: &'static [u8] = match self {
+            Pop::List(End::Front) => b"LPUSH",
+            Pop::List(End::Back) => b"RPUSH",
+            Pop::Move { .. } => return None,
+            Pop::Min | Pop::Max => {
+                let score = format_float(handoff.score.unwrap_or_default());
+                return Some(vec![Bytes::from_static(b"ZADD"), handoff.key.clone(), Bytes::from(score), handoff.value.clone()]);
+            }
+        };
+        Some(vec![Bytes::from_static(push), handoff.key.clone(), handoff.value.clone()])
+    }
+}
+
+/// Value handed to a blocked client.
+#[derive(Debug)]
+pub(crate) struct Handoff {
+    /// The key the value was popped from.
+    pub(crate) key: Bytes,
+    pub(crate) value: Bytes,
+    /// The member's score, for sorted set pops.
+    pub(crate) score: Option<f64>,
+}
+
+/// What a blocked client is handed: a value, or the error serving it ran
+/// into.
+pub(crate) type Served = Result<Handoff, &'static str>;
+
+/// A client blocked on one or more keys.
+///
+/// The same waiter is queued under each of its keys. Whichever key is served
+/// first takes the sender; the stale registrations under the other keys are
+/// skipped and removed when the waiter unregisters.
+#[derive(Debug)]
+struct Waiter {
+    id: u64,
+    pop: Pop,
+    sender: Mutex<Option<oneshot::Sender<Served>>>,
+}
+
+/// Waiters blocked on a single key, oldest first.
+type Queue = VecDeque<Arc<Waiter>>;
+
+/// All blocked clients, keyed by namespace index and key.
+#[derive(Debug, Default)]
+pub(crate) struct Waiters {
+    queues: Mutex<HashMap<(usize, Bytes), Queue>>,
+    next_id: AtomicU64,
+}
+
+impl Waiters {
+    /// Register a new waiter under each of `keys` in namespace `ns`.
+    ///
+    /// Must be called while holding the locks of `keys`, so that a writer
+    /// cannot add data between the caller's last check and the registration.
+    /// Returns the waiter's id, to be passed to `unregister`, and the
+    /// receiving half of its handoff channel.
+    pub(crate) fn register(&self, ns: usize, keys: &[Bytes], pop: Pop) -> (u64, oneshot::Receiver<Served>) {
+        let (tx, rx) = oneshot::channel();
+        let waiter = Arc::new(Waiter {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            pop,
+            sender: Mutex::new(Some(tx)),
+        });
+
+        let mut queues = self.queues.lock().unwrap();
+        for key in keys {
+            queues
+                .entry((ns, key.clone()))
+                .or_default()
+                .push_back(waiter.clone());
+        }
+
+        (waiter.id, rx)
+    }
+
+    /// Remove the waiter `id` from the queues of `keys`.
+    ///
+    /// Called once the waiter is served, times out, or its client goes away.
+    pub(crate) fn unregister(&self, ns: usize, keys: &[Bytes], id: u64) {
+        let mut queues = self.queues.lock().unwrap();
+        for key in keys {
+            let slot = (ns, key.clone());
+            if let Some(queue) = queues.get_mut(&slot) {
+                queue.retain(|waiter| waiter.id != id);
+                if queue.is_empty() {
+                    queues.remove(&slot);
+                }
+            }
+        }
+    }
+
+    /// Keys of namespace `ns` that have clients blocked on them.
+    pub(crate) fn keys(&self, ns: usize) -> Vec<Bytes> {
+        let queues = self.queues.lock().unwrap();
+        queues
+            .keys()
+            .filter(|(index, _)| *index == ns)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+
+    /// Destinations of the clients blocked moving elements out of `key`.
+    pub(crate) fn destinations(&self, ns: usize, key: &[u8]) -> Vec<Bytes> {
+        let queues = self.queues.lock().unwrap();
+        let queue = match queues.get(&(ns, Bytes::copy_from_slice(key))) {
+            Some(queue) => queue,
+            None => return Vec::new(),
+        };
+
+        queue
+            .iter()
+            .filter_map(|waiter| match &waiter.pop {
+                Pop::Move { destination, .. } => Some(destination.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Take the oldest waiter on `key` that can still be served and whose
+    /// pop applies to values of type `type_name`.
+    ///
+    /// Waiters that were already served through another key, or whose client
+    /// has gone away, are discarded along the way. Waiters expecting another
+    /// type stay queued.
+    pub(crate) fn next(&self, ns: usize, key: &[u8], type_name: &str) -> Option<(Pop, oneshot::Sender<Served>)> {
+        let mut queues = self.queues.lock().unwrap();
+        let slot = (ns, Bytes::copy_from_slice(key));
+        let queue = queues.get_mut(&slot)?;
+
+        let mut found = None;
+        let mut index = 0;
+        while index < queue.len() {
+            let waiter = &queue[index];
+            let mut sender = waiter.sender.lock().unwrap();
+
+            if sender.as_ref().is_none_or(|sender| sender.is_closed()) {
+                drop(sender);
+                queue.remove(index);
+                continue;
+            }
+
+            if waiter.pop.accepts(type_name) {
+                found = Some((waiter.pop.clone(), sender.take().expect("sender is present")));
+                drop(sender);
+                queue.remove(index);
+                break;
+            }
+
+            index += 1;
+        }
+
+        if queue.is_empty() {
+            queues.remove(&slot);
+        }
+        found
+    }
+}
+
+/// Watchers registered on a single key, by id.
+type WatchList = Vec<(u64, Arc<Notify>)>;
+
+/// Clients blocked until one of the streams they read is appended to.
+#[derive(Debug, Default)]
+pub(crate) struct Watchers {
+    watchers: Mutex<HashMap<(usize, Bytes), WatchList>>,
+    next_id: AtomicU64,
+}
+
+impl Watchers {
+    /// Register a watcher under each of `keys` in namespace `ns`.
+    ///
+    /// Must be called while holding the locks of `keys`, like
+    /// `Waiters::register`. Returns the watcher's id, to be passed to
+    /// `unregister`, and the `Notify` it is woken through. A wakeup that
+    /// happens before the watcher starts waiting is not lost.
+    pub(crate) fn register(&self, ns: usize, keys: &[Bytes]) -> (u64, Arc<Notify>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let notify = Arc::new(Notify::new());
+
+        let mut watchers = self.watchers.lock().unwrap();
+        for key in keys {
+            watchers
+                .entry((ns, key.clone()))
+                .or_default()
+                .push((id, notify.clone()));
+        }
+
+        (id, notify)
+    }
+
+    /// Remove the watcher `id` from `keys`.
+    pub(crate) fn unregister(&self, ns: usize, keys: &[Bytes], id: u64) {
+        let mut watchers = self.watchers.lock().unwrap();
+        for key in keys {
+            let slot = (ns, key.clone());
+            if let Some(list) = watchers.get_mut(&slot) {
+                list.retain(|(watcher, _)| *watcher != id);
+                if list.is_empty() {
+                    watchers.remove(&slot);
+                }
+            }
+        }
+    }
+
+    /// Wake every watcher registered on any key of namespace `ns`.
+    pub(crate) fn notify_all(&self, ns: usize) {
+        let watchers = self.watchers.lock().unwrap();
+        for ((index, _), list) in watchers.iter() {
+            if *index == ns {
+                for (_, notify) in list {
+                    notify.notify_one();
+                }
+            }
+        }
+    }
+
+    /// Wake every watcher registered on `key`.
+    pub(crate) fn notify(&self, ns: usize, key: &[u8]) {
+        let watchers = self.watchers.lock().unwrap();
+        if let Some(list) = watchers.get(&(ns, Bytes::copy_from_slice(key))) {
+            for (_, notify) in list {
+                notify.notify_one();
+            }
+        }
+    }
+}
@@ -19,13 +19,22 @@ pub fn buffer(client: Client) -> Buffer {
 // Enum used to message pass the requested command from the `Buffer` handle
 #[derive(Debug)]
 enum Command {
-    Get(String),
-    Set(String, String),
+    Get(Bytes),
+    Set(Bytes, Bytes),
     Select(usize),
     Ping,
-    Exists(String),
-    RPush(String, Bytes),
-    LPush(String, Bytes),
+    Exists(Bytes),
+    RPush(Bytes, Bytes),
+    LPush(Bytes, Bytes),
+    HSet(Bytes, Vec<(String, Bytes)>),
+    HGet(Bytes, String),
+    HMGet(Bytes, Vec<String>),
+    HGetAll(Bytes),
+    HDel(Bytes, Vec<String>),
+    HExists(Bytes, String),
+    HLen(Bytes),
+    HIncrBy(Bytes, String, i64),
+    HIncrByFloat(Bytes, String, f64),
     // BLPop(String, usize),
     // BRPop(String, usize),
 }
@@ -37,20 +46,56 @@ enum Command {
 // `oneshot::Sender` is a channel type that sends a **single** value. It is used
 // here to send the response received from the connection back to the original
 // requester.
-type Message = (Command, oneshot::Sender<Result<Option<Bytes>>>);
+type Message = (Command, oneshot::Sender<Result<Reply>>);
+
+// Response sent back to the requester. Most commands reply with at most one
+// value; the hash commands that return several use the other variants.
+#[derive(Debug)]
+enum Reply {
+    Value(Option<Bytes>),
+    Values(Vec<Option<Bytes>>),
+    Pairs(Vec<(Bytes, Bytes)>),
+}
 
 /// Receive commands sent through the channel and forward them to client. The
 /// response is returned back to the caller via a `oneshot`.
 async fn run(mut client: Client, mut rx: Receiver<Message>) {
     while let Some((cmd, tx)) = rx.recv().await {
-        let response: Result<Option<Bytes>> = match cmd {
-            Command::Get(key) => client.get(&key).await,
-            Command::Set(key, value) => client.set(&key, &value).await.map(|_| None),
-            Command::Select(db) => client.select(db).await.map(|_| None),
-            Command::Ping => client.ping().await.map(|_| None),
-            Command::Exists(key) => client.exists(&key).await,
-            Command::RPush(key, value) => client.rpush(&key, value).await.map(|_| None),
-            Command::LPush(key, value) => client.lpush(&key, value).await.map(|_| None),
+        let response: Result<Reply> = match cmd {
+            Command::Get(key) => client.get(&key).await.map(Reply::Value),
+            Command::Set(key, value) => client.set(&key, &value).await.map(|_| Reply::Value(None)),
+            Command::Select(db) => client.select(db).await.map(|_| Reply::Value(None)),
+            Command::Ping => client.ping().await.map(|_| Reply::Value(None)),
+            Command::Exists(key) => client.exists(&key).await.map(Reply::Value),
+            Command::RPush(key, value) => client.rpush(&key, value).await.map(|_| Reply::Value(None)),
+            Command::LPush(key, value) => client.lpush(&key, value).await.map(|_| Reply::Value(None)),
+            Command::HSet(key, fields) => {
+                let fields: Vec<_> = fields.iter().map(|(field, value)| (field.as_str(), value.clone())).collect();
+                client.hset(&key, &fields).await.map(|n| Reply::Value(Some(Bytes::from(n.to_string()))))
+            }
+            Command::HGet(key, field) => client.hget(&key, &field).await.map(Reply::Value),
+            Command::HMGet(key, fields) => {
+                let fields: Vec<_> = fields.iter().map(String::as_str).collect();
+                client.hmget(&key, &fields).await.map(Reply::Values)
+            }
+            Command::HGetAll(key) => client.hgetall(&key).await.map(Reply::Pairs),
+            Command::HDel(key, fields) => {
+                let fields: Vec<_> = fields.iter().map(String::as_str).collect();
+                client.hdel(&key, &fields).await.map(|n| Reply::Value(Some(Bytes::from(n.to_string()))))
+            }
+            Command::HExists(key, field) => client
+                .hexists(&key, &field)
+                .await
+                .map(|exists| Reply::Value(Some(Bytes::from((exists as u8).to_string())))),
+            Command::HLen(key) => client.hlen(&key).await.map(|n| Reply::Value(Some(Bytes::from(n.to_string())))),
+            Command::HIncrBy(key, field, delta) => client
+                .hincrby(&key, &field, delta)
+                .await
+                .map(|n| Reply::Value(Some(Bytes::from(n.to_string())))),
+            Command::HIncrByFloat(key, field, delta) => client
+                .hincrbyfloat(&key, &field, delta)
+                .await
+                .map(|n| Reply::Value(Some(Bytes::from(n.to_string())))),
             // Command::BLPop(key, timeout) => {
             //     client.blpop(&key, timeout).await.map(|opt| opt.map(|(k, v)| Bytes::from([k.as_ref(), v.as_ref()].concat()))
             // Command::BRPop(key, timeout) => {
@@ -58,11 +103,34 @@ async fn run(mut client: Client, mut rx: Receiver<Message>) {
             // },
         };
 
-        let _ = tx.send(response.map_err(|e| e.into()));
+        let _ = tx.send(response);
     }
 }
 
 
+impl Reply {
+    fn into_value(self) -> Option<Bytes> {
+        match self {
+            Reply::Value(value) => value,
+            reply => panic!("expected a single value, got {:?}", reply),
+        }
+    }
+
+    fn into_values(self) -> Vec<Option<Bytes>> {
+        match self {
+            Reply::Values(values) => values,
+            reply => panic!("expected a list of values, got {:?}", reply),
+        }
+    }
+
+    fn into_pairs(self) -> Vec<(Bytes, Bytes)> {
+        match self {
+            Reply::Pairs(pairs) => pairs,
+            reply => panic!("expected field/value pairs, got {:?}", reply),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Buffer {
     tx: Sender<Message>,
@@ -73,9 +141,9 @@ impl Buffer {
     ///
     /// Same as `Client::get` but requests are **buffered** until the associated
     /// connection has the ability to send the request.
-    pub async fn get(&mut self, key: &str) -> Result<Option<Bytes>> {
+    pub async fn get(&mut self, key: impl AsRef<[u8]>) -> Result<Option<Bytes>> {
         // Initialize a new `Get` command to send via the channel.
-        let get = Command::Get(key.into());
+        let get = Command::Get(Bytes::copy_from_slice(key.as_ref()));
 
         // Initialize a new oneshot to be used to receive the response back from the connection.
         let (tx, rx) = oneshot::channel();
@@ -85,7 +153,7 @@ impl Buffer {
 
         // Await the response
         match rx.await {
-            Ok(res) => res,
+            Ok(res) => res.map(Reply::into_value),
             Err(err) => Err(err.into()),
         }
     }
@@ -94,9 +162,9 @@ impl Buffer {
     ///
     /// Same as `Client::set` but requests are **buffered** until the associated
     /// connection has the ability to send the request
-    pub async fn set(&mut self, key: &str, value: Bytes) -> Result<()> {
+    pub async fn set(&mut self, key: impl AsRef<[u8]>, value: Bytes) -> Result<()> {
         // Initialize a new `Set` command to send via the channel.
-        let set = Command::Set(key.into(), String::from_utf8(value.to_vec()).unwrap());
+        let set = Command::Set(Bytes::copy_from_slice(key.as_ref()), value);
 
         // Initialize a new oneshot to be used to receive the response back from the connection.
         let (tx, rx) = oneshot::channel();
@@ -156,9 +224,9 @@ impl Buffer {
 
 
     /// Check if a key exists.
-    pub async fn exists(&mut self, key: &str) -> Result<Option<Bytes>> {
+    pub async fn exists(&mut self, key: impl AsRef<[u8]>) -> Result<Option<Bytes>> {
         // Initialize a new `Exists` command to send via the channel.
-        let exists = Command::Exists(key.into());
+        let exists = Command::Exists(Bytes::copy_from_slice(key.as_ref()));
 
         // Initialize a new oneshot to be used to receive the response back from the connection.
         let (tx, rx) = oneshot::channel();
@@ -168,16 +236,16 @@ impl Buffer {
 
         // Await the response
         match rx.await {
-            Ok(res) => res,
+            Ok(res) => res.map(Reply::into_value),
             Err(err) => Err(err.into()),
         }
     }
 
 
     /// Push a value to the right end of a list.
-    pub async fn rpush(&mut self, key: &str, value: Bytes) -> Result<Option<Bytes>> {
+    pub async fn rpush(&mut self, key: impl AsRef<[u8]>, value: Bytes) -> Result<Option<Bytes>> {
         // Initialize a new `RPush` command to send via the channel.
-        let rpush = Command::RPush(key.into(), value);
+        let rpush = Command::RPush(Bytes::copy_from_slice(key.as_ref()), value);
 
         // Initialize a new oneshot to be used to receive the response back from the connection.
         let (tx, rx) = oneshot::channel();
@@ -187,15 +255,15 @@ impl Buffer {
 
         // Await the response
         match rx.await {
-            Ok(res) => res,
+            Ok(res) => res.map(Reply::into_value),
             Err(err) => Err(err.into()),
         }
     }
 
     /// Push a value to the left end of a list.
-    pub async fn lpush(&mut self, key: &str, value: Bytes) -> Result<Option<Bytes>> {
+    pub async fn lpush(&mut self, key: impl AsRef<[u8]>, value: Bytes) -> Result<Option<Bytes>> {
         // Initialize a new `LPush` command to send via the channel.
-        let lpush = Command::LPush(key.into(), value);
+        let lpush = Command::LPush(Bytes::copy_from_slice(key.as_ref()), value);
 
         // Initialize a new oneshot to be used to receive the response back from the connection.
         let (tx, rx) = oneshot::channel();
@@ -204,15 +272,78 @@ impl Buffer {
         self.tx.send((lpush, tx)).await?;
 
         // Await the response
+        match rx.await {
+            Ok(res) => res.map(Reply::into_value),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Set `fields` in the hash stored at `key`.
+    ///
+    /// Returns the number of fields that were newly added.
+    pub async fn hset(&mut self, key: impl AsRef<[u8]>, fields: &[(&str, Bytes)]) -> Result<Option<Bytes>> {
+        let fields = fields.iter().map(|(field, value)| (field.to_string(), value.clone())).collect();
+        self.send(Command::HSet(Bytes::copy_from_slice(key.as_ref()), fields)).await.map(Reply::into_value)
+    }
+
+    /// Get the value of `field` in the hash stored at `key`.
+    pub async fn hget(&mut self, key: impl AsRef<[u8]>, field: &str) -> Result<Option<Bytes>> {
+        self.send(Command::HGet(Bytes::copy_from_slice(key.as_ref()), field.into())).await.map(Reply::into_value)
+    }
+
+    /// Get the values of `fields` in the hash stored at `key`, in order.
+    pub async fn hmget(&mut self, key: impl AsRef<[u8]>, fields: &[&str]) -> Result<Vec<Option<Bytes>>> {
+        let fields = fields.iter().map(|field| field.to_string()).collect();
+        self.send(Command::HMGet(Bytes::copy_from_slice(key.as_ref()), fields)).await.map(Reply::into_values)
+    }
+
+    /// Get every field and value in the hash stored at `key`.
+    pub async fn hgetall(&mut self, key: impl AsRef<[u8]>) -> Result<Vec<(Bytes, Bytes)>> {
+        self.send(Command::HGetAll(Bytes::copy_from_slice(key.as_ref()))).await.map(Reply::into_pairs)
+    }
+
+    /// Remove `fields` from the hash stored at `key`.
+    ///
+    /// Returns the number of fields removed.
+    pub async fn hdel(&mut self, key: impl AsRef<[u8]>, fields: &[&str]) -> Result<Option<Bytes>> {
+        let fields = fields.iter().map(|field| field.to_string()).collect();
+        self.send(Command::HDel(Bytes::copy_from_slice(key.as_ref()), fields)).await.map(Reply::into_value)
+    }
+
+    /// Check whether `field` exists in the hash stored at `key`.
+    pub async fn hexists(&mut self, key: impl AsRef<[u8]>, field: &str) -> Result<Option<Bytes>> {
+        self.send(Command::HExists(Bytes::copy_from_slice(key.as_ref()), field.into())).await.map(Reply::into_value)
+    }
+
+    /// Number of fields in the hash stored at `key`.
+    pub async fn hlen(&mut self, key: impl AsRef<[u8]>) -> Result<Option<Bytes>> {
+        self.send(Command::HLen(Bytes::copy_from_slice(key.as_ref()))).await.map(Reply::into_value)
+    }
+
+    /// Atomically add `delta` to the integer stored in `field`.
+    pub async fn hincrby(&mut self, key: impl AsRef<[u8]>, field: &str, delta: i64) -> Result<Option<Bytes>> {
+        self.send(Command::HIncrBy(Bytes::copy_from_slice(key.as_ref()), field.into(), delta)).await.map(Reply::into_value)
+    }
+
+    /// Atomically add `delta` to the float stored in `field`.
+    pub async fn hincrbyfloat(&mut self, key: impl AsRef<[u8]>, field: &str, delta: f64) -> Result<Option<Bytes>> {
+        self.send(Command::HIncrByFloat(Bytes::copy_from_slice(key.as_ref()), field.into(), delta)).await.map(Reply::into_value)
+    }
+
+    /// Send `command` to the connection task and wait for its reply.
+    async fn send(&mut self, command: Command) -> Result<Reply> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send((command, tx)).await?;
+
         match rx.await {
             Ok(res) => res,
             Err(err) => Err(err.into()),
         }
     }
 
-    // pub async fn blpop(&mut self, key: &str, timeout: usize) -> Result<Option<Bytes>> {
+    // pub async fn blpop(&mut self, key: impl AsRef<[u8]>, timeout: usize) -> Result<Option<Bytes>> {
     //     // Initialize a new `BLPop` command to send via the channel.
-    //     let blpop = Command::BLPop(key.into(), timeout);
+    //     let blpop = Command::BLPop(Bytes::copy_from_slice(key.as_ref()), timeout);
 
     //     // Initialize a new oneshot to be used to receive the response back from the connection.
     //     let (tx, rx) = oneshot::channel();
@@ -227,9 +358,9 @@ impl Buffer {
     //     }
     // }
 
-    // pub async fn brpop(&mut self, key: &str, timeout: usize) -> Result<Option<Bytes>> {
+    // pub async fn brpop(&mut self, key: impl AsRef<[u8]>, timeout: usize) -> Result<Option<Bytes>> {
     //     // Initialize a new `BRPop` command to send via the channel.
-    //     let brpop = Command::BRPop(key.into(), timeout);
+    //     let brpop = Command::BRPop(Bytes::copy_from_slice(key.as_ref()), timeout);
 
     //     // Initialize a new oneshot to be used to receive the response back from the connection.
     //     let (tx, rx) = oneshot::channel();
@@ -2,6 +2,7 @@ use tokio::net::{TcpStream, ToSocketAddrs};
 use bytes::Bytes;
 use tracing::debug;
 use crate::{Connection, Frame};
+use std::collections::VecDeque;
 use std::io::{Error, ErrorKind};
 
 pub struct Client {
@@ -33,30 +34,30 @@ impl Client {
         let response = self.read_response().await?;
         match response {
             Frame::Simple(msg) if msg == "OK" => Ok(db),
-            Frame::Error(err) => Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, err))),
-            _ => Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Unexpected response type"))),
+            Frame::Error(err) => Err(Box::new(std::io::Error::other(err))),
+            _ => Err(Box::new(std::io::Error::other("Unexpected response type"))),
         }
     }
     
 
-    pub async fn get(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+    pub async fn get(&mut self, key: impl AsRef<[u8]>) -> crate::Result<Option<Bytes>> {
         let command_part = Frame::Bulk(Bytes::from_static(b"GET"));
-        let key_part = Frame::Bulk(Bytes::from(key.to_owned()));
+        let key_part = Frame::Bulk(Bytes::copy_from_slice(key.as_ref()));
         let cmd = Frame::Array(vec![command_part, key_part]);
 
         self.connection.write_frame(&cmd).await?;
         match self.read_response().await? {
             Frame::Bulk(data) => Ok(Some(data)),
             Frame::Null => Ok(None),
-            frame => Err(Error::new(ErrorKind::Other, format!("Unexpected frame type: {:?}", frame)).into()),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
         }
 
     }
 
-    pub async fn set(&mut self, key: &str, value: &str) -> crate::Result<()> {
+    pub async fn set(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> crate::Result<()> {
         let command_part = Frame::Bulk(Bytes::from_static(b"SET"));
-        let key_part = Frame::Bulk(Bytes::from(key.to_owned()));
-        let value_part = Frame::Bulk(Bytes::from(value.to_owned()));
+        let key_part = Frame::Bulk(Bytes::copy_from_slice(key.as_ref()));
+        let value_part = Frame::Bulk(Bytes::copy_from_slice(value.as_ref()));
         let cmd = Frame::Array(vec![command_part, key_part, value_part]);
 
         self.connection.write_frame(&cmd).await?;
@@ -64,27 +65,619 @@ impl Client {
     }
     
 
+    /// Delete `keys`. Returns the number of keys that existed.
+    pub async fn del(&mut self, keys: &[impl AsRef<[u8]>]) -> crate::Result<usize> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"DEL"))];
+        parts.extend(keys.iter().map(|key| Frame::Bulk(Bytes::copy_from_slice(key.as_ref()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Name of the type of the value stored at `key`, `"none"` if missing.
+    pub async fn key_type(&mut self, key: impl AsRef<[u8]>) -> crate::Result<String> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"TYPE")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Simple(type_name) => Ok(type_name),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Rename `source` to `destination`, replacing any value stored there.
+    pub async fn rename(&mut self, source: impl AsRef<[u8]>, destination: impl AsRef<[u8]>) -> crate::Result<()> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"RENAME")),
+            Frame::Bulk(Bytes::copy_from_slice(source.as_ref())),
+            Frame::Bulk(Bytes::copy_from_slice(destination.as_ref())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Select a namespace by index or alias.
+    /// Serialize the value stored at `key`, or `None` if it does not exist.
+    pub async fn dump(&mut self, key: impl AsRef<[u8]>) -> crate::Result<Option<Bytes>> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"DUMP")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Bulk(payload) => Ok(Some(payload)),
+            Frame::Null => Ok(None),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Create `key` from a payload returned by `dump`, expiring after `ttl`
+    /// milliseconds unless it is 0.
+    pub async fn restore(&mut self, key: impl AsRef<[u8]>, ttl: u64, payload: Bytes, replace: bool) -> crate::Result<()> {
+        let mut parts = vec![
+            Frame::Bulk(Bytes::from_static(b"RESTORE")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(ttl.to_string())),
+            Frame::Bulk(payload),
+        ];
+        if replace {
+            parts.push(Frame::Bulk(Bytes::from_static(b"REPLACE")));
+        }
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    pub async fn select_named(&mut self, name: &str) -> crate::Result<()> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"SELECT")),
+            Frame::Bulk(Bytes::from(name.to_string())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Number of keys in the selected namespace.
+    pub async fn dbsize(&mut self) -> crate::Result<usize> {
+        self.connection.write_frame(&Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"DBSIZE"))])).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Atomically exchange the contents of namespaces `a` and `b`.
+    pub async fn swapdb(&mut self, a: usize, b: usize) -> crate::Result<()> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"SWAPDB")),
+            Frame::Bulk(Bytes::from(a.to_string())),
+            Frame::Bulk(Bytes::from(b.to_string())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Change a server setting, such as `maxmemory`, at runtime.
+    pub async fn config_set(&mut self, parameter: &str, value: &str) -> crate::Result<()> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"CONFIG")),
+            Frame::Bulk(Bytes::from_static(b"SET")),
+            Frame::Bulk(Bytes::from(parameter.to_string())),
+            Frame::Bulk(Bytes::from(value.to_string())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Server information and statistics, as `field:value` lines grouped
+    /// into sections. Only `section` is returned if given.
+    pub async fn info(&mut self, section: Option<&str>) -> crate::Result<String> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"INFO"))];
+        if let Some(section) = section {
+            parts.push(Frame::Bulk(Bytes::from(section.to_string())));
+        }
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Bulk(info) => Ok(String::from_utf8_lossy(&info).into_owned()),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Save a snapshot, returning once it is on disk.
+    pub async fn save(&mut self) -> crate::Result<()> {
+        self.connection.write_frame(&Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"SAVE"))])).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Start saving a snapshot in the background.
+    pub async fn bgsave(&mut self) -> crate::Result<()> {
+        self.connection.write_frame(&Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"BGSAVE"))])).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Start compacting the append-only file in the background.
+    pub async fn bgrewriteaof(&mut self) -> crate::Result<()> {
+        self.connection.write_frame(&Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"BGREWRITEAOF"))])).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Unix time in seconds of the last successful save.
+    pub async fn lastsave(&mut self) -> crate::Result<u64> {
+        self.connection.write_frame(&Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"LASTSAVE"))])).await?;
+        match self.read_response().await? {
+            Frame::Integer(time) => Ok(time as u64),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Start a transaction. Commands sent until `exec` or `discard` are
+    /// queued, and reply `QUEUED` rather than their usual reply.
+    pub async fn multi(&mut self) -> crate::Result<()> {
+        self.connection.write_frame(&Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"MULTI"))])).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Run the queued commands and return their replies, or `None` if a
+    /// watched key changed.
+    pub async fn exec(&mut self) -> crate::Result<Option<Vec<Frame>>> {
+        self.connection.write_frame(&Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"EXEC"))])).await?;
+        match self.read_response().await? {
+            Frame::Array(replies) => Ok(Some(replies)),
+            Frame::Null => Ok(None),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Drop the queued commands.
+    pub async fn discard(&mut self) -> crate::Result<()> {
+        self.connection.write_frame(&Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"DISCARD"))])).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Make the next `exec` fail if any of `keys` changes before it.
+    pub async fn watch(&mut self, keys: &[impl AsRef<[u8]>]) -> crate::Result<()> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"WATCH"))];
+        parts.extend(keys.iter().map(|key| Frame::Bulk(Bytes::copy_from_slice(key.as_ref()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Stop watching every key.
+    pub async fn unwatch(&mut self) -> crate::Result<()> {
+        self.connection.write_frame(&Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"UNWATCH"))])).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Run the Lua `script` with `keys` and `args`, returning its reply.
+    pub async fn eval(&mut self, script: &str, keys: &[impl AsRef<[u8]>], args: &[impl AsRef<[u8]>]) -> crate::Result<Frame> {
+        self.script_call(Bytes::from_static(b"EVAL"), Bytes::from(script.to_owned()), keys, args).await
+    }
+
+    /// Run the cached script with SHA1 `sha`, as `eval` does.
+    pub async fn evalsha(&mut self, sha: &str, keys: &[impl AsRef<[u8]>], args: &[impl AsRef<[u8]>]) -> crate::Result<Frame> {
+        self.script_call(Bytes::from_static(b"EVALSHA"), Bytes::from(sha.to_owned()), keys, args).await
+    }
+
+    async fn script_call(&mut self, command: Bytes, script: Bytes, keys: &[impl AsRef<[u8]>], args: &[impl AsRef<[u8]>]) -> crate::Result<Frame> {
+        let mut parts = vec![
+            Frame::Bulk(command),
+            Frame::Bulk(script),
+            Frame::Bulk(Bytes::from(keys.len().to_string())),
+        ];
+        parts.extend(keys.iter().map(|key| Frame::Bulk(Bytes::copy_from_slice(key.as_ref()))));
+        parts.extend(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_ref()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        self.read_response().await
+    }
+
+    /// Cache `script` without running it, returning its SHA1.
+    pub async fn script_load(&mut self, script: &str) -> crate::Result<String> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"SCRIPT")),
+            Frame::Bulk(Bytes::from_static(b"LOAD")),
+            Frame::Bulk(Bytes::from(script.to_owned())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Bulk(sha) => Ok(String::from_utf8_lossy(&sha).into_owned()),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Whether each of `shas` names a cached script.
+    pub async fn script_exists(&mut self, shas: &[&str]) -> crate::Result<Vec<bool>> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"SCRIPT")), Frame::Bulk(Bytes::from_static(b"EXISTS"))];
+        parts.extend(shas.iter().map(|sha| Frame::Bulk(Bytes::from(sha.to_string()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Array(exists) => exists
+                .into_iter()
+                .map(|frame| match frame {
+                    Frame::Integer(n) => Ok(n == 1),
+                    frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                })
+                .collect(),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Empty the script cache.
+    pub async fn script_flush(&mut self) -> crate::Result<()> {
+        let cmd = Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"SCRIPT")), Frame::Bulk(Bytes::from_static(b"FLUSH"))]);
+        self.connection.write_frame(&cmd).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Stop the running script, unless it has written.
+    pub async fn script_kill(&mut self) -> crate::Result<()> {
+        let cmd = Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"SCRIPT")), Frame::Bulk(Bytes::from_static(b"KILL"))]);
+        self.connection.write_frame(&cmd).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Send `message` to the subscribers of `channel`, returning how many
+    /// received it.
+    pub async fn publish(&mut self, channel: &str, message: impl AsRef<[u8]>) -> crate::Result<u64> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"PUBLISH")),
+            Frame::Bulk(Bytes::from(channel.to_owned())),
+            Frame::Bulk(Bytes::copy_from_slice(message.as_ref())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(received) => Ok(received as u64),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Channels with subscribers, those matching the glob `pattern` if given.
+    pub async fn pubsub_channels(&mut self, pattern: Option<&str>) -> crate::Result<Vec<Bytes>> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"PUBSUB")), Frame::Bulk(Bytes::from_static(b"CHANNELS"))];
+        if let Some(pattern) = pattern {
+            parts.push(Frame::Bulk(Bytes::from(pattern.to_owned())));
+        }
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Array(channels) => key_names(channels),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Number of subscribers of each of `channels`.
+    pub async fn pubsub_numsub(&mut self, channels: &[&str]) -> crate::Result<Vec<u64>> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"PUBSUB")), Frame::Bulk(Bytes::from_static(b"NUMSUB"))];
+        parts.extend(channels.iter().map(|channel| Frame::Bulk(Bytes::from(channel.to_string()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Array(counts) => counts
+                .chunks(2)
+                .map(|pair| match pair {
+                    [Frame::Bulk(_), Frame::Integer(count)] => Ok(*count as u64),
+                    pair => Err(Error::other(format!("Unexpected frame type: {:?}", pair)).into()),
+                })
+                .collect(),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Number of patterns subscribed to.
+    pub async fn pubsub_numpat(&mut self) -> crate::Result<u64> {
+        let cmd = Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"PUBSUB")), Frame::Bulk(Bytes::from_static(b"NUMPAT"))]);
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(count) => Ok(count as u64),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// The ID the server gave this connection.
+    pub async fn client_id(&mut self) -> crate::Result<u64> {
+        let cmd = Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"CLIENT")), Frame::Bulk(Bytes::from_static(b"ID"))]);
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(id) => Ok(id as u64),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Name this connection, as shown by `client_list`. An empty name removes
+    /// it.
+    pub async fn client_setname(&mut self, name: &str) -> crate::Result<()> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"CLIENT")),
+            Frame::Bulk(Bytes::from_static(b"SETNAME")),
+            Frame::Bulk(Bytes::from(name.to_string())),
+        ]);
+        self.connection.write_frame(&cmd).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// The name of this connection, if it has one.
+    pub async fn client_getname(&mut self) -> crate::Result<Option<Bytes>> {
+        let cmd = Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"CLIENT")), Frame::Bulk(Bytes::from_static(b"GETNAME"))]);
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Bulk(name) => Ok(Some(name)),
+            Frame::Null => Ok(None),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// The connected clients, one line of `field=value` pairs each. `options`
+    /// are added to the command, as in `["TYPE", "pubsub"]`.
+    pub async fn client_list(&mut self, options: &[&str]) -> crate::Result<Vec<String>> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"CLIENT")), Frame::Bulk(Bytes::from_static(b"LIST"))];
+        parts.extend(options.iter().map(|option| Frame::Bulk(Bytes::from(option.to_string()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Bulk(list) => Ok(String::from_utf8_lossy(&list).lines().map(str::to_string).collect()),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// This connection, as a line of `client_list`.
+    pub async fn client_info(&mut self) -> crate::Result<String> {
+        let cmd = Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"CLIENT")), Frame::Bulk(Bytes::from_static(b"INFO"))]);
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Bulk(info) => Ok(String::from_utf8_lossy(&info).trim_end().to_string()),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Disconnect the clients matching every filter, given as pairs such as
+    /// `("ADDR", "127.0.0.1:5000")`. Returns the number of clients killed.
+    pub async fn client_kill(&mut self, filters: &[(&str, &str)]) -> crate::Result<u64> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"CLIENT")), Frame::Bulk(Bytes::from_static(b"KILL"))];
+        for (filter, value) in filters {
+            parts.push(Frame::Bulk(Bytes::from(filter.to_string())));
+            parts.push(Frame::Bulk(Bytes::from(value.to_string())));
+        }
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Integer(killed) => Ok(killed as u64),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Hold the commands of every client for `millis` milliseconds, or with
+    /// `writes_only` only those that may write.
+    pub async fn client_pause(&mut self, millis: u64, writes_only: bool) -> crate::Result<()> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"CLIENT")),
+            Frame::Bulk(Bytes::from_static(b"PAUSE")),
+            Frame::Bulk(Bytes::from(millis.to_string())),
+            Frame::Bulk(Bytes::from_static(if writes_only { b"WRITE" } else { b"ALL" })),
+        ]);
+        self.connection.write_frame(&cmd).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// End a pause started by `client_pause`.
+    pub async fn client_unpause(&mut self) -> crate::Result<()> {
+        let cmd = Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"CLIENT")), Frame::Bulk(Bytes::from_static(b"UNPAUSE"))]);
+        self.connection.write_frame(&cmd).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Set or clear the no-evict flag of this connection.
+    pub async fn client_no_evict(&mut self, on: bool) -> crate::Result<()> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"CLIENT")),
+            Frame::Bulk(Bytes::from_static(b"NO-EVICT")),
+            Frame::Bulk(Bytes::from_static(if on { b"ON" } else { b"OFF" })),
+        ]);
+        self.connection.write_frame(&cmd).await?;
+        self.read_response().await.map(|_| ())
+    }
+
+    /// Subscribe to `channels`. The connection then only receives messages,
+    /// so the client turns into a `Subscriber`.
+    pub async fn subscribe(self, channels: &[&str]) -> crate::Result<Subscriber> {
+        let mut subscriber = Subscriber { client: self, channels: Vec::new(), patterns: Vec::new(), messages: VecDeque::new() };
+        subscriber.subscribe(channels).await?;
+        Ok(subscriber)
+    }
+
+    /// Subscribe to the channels matching the glob `patterns`, as `subscribe`
+    /// does.
+    pub async fn psubscribe(self, patterns: &[&str]) -> crate::Result<Subscriber> {
+        let mut subscriber = Subscriber { client: self, channels: Vec::new(), patterns: Vec::new(), messages: VecDeque::new() };
+        subscriber.psubscribe(patterns).await?;
+        Ok(subscriber)
+    }
+
+    /// Keys of the selected namespace matching the glob `pattern`.
+    pub async fn keys(&mut self, pattern: &str) -> crate::Result<Vec<Bytes>> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"KEYS")),
+            Frame::Bulk(Bytes::from(pattern.to_owned())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Array(keys) => key_names(keys),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Visit about `count` keys starting at `cursor`, returning those that
+    /// match `pattern` and the cursor to continue from. A returned cursor of
+    /// `0` ends the scan.
+    pub async fn scan(&mut self, cursor: u64, pattern: Option<&str>, count: usize) -> crate::Result<(u64, Vec<Bytes>)> {
+        let mut parts = vec![
+            Frame::Bulk(Bytes::from_static(b"SCAN")),
+            Frame::Bulk(Bytes::from(cursor.to_string())),
+            Frame::Bulk(Bytes::from_static(b"COUNT")),
+            Frame::Bulk(Bytes::from(count.to_string())),
+        ];
+        if let Some(pattern) = pattern {
+            parts.push(Frame::Bulk(Bytes::from_static(b"MATCH")));
+            parts.push(Frame::Bulk(Bytes::from(pattern.to_owned())));
+        }
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Array(parts) => match <[Frame; 2]>::try_from(parts) {
+                Ok([Frame::Bulk(cursor), Frame::Array(keys)]) => {
+                    let cursor = std::str::from_utf8(&cursor)?.parse()?;
+                    Ok((cursor, key_names(keys)?))
+                }
+                Ok(frame) => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                Err(frame) => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+            },
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Atomically add `delta` to the integer stored at `key`, treating a
+    /// missing key as zero. Returns the new value.
+    pub async fn incr_by(&mut self, key: impl AsRef<[u8]>, delta: i64) -> crate::Result<i64> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"INCRBY")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(delta.to_string())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Append `value` to the string stored at `key`. Returns the new length.
+    pub async fn append(&mut self, key: impl AsRef<[u8]>, value: Bytes) -> crate::Result<usize> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"APPEND")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(value),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Get the values of `keys` in one atomic step. Keys that are missing or
+    /// do not hold a string yield `None`.
+    pub async fn mget(&mut self, keys: &[impl AsRef<[u8]>]) -> crate::Result<Vec<Option<Bytes>>> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"MGET"))];
+        parts.extend(keys.iter().map(|key| Frame::Bulk(Bytes::copy_from_slice(key.as_ref()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Array(values) => values
+                .into_iter()
+                .map(|value| match value {
+                    Frame::Bulk(data) => Ok(Some(data)),
+                    Frame::Null => Ok(None),
+                    frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                })
+                .collect(),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Set every key in `pairs` to its value in one atomic step.
+    pub async fn mset(&mut self, pairs: &[(impl AsRef<[u8]>, Bytes)]) -> crate::Result<()> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"MSET"))];
+        for (key, value) in pairs {
+            parts.push(Frame::Bulk(Bytes::copy_from_slice(key.as_ref())));
+            parts.push(Frame::Bulk(value.clone()));
+        }
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        self.read_response().await.map(|_| ())
+    }
+
     pub async fn ping(&mut self) -> crate::Result<()> {
         self.connection.write_frame(&Frame::Array(vec![Frame::Bulk(Bytes::from("PING"))])).await?;
         self.read_response().await.map(|_| ())
     }
 
-    pub async fn exists(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+    pub async fn exists(&mut self, key: impl AsRef<[u8]>) -> crate::Result<Option<Bytes>> {
         let command_part = Frame::Bulk(Bytes::from_static(b"EXISTS"));
-        let key_part = Frame::Bulk(Bytes::from(key.to_owned()));
+        let key_part = Frame::Bulk(Bytes::copy_from_slice(key.as_ref()));
         let cmd = Frame::Array(vec![command_part, key_part]);
 
         self.connection.write_frame(&cmd).await?;
         match self.read_response().await? {
             Frame::Integer(n) => Ok(Some(Bytes::from(n.to_string()))),
             Frame::Null => Ok(None),
-            frame => Err(Error::new(ErrorKind::Other, format!("Unexpected frame type: {:?}", frame)).into()),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Set a timeout of `seconds` on `key`. Returns `false` if the key does not
+    /// exist.
+    pub async fn expire(&mut self, key: impl AsRef<[u8]>, seconds: i64) -> crate::Result<bool> {
+        let command_part = Frame::Bulk(Bytes::from_static(b"EXPIRE"));
+        let key_part = Frame::Bulk(Bytes::copy_from_slice(key.as_ref()));
+        let seconds_part = Frame::Bulk(Bytes::from(seconds.to_string()));
+        let cmd = Frame::Array(vec![command_part, key_part, seconds_part]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n == 1),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Remaining time to live of `key` in seconds, `-1` if it has no timeout
+    /// and `-2` if it does not exist.
+    pub async fn ttl(&mut self, key: impl AsRef<[u8]>) -> crate::Result<i64> {
+        let command_part = Frame::Bulk(Bytes::from_static(b"TTL"));
+        let key_part = Frame::Bulk(Bytes::copy_from_slice(key.as_ref()));
+        let cmd = Frame::Array(vec![command_part, key_part]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Remove the timeout from `key`. Returns `false` if the key does not
+    /// exist or has no timeout.
+    pub async fn persist(&mut self, key: impl AsRef<[u8]>) -> crate::Result<bool> {
+        let command_part = Frame::Bulk(Bytes::from_static(b"PERSIST"));
+        let key_part = Frame::Bulk(Bytes::copy_from_slice(key.as_ref()));
+        let cmd = Frame::Array(vec![command_part, key_part]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n == 1),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
         }
     }
 
-    pub async fn rpush(&mut self, key: &str, value: Bytes) -> crate::Result<Option<Bytes>> {
+    pub async fn rpush(&mut self, key: impl AsRef<[u8]>, value: Bytes) -> crate::Result<Option<Bytes>> {
         let command_part = Frame::Bulk(Bytes::from_static(b"RPUSH"));
-        let key_part = Frame::Bulk(Bytes::from(key.to_owned()));
+        let key_part = Frame::Bulk(Bytes::copy_from_slice(key.as_ref()));
         let value_part = Frame::Bulk(value);
         let cmd = Frame::Array(vec![command_part, key_part, value_part]);
 
@@ -92,14 +685,14 @@ impl Client {
         match self.read_response().await? {
             Frame::Integer(n) => Ok(Some(Bytes::from(n.to_string()))),
             Frame::Null => Ok(None),
-            frame => Err(Error::new(ErrorKind::Other, format!("Unexpected frame type: {:?}", frame)).into()),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
         }
 
     }
 
-    pub async fn lpush(&mut self, key: &str, value: Bytes) -> crate::Result<Option<Bytes>> {
+    pub async fn lpush(&mut self, key: impl AsRef<[u8]>, value: Bytes) -> crate::Result<Option<Bytes>> {
         let command_part = Frame::Bulk(Bytes::from_static(b"LPUSH"));
-        let key_part = Frame::Bulk(Bytes::from(key.to_owned()));
+        let key_part = Frame::Bulk(Bytes::copy_from_slice(key.as_ref()));
         let value_part = Frame::Bulk(value);
         let cmd = Frame::Array(vec![command_part, key_part, value_part]);
 
@@ -107,15 +700,654 @@ impl Client {
         match self.read_response().await? {
             Frame::Integer(n) => Ok(Some(Bytes::from(n.to_string()))),
             Frame::Null => Ok(None),
-            frame => Err(Error::new(ErrorKind::Other, format!("Unexpected frame type: {:?}", frame)).into()),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
         }
     }
-    pub async fn blpop(&mut self, _keys: &[String], _timeout: usize) -> crate::Result<Option<(String, Bytes)>> {
-        todo!("blpop")
+    /// Pop from the head of the first non-empty list among `keys`, waiting up
+    /// to `timeout` seconds for an element. A zero timeout waits forever.
+    pub async fn blpop(&mut self, keys: &[Bytes], timeout: usize) -> crate::Result<Option<(Bytes, Bytes)>> {
+        self.blocking_pop(b"BLPOP", keys, timeout).await
     }
 
-    pub async fn brpop(&mut self, _keys: &[String], _timeout: usize) -> crate::Result<Option<(String, Bytes)>> {
-        todo!("brpop")
+    /// Pop from the tail of the first non-empty list among `keys`, waiting up
+    /// to `timeout` seconds for an element. A zero timeout waits forever.
+    pub async fn brpop(&mut self, keys: &[Bytes], timeout: usize) -> crate::Result<Option<(Bytes, Bytes)>> {
+        self.blocking_pop(b"BRPOP", keys, timeout).await
+    }
+
+    async fn blocking_pop(&mut self, command: &'static [u8], keys: &[Bytes], timeout: usize) -> crate::Result<Option<(Bytes, Bytes)>> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(command))];
+        parts.extend(keys.iter().map(|key| Frame::Bulk(key.clone())));
+        parts.push(Frame::Bulk(Bytes::from(timeout.to_string())));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Array(parts) => match <[Frame; 2]>::try_from(parts) {
+                Ok([Frame::Bulk(key), Frame::Bulk(value)]) => Ok(Some((key, value))),
+                Ok(frame) => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                Err(frame) => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+            },
+            Frame::Null => Ok(None),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Length of the list stored at `key`.
+    pub async fn llen(&mut self, key: impl AsRef<[u8]>) -> crate::Result<usize> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"LLEN")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Elements of the list stored at `key` with indexes `start..=stop`.
+    /// Negative indexes count from the tail.
+    pub async fn lrange(&mut self, key: impl AsRef<[u8]>, start: i64, stop: i64) -> crate::Result<Vec<Bytes>> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"LRANGE")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(start.to_string())),
+            Frame::Bulk(Bytes::from(stop.to_string())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Array(values) => values
+                .into_iter()
+                .map(|value| match value {
+                    Frame::Bulk(data) => Ok(data),
+                    frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                })
+                .collect(),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Atomically pop an element from the `from` end (`"LEFT"` or `"RIGHT"`)
+    /// of the list at `source` and push it onto the `to` end of the list at
+    /// `destination`. Returns `None` if `source` is empty.
+    pub async fn lmove(&mut self, source: impl AsRef<[u8]>, destination: impl AsRef<[u8]>, from: &str, to: &str) -> crate::Result<Option<Bytes>> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"LMOVE")),
+            Frame::Bulk(Bytes::copy_from_slice(source.as_ref())),
+            Frame::Bulk(Bytes::copy_from_slice(destination.as_ref())),
+            Frame::Bulk(Bytes::from(from.to_owned())),
+            Frame::Bulk(Bytes::from(to.to_owned())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Like `lmove`, but waits up to `timeout` seconds for `source` to
+    /// receive an element. A zero timeout waits forever.
+    pub async fn blmove(&mut self, source: impl AsRef<[u8]>, destination: impl AsRef<[u8]>, from: &str, to: &str, timeout: usize) -> crate::Result<Option<Bytes>> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"BLMOVE")),
+            Frame::Bulk(Bytes::copy_from_slice(source.as_ref())),
+            Frame::Bulk(Bytes::copy_from_slice(destination.as_ref())),
+            Frame::Bulk(Bytes::from(from.to_owned())),
+            Frame::Bulk(Bytes::from(to.to_owned())),
+            Frame::Bulk(Bytes::from(timeout.to_string())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Set `fields` in the hash stored at `key`. Returns the number of fields
+    /// that were newly added.
+    pub async fn hset(&mut self, key: impl AsRef<[u8]>, fields: &[(&str, Bytes)]) -> crate::Result<usize> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"HSET")), Frame::Bulk(Bytes::copy_from_slice(key.as_ref()))];
+        for (field, value) in fields {
+            parts.push(Frame::Bulk(Bytes::from(field.to_string())));
+            parts.push(Frame::Bulk(value.clone()));
+        }
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Set `field` in the hash stored at `key` only if it does not exist yet.
+    pub async fn hsetnx(&mut self, key: impl AsRef<[u8]>, field: &str, value: Bytes) -> crate::Result<bool> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"HSETNX")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(field.to_owned())),
+            Frame::Bulk(value),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n == 1),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Get the value of `field` in the hash stored at `key`.
+    pub async fn hget(&mut self, key: impl AsRef<[u8]>, field: &str) -> crate::Result<Option<Bytes>> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"HGET")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(field.to_owned())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Bulk(data) => Ok(Some(data)),
+            Frame::Null => Ok(None),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Get the values of `fields` in the hash stored at `key`, in order.
+    pub async fn hmget(&mut self, key: impl AsRef<[u8]>, fields: &[&str]) -> crate::Result<Vec<Option<Bytes>>> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"HMGET")), Frame::Bulk(Bytes::copy_from_slice(key.as_ref()))];
+        parts.extend(fields.iter().map(|field| Frame::Bulk(Bytes::from(field.to_string()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Array(values) => values
+                .into_iter()
+                .map(|value| match value {
+                    Frame::Bulk(data) => Ok(Some(data)),
+                    Frame::Null => Ok(None),
+                    frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                })
+                .collect(),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Get every field and value in the hash stored at `key`.
+    pub async fn hgetall(&mut self, key: impl AsRef<[u8]>) -> crate::Result<Vec<(Bytes, Bytes)>> {
+        let values = self.bulks(b"HGETALL", key).await?;
+        Ok(values
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect())
+    }
+
+    /// Get every field name in the hash stored at `key`.
+    pub async fn hkeys(&mut self, key: impl AsRef<[u8]>) -> crate::Result<Vec<Bytes>> {
+        self.bulks(b"HKEYS", key).await
+    }
+
+    /// Get every value in the hash stored at `key`.
+    pub async fn hvals(&mut self, key: impl AsRef<[u8]>) -> crate::Result<Vec<Bytes>> {
+        self.bulks(b"HVALS", key).await
+    }
+
+    /// Send `command key` and collect the bulk strings of the array reply.
+    async fn bulks(&mut self, command: &'static [u8], key: impl AsRef<[u8]>) -> crate::Result<Vec<Bytes>> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(command)),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Array(values) => values
+                .into_iter()
+                .map(|value| match value {
+                    Frame::Bulk(data) => Ok(data),
+                    frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                })
+                .collect(),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Remove `fields` from the hash stored at `key`. Returns the number of
+    /// fields removed.
+    pub async fn hdel(&mut self, key: impl AsRef<[u8]>, fields: &[&str]) -> crate::Result<usize> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"HDEL")), Frame::Bulk(Bytes::copy_from_slice(key.as_ref()))];
+        parts.extend(fields.iter().map(|field| Frame::Bulk(Bytes::from(field.to_string()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Check whether `field` exists in the hash stored at `key`.
+    pub async fn hexists(&mut self, key: impl AsRef<[u8]>, field: &str) -> crate::Result<bool> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"HEXISTS")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(field.to_owned())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n == 1),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Number of fields in the hash stored at `key`.
+    pub async fn hlen(&mut self, key: impl AsRef<[u8]>) -> crate::Result<usize> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"HLEN")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Atomically add `delta` to the integer stored in `field`. Returns the
+    /// new value.
+    pub async fn hincrby(&mut self, key: impl AsRef<[u8]>, field: &str, delta: i64) -> crate::Result<i64> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"HINCRBY")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(field.to_owned())),
+            Frame::Bulk(Bytes::from(delta.to_string())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Atomically add `delta` to the float stored in `field`. Returns the new
+    /// value.
+    pub async fn hincrbyfloat(&mut self, key: impl AsRef<[u8]>, field: &str, delta: f64) -> crate::Result<f64> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"HINCRBYFLOAT")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(field.to_owned())),
+            Frame::Bulk(Bytes::from(delta.to_string())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Bulk(data) => Ok(std::str::from_utf8(&data)?.parse()?),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Add `members` to the set stored at `key`. Returns the number of
+    /// members that were not already present.
+    pub async fn sadd(&mut self, key: impl AsRef<[u8]>, members: &[&str]) -> crate::Result<usize> {
+        self.set_members_command(b"SADD", key, members).await
+    }
+
+    /// Remove `members` from the set stored at `key`. Returns the number of
+    /// members removed.
+    pub async fn srem(&mut self, key: impl AsRef<[u8]>, members: &[&str]) -> crate::Result<usize> {
+        self.set_members_command(b"SREM", key, members).await
+    }
+
+    async fn set_members_command(&mut self, command: &'static [u8], key: impl AsRef<[u8]>, members: &[&str]) -> crate::Result<usize> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(command)), Frame::Bulk(Bytes::copy_from_slice(key.as_ref()))];
+        parts.extend(members.iter().map(|member| Frame::Bulk(Bytes::from(member.to_string()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Check whether `member` belongs to the set stored at `key`.
+    pub async fn sismember(&mut self, key: impl AsRef<[u8]>, member: &str) -> crate::Result<bool> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"SISMEMBER")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(member.to_owned())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n == 1),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Get every member of the set stored at `key`.
+    pub async fn smembers(&mut self, key: impl AsRef<[u8]>) -> crate::Result<Vec<Bytes>> {
+        self.bulks(b"SMEMBERS", key).await
+    }
+
+    /// Number of members of the set stored at `key`.
+    pub async fn scard(&mut self, key: impl AsRef<[u8]>) -> crate::Result<usize> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"SCARD")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Atomically move `member` from the set at `source` to the set at
+    /// `destination`. Returns `false` if `source` did not contain it.
+    pub async fn smove(&mut self, source: impl AsRef<[u8]>, destination: impl AsRef<[u8]>, member: &str) -> crate::Result<bool> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"SMOVE")),
+            Frame::Bulk(Bytes::copy_from_slice(source.as_ref())),
+            Frame::Bulk(Bytes::copy_from_slice(destination.as_ref())),
+            Frame::Bulk(Bytes::from(member.to_owned())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n == 1),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Add `members` with their scores to the sorted set stored at `key`.
+    /// Returns the number of members that were newly added.
+    pub async fn zadd(&mut self, key: impl AsRef<[u8]>, members: &[(f64, &str)]) -> crate::Result<usize> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"ZADD")), Frame::Bulk(Bytes::copy_from_slice(key.as_ref()))];
+        for (score, member) in members {
+            parts.push(Frame::Bulk(Bytes::from(score.to_string())));
+            parts.push(Frame::Bulk(Bytes::from(member.to_string())));
+        }
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Score of `member` in the sorted set stored at `key`.
+    pub async fn zscore(&mut self, key: impl AsRef<[u8]>, member: &str) -> crate::Result<Option<f64>> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"ZSCORE")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(member.to_owned())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Bulk(data) => Ok(Some(std::str::from_utf8(&data)?.parse()?)),
+            Frame::Null => Ok(None),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Zero-based rank of `member` in the sorted set stored at `key`, lowest
+    /// score first.
+    pub async fn zrank(&mut self, key: impl AsRef<[u8]>, member: &str) -> crate::Result<Option<usize>> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"ZRANK")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(member.to_owned())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(Some(n as usize)),
+            Frame::Null => Ok(None),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Members of the sorted set stored at `key` with ranks `start..=stop`.
+    /// Negative ranks count from the highest score.
+    pub async fn zrange(&mut self, key: impl AsRef<[u8]>, start: i64, stop: i64) -> crate::Result<Vec<Bytes>> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"ZRANGE")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(start.to_string())),
+            Frame::Bulk(Bytes::from(stop.to_string())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Array(values) => values
+                .into_iter()
+                .map(|value| match value {
+                    Frame::Bulk(data) => Ok(data),
+                    frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                })
+                .collect(),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Pop the lowest scored member of the first non-empty sorted set among
+    /// `keys`, waiting up to `timeout` seconds. A zero timeout waits forever.
+    pub async fn bzpopmin(&mut self, keys: &[Bytes], timeout: usize) -> crate::Result<Option<(Bytes, Bytes, f64)>> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"BZPOPMIN"))];
+        parts.extend(keys.iter().map(|key| Frame::Bulk(key.clone())));
+        parts.push(Frame::Bulk(Bytes::from(timeout.to_string())));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Array(parts) => match <[Frame; 3]>::try_from(parts) {
+                Ok([Frame::Bulk(key), Frame::Bulk(member), Frame::Bulk(score)]) => {
+                    let score = std::str::from_utf8(&score)?.parse()?;
+                    Ok(Some((key, member, score)))
+                }
+                Ok(frame) => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                Err(frame) => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+            },
+            Frame::Null => Ok(None),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Append an entry to the stream stored at `key`. `id` is either `*` to
+    /// let the server generate it or an explicit ID. Returns the entry's ID.
+    pub async fn xadd(&mut self, key: impl AsRef<[u8]>, id: &str, fields: &[(&str, Bytes)]) -> crate::Result<String> {
+        let mut parts = vec![
+            Frame::Bulk(Bytes::from_static(b"XADD")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(id.to_owned())),
+        ];
+        for (field, value) in fields {
+            parts.push(Frame::Bulk(Bytes::from(field.to_string())));
+            parts.push(Frame::Bulk(value.clone()));
+        }
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Bulk(id) => Ok(String::from_utf8(id.to_vec())?),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Number of entries in the stream stored at `key`.
+    pub async fn xlen(&mut self, key: impl AsRef<[u8]>) -> crate::Result<usize> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"XLEN")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Entries of the stream stored at `key` with IDs between `start` and
+    /// `end`, oldest first. `-` and `+` stand for the smallest and largest
+    /// possible IDs.
+    pub async fn xrange(&mut self, key: impl AsRef<[u8]>, start: &str, end: &str) -> crate::Result<Vec<StreamEntry>> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"XRANGE")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(start.to_owned())),
+            Frame::Bulk(Bytes::from(end.to_owned())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        stream_entries(self.read_response().await?)
+    }
+
+    /// Evict old entries of the stream stored at `key`. `strategy` is
+    /// `MAXLEN` or `MINID`. Returns the number of entries evicted.
+    pub async fn xtrim(&mut self, key: impl AsRef<[u8]>, strategy: &str, threshold: &str) -> crate::Result<usize> {
+        let cmd = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"XTRIM")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(strategy.to_owned())),
+            Frame::Bulk(Bytes::from(threshold.to_owned())),
+        ]);
+
+        self.connection.write_frame(&cmd).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Entries added after the given ID to each of `streams`, given as
+    /// `(key, id)` pairs where `$` stands for the last entry at the time of
+    /// the call. With `block`, waits up to that many milliseconds for an entry
+    /// if there is none yet; zero waits forever.
+    pub async fn xread(&mut self, streams: &[(impl AsRef<[u8]>, &str)], block: Option<u64>) -> crate::Result<Vec<(Bytes, Vec<StreamEntry>)>> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(b"XREAD"))];
+        if let Some(block) = block {
+            parts.push(Frame::Bulk(Bytes::from_static(b"BLOCK")));
+            parts.push(Frame::Bulk(Bytes::from(block.to_string())));
+        }
+        parts.push(Frame::Bulk(Bytes::from_static(b"STREAMS")));
+        parts.extend(streams.iter().map(|(key, _)| Frame::Bulk(Bytes::copy_from_slice(key.as_ref()))));
+        parts.extend(streams.iter().map(|(_, id)| Frame::Bulk(Bytes::from(id.to_string()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Array(streams) => streams
+                .into_iter()
+                .map(|stream| match stream {
+                    Frame::Array(parts) => match <[Frame; 2]>::try_from(parts) {
+                        Ok([Frame::Bulk(key), entries]) => Ok((key, stream_entries(entries)?)),
+                        Ok(frame) => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                        Err(frame) => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                    },
+                    frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                })
+                .collect(),
+            Frame::Null => Ok(Vec::new()),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Create consumer group `group` on the stream stored at `key`, reading
+    /// entries after `id` (`$` for only new entries). With `create`, a missing
+    /// stream is created empty.
+    pub async fn xgroup_create(&mut self, key: impl AsRef<[u8]>, group: &str, id: &str, create: bool) -> crate::Result<()> {
+        let mut parts = vec![
+            Frame::Bulk(Bytes::from_static(b"XGROUP")),
+            Frame::Bulk(Bytes::from_static(b"CREATE")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(group.to_owned())),
+            Frame::Bulk(Bytes::from(id.to_owned())),
+        ];
+        if create {
+            parts.push(Frame::Bulk(Bytes::from_static(b"MKSTREAM")));
+        }
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Read from the stream stored at `key` as `consumer` of `group`. An `id`
+    /// of `>` reads up to `count` entries never delivered to the group;
+    /// any other ID re-reads the consumer's own pending entries after it,
+    /// with empty fields for entries deleted since. With `block`, waits up to
+    /// that many milliseconds for new entries; zero waits forever.
+    pub async fn xreadgroup(&mut self, group: &str, consumer: &str, key: impl AsRef<[u8]>, id: &str, count: usize, block: Option<u64>) -> crate::Result<Vec<StreamEntry>> {
+        let mut parts = vec![
+            Frame::Bulk(Bytes::from_static(b"XREADGROUP")),
+            Frame::Bulk(Bytes::from_static(b"GROUP")),
+            Frame::Bulk(Bytes::from(group.to_owned())),
+            Frame::Bulk(Bytes::from(consumer.to_owned())),
+            Frame::Bulk(Bytes::from_static(b"COUNT")),
+            Frame::Bulk(Bytes::from(count.to_string())),
+        ];
+        if let Some(block) = block {
+            parts.push(Frame::Bulk(Bytes::from_static(b"BLOCK")));
+            parts.push(Frame::Bulk(Bytes::from(block.to_string())));
+        }
+        parts.push(Frame::Bulk(Bytes::from_static(b"STREAMS")));
+        parts.push(Frame::Bulk(Bytes::copy_from_slice(key.as_ref())));
+        parts.push(Frame::Bulk(Bytes::from(id.to_owned())));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Array(streams) => match <[Frame; 1]>::try_from(streams) {
+                Ok([Frame::Array(stream)]) => match <[Frame; 2]>::try_from(stream) {
+                    Ok([_, entries]) => stream_entries(entries),
+                    Err(frame) => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                },
+                Ok(frame) => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                Err(frame) => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+            },
+            Frame::Null => Ok(Vec::new()),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Acknowledge `ids` in consumer group `group`. Returns the number of
+    /// entries that were pending.
+    pub async fn xack(&mut self, key: impl AsRef<[u8]>, group: &str, ids: &[&str]) -> crate::Result<usize> {
+        let mut parts = vec![
+            Frame::Bulk(Bytes::from_static(b"XACK")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(group.to_owned())),
+        ];
+        parts.extend(ids.iter().map(|id| Frame::Bulk(Bytes::from(id.to_string()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        match self.read_response().await? {
+            Frame::Integer(n) => Ok(n as usize),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        }
+    }
+
+    /// Take over the pending entries `ids` of consumer group `group` that
+    /// have been idle for at least `min_idle` milliseconds, on behalf of
+    /// `consumer`. Returns the entries claimed.
+    pub async fn xclaim(&mut self, key: impl AsRef<[u8]>, group: &str, consumer: &str, min_idle: u64, ids: &[&str]) -> crate::Result<Vec<StreamEntry>> {
+        let mut parts = vec![
+            Frame::Bulk(Bytes::from_static(b"XCLAIM")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_ref())),
+            Frame::Bulk(Bytes::from(group.to_owned())),
+            Frame::Bulk(Bytes::from(consumer.to_owned())),
+            Frame::Bulk(Bytes::from(min_idle.to_string())),
+        ];
+        parts.extend(ids.iter().map(|id| Frame::Bulk(Bytes::from(id.to_string()))));
+
+        self.connection.write_frame(&Frame::Array(parts)).await?;
+        stream_entries(self.read_response().await?)
     }
 
     async fn read_response(&mut self) -> crate::Result<Frame> {
@@ -137,4 +1369,167 @@ impl Client {
             }
         }
     }
-}
\ No newline at end of file
+}
+/// A client in subscriber mode, created by `Client::subscribe` or
+/// `Client::psubscribe`.
+pub struct Subscriber {
+    client: Client,
+    channels: Vec<String>,
+    patterns: Vec<String>,
+
+    /// Messages received while waiting for a confirmation.
+    messages: VecDeque<Message>,
+}
+
+/// A message received by a `Subscriber`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// The pattern it was received through, for pattern subscriptions.
+    pub pattern: Option<Bytes>,
+    pub channel: Bytes,
+    pub content: Bytes,
+}
+
+impl Subscriber {
+    /// Channels subscribed to.
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    /// Patterns subscribed to.
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Wait for the next message. Returns `None` once the server closed the
+    /// connection.
+    pub async fn next_message(&mut self) -> crate::Result<Option<Message>> {
+        if let Some(message) = self.messages.pop_front() {
+            return Ok(Some(message));
+        }
+        match self.client.connection.read_frame().await? {
+            Some(frame) => match message(&frame) {
+                Some(message) => Ok(Some(message)),
+                None => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Subscribe to more `channels`.
+    pub async fn subscribe(&mut self, channels: &[&str]) -> crate::Result<()> {
+        self.send("SUBSCRIBE", channels, channels.len()).await?;
+        self.channels.extend(channels.iter().map(|channel| channel.to_string()));
+        Ok(())
+    }
+
+    /// Subscribe to more `patterns`.
+    pub async fn psubscribe(&mut self, patterns: &[&str]) -> crate::Result<()> {
+        self.send("PSUBSCRIBE", patterns, patterns.len()).await?;
+        self.patterns.extend(patterns.iter().map(|pattern| pattern.to_string()));
+        Ok(())
+    }
+
+    /// Unsubscribe from `channels`, or from every channel if empty.
+    pub async fn unsubscribe(&mut self, channels: &[&str]) -> crate::Result<()> {
+        let confirmations = if channels.is_empty() { self.channels.len().max(1) } else { channels.len() };
+        self.send("UNSUBSCRIBE", channels, confirmations).await?;
+        self.channels.retain(|channel| !channels.is_empty() && !channels.contains(&channel.as_str()));
+        Ok(())
+    }
+
+    /// Unsubscribe from `patterns`, or from every pattern if empty.
+    pub async fn punsubscribe(&mut self, patterns: &[&str]) -> crate::Result<()> {
+        let confirmations = if patterns.is_empty() { self.patterns.len().max(1) } else { patterns.len() };
+        self.send("PUNSUBSCRIBE", patterns, confirmations).await?;
+        self.patterns.retain(|pattern| !patterns.is_empty() && !patterns.contains(&pattern.as_str()));
+        Ok(())
+    }
+
+    /// Send `command` with `channels` and wait for its `confirmations`,
+    /// keeping the messages received in the meantime.
+    async fn send(&mut self, command: &'static str, channels: &[&str], confirmations: usize) -> crate::Result<()> {
+        let mut parts = vec![Frame::Bulk(Bytes::from_static(command.as_bytes()))];
+        parts.extend(channels.iter().map(|channel| Frame::Bulk(Bytes::from(channel.to_string()))));
+        self.client.connection.write_frame(&Frame::Array(parts)).await?;
+
+        let mut confirmed = 0;
+        while confirmed < confirmations {
+            let frame = self.client.read_response().await?;
+            match message(&frame) {
+                Some(message) => self.messages.push_back(message),
+                None => match &frame {
+                    Frame::Array(parts) if matches!(parts.first(), Some(Frame::Bulk(kind)) if kind.eq_ignore_ascii_case(command.as_bytes())) => {
+                        confirmed += 1
+                    }
+                    frame => return Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decode a pushed message, if `frame` is one.
+fn message(frame: &Frame) -> Option<Message> {
+    let parts = match frame {
+        Frame::Array(parts) => parts,
+        _ => return None,
+    };
+    match parts.as_slice() {
+        [Frame::Bulk(kind), Frame::Bulk(channel), Frame::Bulk(content)] if kind == "message" => {
+            Some(Message { pattern: None, channel: channel.clone(), content: content.clone() })
+        }
+        [Frame::Bulk(kind), Frame::Bulk(pattern), Frame::Bulk(channel), Frame::Bulk(content)] if kind == "pmessage" => {
+            Some(Message { pattern: Some(pattern.clone()), channel: channel.clone(), content: content.clone() })
+        }
+        _ => None,
+    }
+}
+
+/// A stream entry as returned by the server: its ID and field/value pairs.
+pub type StreamEntry = (String, Vec<(Bytes, Bytes)>);
+
+/// Decode an array of stream entries.
+fn stream_entries(frame: Frame) -> crate::Result<Vec<StreamEntry>> {
+    let unexpected = |frame| -> crate::Error { Error::other(format!("Unexpected frame type: {:?}", frame)).into() };
+
+    let entries = match frame {
+        Frame::Array(entries) => entries,
+        frame => return Err(unexpected(frame)),
+    };
+
+    entries
+        .into_iter()
+        .map(|entry| match entry {
+            Frame::Array(parts) => match <[Frame; 2]>::try_from(parts) {
+                Ok([Frame::Bulk(id), Frame::Array(fields)]) => {
+                    let fields = fields
+                        .chunks(2)
+                        .map(|pair| match pair {
+                            [Frame::Bulk(field), Frame::Bulk(value)] => Ok((field.clone(), value.clone())),
+                            _ => Err(unexpected(Frame::Array(pair.to_vec()))),
+                        })
+                        .collect::<crate::Result<_>>()?;
+                    Ok((String::from_utf8(id.to_vec())?, fields))
+                }
+                // Entries re-read from a consumer group come back without
+                // fields once deleted from the stream.
+                Ok([Frame::Bulk(id), Frame::Null]) => Ok((String::from_utf8(id.to_vec())?, Vec::new())),
+                Ok(frame) => Err(unexpected(Frame::Array(frame.into()))),
+                Err(frame) => Err(unexpected(Frame::Array(frame))),
+            },
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        })
+        .collect()
+}
+
+/// Decode an array of key names.
+fn key_names(keys: Vec<Frame>) -> crate::Result<Vec<Bytes>> {
+    keys.into_iter()
+        .map(|key| match key {
+            Frame::Bulk(key) => Ok(key),
+            frame => Err(Error::other(format!("Unexpected frame type: {:?}", frame)).into()),
+        })
+        .collect()
+}
@@ -0,0 +1,335 @@
+//! The registry of connected clients, behind the `CLIENT` commands.
+//!
+//! Every connection registers with the `Clients` registry, shared through
+//! the `Db`, for as long as it is open. Its `ClientInfo` records who it is
+//! and what it is doing: the server updates it around each command, and
+//! `CLIENT LIST` reports it.
+//!
+//! `CLIENT KILL` marks a client as killed and takes it out of the registry;
+//! its connection notices and closes. `CLIENT PAUSE` holds the commands of
+//! every client, or only those that may write, until a deadline or `CLIENT
+//! UNPAUSE`.
+
+use crate::Frame;
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::time::{Duration, Instant};
+
+/// Commands whose subcommand is part of the name reported by `CLIENT LIST`,
+/// as in `client|list`.
+const CONTAINER_COMMANDS: &[&str] = &["CLIENT", "CONFIG", "MEMORY", "PUBSUB", "SCRIPT", "XGROUP", "XINFO"];
+
+/// Every connected client, by ID.
+#[derive(Debug)]
+pub(crate) struct Clients {
+    clients: Mutex<BTreeMap<u64, Arc<ClientInfo>>>,
+
+    /// Identifies the next client.
+    next_id: AtomicU64,
+
+    /// The pause in effect, if any. It may be over already.
+    pause: Mutex<Option<Pause>>,
+
+    /// Wakes paused commands up on `CLIENT UNPAUSE`.
+    unpaused: Notify,
+}
+
+/// A `CLIENT PAUSE`.
+#[derive(Debug, Clone, Copy)]
+struct Pause {
+    until: Instant,
+
+    /// Whether only commands that may write are held (`WRITE`), rather
+    /// than all of them (`ALL`).
+    writes_only: bool,
+}
+
+/// A connected client.
+#[derive(Debug)]
+pub(crate) struct ClientInfo {
+    id: u64,
+    addr: SocketAddr,
+    laddr: SocketAddr,
+    connected_at: Instant,
+    state: Mutex<ClientState>,
+
+    /// Notified once the client is killed.
+    kill: Notify,
+}
+
+/// What a client has been doing, updated as it goes.
+#[derive(Debug)]
+struct ClientState {
+    /// Set by `CLIENT SETNAME`.
+    name: Option<Bytes>,
+    namespace: usize,
+    last_interaction: Instant,
+
+    /// The name of the last command, lowercase, such as `client|list`.
+    last_command: String,
+
+    /// Commands queued, while in a transaction.
+    multi: Option<usize>,
+    channels: usize,
+    patterns: usize,
+
+    /// Bytes read but not yet parsed into a command.
+    query_buffer: usize,
+
+    /// Bytes of the reply being written.
+    output_buffer: usize,
+
+    /// Set by `CLIENT NO-EVICT`. Clients are never evicted here, so it is
+    /// only reported.
+    no_evict: bool,
+}
+
+/// Which clients `CLIENT KILL` and `CLIENT LIST` apply to. Every filter
+/// given must match.
+#[derive(Debug, Default)]
+pub(crate) struct ClientFilter {
+    pub(crate) ids: Option<Vec<u64>>,
+    pub(crate) addr: Option<String>,
+    pub(crate) laddr: Option<String>,
+
+    /// `true` for subscribers (`pubsub`), `false` for other clients
+    /// (`normal`).
+    pub(crate) pubsub: Option<bool>,
+
+    /// Only clients connected for longer than this.
+    pub(crate) max_age: Option<Duration>,
+
+    /// A client the filter never matches, normally the one asking.
+    pub(crate) skip: Option<u64>,
+}
+
+impl Clients {
+    pub(crate) fn new() -> Clients {
+        Clients { clients: Mutex::new(BTreeMap::new()), next_id: AtomicU64::new(1), pause: Mutex::new(None), unpaused: Notify::new() }
+    }
+
+    /// Register a client connected from `addr` to `laddr`.
+    pub(crate) fn register(&self, addr: SocketAddr, laddr: SocketAddr) -> Arc<ClientInfo> {
+        let now = Instant::now();
+        let client = Arc::new(ClientInfo {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            addr,
+            laddr,
+            connected_at: now,
+            state: Mutex::new(ClientState {
+                name: None,
+                namespace: 0,
+                last_interaction: now,
+                last_command: "NULL".to_string(),
+                multi: None,
+                channels: 0,
+                patterns: 0,
+                query_buffer: 0,
+                output_buffer: 0,
+                no_evict: false,
+            }),
+            kill: Notify::new(),
+        });
+        self.clients.lock().unwrap().insert(client.id, client.clone());
+        client
+    }
+
+    /// Forget the client `id`, once its connection is closed.
+    pub(crate) fn unregister(&self, id: u64) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    /// Number of connected clients.
+    pub(crate) fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /// The clients matching `filter`, oldest first.
+    pub(crate) fn list(&self, filter: &ClientFilter) -> Vec<Arc<ClientInfo>> {
+        let clients = self.clients.lock().unwrap();
+        clients.values().filter(|client| client.matches(filter)).cloned().collect()
+    }
+
+    /// Kill the clients matching `filter`. Returns how many there were.
+    pub(crate) fn kill(&self, filter: &ClientFilter) -> usize {
+        let mut clients = self.clients.lock().unwrap();
+        let killed: Vec<u64> = clients.values().filter(|client| client.matches(filter)).map(|client| client.id).collect();
+        for id in &killed {
+            if let Some(client) = clients.remove(id) {
+                client.kill.notify_one();
+            }
+        }
+        killed.len()
+    }
+
+    /// Hold commands for `timeout`: all of them, or with `writes_only` only
+    /// those that may write. A pause already in effect is only ever
+    /// extended, and made stricter.
+    pub(crate) fn pause(&self, timeout: Duration, writes_only: bool) {
+        let until = Instant::now() + timeout;
+        let mut pause = self.pause.lock().unwrap();
+        *pause = Some(match *pause {
+            Some(current) if current.until > Instant::now() => {
+                Pause { until: current.until.max(until), writes_only: current.writes_only && writes_only }
+            }
+            _ => Pause { until, writes_only },
+        });
+    }
+
+    /// End the pause in effect, if any.
+    pub(crate) fn unpause(&self) {
+        *self.pause.lock().unwrap() = None;
+        self.unpaused.notify_waiters();
+    }
+
+    /// Whether commands that may write are being held.
+    pub(crate) fn writes_paused(&self) -> bool {
+        self.pause.lock().unwrap().is_some_and(|pause| pause.until > Instant::now())
+    }
+
+    /// Wait for the pause in effect to end, if it holds this command; `write`
+    /// tells whether it may write.
+    pub(crate) async fn wait_unpaused(&self, write: bool) {
+        loop {
+            // Created before checking, so that an unpause in between is not
+            // missed.
+            let unpaused = self.unpaused.notified();
+            let until = match *self.pause.lock().unwrap() {
+                Some(pause) if pause.until > Instant::now() && (write || !pause.writes_only) => pause.until,
+                _ => return,
+            };
+            tokio::select! {
+                _ = unpaused => {}
+                _ = tokio::time::sleep_until(until) => {}
+            }
+        }
+    }
+}
+
+impl ClientInfo {
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn name(&self) -> Option<Bytes> {
+        self.state.lock().unwrap().name.clone()
+    }
+
+    /// Name the client; an empty name removes it.
+    pub(crate) fn set_name(&self, name: Bytes) {
+        self.state.lock().unwrap().name = Some(name).filter(|name| !name.is_empty());
+    }
+
+    pub(crate) fn set_no_evict(&self, no_evict: bool) {
+        self.state.lock().unwrap().no_evict = no_evict;
+    }
+
+    /// Record that the client sent `frame`, with `query_buffer` bytes
+    /// still waiting behind it.
+    pub(crate) fn received(&self, frame: &Frame, query_buffer: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.last_interaction = Instant::now();
+        state.last_command = command_name(frame);
+        state.query_buffer = query_buffer;
+    }
+
+    /// Record where the client stands once its command has run.
+    pub(crate) fn ran(&self, namespace: usize, multi: Option<usize>) {
+        let mut state = self.state.lock().unwrap();
+        state.last_interaction = Instant::now();
+        state.namespace = namespace;
+        state.multi = multi;
+    }
+
+    /// Record the number of channels and patterns subscribed to.
+    pub(crate) fn subscribed(&self, channels: usize, patterns: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.channels = channels;
+        state.patterns = patterns;
+    }
+
+    /// Record that `bytes` of reply are being written; 0 once written.
+    pub(crate) fn writing(&self, bytes: usize) {
+        self.state.lock().unwrap().output_buffer = bytes;
+    }
+
+    /// Wait until the client is killed.
+    pub(crate) async fn killed(&self) {
+        self.kill.notified().await
+    }
+
+    fn matches(&self, filter: &ClientFilter) -> bool {
+        let state = self.state.lock().unwrap();
+        filter.ids.as_ref().is_none_or(|ids| ids.contains(&self.id))
+            && filter.addr.as_ref().is_none_or(|addr| *addr == self.addr.to_string())
+            && filter.laddr.as_ref().is_none_or(|laddr| *laddr == self.laddr.to_string())
+            && filter.pubsub.is_none_or(|pubsub| pubsub == (state.channels + state.patterns > 0))
+            && filter.max_age.is_none_or(|max_age| self.connected_at.elapsed() > max_age)
+            && filter.skip != Some(self.id)
+    }
+
+    /// The client as a line of `CLIENT LIST`, without the line break.
+    pub(crate) fn describe(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut flags = String::new();
+        if state.multi.is_some() {
+            flags.push('x');
+        }
+        if state.channels + state.patterns > 0 {
+            flags.push('P');
+        }
+        if state.no_evict {
+            flags.push('e');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+
+        let mut line = String::new();
+        let _ = write!(
+            line,
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db={} sub={} psub={} multi={} qbuf={} omem={} cmd={} no-evict={}",
+            self.id,
+            self.addr,
+            self.laddr,
+            String::from_utf8_lossy(state.name.as_deref().unwrap_or_default()),
+            self.connected_at.elapsed().as_secs(),
+            state.last_interaction.elapsed().as_secs(),
+            flags,
+            state.namespace,
+            state.channels,
+            state.patterns,
+            state.multi.map_or(-1, |queued| queued as i64),
+            state.query_buffer,
+            state.output_buffer,
+            state.last_command,
+            if state.no_evict { "on" } else { "off" },
+        );
+        line
+    }
+}
+
+/// The name of the command in `frame` as `CLIENT LIST` reports it:
+/// lowercase, with the subcommand for commands that have them.
+fn command_name(frame: &Frame) -> String {
+    let parts = match frame {
+        Frame::Array(parts) => parts,
+        _ => return "NULL".to_string(),
+    };
+    let word = |index: usize| match parts.get(index) {
+        Some(Frame::Bulk(word)) => Some(String::from_utf8_lossy(word).to_lowercase()),
+        Some(Frame::Simple(word)) => Some(word.to_lowercase()),
+        _ => None,
+    };
+
+    let Some(name) = word(0) else { return "NULL".to_string() };
+    match word(1) {
+        Some(subcommand) if CONTAINER_COMMANDS.contains(&name.to_uppercase().as_str()) => format!("{}|{}", name, subcommand),
+        _ => name,
+    }
+}
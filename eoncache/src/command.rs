@@ -1,168 +1,3313 @@
-use std::sync::Arc;
-use crate::{Db, Frame, Parse};
-use tokio::time::Duration;
-use tracing::warn;
+use crate::db::{
+    format_float, Aggregate, ClaimOptions, End, ExpireCondition, Expiration, GroupEntry, LexBound, RestoreOptions, ScoreBound, SetOp,
+    SetOptions, StreamEntry, StreamId, Trim, TrimStrategy, Ttl, XAddId, XReadFrom, ZAddOptions, ZRange,
+};
+use crate::clients::ClientFilter;
+use crate::config::{parse_memory, parse_save, SaveRules};
+use crate::aof::{self, poll_once};
+use crate::glob;
+use crate::parse::{self, ParseError};
+use crate::scripting::{self, Script};
+use crate::{AppendFsync, EvictionPolicy, Frame, KeyspaceEvents, Parse, Session};
+use bytes::Bytes;
+use std::future::{poll_fn, Future};
+use std::iter::Peekable;
+use std::pin::pin;
+use std::task::Poll;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{Duration, Instant};
 
-pub async fn handle_command(parse: &mut Parse, db: &Arc<Db>) -> crate::Result<Frame> {
-    println!("Received command: {:?}", parse);  // Debug output for incoming frames
+/// Execute a single command on behalf of `session`.
+///
+/// Key-space commands operate on the namespace currently selected by the
+/// session, so concurrent clients never observe each other's `SELECT`.
+pub async fn handle_command(parse: &mut Parse, session: &mut Session) -> crate::Result<Frame> {
     let command = parse.next_string()?.to_uppercase();
+
+    // `CLIENT PAUSE` holds commands here. `CLIENT` itself is never held, so
+    // that the pause can be lifted.
+    if command != "CLIENT" {
+        let write = match command.as_str() {
+            "EXEC" => true,
+            _ if session.in_transaction() => false,
+            "EVAL" | "EVALSHA" | "PUBLISH" => true,
+            _ => WRITE_COMMANDS.contains(&command.as_str()),
+        };
+        session.db().clients().wait_unpaused(write).await;
+    }
+
     match command.as_str() {
-        "SELECT" => handle_select(parse, db).await,
-        "SET" => handle_set(parse, db).await,
-        "GET" => handle_get(parse, db).await,
+        "MULTI" => handle_multi(parse, session),
+        "EXEC" => handle_exec(parse, session).await,
+        "DISCARD" => handle_discard(parse, session),
+        "WATCH" => handle_watch(parse, session),
+        "EVAL" | "EVALSHA" if !session.in_transaction() => handle_eval(parse, session, &command).await,
+        "SCRIPT" if !session.in_transaction() => handle_script(parse, session).await,
+        "CLIENT" if !session.in_transaction() => handle_client(parse, session).await,
+        "BGREWRITEAOF" if !session.in_transaction() => handle_bgrewriteaof(parse, session).await,
+        _ if session.in_transaction() => queue(parse, session, &command),
+        _ => run(parse, session, &command).await,
+    }
+}
+
+/// Run `command` on its own, outside of any transaction.
+async fn run(parse: &mut Parse, session: &mut Session, command: &str) -> crate::Result<Frame> {
+    // Make room under `maxmemory` first. If that is not possible, commands
+    // that may grow memory are refused while the others still run.
+    match session.db().free_memory() {
+        Ok(0) => {}
+        // Log the evictions before the command that made them.
+        Ok(_) => session.db().aof().write_pending().await,
+        Err(e) if DENY_OOM.contains(&command) => return Err(e.into()),
+        Err(_) => {}
+    }
+
+    // Commands pass the exec lock together, so that a transaction runs
+    // alone. With the append-only file enabled, a write takes its place in
+    // the log as it runs, without yielding, and is written to it after.
+    let db = session.db().clone();
+    let gate = db.exec_lock().shared().await;
+    let args = parse.remaining();
+    let namespace = session.namespace();
+    let scope = if WRITE_COMMANDS.contains(&command) { db.aof().scope() } else { None };
+
+    let mut execution = pin!(execute(parse, session, command));
+    let result = match poll_fn(|cx| Poll::Ready(execution.as_mut().poll(cx))).await {
+        Poll::Ready(result) => result,
+        Poll::Pending => {
+            // The command blocked; let other commands and transactions
+            // through in the meantime. A blocked pop is logged by the write
+            // that serves it, a blocked `XREADGROUP` once it has read.
+            drop(scope);
+            drop(gate);
+            let result = execution.await;
+            match &result {
+                Ok(reply) if command == "XREADGROUP" && db.aof().enabled() => {
+                    let commands = propagate(command, args, reply);
+                    let slot = db.aof().log_now(commands.into_iter().map(|command| (namespace, command)).collect());
+                    db.aof().write_through(slot).await;
+                }
+                _ => {}
+            }
+            return result;
+        }
+    };
+
+    let slot = scope.and_then(|scope| {
+        let commands = match &result {
+            Ok(reply) => propagate(command, args, reply),
+            Err(_) => Vec::new(),
+        };
+        scope.log(namespace, commands)
+    });
+    drop(gate);
+    if let Some(slot) = slot {
+        db.aof().write_through(slot).await;
+    }
+    result
+}
+
+/// Handles `MULTI`: commands are queued from now on, until `EXEC` or
+/// `DISCARD`.
+fn handle_multi(parse: &mut Parse, session: &mut Session) -> crate::Result<Frame> {
+    parse.finish()?;
+
+    session.multi()?;
+    Ok(Frame::Simple("OK".to_string()))
+}
+
+/// Queue `command` for `EXEC`, once it is known to exist and to have a valid
+/// number of arguments. A command refused here makes `EXEC` fail.
+fn queue(parse: &mut Parse, session: &mut Session, command: &str) -> crate::Result<Frame> {
+    let args = parse.remaining();
+    let transaction = session.transaction_mut().expect("a transaction is open");
+
+    let error = match ARITY.iter().find(|(name, _)| *name == command) {
+        None => Some(format!("ERR unknown command '{}'", command.to_lowercase()).into()),
+        Some(&(_, arity)) => {
+            let given = args.len() as i32 + 1;
+            let valid = if arity >= 0 { given == arity } else { given >= -arity };
+            (!valid).then(|| wrong_arity(command))
+        }
+    };
+    if let Some(error) = error {
+        transaction.aborted = true;
+        return Err(error);
+    }
+
+    let name = Bytes::copy_from_slice(command.as_bytes());
+    transaction.commands.push(std::iter::once(name).chain(args).collect());
+    Ok(Frame::Simple("QUEUED".to_string()))
+}
+
+/// Handles `EXEC`: runs the queued commands as one, with no command of
+/// another client in between. Fails if a command was refused while
+/// queueing, and replies `Null` without running anything if a watched key
+/// changed.
+async fn handle_exec(parse: &mut Parse, session: &mut Session) -> crate::Result<Frame> {
+    parse.finish()?;
+
+    let transaction = session.take_transaction().ok_or("ERR EXEC without MULTI")?;
+    let db = session.db().clone();
+    let gate = db.exec_lock().exclusive().await;
+    let changed = session.watched_changed();
+    session.unwatch();
+    if transaction.aborted {
+        return Err("EXECABORT Transaction discarded because of previous errors.".into());
+    }
+    if changed {
+        return Ok(Frame::Null);
+    }
+
+    let scope = db.aof().scope();
+    let mut logged = Vec::new();
+    let replies = transaction
+        .commands
+        .into_iter()
+        .map(|args| call(session, args, &mut logged).unwrap_or_else(|e| Frame::Error(e.to_string())))
+        .collect();
+
+    let slot = scope.and_then(|scope| scope.log_transaction(logged));
+    drop(gate);
+    if let Some(slot) = slot {
+        db.aof().write_through(slot).await;
+    }
+    Ok(Frame::Array(replies))
+}
+
+/// Run `args`, a command of a transaction or a script, name first. The
+/// caller holds the exec lock exclusively. Blocking commands do not wait;
+/// they reply as if they had timed out. What the command writes is added to
+/// `logged`, along with the namespace it ran in.
+pub(crate) fn call(session: &mut Session, args: Vec<Bytes>, logged: &mut Vec<(usize, Vec<Bytes>)>) -> crate::Result<Frame> {
+    let command = String::from_utf8_lossy(&args[0]).to_uppercase();
+    let namespace = session.namespace();
+    let mut parse = Parse::new(Frame::Array(args.iter().cloned().map(Frame::Bulk).collect()))?;
+    parse.next_string()?;
+
+    if let Err(e) = session.db().free_memory() {
+        if DENY_OOM.contains(&command.as_str()) {
+            return Err(e.into());
+        }
+    }
+    // Evictions are logged before the command that made them.
+    logged.extend(aof::take_recorded());
+
+    // A script queued in a transaction runs inline, the exec lock being
+    // held already.
+    if command == "EVAL" || command == "EVALSHA" {
+        let script = parse_script(&mut parse, session, &command)?;
+        let db = session.db().clone();
+        return scripting::eval(&db, namespace, script, logged);
+    }
+
+    let reply = match poll_once(execute(&mut parse, session, &command)) {
+        Poll::Ready(result) => result?,
+        Poll::Pending => Frame::Null,
+    };
+    if WRITE_COMMANDS.contains(&command.as_str()) {
+        let commands = propagate(&command, args[1..].to_vec(), &reply);
+        logged.extend(commands.into_iter().map(|command| (namespace, command)));
+    }
+    logged.extend(aof::take_recorded());
+    Ok(reply)
+}
+
+/// Handles `EVAL script numkeys [key ...] [arg ...]` and `EVALSHA sha1
+/// numkeys [key ...] [arg ...]`. The script runs on a blocking thread under
+/// the exec lock, like a transaction, and what it writes is logged as one.
+async fn handle_eval(parse: &mut Parse, session: &mut Session, command: &str) -> crate::Result<Frame> {
+    let script = parse_script(parse, session, command)?;
+    let db = session.db().clone();
+    let namespace = session.namespace();
+
+    tokio::task::spawn_blocking(move || {
+        let runtime = tokio::runtime::Handle::current();
+        let gate = runtime.block_on(db.exec_lock().exclusive());
+        let scope = db.aof().scope();
+        let mut logged = Vec::new();
+        let reply = scripting::eval(&db, namespace, script, &mut logged);
+        let slot = scope.and_then(|scope| scope.log_transaction(logged));
+        drop(gate);
+        if let Some(slot) = slot {
+            runtime.block_on(db.aof().write_through(slot));
+        }
+        reply
+    })
+    .await?
+}
+
+/// Read the script, keys and arguments of `EVAL` or `EVALSHA`. `EVAL` caches
+/// its script for later `EVALSHA` calls.
+fn parse_script(parse: &mut Parse, session: &mut Session, command: &str) -> crate::Result<Script> {
+    let source = parse.next_bytes()?;
+    let scripts = session.db().scripts();
+    let body = if command == "EVAL" {
+        scripts.load(source.clone());
+        source
+    } else {
+        scripts.get(&source).ok_or("NOSCRIPT No matching script. Please use EVAL.")?
+    };
+
+    let numkeys = parse.next_signed()?;
+    let mut keys = remaining_bytes(parse, 0, command)?;
+    if numkeys < 0 {
+        return Err("ERR Number of keys can't be negative".into());
+    }
+    if numkeys as usize > keys.len() {
+        return Err("ERR Number of keys can't be greater than number of args".into());
+    }
+    let args = keys.split_off(numkeys as usize);
+    Ok(Script { body, keys, args })
+}
+
+/// Handles `SCRIPT LOAD script`, `SCRIPT EXISTS sha1 [sha1 ...]`, `SCRIPT
+/// FLUSH [ASYNC|SYNC]` and `SCRIPT KILL`. Outside of transactions this runs
+/// without the exec lock, so that `SCRIPT KILL` can reach a running script.
+async fn handle_script(parse: &mut Parse, session: &mut Session) -> crate::Result<Frame> {
+    let subcommand = parse.next_string()?.to_uppercase();
+    let scripts = session.db().scripts();
+
+    match subcommand.as_str() {
+        "LOAD" => {
+            let body = parse.next_bytes()?;
+            parse.finish()?;
+            Ok(Frame::Bulk(Bytes::from(scripts.load(body))))
+        }
+        "EXISTS" => {
+            let shas = remaining_bytes(parse, 1, "SCRIPT|EXISTS")?;
+            let exists = shas.iter().map(|sha| Frame::Integer(scripts.get(sha).is_some() as i64));
+            Ok(Frame::Array(exists.collect()))
+        }
+        "FLUSH" => {
+            match parse.next_string() {
+                Ok(mode) if mode.eq_ignore_ascii_case("ASYNC") || mode.eq_ignore_ascii_case("SYNC") => parse.finish()?,
+                Ok(_) => return Err("ERR syntax error".into()),
+                Err(ParseError::EndOfStream) => {}
+                Err(e) => return Err(e.into()),
+            }
+            scripts.flush();
+            Ok(Frame::Simple("OK".to_string()))
+        }
+        "KILL" => {
+            parse.finish()?;
+            scripts.kill()?;
+            Ok(Frame::Simple("OK".to_string()))
+        }
+        _ => Err(format!("ERR unknown subcommand '{}'. Try SCRIPT HELP.", subcommand.to_lowercase()).into()),
+    }
+}
+
+/// Handles `DISCARD`: drops the queued commands and every watch.
+fn handle_discard(parse: &mut Parse, session: &mut Session) -> crate::Result<Frame> {
+    parse.finish()?;
+
+    session.take_transaction().ok_or("ERR DISCARD without MULTI")?;
+    session.unwatch();
+    Ok(Frame::Simple("OK".to_string()))
+}
+
+/// Handles `WATCH key [key ...]`: a later `EXEC` does nothing if any of the
+/// keys changes in the meantime.
+fn handle_watch(parse: &mut Parse, session: &mut Session) -> crate::Result<Frame> {
+    if session.in_transaction() {
+        return Err("ERR WATCH inside MULTI is not allowed".into());
+    }
+    for key in remaining_bytes(parse, 1, "WATCH")? {
+        session.watch(key);
+    }
+    Ok(Frame::Simple("OK".to_string()))
+}
+
+/// Handles `UNWATCH`.
+async fn handle_unwatch(parse: &mut Parse, session: &mut Session) -> crate::Result<Frame> {
+    parse.finish()?;
+
+    session.unwatch();
+    Ok(Frame::Simple("OK".to_string()))
+}
+
+/// Run `command`, whose name has already been taken from `parse`.
+async fn execute(parse: &mut Parse, session: &mut Session, command: &str) -> crate::Result<Frame> {
+    match command {
+        "SELECT" => handle_select(parse, session).await,
+        "SET" => handle_set(parse, session).await,
+        "GET" => handle_get(parse, session).await,
+        "SETNX" => handle_setnx(parse, session).await,
+        "GETSET" => handle_getset(parse, session).await,
+        "GETDEL" => handle_getdel(parse, session).await,
+        "GETEX" => handle_getex(parse, session).await,
+        "MGET" => handle_mget(parse, session).await,
+        "MSET" | "MSETNX" => handle_mset(parse, session, command).await,
+        "INCR" | "DECR" | "INCRBY" | "DECRBY" => handle_incr(parse, session, command).await,
+        "INCRBYFLOAT" => handle_incrbyfloat(parse, session).await,
+        "APPEND" => handle_append(parse, session).await,
+        "STRLEN" => handle_strlen(parse, session).await,
+        "GETRANGE" | "SUBSTR" => handle_getrange(parse, session).await,
+        "SETRANGE" => handle_setrange(parse, session).await,
         "PING" => handle_ping().await,
-        "EXISTS" => handle_exists(parse, db).await,
-        "RPUSH" => handle_rpush(parse, db).await,
-        "LPUSH" => handle_lpush(parse, db).await,
-        "BLPOP" => {
-            let timeout = parse.next_string()?.parse().map_err(|_| "Invalid timeout")?;
-            handle_blpop(parse, db, timeout).await
-        },
-        "BRPOP" => {
-            let timeout = parse.next_string()?.parse().map_err(|_| "Invalid timeout")?;
-            handle_brpop(parse, db, timeout).await
-        },
+        "EXISTS" => handle_exists(parse, session).await,
+        "EXPIRE" | "PEXPIRE" | "EXPIREAT" | "PEXPIREAT" => handle_expire(parse, session, command).await,
+        "TTL" | "PTTL" | "EXPIRETIME" | "PEXPIRETIME" => handle_ttl(parse, session, command).await,
+        "PERSIST" => handle_persist(parse, session).await,
+        "DEL" | "UNLINK" => handle_del(parse, session, command).await,
+        "TYPE" => handle_type(parse, session).await,
+        "RENAME" | "RENAMENX" => handle_rename(parse, session, command).await,
+        "COPY" => handle_copy(parse, session).await,
+        "MOVE" => handle_move(parse, session).await,
+        "DUMP" => handle_dump(parse, session).await,
+        "RESTORE" => handle_restore(parse, session).await,
+        "DBSIZE" => handle_dbsize(parse, session).await,
+        "FLUSHDB" | "FLUSHALL" => handle_flush(parse, session, command).await,
+        "SWAPDB" => handle_swapdb(parse, session).await,
+        "RANDOMKEY" => handle_randomkey(parse, session).await,
+        "SCAN" => handle_scan(parse, session).await,
+        "KEYS" => handle_keys(parse, session).await,
+        "LPUSH" | "RPUSH" | "LPUSHX" | "RPUSHX" => handle_push(parse, session, command).await,
+        "LPOP" | "RPOP" => handle_pop(parse, session, command).await,
+        "BLPOP" => handle_blpop(parse, session).await,
+        "BRPOP" => handle_brpop(parse, session).await,
+        "LLEN" => handle_llen(parse, session).await,
+        "LRANGE" => handle_lrange(parse, session).await,
+        "LINDEX" => handle_lindex(parse, session).await,
+        "LSET" => handle_lset(parse, session).await,
+        "LREM" => handle_lrem(parse, session).await,
+        "LTRIM" => handle_ltrim(parse, session).await,
+        "LINSERT" => handle_linsert(parse, session).await,
+        "LPOS" => handle_lpos(parse, session).await,
+        "LMOVE" | "RPOPLPUSH" => handle_lmove(parse, session, command).await,
+        "BLMOVE" | "BRPOPLPUSH" => handle_blmove(parse, session, command).await,
+        "HSET" | "HMSET" => handle_hset(parse, session, command).await,
+        "HSETNX" => handle_hsetnx(parse, session).await,
+        "HGET" => handle_hget(parse, session).await,
+        "HMGET" => handle_hmget(parse, session).await,
+        "HGETALL" | "HKEYS" | "HVALS" => handle_hgetall(parse, session, command).await,
+        "HDEL" => handle_hdel(parse, session).await,
+        "HEXISTS" => handle_hexists(parse, session).await,
+        "HLEN" => handle_hlen(parse, session).await,
+        "HSTRLEN" => handle_hstrlen(parse, session).await,
+        "HINCRBY" => handle_hincrby(parse, session).await,
+        "HINCRBYFLOAT" => handle_hincrbyfloat(parse, session).await,
+        "HRANDFIELD" => handle_hrandfield(parse, session).await,
+        "HSCAN" | "SSCAN" | "ZSCAN" => handle_collection_scan(parse, session, command).await,
+        "SADD" => handle_sadd(parse, session).await,
+        "SREM" => handle_srem(parse, session).await,
+        "SISMEMBER" | "SMISMEMBER" => handle_smismember(parse, session, command).await,
+        "SMEMBERS" => handle_smembers(parse, session).await,
+        "SCARD" => handle_scard(parse, session).await,
+        "SPOP" => handle_spop(parse, session).await,
+        "SRANDMEMBER" => handle_srandmember(parse, session).await,
+        "SMOVE" => handle_smove(parse, session).await,
+        "SINTER" | "SUNION" | "SDIFF" => handle_set_algebra(parse, session, command).await,
+        "SINTERSTORE" | "SUNIONSTORE" | "SDIFFSTORE" => handle_set_algebra_store(parse, session, command).await,
+        "SINTERCARD" => handle_sintercard(parse, session).await,
+        "ZADD" => handle_zadd(parse, session).await,
+        "ZINCRBY" => handle_zincrby(parse, session).await,
+        "ZREM" => handle_zrem(parse, session).await,
+        "ZCARD" => handle_zcard(parse, session).await,
+        "ZSCORE" | "ZMSCORE" => handle_zmscore(parse, session, command).await,
+        "ZRANK" | "ZREVRANK" => handle_zrank(parse, session, command).await,
+        "ZCOUNT" | "ZLEXCOUNT" => handle_zcount(parse, session, command).await,
+        "ZRANGE" | "ZREVRANGE" | "ZRANGEBYSCORE" | "ZREVRANGEBYSCORE" | "ZRANGEBYLEX" | "ZREVRANGEBYLEX" => {
+            handle_zrange(parse, session, command).await
+        }
+        "ZREMRANGEBYRANK" | "ZREMRANGEBYSCORE" | "ZREMRANGEBYLEX" => handle_zremrange(parse, session, command).await,
+        "ZPOPMIN" | "ZPOPMAX" => handle_zpop(parse, session, command).await,
+        "BZPOPMIN" | "BZPOPMAX" => handle_bzpop(parse, session, command).await,
+        "ZUNIONSTORE" | "ZINTERSTORE" | "ZDIFFSTORE" => handle_zstore(parse, session, command).await,
+        "XADD" => handle_xadd(parse, session).await,
+        "XLEN" => handle_xlen(parse, session).await,
+        "XRANGE" | "XREVRANGE" => handle_xrange(parse, session, command).await,
+        "XDEL" => handle_xdel(parse, session).await,
+        "XTRIM" => handle_xtrim(parse, session).await,
+        "XREAD" => handle_xread(parse, session).await,
+        "XGROUP" => handle_xgroup(parse, session).await,
+        "XREADGROUP" => handle_xreadgroup(parse, session).await,
+        "XACK" => handle_xack(parse, session).await,
+        "XPENDING" => handle_xpending(parse, session).await,
+        "XCLAIM" => handle_xclaim(parse, session).await,
+        "XAUTOCLAIM" => handle_xautoclaim(parse, session).await,
+        "XINFO" => handle_xinfo(parse, session).await,
+        "CONFIG" => handle_config(parse, session).await,
+        "INFO" => handle_info(parse, session).await,
+        "MEMORY" => handle_memory(parse, session).await,
+        "SAVE" => handle_save(parse, session).await,
+        "BGSAVE" => handle_bgsave(parse, session).await,
+        "LASTSAVE" => handle_lastsave(parse, session).await,
+        "BGREWRITEAOF" => handle_bgrewriteaof(parse, session).await,
+        "UNWATCH" => handle_unwatch(parse, session).await,
+        "SCRIPT" => handle_script(parse, session).await,
+        "PUBLISH" => handle_publish(parse, session).await,
+        "PUBSUB" => handle_pubsub(parse, session).await,
+        "CLIENT" => handle_client(parse, session).await,
         _ => Err("Unsupported command".into()),
     }
 }
-pub async fn handle_select(parse: &mut Parse, db: &Arc<Db>) -> crate::Result<Frame> {
-    match parse.next_int() {  // Directly parse as integer
-        Ok(index) if index < 16 => {  // Validate index range if there are 16 namespaces
-            match db.select_namespace(index as usize) {
-                Ok(_) => {
-                    println!("Selected namespace: {}", index);
-                    Ok(Frame::Simple("OK".to_string()))
-                },
-                Err(e) => {
-                    println!("Failed to select namespace {}: {}", index, e);
-                    Err(e.into())
-                }
+/// Commands that may grow memory use, refused once memory is over
+/// `maxmemory` and nothing can be evicted.
+const DENY_OOM: &[&str] = &[
+    "SET", "SETNX", "GETSET", "GETEX", "MSET", "MSETNX", "INCR", "DECR", "INCRBY", "DECRBY", "INCRBYFLOAT", "APPEND", "SETRANGE",
+    "COPY", "RESTORE", "LPUSH", "RPUSH", "LPUSHX", "RPUSHX", "LSET", "LINSERT", "LMOVE", "RPOPLPUSH", "BLMOVE", "BRPOPLPUSH", "HSET",
+    "HMSET", "HSETNX", "HINCRBY", "HINCRBYFLOAT", "SADD", "SMOVE", "SINTERSTORE", "SUNIONSTORE", "SDIFFSTORE", "ZADD",
+    "ZINCRBY", "ZUNIONSTORE", "ZINTERSTORE", "ZDIFFSTORE", "XADD", "XGROUP", "XREADGROUP", "XCLAIM", "XAUTOCLAIM",
+];
+
+/// Number of arguments of each command, its name included, checked when
+/// queueing it in a transaction. A negative arity is a minimum.
+const ARITY: &[(&str, i32)] = &[
+    ("SELECT", 2), ("SET", -3), ("GET", 2), ("SETNX", 3), ("GETSET", 3), ("GETDEL", 2), ("GETEX", -2), ("MGET", -2),
+    ("MSET", -3), ("MSETNX", -3), ("INCR", 2), ("DECR", 2), ("INCRBY", 3), ("DECRBY", 3), ("INCRBYFLOAT", 3),
+    ("APPEND", 3), ("STRLEN", 2), ("GETRANGE", 4), ("SUBSTR", 4), ("SETRANGE", 4), ("PING", -1), ("EXISTS", -2),
+    ("EXPIRE", -3), ("PEXPIRE", -3), ("EXPIREAT", -3), ("PEXPIREAT", -3), ("TTL", 2), ("PTTL", 2), ("EXPIRETIME", 2), ("PEXPIRETIME", 2), ("PERSIST", 2),
+    ("DEL", -2), ("UNLINK", -2), ("TYPE", 2), ("RENAME", 3), ("RENAMENX", 3), ("COPY", -3), ("MOVE", 3), ("DUMP", 2),
+    ("RESTORE", -4), ("DBSIZE", 1), ("FLUSHDB", -1), ("FLUSHALL", -1), ("SWAPDB", 3), ("RANDOMKEY", 1), ("SCAN", -2),
+    ("KEYS", 2), ("LPUSH", -3), ("RPUSH", -3), ("LPUSHX", -3), ("RPUSHX", -3), ("LPOP", -2), ("RPOP", -2),
+    ("BLPOP", -3), ("BRPOP", -3), ("LLEN", 2), ("LRANGE", 4), ("LINDEX", 3), ("LSET", 4), ("LREM", 4), ("LTRIM", 4),
+    ("LINSERT", 5), ("LPOS", -3), ("LMOVE", 5), ("RPOPLPUSH", 3), ("BLMOVE", 6), ("BRPOPLPUSH", 4), ("HSET", -4),
+    ("HMSET", -4), ("HSETNX", 4), ("HGET", 3), ("HMGET", -3), ("HGETALL", 2), ("HKEYS", 2), ("HVALS", 2),
+    ("HDEL", -3), ("HEXISTS", 3), ("HLEN", 2), ("HSTRLEN", 3), ("HINCRBY", 4), ("HINCRBYFLOAT", 4),
+    ("HRANDFIELD", -2), ("HSCAN", -3), ("SSCAN", -3), ("ZSCAN", -3), ("SADD", -3), ("SREM", -3), ("SISMEMBER", 3),
+    ("SMISMEMBER", -3), ("SMEMBERS", 2), ("SCARD", 2), ("SPOP", -2), ("SRANDMEMBER", -2), ("SMOVE", 4),
+    ("SINTER", -2), ("SUNION", -2), ("SDIFF", -2), ("SINTERSTORE", -3), ("SUNIONSTORE", -3), ("SDIFFSTORE", -3),
+    ("SINTERCARD", -3), ("ZADD", -4), ("ZINCRBY", 4), ("ZREM", -3), ("ZCARD", 2), ("ZSCORE", 3), ("ZMSCORE", -3),
+    ("ZRANK", -3), ("ZREVRANK", -3), ("ZCOUNT", 4), ("ZLEXCOUNT", 4), ("ZRANGE", -4), ("ZREVRANGE", -4),
+    ("ZRANGEBYSCORE", -4), ("ZREVRANGEBYSCORE", -4), ("ZRANGEBYLEX", -4), ("ZREVRANGEBYLEX", -4),
+    ("ZREMRANGEBYRANK", 4), ("ZREMRANGEBYSCORE", 4), ("ZREMRANGEBYLEX", 4), ("ZPOPMIN", -2), ("ZPOPMAX", -2),
+    ("BZPOPMIN", -3), ("BZPOPMAX", -3), ("ZUNIONSTORE", -4), ("ZINTERSTORE", -4), ("ZDIFFSTORE", -4), ("XADD", -5),
+    ("XLEN", 2), ("XRANGE", -4), ("XREVRANGE", -4), ("XDEL", -3), ("XTRIM", -4), ("XREAD", -4), ("XGROUP", -2),
+    ("XREADGROUP", -7), ("XACK", -4), ("XPENDING", -3), ("XCLAIM", -6), ("XAUTOCLAIM", -6), ("XINFO", -2),
+    ("CONFIG", -2), ("INFO", -1), ("MEMORY", -2), ("SAVE", 1), ("BGSAVE", -1), ("LASTSAVE", 1), ("BGREWRITEAOF", 1),
+    ("UNWATCH", 1), ("EVAL", -3), ("EVALSHA", -3), ("SCRIPT", -2),
+    ("PUBLISH", 3), ("PUBSUB", -2), ("CLIENT", -2),
+];
+
+/// Commands that may change the data, logged to the append-only file.
+const WRITE_COMMANDS: &[&str] = &[
+    "SET", "SETNX", "GETSET", "GETDEL", "GETEX", "MSET", "MSETNX", "INCR", "DECR", "INCRBY", "DECRBY", "INCRBYFLOAT", "APPEND",
+    "SETRANGE", "EXPIRE", "PEXPIRE", "EXPIREAT", "PEXPIREAT", "PERSIST", "DEL", "UNLINK", "RENAME", "RENAMENX", "COPY", "MOVE",
+    "RESTORE", "FLUSHDB", "FLUSHALL", "SWAPDB", "LPUSH", "RPUSH", "LPUSHX", "RPUSHX", "LPOP", "RPOP", "BLPOP", "BRPOP", "LSET", "LREM",
+    "LTRIM", "LINSERT", "LMOVE", "RPOPLPUSH", "BLMOVE", "BRPOPLPUSH", "HSET", "HMSET", "HSETNX", "HDEL", "HINCRBY",
+    "HINCRBYFLOAT", "SADD", "SREM", "SPOP", "SMOVE", "SINTERSTORE", "SUNIONSTORE", "SDIFFSTORE", "ZADD", "ZINCRBY", "ZREM",
+    "ZREMRANGEBYRANK", "ZREMRANGEBYSCORE", "ZREMRANGEBYLEX", "ZPOPMIN", "ZPOPMAX", "BZPOPMIN", "BZPOPMAX", "ZUNIONSTORE",
+    "ZINTERSTORE", "ZDIFFSTORE", "XADD", "XDEL", "XTRIM", "XGROUP", "XREADGROUP", "XACK", "XCLAIM", "XAUTOCLAIM",
+];
+
+/// The commands to log for `command`, called with `args`, that replied
+/// `reply`. That is the command itself unless replaying it could have a
+/// different effect: relative expirations are made absolute, blocking and
+/// random pops are logged as the pops that took place, and generated stream
+/// IDs and claims are spelled out.
+fn propagate(command: &str, mut args: Vec<Bytes>, reply: &Frame) -> Vec<Vec<Bytes>> {
+    let name = |name: &str| Bytes::copy_from_slice(name.as_bytes());
+    let with_name = |name: Bytes, args: Vec<Bytes>| std::iter::once(name).chain(args).collect();
+    let is = |arg: &Bytes, option: &str| arg.eq_ignore_ascii_case(option.as_bytes());
+
+    match command {
+        "EXPIRE" | "PEXPIRE" => {
+            let unit = if command == "EXPIRE" { 1000 } else { 1 };
+            args[1] = absolute_millis(&args[1], unit);
+            vec![with_name(name("PEXPIREAT"), args)]
+        }
+        "SET" | "GETEX" => {
+            let first = if command == "SET" { 2 } else { 1 };
+            if let Some(i) = (first..args.len()).find(|&i| is(&args[i], "EX") || is(&args[i], "PX")) {
+                let unit = if is(&args[i], "EX") { 1000 } else { 1 };
+                args[i + 1] = absolute_millis(&args[i + 1], unit);
+                args[i] = name("PXAT");
+            }
+            vec![with_name(name(command), args)]
+        }
+        "RESTORE" => {
+            if args[1].as_ref() != b"0" && !args.iter().any(|arg| is(arg, "ABSTTL")) {
+                args[1] = absolute_millis(&args[1], 1);
+                args.push(name("ABSTTL"));
+            }
+            vec![with_name(name("RESTORE"), args)]
+        }
+        "BLPOP" | "BRPOP" | "BZPOPMIN" | "BZPOPMAX" => match reply {
+            Frame::Array(popped) => {
+                let key = match &popped[0] {
+                    Frame::Bulk(key) => key.clone(),
+                    _ => return Vec::new(),
+                };
+                vec![vec![name(&command[1..]), key]]
             }
+            _ => Vec::new(),
         },
-        Ok(_) => {
-            let err_msg = "Invalid index: index out of allowed range (0-15)".to_string();
-            warn!("{}", err_msg);
-            Err(err_msg.into())
+        "BLMOVE" | "BRPOPLPUSH" => match reply {
+            Frame::Bulk(_) => {
+                args.pop();
+                vec![with_name(name(&command[1..]), args)]
+            }
+            _ => Vec::new(),
         },
-        Err(_) => {
-            warn!("Failed to parse index for SELECT command");
-            Err("Failed to parse index".into())
+        "SPOP" => {
+            let members = match reply {
+                Frame::Bulk(member) => vec![member.clone()],
+                Frame::Array(members) => frame_bulks(members),
+                _ => Vec::new(),
+            };
+            if members.is_empty() {
+                return Vec::new();
+            }
+            vec![with_name(name("SREM"), std::iter::once(args[0].clone()).chain(members).collect())]
+        }
+        "XADD" => {
+            let id = match reply {
+                Frame::Bulk(id) => id.clone(),
+                _ => return Vec::new(),
+            };
+            // Skip the key and the options in front of the ID.
+            let mut i = 1;
+            while i < args.len() {
+                if is(&args[i], "NOMKSTREAM") {
+                    i += 1;
+                } else if is(&args[i], "MAXLEN") || is(&args[i], "MINID") {
+                    i += 1;
+                    if is(&args[i], "=") || is(&args[i], "~") {
+                        i += 1;
+                    }
+                    i += 1;
+                    if i < args.len() && is(&args[i], "LIMIT") {
+                        i += 2;
+                    }
+                } else {
+                    break;
+                }
+            }
+            args[i] = id;
+            vec![with_name(name("XADD"), args)]
+        }
+        "XREADGROUP" => {
+            if let Some(i) = args.iter().position(|arg| is(arg, "BLOCK")) {
+                args.drain(i..i + 2);
+            }
+            vec![with_name(name("XREADGROUP"), args)]
+        }
+        "XCLAIM" => {
+            // Entries were idle long enough when claimed; on replay they
+            // would not be.
+            args[3] = name("0");
+            vec![with_name(name("XCLAIM"), args)]
+        }
+        "XAUTOCLAIM" => {
+            let (claimed, deleted) = match reply {
+                Frame::Array(parts) => match (&parts[1], &parts[2]) {
+                    (Frame::Array(claimed), Frame::Array(deleted)) => (claimed, deleted),
+                    _ => return Vec::new(),
+                },
+                _ => return Vec::new(),
+            };
+            let just_id = args.iter().any(|arg| is(arg, "JUSTID"));
+            let claimed = claimed.iter().filter_map(|claimed| match claimed {
+                Frame::Bulk(id) => Some(id.clone()),
+                Frame::Array(entry) => frame_bulks(&entry[..1]).pop(),
+                _ => None,
+            });
+            // Claiming the IDs of deleted entries drops them from the PEL,
+            // as the scan did.
+            let mut ids: Vec<Bytes> = claimed.chain(frame_bulks(deleted)).collect();
+            if ids.is_empty() {
+                return Vec::new();
+            }
+            if just_id {
+                ids.push(name("JUSTID"));
+            }
+            let prefix = args[..3].iter().cloned().chain(std::iter::once(name("0")));
+            vec![with_name(name("XCLAIM"), prefix.chain(ids).collect())]
         }
+        _ => vec![with_name(name(command), args)],
+    }
+}
+
+/// The bulk strings among `frames`.
+fn frame_bulks(frames: &[Frame]) -> Vec<Bytes> {
+    frames
+        .iter()
+        .filter_map(|frame| match frame {
+            Frame::Bulk(bytes) => Some(bytes.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The unix time in milliseconds `amount` units of `unit` milliseconds from
+/// now, for an argument already validated by the command.
+fn absolute_millis(amount: &[u8], unit: i64) -> Bytes {
+    let amount: i64 = std::str::from_utf8(amount).ok().and_then(|amount| amount.parse().ok()).unwrap_or(0);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or(0);
+    Bytes::from(now.saturating_add(amount.saturating_mul(unit)).to_string())
+}
+
+/// Handles `SELECT index`, where `index` may also be a namespace alias.
+pub async fn handle_select(parse: &mut Parse, session: &mut Session) -> crate::Result<Frame> {
+    let name = parse.next_string()?;
+    parse.finish()?;
+
+    let index = session.db().resolve_namespace(&name)?;
+    session.select(index)?;
+    Ok(Frame::Simple("OK".to_string()))
+}
+
+async fn handle_get(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    match session.db().get(session.namespace(), &key)? {
+        Some(value) => Ok(Frame::Bulk(value)),
+        None => Ok(Frame::Null),
     }
 }
 
+/// Handles `SET key value [NX|XX] [GET] [EX|PX|EXAT|PXAT time|KEEPTTL]`.
+///
+/// Replies `OK`, or `Null` if `NX` or `XX` prevented the write. With `GET`
+/// the previous value is returned instead.
+async fn handle_set(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let value = parse.next_bytes()?;
 
-async fn handle_get(parse: &mut Parse, db: &Db) -> crate::Result<Frame> {
-    println!("Attempting to handle GET command");  // Debug print
-    if let Ok(key) = parse.next_string() {
-        println!("Parsed key for GET: {}", key);  // Debug print
-        parse.finish()?;
+    let mut options = SetOptions::default();
+    let mut expiration = None;
+    loop {
+        let option = match parse.next_string() {
+            Ok(option) => option.to_uppercase(),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        };
 
-        match db.get(&key) {
-            Some(value) => {
-                println!("Found value for key '{}'", key);  // Debug print
-                Ok(Frame::Bulk(value))
-            },
-            None => {
-                println!("No value found for key '{}'", key);  // Debug print
-                Ok(Frame::Null)
-            },
+        match option.as_str() {
+            "NX" if !options.only_existing => options.only_new = true,
+            "XX" if !options.only_new => options.only_existing = true,
+            "GET" => options.get = true,
+            "KEEPTTL" if expiration.is_none() => expiration = Some(Expiration::Keep),
+            "EX" | "PX" | "EXAT" | "PXAT" if expiration.is_none() => {
+                expiration = Some(parse_expire_time(parse, &option, "set")?);
+            }
+            _ => return Err("ERR syntax error".into()),
         }
+    }
+    options.expiration = expiration.unwrap_or(Expiration::Clear);
+
+    let (written, previous) = session.db().set_with_options(session.namespace(), key, value, options)?;
+    if options.get {
+        return Ok(previous.map_or(Frame::Null, Frame::Bulk));
+    }
+    if written {
+        Ok(Frame::Simple("OK".to_string()))
     } else {
-        println!("Failed to parse key for GET command");  // Debug print
-        Err("Failed to parse key for GET command".into())
+        Ok(Frame::Null)
+    }
+}
+
+/// Parse the amount following an `EX`, `PX`, `EXAT` or `PXAT` option into
+/// a deadline.
+fn parse_expire_time(parse: &mut Parse, option: &str, command: &str) -> crate::Result<Expiration> {
+    let invalid = || format!("ERR invalid expire time in '{}' command", command);
+
+    let amount = parse.next_signed()?;
+    if amount <= 0 {
+        return Err(invalid().into());
     }
+
+    let when = match option {
+        "EX" => amount.checked_mul(1000).and_then(deadline_after),
+        "PX" => deadline_after(amount),
+        "EXAT" => amount.checked_mul(1000).and_then(deadline_at_unix),
+        _ => deadline_at_unix(amount),
+    };
+    Ok(Expiration::At(when.ok_or_else(invalid)?))
+}
+
+async fn handle_setnx(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let value = parse.next_bytes()?;
+    parse.finish()?;
+
+    let options = SetOptions { only_new: true, ..SetOptions::default() };
+    let (written, _) = session.db().set_with_options(session.namespace(), key, value, options)?;
+    Ok(Frame::Integer(written as i64))
 }
 
-async fn handle_set(parse: &mut Parse, db:  &Arc<Db>) -> crate::Result<Frame> {
-    let key = parse.next_string()?;
+async fn handle_getset(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
     let value = parse.next_bytes()?;
     parse.finish()?;
-    db.set(key, value);
+
+    let options = SetOptions { get: true, ..SetOptions::default() };
+    let (_, previous) = session.db().set_with_options(session.namespace(), key, value, options)?;
+    Ok(previous.map_or(Frame::Null, Frame::Bulk))
+}
+
+async fn handle_getdel(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    let value = session.db().getdel(session.namespace(), &key)?;
+    Ok(value.map_or(Frame::Null, Frame::Bulk))
+}
+
+/// Handles `GETEX key [EX|PX|EXAT|PXAT time|PERSIST]`.
+async fn handle_getex(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+
+    let expiration = match parse.next_string() {
+        Ok(option) => match option.to_uppercase().as_str() {
+            "PERSIST" => Expiration::Clear,
+            option @ ("EX" | "PX" | "EXAT" | "PXAT") => parse_expire_time(parse, option, "getex")?,
+            _ => return Err("ERR syntax error".into()),
+        },
+        Err(ParseError::EndOfStream) => Expiration::Keep,
+        Err(e) => return Err(e.into()),
+    };
+    if parse.finish().is_err() {
+        return Err("ERR syntax error".into());
+    }
+
+    let value = session.db().getex(session.namespace(), &key, expiration)?;
+    Ok(value.map_or(Frame::Null, Frame::Bulk))
+}
+
+async fn handle_mget(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let keys = remaining_keys(parse, 1, "MGET")?;
+
+    Ok(optional_bulks(session.db().mget(session.namespace(), &keys)))
+}
+
+/// Handles `MSET` and `MSETNX`, which only sets the keys if none of them
+/// exists.
+async fn handle_mset(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let mut pairs = Vec::new();
+    loop {
+        let key = match parse.next_bytes() {
+            Ok(key) => key,
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let value = parse.next_bytes().map_err(|_| wrong_arity(command))?;
+        pairs.push((key, value));
+    }
+    if pairs.is_empty() {
+        return Err(wrong_arity(command));
+    }
+
+    if command == "MSETNX" {
+        let written = session.db().msetnx(session.namespace(), pairs);
+        return Ok(Frame::Integer(written as i64));
+    }
+    session.db().mset(session.namespace(), pairs);
     Ok(Frame::Simple("OK".to_string()))
 }
 
+/// Handles `INCR`, `DECR`, `INCRBY` and `DECRBY`.
+async fn handle_incr(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let delta = match command {
+        "INCR" => 1,
+        "DECR" => -1,
+        "INCRBY" => parse.next_signed()?,
+        _ => parse
+            .next_signed()?
+            .checked_neg()
+            .ok_or("ERR decrement would overflow")?,
+    };
+    parse.finish()?;
+
+    let updated = session.db().incr_by(session.namespace(), &key, delta)?;
+    Ok(Frame::Integer(updated))
+}
+
+async fn handle_incrbyfloat(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let delta = parse.next_float()?;
+    parse.finish()?;
+    if !delta.is_finite() {
+        return Err("ERR increment would produce NaN or Infinity".into());
+    }
+
+    let updated = session.db().incr_by_float(session.namespace(), &key, delta)?;
+    Ok(Frame::Bulk(updated))
+}
+
+async fn handle_append(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let value = parse.next_bytes()?;
+    parse.finish()?;
+
+    let len = session.db().append(session.namespace(), &key, &value)?;
+    Ok(Frame::Integer(len as i64))
+}
+
+async fn handle_strlen(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    let len = session.db().strlen(session.namespace(), &key)?;
+    Ok(Frame::Integer(len as i64))
+}
+
+/// Handles `GETRANGE` and its deprecated alias `SUBSTR`.
+async fn handle_getrange(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let start = parse.next_signed()?;
+    let end = parse.next_signed()?;
+    parse.finish()?;
+
+    Ok(Frame::Bulk(session.db().getrange(session.namespace(), &key, start, end)?))
+}
+
+async fn handle_setrange(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let offset = parse.next_signed()?;
+    let value = parse.next_bytes()?;
+    parse.finish()?;
+    if offset < 0 {
+        return Err("ERR offset is out of range".into());
+    }
+
+    let len = session.db().setrange(session.namespace(), &key, offset as usize, &value)?;
+    Ok(Frame::Integer(len as i64))
+}
+
 async fn handle_ping() -> crate::Result<Frame> {
     Ok(Frame::Simple("PONG".to_string()))
 }
 
-async fn handle_exists(parse: &mut Parse, db: &Arc<Db>) -> crate::Result<Frame> {
-    let key = parse.next_string()?;
+async fn handle_exists(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
     let _ = parse.finish();
-    if db.exists(&key) {
+    if session.db().exists(session.namespace(), &key) {
         Ok(Frame::Integer(1))
     } else {
         Ok(Frame::Integer(0))
     }
 }
 
-async fn handle_rpush(parse: &mut Parse, db: &Arc<Db>) -> crate::Result<Frame> {
-    if let Ok(key) = parse.next_string() {
-        if let Ok(value) = parse.next_bytes() {
-            parse.finish()?;
-            match db.rpush(key, value) {
-                Ok(len) => Ok(Frame::Integer(len as u64)),
-                Err(e) => Err(e.into()),
-            }
-        } else {
-            Err("Failed to parse value for RPUSH command".into())
-        }
+/// Handles `DEL` and `UNLINK`. Values are small enough that there is no
+/// point in freeing them in the background, so both delete synchronously.
+async fn handle_del(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let keys = remaining_keys(parse, 1, command)?;
+
+    let removed = session.db().del(session.namespace(), &keys);
+    Ok(Frame::Integer(removed as i64))
+}
+
+async fn handle_type(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    let type_name = session.db().type_of(session.namespace(), &key).unwrap_or("none");
+    Ok(Frame::Simple(type_name.to_string()))
+}
+
+/// Handles `RENAME`, and `RENAMENX` which refuses to replace an existing
+/// destination.
+async fn handle_rename(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let source = parse.next_bytes()?;
+    let destination = parse.next_bytes()?;
+    parse.finish()?;
+
+    let renamed = session.db().rename(session.namespace(), &source, &destination, command == "RENAMENX")?;
+    if command == "RENAMENX" {
+        Ok(Frame::Integer(renamed as i64))
     } else {
-        Err("Failed to parse key for RPUSH command".into())
+        Ok(Frame::Simple("OK".to_string()))
     }
 }
 
+/// Parse a namespace index given as a command argument.
+fn parse_db_index(parse: &mut Parse, session: &Session) -> crate::Result<usize> {
+    let name = parse.next_string()?;
+    Ok(session.db().resolve_namespace(&name)?)
+}
 
-async fn handle_lpush(parse: &mut Parse, db: &Arc<Db>) -> crate::Result<Frame> {
-    if let Ok(key) = parse.next_string() {
-        if let Ok(value) = parse.next_bytes() {
-            parse.finish()?;
-            match db.lpush(key, value) {
-                Ok(len) => Ok(Frame::Integer(len as u64)),
-                Err(e) => Err(e.into()),
-            }
-        } else {
-            Err("Failed to parse value for LPUSH command".into())
+/// Handles `COPY source destination [DB index] [REPLACE]`.
+async fn handle_copy(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let source = parse.next_bytes()?;
+    let destination = parse.next_bytes()?;
+
+    let mut to = session.namespace();
+    let mut replace = false;
+    loop {
+        let option = match parse.next_string() {
+            Ok(option) => option.to_uppercase(),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        match option.as_str() {
+            "DB" => to = parse_db_index(parse, session)?,
+            "REPLACE" => replace = true,
+            _ => return Err("ERR syntax error".into()),
         }
-    } else {
-        Err("Failed to parse key for LPUSH command".into())
     }
+
+    let copied = session.db().copy(session.namespace(), &source, to, &destination, replace)?;
+    Ok(Frame::Integer(copied as i64))
 }
 
-async fn handle_blpop(parse: &mut Parse, db: &Arc<Db>, timeout: f64) -> crate::Result<Frame> {
-    let mut keys = Vec::new();
-    while let Ok(key) = parse.next_string() {
-        keys.push(key);
+async fn handle_move(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let to = parse_db_index(parse, session)?;
+    parse.finish()?;
+
+    let moved = session.db().move_key(session.namespace(), &key, to)?;
+    Ok(Frame::Integer(moved as i64))
+}
+
+async fn handle_dump(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    match session.db().dump(session.namespace(), &key) {
+        Some(payload) => Ok(Frame::Bulk(payload)),
+        None => Ok(Frame::Null),
     }
+}
 
-    if keys.is_empty() {
-        return Err("BLPOP requires at least one key".into());
+/// Handles `RESTORE key ttl payload [REPLACE] [ABSTTL] [IDLETIME seconds]
+/// [FREQ frequency]`. A `ttl` of 0 creates the key without an expiration.
+async fn handle_restore(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let ttl = parse.next_signed()?;
+    let payload = parse.next_bytes()?;
+    if ttl < 0 {
+        return Err("ERR Invalid TTL value, must be >= 0".into());
     }
 
-    let timeout_duration = Duration::from_secs_f64(timeout);
+    let mut options = RestoreOptions::default();
+    let mut absolute = false;
+    loop {
+        let option = match parse.next_string() {
+            Ok(option) => option.to_uppercase(),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        };
 
-    match db.blpop(keys, timeout_duration).await {
-        Some((key, value)) => Ok(Frame::Array(vec![Frame::Bulk(key.into()), Frame::Bulk(value)])),
-        None => Ok(Frame::Null),
+        match option.as_str() {
+            "REPLACE" => options.replace = true,
+            "ABSTTL" => absolute = true,
+            "IDLETIME" if options.frequency.is_none() => {
+                let idle = parse.next_signed()?;
+                if idle < 0 {
+                    return Err("ERR Invalid IDLETIME value, must be >= 0".into());
+                }
+                options.idle = Some(Duration::from_secs(idle as u64));
+            }
+            "FREQ" if options.idle.is_none() => {
+                let frequency = parse.next_signed()?;
+                if !(0..=255).contains(&frequency) {
+                    return Err("ERR Invalid FREQ value, must be >= 0 and <= 255".into());
+                }
+                options.frequency = Some(frequency as u8);
+            }
+            _ => return Err("ERR syntax error".into()),
+        }
+    }
+
+    if ttl > 0 {
+        let when = if absolute { deadline_at_unix(ttl) } else { deadline_after(ttl) };
+        options.expires_at = Some(when.ok_or("ERR Invalid TTL value, must be >= 0")?);
     }
+
+    session.db().restore(session.namespace(), key, payload, options)?;
+    Ok(Frame::Simple("OK".to_string()))
 }
 
+async fn handle_dbsize(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    parse.finish()?;
 
-async fn handle_brpop(parse: &mut Parse, db: &Arc<Db>, timeout: f64) -> crate::Result<Frame> {
-    let mut keys = Vec::new();
-    while let Ok(key) = parse.next_string() {
-        keys.push(key);
+    Ok(Frame::Integer(session.db().dbsize(session.namespace()) as i64))
+}
+
+/// Handles `FLUSHDB [ASYNC|SYNC]` and `FLUSHALL [ASYNC|SYNC]`. Flushing is
+/// always synchronous; the modes are accepted for compatibility.
+async fn handle_flush(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    match parse.next_string() {
+        Ok(mode) if mode.eq_ignore_ascii_case("ASYNC") || mode.eq_ignore_ascii_case("SYNC") => parse.finish()?,
+        Ok(_) => return Err("ERR syntax error".into()),
+        Err(ParseError::EndOfStream) => {}
+        Err(e) => return Err(e.into()),
     }
 
-    if keys.is_empty() {
-        return Err("BRPOP requires at least one key".into());
+    if command == "FLUSHALL" {
+        session.db().flushall();
+    } else {
+        session.db().flushdb(session.namespace());
     }
+    Ok(Frame::Simple("OK".to_string()))
+}
+
+async fn handle_swapdb(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let a = parse_db_index(parse, session)?;
+    let b = parse_db_index(parse, session)?;
+    parse.finish()?;
 
-    let timeout_duration = Duration::from_secs_f64(timeout);
+    session.db().swapdb(a, b);
+    Ok(Frame::Simple("OK".to_string()))
+}
+
+async fn handle_randomkey(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    parse.finish()?;
 
-    match db.brpop(keys, timeout_duration).await {
-        Some((key, value)) => Ok(Frame::Array(vec![Frame::Bulk(key.into()), Frame::Bulk(value)])),
+    match session.db().randomkey(session.namespace()) {
+        Some(key) => Ok(Frame::Bulk(key)),
         None => Ok(Frame::Null),
     }
 }
 
+/// Handles `EXPIRE`, `PEXPIRE`, `EXPIREAT` and `PEXPIREAT`, which differ only
+/// in the unit of the argument and whether it is relative or a unix time.
+async fn handle_expire(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let amount = parse.next_signed()?;
+
+    let condition = match parse.next_string() {
+        Ok(option) => match option.to_uppercase().as_str() {
+            "NX" => ExpireCondition::NoExpiry,
+            "XX" => ExpireCondition::HasExpiry,
+            "GT" => ExpireCondition::Greater,
+            "LT" => ExpireCondition::Less,
+            _ => return Err(format!("ERR Unsupported option {}", option).into()),
+        },
+        Err(ParseError::EndOfStream) => ExpireCondition::Always,
+        Err(e) => return Err(e.into()),
+    };
+    parse.finish()?;
+
+    let when = match command {
+        "EXPIRE" => amount.checked_mul(1000).and_then(deadline_after),
+        "PEXPIRE" => deadline_after(amount),
+        "EXPIREAT" => amount.checked_mul(1000).and_then(deadline_at_unix),
+        _ => deadline_at_unix(amount),
+    };
+    let when = when.ok_or_else(|| {
+        format!("ERR invalid expire time in '{}' command", command.to_lowercase())
+    })?;
+
+    let applied = session.db().expire_at(session.namespace(), &key, when, condition);
+    Ok(Frame::Integer(applied as i64))
+}
+
+/// Handles `TTL` and `PTTL`, which report the time left, and `EXPIRETIME`
+/// and `PEXPIRETIME`, which report the deadline as a unix time. Missing
+/// keys report `-2` and keys without a deadline report `-1`.
+async fn handle_ttl(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    let reply = match session.db().ttl(session.namespace(), &key) {
+        Ttl::Missing => -2,
+        Ttl::Persistent => -1,
+        Ttl::Expires(remaining) => match command {
+            "PTTL" => remaining.as_millis() as i64,
+            // Round to the nearest second, like Redis does.
+            "TTL" => ((remaining.as_millis() + 500) / 1000) as i64,
+            "PEXPIRETIME" => unix_deadline_millis(remaining),
+            _ => unix_deadline_millis(remaining) / 1000,
+        },
+    };
+    Ok(Frame::Integer(reply))
+}
+
+async fn handle_persist(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    let removed = session.db().persist(session.namespace(), &key);
+    Ok(Frame::Integer(removed as i64))
+}
+
+/// Deadline `millis` milliseconds from now. Non-positive values yield a
+/// deadline that has already passed. Returns `None` on overflow.
+fn deadline_after(millis: i64) -> Option<Instant> {
+    let now = Instant::now();
+    if millis <= 0 {
+        return Some(now);
+    }
+    now.checked_add(Duration::from_millis(millis as u64))
+}
+
+/// Deadline at the given unix time in milliseconds. It is located to the
+/// microsecond, so that `PEXPIRETIME` reports the same time back.
+fn deadline_at_unix(unix_millis: i64) -> Option<Instant> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_micros() as i64)
+        .unwrap_or(0);
+    let micros = unix_millis.checked_mul(1000)?.checked_sub(now)?;
+    if micros <= 0 {
+        return Some(Instant::now());
+    }
+    Instant::now().checked_add(Duration::from_micros(micros as u64))
+}
+
+/// The unix time in milliseconds `remaining` from now. Deadlines are kept
+/// relative to the monotonic clock, so this rounds to the nearest
+/// millisecond to get back the one the deadline was set at.
+fn unix_deadline_millis(remaining: Duration) -> i64 {
+    let micros = (SystemTime::now() + remaining)
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_micros() as i64)
+        .unwrap_or(0);
+    (micros + 500) / 1000
+}
+
+/// Handles `LPUSH`, `RPUSH` and their `X` variants, which only push onto
+/// lists that already exist.
+async fn handle_push(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let values = remaining_bytes(parse, 1, command)?;
+
+    let db = session.db();
+    let len = match command {
+        "LPUSH" => db.lpush(session.namespace(), key, values)?,
+        "RPUSH" => db.rpush(session.namespace(), key, values)?,
+        "LPUSHX" => db.lpushx(session.namespace(), key, values)?,
+        _ => db.rpushx(session.namespace(), key, values)?,
+    };
+    Ok(Frame::Integer(len as i64))
+}
+
+/// Handles `LPOP key [count]` and `RPOP key [count]`. Without a count a
+/// single element (or `Null`) is returned rather than an array.
+async fn handle_pop(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let count = optional_count(parse)?;
+    if count.is_some_and(|count| count < 0) {
+        return Err("ERR value is out of range, must be positive".into());
+    }
+
+    let db = session.db();
+    let n = count.unwrap_or(1) as usize;
+    let popped = match command {
+        "LPOP" => db.lpop(session.namespace(), &key, n)?,
+        _ => db.rpop(session.namespace(), &key, n)?,
+    };
+    match (popped, count) {
+        (None, _) => Ok(Frame::Null),
+        (Some(popped), Some(_)) => Ok(bulks(popped)),
+        (Some(popped), None) => Ok(popped.into_iter().next().map_or(Frame::Null, Frame::Bulk)),
+    }
+}
+
+async fn handle_blpop(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let (keys, timeout) = parse_blocking_args(parse, "BLPOP")?;
+
+    match session.db().blpop(session.namespace(), keys, timeout).await? {
+        Some((key, value)) => Ok(Frame::Array(vec![Frame::Bulk(key), Frame::Bulk(value)])),
+        None => Ok(Frame::Null),
+    }
+}
+
+
+async fn handle_brpop(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let (keys, timeout) = parse_blocking_args(parse, "BRPOP")?;
+
+    match session.db().brpop(session.namespace(), keys, timeout).await? {
+        Some((key, value)) => Ok(Frame::Array(vec![Frame::Bulk(key), Frame::Bulk(value)])),
+        None => Ok(Frame::Null),
+    }
+}
+
+/// Parse the `key [key ...] timeout` arguments shared by the blocking pops.
+/// The timeout is given in (possibly fractional) seconds; zero blocks forever.
+fn parse_blocking_args(parse: &mut Parse, command: &str) -> crate::Result<(Vec<Bytes>, Duration)> {
+    let mut keys = Vec::new();
+    while let Ok(key) = parse.next_bytes() {
+        keys.push(key);
+    }
+
+    let timeout = match keys.pop() {
+        Some(timeout) if !keys.is_empty() => timeout,
+        _ => return Err(format!("{} requires at least one key and a timeout", command).into()),
+    };
+
+    Ok((keys, parse_timeout(parse::text(&timeout)?)?))
+}
+
+/// Parse a blocking timeout in (possibly fractional) seconds.
+fn parse_timeout(timeout: &str) -> crate::Result<Duration> {
+    let timeout: f64 = timeout
+        .parse()
+        .map_err(|_| "ERR timeout is not a float or out of range")?;
+    if !timeout.is_finite() || timeout < 0.0 {
+        return Err("ERR timeout is negative".into());
+    }
+
+    Duration::try_from_secs_f64(timeout).map_err(|_| "ERR timeout is out of range".into())
+}
+
+async fn handle_llen(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    let len = session.db().llen(session.namespace(), &key)?;
+    Ok(Frame::Integer(len as i64))
+}
+
+async fn handle_lrange(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let start = parse.next_signed()?;
+    let stop = parse.next_signed()?;
+    parse.finish()?;
+
+    Ok(bulks(session.db().lrange(session.namespace(), &key, start, stop)?))
+}
+
+async fn handle_lindex(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let index = parse.next_signed()?;
+    parse.finish()?;
+
+    match session.db().lindex(session.namespace(), &key, index)? {
+        Some(value) => Ok(Frame::Bulk(value)),
+        None => Ok(Frame::Null),
+    }
+}
+
+async fn handle_lset(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let index = parse.next_signed()?;
+    let value = parse.next_bytes()?;
+    parse.finish()?;
+
+    session.db().lset(session.namespace(), &key, index, value)?;
+    Ok(Frame::Simple("OK".to_string()))
+}
+
+async fn handle_lrem(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let count = parse.next_signed()?;
+    let element = parse.next_bytes()?;
+    parse.finish()?;
+
+    let removed = session.db().lrem(session.namespace(), &key, count, &element)?;
+    Ok(Frame::Integer(removed as i64))
+}
+
+async fn handle_ltrim(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let start = parse.next_signed()?;
+    let stop = parse.next_signed()?;
+    parse.finish()?;
+
+    session.db().ltrim(session.namespace(), &key, start, stop)?;
+    Ok(Frame::Simple("OK".to_string()))
+}
+
+/// Handles `LINSERT key BEFORE|AFTER pivot element`.
+async fn handle_linsert(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let after = match parse.next_string()?.to_uppercase().as_str() {
+        "BEFORE" => false,
+        "AFTER" => true,
+        _ => return Err("ERR syntax error".into()),
+    };
+    let pivot = parse.next_bytes()?;
+    let element = parse.next_bytes()?;
+    parse.finish()?;
+
+    let len = session.db().linsert(session.namespace(), &key, after, &pivot, element)?;
+    Ok(Frame::Integer(len))
+}
+
+/// Handles `LPOS key element [RANK rank] [COUNT count] [MAXLEN len]`.
+/// Without `COUNT` a single index (or `Null`) is returned rather than an
+/// array.
+async fn handle_lpos(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let element = parse.next_bytes()?;
+
+    let mut rank = 1;
+    let mut count = None;
+    let mut max_len = 0;
+    loop {
+        let option = match parse.next_string() {
+            Ok(option) => option.to_uppercase(),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        match option.as_str() {
+            "RANK" => {
+                rank = parse.next_signed()?;
+                if rank == 0 {
+                    return Err("ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list".into());
+                }
+            }
+            "COUNT" => {
+                let n = parse.next_signed()?;
+                if n < 0 {
+                    return Err("ERR COUNT can't be negative".into());
+                }
+                count = Some(n as usize);
+            }
+            "MAXLEN" => {
+                let n = parse.next_signed()?;
+                if n < 0 {
+                    return Err("ERR MAXLEN can't be negative".into());
+                }
+                max_len = n as usize;
+            }
+            _ => return Err("ERR syntax error".into()),
+        }
+    }
+
+    let found = session.db().lpos(session.namespace(), &key, &element, rank, count.unwrap_or(1), max_len)?;
+    match count {
+        Some(_) => Ok(Frame::Array(found.into_iter().map(|index| Frame::Integer(index as i64)).collect())),
+        None => Ok(found.first().map_or(Frame::Null, |index| Frame::Integer(*index as i64))),
+    }
+}
+
+fn parse_end(parse: &mut Parse) -> crate::Result<End> {
+    match parse.next_string()?.to_uppercase().as_str() {
+        "LEFT" => Ok(End::Front),
+        "RIGHT" => Ok(End::Back),
+        _ => Err("ERR syntax error".into()),
+    }
+}
+
+/// Handles `LMOVE source destination LEFT|RIGHT LEFT|RIGHT` and its
+/// deprecated form `RPOPLPUSH source destination`.
+async fn handle_lmove(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let source = parse.next_bytes()?;
+    let destination = parse.next_bytes()?;
+    let (from, to) = match command {
+        "LMOVE" => (parse_end(parse)?, parse_end(parse)?),
+        _ => (End::Back, End::Front),
+    };
+    parse.finish()?;
+
+    match session.db().lmove(session.namespace(), &source, &destination, from, to)? {
+        Some(value) => Ok(Frame::Bulk(value)),
+        None => Ok(Frame::Null),
+    }
+}
+
+/// Handles `BLMOVE source destination LEFT|RIGHT LEFT|RIGHT timeout` and its
+/// deprecated form `BRPOPLPUSH source destination timeout`.
+async fn handle_blmove(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let source = parse.next_bytes()?;
+    let destination = parse.next_bytes()?;
+    let (from, to) = match command {
+        "BLMOVE" => (parse_end(parse)?, parse_end(parse)?),
+        _ => (End::Back, End::Front),
+    };
+    let timeout = parse_timeout(&parse.next_string()?)?;
+    parse.finish()?;
+
+    match session.db().blmove(session.namespace(), source, destination, from, to, timeout).await? {
+        Some(value) => Ok(Frame::Bulk(value)),
+        None => Ok(Frame::Null),
+    }
+}
+
+/// Collect every remaining argument. Fails unless at least `min` remain.
+fn remaining_bytes(parse: &mut Parse, min: usize, command: &str) -> crate::Result<Vec<Bytes>> {
+    let mut args = Vec::new();
+    loop {
+        match parse.next_bytes() {
+            Ok(arg) => args.push(arg),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if args.len() < min {
+        return Err(wrong_arity(command));
+    }
+    Ok(args)
+}
+
+fn wrong_arity(command: &str) -> crate::Error {
+    format!("ERR wrong number of arguments for '{}' command", command.to_lowercase()).into()
+}
+
+/// Bulk replies for each value, `Null` for missing ones.
+fn optional_bulks(values: Vec<Option<Bytes>>) -> Frame {
+    Frame::Array(values.into_iter().map(|value| value.map_or(Frame::Null, Frame::Bulk)).collect())
+}
+
+/// Handles `HSET` and its deprecated alias `HMSET`, which replies `OK`
+/// instead of the number of new fields.
+async fn handle_hset(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let args = remaining_bytes(parse, 2, command)?;
+    if args.len() % 2 != 0 {
+        return Err(wrong_arity(command));
+    }
+
+    let fields = args.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+    let added = session.db().hset(session.namespace(), &key, fields)?;
+
+    if command == "HMSET" {
+        Ok(Frame::Simple("OK".to_string()))
+    } else {
+        Ok(Frame::Integer(added as i64))
+    }
+}
+
+async fn handle_hsetnx(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let field = parse.next_bytes()?;
+    let value = parse.next_bytes()?;
+    parse.finish()?;
+
+    let set = session.db().hsetnx(session.namespace(), &key, field, value)?;
+    Ok(Frame::Integer(set as i64))
+}
+
+async fn handle_hget(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let field = parse.next_bytes()?;
+    parse.finish()?;
+
+    match session.db().hget(session.namespace(), &key, &field)? {
+        Some(value) => Ok(Frame::Bulk(value)),
+        None => Ok(Frame::Null),
+    }
+}
+
+async fn handle_hmget(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let fields = remaining_bytes(parse, 1, "HMGET")?;
+
+    let values = session.db().hmget(session.namespace(), &key, &fields)?;
+    Ok(optional_bulks(values))
+}
+
+/// Handles `HGETALL`, `HKEYS` and `HVALS`, which differ only in which halves
+/// of each field/value pair they reply with.
+async fn handle_hgetall(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    let pairs = session.db().hgetall(session.namespace(), &key)?;
+    let reply = pairs
+        .into_iter()
+        .flat_map(|(field, value)| match command {
+            "HKEYS" => vec![Frame::Bulk(field)],
+            "HVALS" => vec![Frame::Bulk(value)],
+            _ => vec![Frame::Bulk(field), Frame::Bulk(value)],
+        })
+        .collect();
+    Ok(Frame::Array(reply))
+}
+
+async fn handle_hdel(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let fields = remaining_bytes(parse, 1, "HDEL")?;
+
+    let removed = session.db().hdel(session.namespace(), &key, &fields)?;
+    Ok(Frame::Integer(removed as i64))
+}
+
+async fn handle_hexists(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let field = parse.next_bytes()?;
+    parse.finish()?;
+
+    let exists = session.db().hget(session.namespace(), &key, &field)?.is_some();
+    Ok(Frame::Integer(exists as i64))
+}
+
+async fn handle_hlen(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    let len = session.db().hlen(session.namespace(), &key)?;
+    Ok(Frame::Integer(len as i64))
+}
+
+async fn handle_hstrlen(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let field = parse.next_bytes()?;
+    parse.finish()?;
+
+    let value = session.db().hget(session.namespace(), &key, &field)?;
+    Ok(Frame::Integer(value.map_or(0, |value| value.len()) as i64))
+}
+
+async fn handle_hincrby(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let field = parse.next_bytes()?;
+    let delta = parse.next_signed()?;
+    parse.finish()?;
+
+    let updated = session.db().hincrby(session.namespace(), &key, field, delta)?;
+    Ok(Frame::Integer(updated))
+}
+
+async fn handle_hincrbyfloat(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let field = parse.next_bytes()?;
+    let delta = parse.next_float()?;
+    parse.finish()?;
+
+    let updated = session.db().hincrbyfloat(session.namespace(), &key, field, delta)?;
+    Ok(Frame::Bulk(updated))
+}
+
+/// Handles `HRANDFIELD key [count [WITHVALUES]]`. Without a count a single
+/// field (or `Null`) is returned rather than an array.
+async fn handle_hrandfield(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+
+    let count = match parse.next_signed() {
+        Ok(count) => Some(count),
+        Err(ParseError::EndOfStream) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let with_values = match parse.next_string() {
+        Ok(option) if count.is_some() && option.eq_ignore_ascii_case("WITHVALUES") => true,
+        Ok(_) => return Err("ERR syntax error".into()),
+        Err(ParseError::EndOfStream) => false,
+        Err(e) => return Err(e.into()),
+    };
+    parse.finish()?;
+
+    // As in Redis, the reply must be able to hold `count` fields and values.
+    let max = if with_values { i64::MAX / 2 } else { i64::MAX };
+    if count.is_some_and(|count| count.unsigned_abs() > max as u64) {
+        return Err("ERR value is out of range".into());
+    }
+
+    let pairs = session.db().hrandfield(session.namespace(), &key, count.unwrap_or(1))?;
+    if count.is_none() {
+        return Ok(pairs
+            .into_iter()
+            .next()
+            .map_or(Frame::Null, |(field, _)| Frame::Bulk(field)));
+    }
+
+    let reply = pairs
+        .into_iter()
+        .flat_map(|(field, value)| {
+            if with_values {
+                vec![Frame::Bulk(field), Frame::Bulk(value)]
+            } else {
+                vec![Frame::Bulk(field)]
+            }
+        })
+        .collect();
+    Ok(Frame::Array(reply))
+}
+
+/// Options shared by `SCAN` and the collection scans.
+struct ScanOptions {
+    /// Only return elements matching this glob (`MATCH`).
+    pattern: Option<Bytes>,
+    /// Roughly how many elements to visit per call (`COUNT`).
+    count: usize,
+    /// Only return keys of this type (`TYPE`, `SCAN` only).
+    type_name: Option<String>,
+}
+
+/// Parse `[MATCH pattern] [COUNT count]`, plus `[TYPE type]` when
+/// `allow_type` is set.
+fn parse_scan_options(parse: &mut Parse, allow_type: bool) -> crate::Result<ScanOptions> {
+    let mut options = ScanOptions { pattern: None, count: 10, type_name: None };
+    loop {
+        let option = match parse.next_string() {
+            Ok(option) => option.to_uppercase(),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        match option.as_str() {
+            "MATCH" => options.pattern = Some(parse.next_bytes()?),
+            "COUNT" => {
+                options.count = parse.next_int().map_err(|_| "ERR value is not an integer or out of range")? as usize;
+                if options.count == 0 {
+                    return Err("ERR syntax error".into());
+                }
+            }
+            "TYPE" if allow_type => options.type_name = Some(parse.next_string()?.to_lowercase()),
+            _ => return Err("ERR syntax error".into()),
+        }
+    }
+    Ok(options)
+}
+
+/// Handles `SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]`.
+async fn handle_scan(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let cursor = parse.next_int().map_err(|_| "ERR invalid cursor")?;
+    let options = parse_scan_options(parse, true)?;
+
+    let (next, keys) = session.db().scan(
+        session.namespace(),
+        cursor,
+        options.count,
+        options.pattern.as_deref(),
+        options.type_name.as_deref(),
+    );
+    Ok(Frame::Array(vec![Frame::Bulk(Bytes::from(next.to_string())), bulks(keys)]))
+}
+
+async fn handle_keys(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let pattern = parse.next_bytes()?;
+    parse.finish()?;
+
+    let keys = session.db().keys(session.namespace(), Some(&pattern));
+    Ok(bulks(keys))
+}
+
+/// Handles `HSCAN`, `SSCAN` and `ZSCAN`: `key cursor [MATCH pattern]
+/// [COUNT count]`.
+///
+/// Hash fields and sorted set members are followed by their values and
+/// scores.
+async fn handle_collection_scan(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let cursor = parse.next_int().map_err(|_| "ERR invalid cursor")?;
+    let options = parse_scan_options(parse, false)?;
+
+    let db = session.db();
+    let (next, elements): (u64, Vec<(Bytes, Option<Bytes>)>) = match command {
+        "HSCAN" => {
+            let (next, fields) = db.hscan(session.namespace(), &key, cursor, options.count)?;
+            (next, fields.into_iter().map(|(field, value)| (field, Some(value))).collect())
+        }
+        "SSCAN" => {
+            let (next, members) = db.sscan(session.namespace(), &key, cursor, options.count)?;
+            (next, members.into_iter().map(|member| (member, None)).collect())
+        }
+        _ => {
+            let (next, members) = db.zscan(session.namespace(), &key, cursor, options.count)?;
+            let members = members
+                .into_iter()
+                .map(|(member, score)| (member, Some(Bytes::from(format_float(score)))))
+                .collect();
+            (next, members)
+        }
+    };
+
+    let reply = elements
+        .into_iter()
+        .filter(|(element, _)| options.pattern.as_ref().is_none_or(|pattern| glob::matches(pattern, element)))
+        .flat_map(|(element, extra)| std::iter::once(Frame::Bulk(element)).chain(extra.map(Frame::Bulk)))
+        .collect();
+    Ok(Frame::Array(vec![Frame::Bulk(Bytes::from(next.to_string())), Frame::Array(reply)]))
+}
+
+/// Collect every remaining argument as a key. Fails unless at least `min`
+/// remain.
+fn remaining_keys(parse: &mut Parse, min: usize, command: &str) -> crate::Result<Vec<Bytes>> {
+    let mut keys = Vec::new();
+    loop {
+        match parse.next_bytes() {
+            Ok(key) => keys.push(key),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if keys.len() < min {
+        return Err(wrong_arity(command));
+    }
+    Ok(keys)
+}
+
+fn bulks(values: Vec<Bytes>) -> Frame {
+    Frame::Array(values.into_iter().map(Frame::Bulk).collect())
+}
+
+/// Parse an optional trailing count argument.
+fn optional_count(parse: &mut Parse) -> crate::Result<Option<i64>> {
+    let count = match parse.next_signed() {
+        Ok(count) => Some(count),
+        Err(ParseError::EndOfStream) => None,
+        Err(e) => return Err(e.into()),
+    };
+    parse.finish()?;
+    Ok(count)
+}
+
+async fn handle_sadd(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let members = remaining_bytes(parse, 1, "SADD")?;
+
+    let added = session.db().sadd(session.namespace(), &key, members)?;
+    Ok(Frame::Integer(added as i64))
+}
+
+async fn handle_srem(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let members = remaining_bytes(parse, 1, "SREM")?;
+
+    let removed = session.db().srem(session.namespace(), &key, &members)?;
+    Ok(Frame::Integer(removed as i64))
+}
+
+/// Handles `SISMEMBER key member` and `SMISMEMBER key member [member ...]`.
+async fn handle_smismember(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let members = remaining_bytes(parse, 1, command)?;
+    if command == "SISMEMBER" && members.len() != 1 {
+        return Err(wrong_arity(command));
+    }
+
+    let found = session.db().smismember(session.namespace(), &key, &members)?;
+    if command == "SISMEMBER" {
+        return Ok(Frame::Integer(found[0] as i64));
+    }
+    Ok(Frame::Array(found.into_iter().map(|found| Frame::Integer(found as i64)).collect()))
+}
+
+async fn handle_smembers(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    Ok(bulks(session.db().smembers(session.namespace(), &key)?))
+}
+
+async fn handle_scard(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    let len = session.db().scard(session.namespace(), &key)?;
+    Ok(Frame::Integer(len as i64))
+}
+
+/// Handles `SPOP key [count]`. Without a count a single member (or `Null`)
+/// is returned rather than an array.
+async fn handle_spop(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let count = optional_count(parse)?;
+    if count.is_some_and(|count| count < 0) {
+        return Err("ERR value is out of range, must be positive".into());
+    }
+
+    let popped = session.db().spop(session.namespace(), &key, count.unwrap_or(1) as usize)?;
+    match count {
+        Some(_) => Ok(bulks(popped)),
+        None => Ok(popped.into_iter().next().map_or(Frame::Null, Frame::Bulk)),
+    }
+}
+
+/// Handles `SRANDMEMBER key [count]`. Without a count a single member (or
+/// `Null`) is returned rather than an array.
+async fn handle_srandmember(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let count = optional_count(parse)?;
+    if count == Some(i64::MIN) {
+        return Err("ERR value is out of range".into());
+    }
+
+    let members = session.db().srandmember(session.namespace(), &key, count.unwrap_or(1))?;
+    match count {
+        Some(_) => Ok(bulks(members)),
+        None => Ok(members.into_iter().next().map_or(Frame::Null, Frame::Bulk)),
+    }
+}
+
+async fn handle_smove(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let source = parse.next_bytes()?;
+    let destination = parse.next_bytes()?;
+    let member = parse.next_bytes()?;
+    parse.finish()?;
+
+    let moved = session.db().smove(session.namespace(), &source, &destination, member)?;
+    Ok(Frame::Integer(moved as i64))
+}
+
+fn set_op(command: &str) -> SetOp {
+    match command.trim_end_matches("STORE") {
+        "SINTER" => SetOp::Inter,
+        "SUNION" => SetOp::Union,
+        _ => SetOp::Diff,
+    }
+}
+
+/// Handles `SINTER`, `SUNION` and `SDIFF`.
+async fn handle_set_algebra(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let keys = remaining_keys(parse, 1, command)?;
+
+    let members = session.db().set_algebra(session.namespace(), set_op(command), &keys)?;
+    Ok(bulks(members))
+}
+
+/// Handles `SINTERSTORE`, `SUNIONSTORE` and `SDIFFSTORE`.
+async fn handle_set_algebra_store(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let destination = parse.next_bytes()?;
+    let keys = remaining_keys(parse, 1, command)?;
+
+    let len = session.db().set_algebra_store(session.namespace(), set_op(command), destination, &keys)?;
+    Ok(Frame::Integer(len as i64))
+}
+
+/// Handles `SINTERCARD numkeys key [key ...] [LIMIT limit]`.
+async fn handle_sintercard(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let numkeys = parse.next_int().map_err(|_| "ERR numkeys should be greater than 0")?;
+    if numkeys == 0 {
+        return Err("ERR numkeys should be greater than 0".into());
+    }
+
+    let mut keys = Vec::new();
+    for _ in 0..numkeys {
+        keys.push(parse.next_bytes().map_err(|_| "ERR Number of keys can't be greater than number of args")?);
+    }
+
+    let mut limit = 0;
+    match parse.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("LIMIT") => {
+            limit = parse.next_int().map_err(|_| "ERR LIMIT can't be negative")?;
+        }
+        Ok(_) => return Err("ERR syntax error".into()),
+        Err(ParseError::EndOfStream) => {}
+        Err(e) => return Err(e.into()),
+    }
+    parse.finish()?;
+
+    let len = session.db().sintercard(session.namespace(), &keys, limit as usize)?;
+    Ok(Frame::Integer(len as i64))
+}
+
+fn score_frame(score: f64) -> Frame {
+    Frame::Bulk(Bytes::from(format_float(score)))
+}
+
+/// Members followed by their scores, flattened into one array when
+/// `with_scores` is set.
+fn scored_members(members: Vec<(Bytes, f64)>, with_scores: bool) -> Frame {
+    let mut reply = Vec::with_capacity(members.len() * if with_scores { 2 } else { 1 });
+    for (member, score) in members {
+        reply.push(Frame::Bulk(member));
+        if with_scores {
+            reply.push(score_frame(score));
+        }
+    }
+    Frame::Array(reply)
+}
+
+/// Parse a score range bound such as `1.5`, `(1.5` or `-inf`.
+fn parse_score_bound(bound: &str) -> crate::Result<ScoreBound> {
+    let (exclusive, value) = match bound.strip_prefix('(') {
+        Some(value) => (true, value),
+        None => (false, bound),
+    };
+
+    let value: f64 = value
+        .parse()
+        .ok()
+        .filter(|value: &f64| !value.is_nan())
+        .ok_or("ERR min or max is not a float")?;
+
+    Ok(if exclusive {
+        ScoreBound::Exclusive(value)
+    } else {
+        ScoreBound::Inclusive(value)
+    })
+}
+
+/// Parse a lexicographic range bound: `-`, `+`, `[member` or `(member`.
+fn parse_lex_bound(bound: Bytes) -> crate::Result<LexBound> {
+    match bound.first() {
+        Some(b'-') if bound.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if bound.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(bound.slice(1..))),
+        Some(b'(') => Ok(LexBound::Exclusive(bound.slice(1..))),
+        _ => Err("ERR min or max not valid string range item".into()),
+    }
+}
+
+fn parse_zset_score(score: &[u8]) -> crate::Result<f64> {
+    std::str::from_utf8(score)
+        .ok()
+        .and_then(|score| score.parse::<f64>().ok())
+        .filter(|score| !score.is_nan())
+        .ok_or_else(|| "ERR value is not a valid float".into())
+}
+
+/// Handles `ZADD key [NX|XX] [GT|LT] [CH] [INCR] score member [score member ...]`.
+async fn handle_zadd(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let args = remaining_bytes(parse, 2, "ZADD")?;
+
+    let mut options = ZAddOptions::default();
+    let mut changed = false;
+    let mut incr = false;
+
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.peek() {
+        match arg.to_ascii_uppercase().as_slice() {
+            b"NX" => options.only_new = true,
+            b"XX" => options.only_existing = true,
+            b"GT" => options.greater = true,
+            b"LT" => options.less = true,
+            b"CH" => changed = true,
+            b"INCR" => incr = true,
+            _ => break,
+        }
+        args.next();
+    }
+
+    if options.only_new && options.only_existing {
+        return Err("ERR XX and NX options at the same time are not compatible".into());
+    }
+    if [options.only_new, options.greater, options.less].iter().filter(|set| **set).count() > 1 {
+        return Err("ERR GT, LT, and/or NX options at the same time are not compatible".into());
+    }
+
+    let args: Vec<Bytes> = args.collect();
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return Err("ERR syntax error".into());
+    }
+
+    let mut members = Vec::with_capacity(args.len() / 2);
+    for pair in args.chunks(2) {
+        members.push((parse_zset_score(&pair[0])?, pair[1].clone()));
+    }
+
+    if incr {
+        if members.len() != 1 {
+            return Err("ERR INCR option supports a single increment-element pair".into());
+        }
+        let (delta, member) = members.pop().unwrap();
+        return match session.db().zincrby(session.namespace(), &key, options, delta, member)? {
+            Some(score) => Ok(score_frame(score)),
+            None => Ok(Frame::Null),
+        };
+    }
+
+    let (added, updated) = session.db().zadd(session.namespace(), &key, options, members)?;
+    Ok(Frame::Integer(if changed { added + updated } else { added } as i64))
+}
+
+async fn handle_zincrby(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let delta = parse_zset_score(&parse.next_bytes()?)?;
+    let member = parse.next_bytes()?;
+    parse.finish()?;
+
+    let score = session.db().zincrby(session.namespace(), &key, ZAddOptions::default(), delta, member)?;
+    Ok(score.map_or(Frame::Null, score_frame))
+}
+
+async fn handle_zrem(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let members = remaining_bytes(parse, 1, "ZREM")?;
+
+    let removed = session.db().zrem(session.namespace(), &key, &members)?;
+    Ok(Frame::Integer(removed as i64))
+}
+
+async fn handle_zcard(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    let len = session.db().zcard(session.namespace(), &key)?;
+    Ok(Frame::Integer(len as i64))
+}
+
+/// Handles `ZSCORE key member` and `ZMSCORE key member [member ...]`.
+async fn handle_zmscore(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let members = remaining_bytes(parse, 1, command)?;
+    if command == "ZSCORE" && members.len() != 1 {
+        return Err(wrong_arity(command));
+    }
+
+    let scores = session.db().zmscore(session.namespace(), &key, &members)?;
+    let mut scores = scores.into_iter().map(|score| score.map_or(Frame::Null, score_frame));
+    if command == "ZSCORE" {
+        return Ok(scores.next().unwrap_or(Frame::Null));
+    }
+    Ok(Frame::Array(scores.collect()))
+}
+
+/// Handles `ZRANK` and `ZREVRANK`, with the optional `WITHSCORE` flag.
+async fn handle_zrank(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let member = parse.next_bytes()?;
+    let with_score = match parse.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("WITHSCORE") => true,
+        Ok(_) => return Err("ERR syntax error".into()),
+        Err(ParseError::EndOfStream) => false,
+        Err(e) => return Err(e.into()),
+    };
+    parse.finish()?;
+
+    let rank = session.db().zrank(session.namespace(), &key, &member, command == "ZREVRANK")?;
+    match rank {
+        Some((rank, score)) if with_score => Ok(Frame::Array(vec![Frame::Integer(rank as i64), score_frame(score)])),
+        Some((rank, _)) => Ok(Frame::Integer(rank as i64)),
+        None => Ok(Frame::Null),
+    }
+}
+
+/// Handles `ZCOUNT key min max` and `ZLEXCOUNT key min max`.
+async fn handle_zcount(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let min = parse.next_bytes()?;
+    let max = parse.next_bytes()?;
+    parse.finish()?;
+
+    let range = if command == "ZLEXCOUNT" {
+        ZRange::Lex(parse_lex_bound(min)?, parse_lex_bound(max)?)
+    } else {
+        ZRange::Score(parse_score_bound(&String::from_utf8_lossy(&min))?, parse_score_bound(&String::from_utf8_lossy(&max))?)
+    };
+
+    let count = session.db().zcount(session.namespace(), &key, &range)?;
+    Ok(Frame::Integer(count as i64))
+}
+
+/// How the two range arguments of a `ZRANGE`-style command are interpreted.
+#[derive(Clone, Copy, PartialEq)]
+enum RangeKind {
+    Rank,
+    Score,
+    Lex,
+}
+
+/// Build a `ZRange` from the two range arguments, which for reversed score
+/// and lexicographic ranges are given high to low.
+fn parse_zrange(kind: RangeKind, first: Bytes, second: Bytes, reverse: bool) -> crate::Result<ZRange> {
+    let (low, high) = if reverse && kind != RangeKind::Rank { (second, first) } else { (first, second) };
+
+    match kind {
+        RangeKind::Rank => {
+            let rank = |arg: &Bytes| {
+                std::str::from_utf8(arg)
+                    .ok()
+                    .and_then(|arg| arg.parse::<i64>().ok())
+                    .ok_or("ERR value is not an integer or out of range")
+            };
+            Ok(ZRange::Rank(rank(&low)?, rank(&high)?))
+        }
+        RangeKind::Score => Ok(ZRange::Score(
+            parse_score_bound(&String::from_utf8_lossy(&low))?,
+            parse_score_bound(&String::from_utf8_lossy(&high))?,
+        )),
+        RangeKind::Lex => Ok(ZRange::Lex(parse_lex_bound(low)?, parse_lex_bound(high)?)),
+    }
+}
+
+/// Handles `ZRANGE key start stop [BYSCORE|BYLEX] [REV] [LIMIT offset count]
+/// [WITHSCORES]` as well as the older `ZREVRANGE`, `ZRANGEBYSCORE`,
+/// `ZREVRANGEBYSCORE`, `ZRANGEBYLEX` and `ZREVRANGEBYLEX` forms, which fix the
+/// range kind and direction in the command name.
+async fn handle_zrange(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let first = parse.next_bytes()?;
+    let second = parse.next_bytes()?;
+
+    let mut reverse = command.starts_with("ZREV");
+    let mut kind = if command.ends_with("BYSCORE") {
+        RangeKind::Score
+    } else if command.ends_with("BYLEX") {
+        RangeKind::Lex
+    } else {
+        RangeKind::Rank
+    };
+    let mut with_scores = false;
+    let mut limit = None;
+
+    loop {
+        let option = match parse.next_string() {
+            Ok(option) => option.to_uppercase(),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        match option.as_str() {
+            "BYSCORE" if command == "ZRANGE" => kind = RangeKind::Score,
+            "BYLEX" if command == "ZRANGE" => kind = RangeKind::Lex,
+            "REV" if command == "ZRANGE" => reverse = true,
+            "WITHSCORES" => with_scores = true,
+            "LIMIT" => {
+                let offset = parse.next_signed()?;
+                let count = parse.next_signed()?;
+                limit = Some((offset, count));
+            }
+            _ => return Err("ERR syntax error".into()),
+        }
+    }
+
+    if limit.is_some() && kind == RangeKind::Rank {
+        return Err("ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX".into());
+    }
+    if with_scores && kind == RangeKind::Lex {
+        return Err("ERR syntax error, WITHSCORES not supported in combination with BYLEX".into());
+    }
+
+    let range = parse_zrange(kind, first, second, reverse)?;
+
+    // A negative offset selects nothing.
+    let limit = match limit {
+        Some((offset, _)) if offset < 0 => return Ok(Frame::Array(Vec::new())),
+        Some((offset, count)) => Some((offset as usize, count)),
+        None => None,
+    };
+
+    let members = session.db().zrange(session.namespace(), &key, &range, reverse, limit)?;
+    Ok(scored_members(members, with_scores))
+}
+
+/// Handles `ZREMRANGEBYRANK`, `ZREMRANGEBYSCORE` and `ZREMRANGEBYLEX`.
+async fn handle_zremrange(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let first = parse.next_bytes()?;
+    let second = parse.next_bytes()?;
+    parse.finish()?;
+
+    let kind = match command {
+        "ZREMRANGEBYSCORE" => RangeKind::Score,
+        "ZREMRANGEBYLEX" => RangeKind::Lex,
+        _ => RangeKind::Rank,
+    };
+    let range = parse_zrange(kind, first, second, false)?;
+
+    let removed = session.db().zremrange(session.namespace(), &key, &range)?;
+    Ok(Frame::Integer(removed as i64))
+}
+
+/// Handles `ZPOPMIN key [count]` and `ZPOPMAX key [count]`.
+async fn handle_zpop(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let count = optional_count(parse)?;
+    if count.is_some_and(|count| count < 0) {
+        return Err("ERR value is out of range, must be positive".into());
+    }
+
+    let popped = session.db().zpop(session.namespace(), &key, count.unwrap_or(1) as usize, command == "ZPOPMAX")?;
+    Ok(scored_members(popped, true))
+}
+
+/// Handles `BZPOPMIN` and `BZPOPMAX`. Replies with the key, member and score
+/// of the popped member, or `Null` on timeout.
+async fn handle_bzpop(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let (keys, timeout) = parse_blocking_args(parse, command)?;
+
+    match session.db().bzpop(session.namespace(), keys, timeout, command == "BZPOPMAX").await? {
+        Some((key, member, score)) => Ok(Frame::Array(vec![Frame::Bulk(key), Frame::Bulk(member), score_frame(score)])),
+        None => Ok(Frame::Null),
+    }
+}
+
+/// Handles `ZUNIONSTORE`, `ZINTERSTORE` and `ZDIFFSTORE`:
+/// `destination numkeys key [key ...] [WEIGHTS weight ...] [AGGREGATE SUM|MIN|MAX]`.
+/// `ZDIFFSTORE` accepts neither option.
+async fn handle_zstore(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let destination = parse.next_bytes()?;
+    let numkeys = parse.next_int().map_err(|_| "ERR value is not an integer or out of range")? as usize;
+    if numkeys == 0 {
+        return Err(format!("ERR at least 1 input key is needed for '{}' command", command.to_lowercase()).into());
+    }
+
+    // `numkeys` comes from the client; the keys it promises may not be there.
+    let mut keys = Vec::new();
+    for _ in 0..numkeys {
+        keys.push(parse.next_bytes().map_err(|_| "ERR syntax error")?);
+    }
+
+    let mut weights = Vec::new();
+    let mut aggregate = Aggregate::Sum;
+    loop {
+        let option = match parse.next_string() {
+            Ok(option) => option.to_uppercase(),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        match option.as_str() {
+            "WEIGHTS" if command != "ZDIFFSTORE" => {
+                for _ in 0..numkeys {
+                    let weight = parse.next_bytes().map_err(|_| "ERR syntax error")?;
+                    weights.push(parse_zset_score(&weight).map_err(|_| "ERR weight value is not a float")?);
+                }
+            }
+            "AGGREGATE" if command != "ZDIFFSTORE" => {
+                aggregate = match parse.next_string()?.to_uppercase().as_str() {
+                    "SUM" => Aggregate::Sum,
+                    "MIN" => Aggregate::Min,
+                    "MAX" => Aggregate::Max,
+                    _ => return Err("ERR syntax error".into()),
+                };
+            }
+            _ => return Err("ERR syntax error".into()),
+        }
+    }
+
+    let op = match command {
+        "ZUNIONSTORE" => SetOp::Union,
+        "ZINTERSTORE" => SetOp::Inter,
+        _ => SetOp::Diff,
+    };
+
+    let len = session.db().zstore(session.namespace(), op, destination, &keys, &weights, aggregate)?;
+    Ok(Frame::Integer(len as i64))
+}
+
+fn parse_stream_id(id: &[u8]) -> crate::Result<StreamId> {
+    Ok(std::str::from_utf8(id)
+        .map_err(|_| "ERR Invalid stream ID specified as stream command argument")?
+        .parse::<StreamId>()?)
+}
+
+/// Parse one end of an `XRANGE` interval: `-`, `+`, an ID, or an ID
+/// prefixed with `(` to exclude it. A missing sequence number covers the
+/// whole millisecond.
+fn parse_range_id(arg: &[u8], end: bool) -> crate::Result<StreamId> {
+    let invalid = if end { "ERR invalid end ID for the interval" } else { "ERR invalid start ID for the interval" };
+    let arg = std::str::from_utf8(arg).map_err(|_| invalid)?;
+
+    match arg {
+        "-" => return Ok(StreamId::MIN),
+        "+" => return Ok(StreamId::MAX),
+        _ => {}
+    }
+
+    let (exclusive, arg) = match arg.strip_prefix('(') {
+        Some(arg) => (true, arg),
+        None => (false, arg),
+    };
+    let id = StreamId::parse_with_default(arg, if end { u64::MAX } else { 0 })
+        .ok_or("ERR Invalid stream ID specified as stream command argument")?;
+
+    match (exclusive, end) {
+        (false, _) => Ok(id),
+        (true, false) => Ok(id.next().ok_or(invalid)?),
+        (true, true) => Ok(id.prev().ok_or(invalid)?),
+    }
+}
+
+/// A stream entry as an array of its ID and flattened field/value pairs.
+fn stream_entry(entry: StreamEntry) -> Frame {
+    let (id, fields) = entry;
+    let fields = fields
+        .into_iter()
+        .flat_map(|(field, value)| [Frame::Bulk(field), Frame::Bulk(value)])
+        .collect();
+    Frame::Array(vec![Frame::Bulk(Bytes::from(id.to_string())), Frame::Array(fields)])
+}
+
+fn stream_entries(entries: Vec<StreamEntry>) -> Frame {
+    Frame::Array(entries.into_iter().map(stream_entry).collect())
+}
+
+/// Parse the trimming arguments that follow `MAXLEN` or `MINID`:
+/// `[=|~] threshold [LIMIT count]`.
+///
+/// Trimming is always exact; `~` is accepted for compatibility and is only
+/// needed to allow `LIMIT`.
+fn parse_trim(strategy: &[u8], args: &mut Peekable<impl Iterator<Item = Bytes>>) -> crate::Result<Trim> {
+    let mut approximate = false;
+    if let Some(operator) = args.next_if(|arg| arg.as_ref() == b"=" || arg.as_ref() == b"~") {
+        approximate = operator.as_ref() == b"~";
+    }
+
+    let threshold = args.next().ok_or("ERR syntax error")?;
+    let strategy = if strategy.eq_ignore_ascii_case(b"MAXLEN") {
+        let max = std::str::from_utf8(&threshold)
+            .ok()
+            .and_then(|max| max.parse::<i64>().ok())
+            .ok_or("ERR value is not an integer or out of range")?;
+        let max = usize::try_from(max).map_err(|_| "ERR The MAXLEN argument must be >= 0.")?;
+        TrimStrategy::MaxLen(max)
+    } else {
+        TrimStrategy::MinId(parse_stream_id(&threshold)?)
+    };
+
+    let mut limit = None;
+    if args.next_if(|arg| arg.eq_ignore_ascii_case(b"LIMIT")).is_some() {
+        let count = args.next().ok_or("ERR syntax error")?;
+        let count = std::str::from_utf8(&count)
+            .ok()
+            .and_then(|count| count.parse::<i64>().ok())
+            .ok_or("ERR value is not an integer or out of range")?;
+        let count = usize::try_from(count).map_err(|_| "ERR The LIMIT argument must be >= 0.")?;
+        if !approximate {
+            return Err("ERR syntax error, LIMIT cannot be used without the special ~ option".into());
+        }
+        // A zero limit means no limit.
+        limit = Some(count).filter(|count| *count > 0);
+    }
+
+    Ok(Trim { strategy, limit })
+}
+
+/// Handles `XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]]
+/// *|id field value [field value ...]`.
+async fn handle_xadd(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let mut args = remaining_bytes(parse, 3, "XADD")?.into_iter().peekable();
+
+    let mut no_create = false;
+    let mut trim = None;
+    while let Some(arg) = args.peek() {
+        if arg.eq_ignore_ascii_case(b"NOMKSTREAM") {
+            no_create = true;
+            args.next();
+        } else if arg.eq_ignore_ascii_case(b"MAXLEN") || arg.eq_ignore_ascii_case(b"MINID") {
+            let strategy = args.next().expect("peeked");
+            trim = Some(parse_trim(&strategy, &mut args)?);
+        } else {
+            break;
+        }
+    }
+
+    let id = args.next().ok_or("ERR syntax error")?;
+    let id = match id.as_ref() {
+        b"*" => XAddId::Auto,
+        id => match id.strip_suffix(b"-*") {
+            Some(ms) => XAddId::AutoSeq(
+                std::str::from_utf8(ms)
+                    .ok()
+                    .and_then(|ms| ms.parse().ok())
+                    .ok_or("ERR Invalid stream ID specified as stream command argument")?,
+            ),
+            None => XAddId::Explicit(parse_stream_id(id)?),
+        },
+    };
+
+    let args: Vec<Bytes> = args.collect();
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return Err(wrong_arity("XADD"));
+    }
+    let fields = args.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+
+    match session.db().xadd(session.namespace(), &key, id, fields, no_create, trim)? {
+        Some(id) => Ok(Frame::Bulk(Bytes::from(id.to_string()))),
+        None => Ok(Frame::Null),
+    }
+}
+
+async fn handle_xlen(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    parse.finish()?;
+
+    let len = session.db().xlen(session.namespace(), &key)?;
+    Ok(Frame::Integer(len as i64))
+}
+
+/// Handles `XRANGE key start end [COUNT count]` and `XREVRANGE key end start
+/// [COUNT count]`.
+async fn handle_xrange(parse: &mut Parse, session: &Session, command: &str) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let first = parse.next_bytes()?;
+    let second = parse.next_bytes()?;
+
+    let reverse = command == "XREVRANGE";
+    let (start, end) = if reverse { (second, first) } else { (first, second) };
+    let start = parse_range_id(&start, false)?;
+    let end = parse_range_id(&end, true)?;
+
+    let count = match parse.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("COUNT") => {
+            let count = parse.next_signed()?;
+            usize::try_from(count).unwrap_or(0)
+        }
+        Ok(_) => return Err("ERR syntax error".into()),
+        Err(ParseError::EndOfStream) => usize::MAX,
+        Err(e) => return Err(e.into()),
+    };
+    parse.finish()?;
+
+    let entries = session.db().xrange(session.namespace(), &key, start, end, count, reverse)?;
+    Ok(stream_entries(entries))
+}
+
+async fn handle_xdel(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let ids = remaining_bytes(parse, 1, "XDEL")?
+        .iter()
+        .map(|id| parse_stream_id(id))
+        .collect::<crate::Result<Vec<_>>>()?;
+
+    let removed = session.db().xdel(session.namespace(), &key, &ids)?;
+    Ok(Frame::Integer(removed as i64))
+}
+
+/// Handles `XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]`.
+async fn handle_xtrim(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let mut args = remaining_bytes(parse, 2, "XTRIM")?.into_iter().peekable();
+
+    let strategy = args.next().expect("at least two arguments");
+    if !strategy.eq_ignore_ascii_case(b"MAXLEN") && !strategy.eq_ignore_ascii_case(b"MINID") {
+        return Err("ERR syntax error".into());
+    }
+    let trim = parse_trim(&strategy, &mut args)?;
+    if args.next().is_some() {
+        return Err("ERR syntax error".into());
+    }
+
+    let evicted = session.db().xtrim(session.namespace(), &key, trim)?;
+    Ok(Frame::Integer(evicted as i64))
+}
+
+/// Handles `XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...]
+/// id [id ...]`. Replies `Null` if no stream has new entries.
+async fn handle_xread(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let mut count = usize::MAX;
+    let mut block = None;
+
+    loop {
+        let option = parse.next_string().map_err(|_| "ERR syntax error")?.to_uppercase();
+        match option.as_str() {
+            "COUNT" => {
+                // Like Redis, a zero or negative count means no limit.
+                let value = parse.next_signed()?;
+                count = usize::try_from(value).ok().filter(|value| *value > 0).unwrap_or(usize::MAX);
+            }
+            "BLOCK" => {
+                let millis = parse.next_signed()?;
+                if millis < 0 {
+                    return Err("ERR timeout is negative".into());
+                }
+                block = Some(Duration::from_millis(millis as u64));
+            }
+            "STREAMS" => break,
+            _ => return Err("ERR syntax error".into()),
+        }
+    }
+
+    let mut streams = Vec::new();
+    for (key, id) in parse_streams(parse, "XREAD")? {
+        let from = match parse::text(&id)? {
+            "$" => XReadFrom::New,
+            id => XReadFrom::After(id.parse()?),
+        };
+        streams.push((key, from));
+    }
+
+    let result = session.db().xread(session.namespace(), streams, count, block).await?;
+    if result.is_empty() {
+        return Ok(Frame::Null);
+    }
+
+    Ok(Frame::Array(
+        result
+            .into_iter()
+            .map(|(key, entries)| Frame::Array(vec![Frame::Bulk(key), stream_entries(entries)]))
+            .collect(),
+    ))
+}
+
+/// Parse the `key [key ...] id [id ...]` list following `STREAMS` into
+/// key/ID pairs.
+fn parse_streams(parse: &mut Parse, command: &str) -> crate::Result<Vec<(Bytes, Bytes)>> {
+    let mut args = remaining_keys(parse, 2, command)?;
+    if !args.len().is_multiple_of(2) {
+        return Err(format!(
+            "ERR Unbalanced '{}' list of streams: for each stream key an ID or '$' must be specified.",
+            command.to_lowercase()
+        )
+        .into());
+    }
+
+    let ids = args.split_off(args.len() / 2);
+    Ok(args.into_iter().zip(ids).collect())
+}
+
+/// Parse a milliseconds argument such as a minimum idle time. Negative
+/// values count as zero.
+fn parse_millis(parse: &mut Parse) -> crate::Result<Duration> {
+    Ok(Duration::from_millis(parse.next_signed()?.max(0) as u64))
+}
+
+/// Parse the start ID of a consumer group, where `$` means the last entry.
+fn parse_group_start(id: &str) -> crate::Result<XReadFrom> {
+    match id {
+        "$" => Ok(XReadFrom::New),
+        id => Ok(XReadFrom::After(id.parse()?)),
+    }
+}
+
+/// An entry read from a consumer group, with `Null` fields if the entry was
+/// deleted after it was delivered.
+fn group_entry(entry: GroupEntry) -> Frame {
+    match entry {
+        (id, Some(fields)) => stream_entry((id, fields)),
+        (id, None) => Frame::Array(vec![Frame::Bulk(Bytes::from(id.to_string())), Frame::Null]),
+    }
+}
+
+fn stream_ids(ids: impl IntoIterator<Item = StreamId>) -> Frame {
+    Frame::Array(ids.into_iter().map(|id| Frame::Bulk(Bytes::from(id.to_string()))).collect())
+}
+
+/// Handles the `XGROUP` subcommands: `CREATE key group id|$ [MKSTREAM]`,
+/// `SETID key group id|$`, `DESTROY key group`, `CREATECONSUMER key group
+/// consumer` and `DELCONSUMER key group consumer`.
+async fn handle_xgroup(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let subcommand = parse.next_string()?.to_uppercase();
+    let key = parse.next_bytes()?;
+    let group = parse.next_bytes()?;
+    let db = session.db();
+
+    match subcommand.as_str() {
+        "CREATE" => {
+            let start = parse_group_start(&parse.next_string()?)?;
+            let create = match parse.next_string() {
+                Ok(option) if option.eq_ignore_ascii_case("MKSTREAM") => true,
+                Ok(_) => return Err("ERR syntax error".into()),
+                Err(ParseError::EndOfStream) => false,
+                Err(e) => return Err(e.into()),
+            };
+            parse.finish()?;
+
+            db.xgroup_create(session.namespace(), &key, group, start, create)?;
+            Ok(Frame::Simple("OK".to_string()))
+        }
+        "SETID" => {
+            let start = parse_group_start(&parse.next_string()?)?;
+            parse.finish()?;
+
+            db.xgroup_setid(session.namespace(), &key, &group, start)?;
+            Ok(Frame::Simple("OK".to_string()))
+        }
+        "DESTROY" => {
+            parse.finish()?;
+
+            let destroyed = db.xgroup_destroy(session.namespace(), &key, &group)?;
+            Ok(Frame::Integer(destroyed as i64))
+        }
+        "CREATECONSUMER" => {
+            let consumer = parse.next_bytes()?;
+            parse.finish()?;
+
+            let created = db.xgroup_createconsumer(session.namespace(), &key, &group, &consumer)?;
+            Ok(Frame::Integer(created as i64))
+        }
+        "DELCONSUMER" => {
+            let consumer = parse.next_bytes()?;
+            parse.finish()?;
+
+            let pending = db.xgroup_delconsumer(session.namespace(), &key, &group, &consumer)?;
+            Ok(Frame::Integer(pending as i64))
+        }
+        _ => Err(format!("ERR unknown subcommand '{}'. Try XGROUP HELP.", subcommand.to_lowercase()).into()),
+    }
+}
+
+/// Handles `XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds]
+/// [NOACK] STREAMS key [key ...] id [id ...]`, where `>` reads entries never
+/// delivered to the group and any other ID reads the consumer's pending
+/// entries after it. Replies `Null` if there is nothing to report.
+async fn handle_xreadgroup(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    if !parse.next_string()?.eq_ignore_ascii_case("GROUP") {
+        return Err("ERR syntax error".into());
+    }
+    let group = parse.next_bytes()?;
+    let consumer = parse.next_bytes()?;
+
+    let mut count = usize::MAX;
+    let mut block = None;
+    let mut no_ack = false;
+    loop {
+        let option = parse.next_string().map_err(|_| "ERR syntax error")?.to_uppercase();
+        match option.as_str() {
+            "COUNT" => {
+                let value = parse.next_signed()?;
+                count = usize::try_from(value).ok().filter(|value| *value > 0).unwrap_or(usize::MAX);
+            }
+            "BLOCK" => {
+                let millis = parse.next_signed()?;
+                if millis < 0 {
+                    return Err("ERR timeout is negative".into());
+                }
+                block = Some(Duration::from_millis(millis as u64));
+            }
+            "NOACK" => no_ack = true,
+            "STREAMS" => break,
+            _ => return Err("ERR syntax error".into()),
+        }
+    }
+
+    let mut streams = Vec::new();
+    for (key, id) in parse_streams(parse, "XREADGROUP")? {
+        let from = match parse::text(&id)? {
+            ">" => XReadFrom::New,
+            "$" => return Err("ERR The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.".into()),
+            id => XReadFrom::After(id.parse()?),
+        };
+        streams.push((key, from));
+    }
+
+    let result = session
+        .db()
+        .xreadgroup(session.namespace(), &group, &consumer, streams, count, no_ack, block)
+        .await?;
+    if result.is_empty() {
+        return Ok(Frame::Null);
+    }
+
+    Ok(Frame::Array(
+        result
+            .into_iter()
+            .map(|(key, entries)| {
+                let entries = Frame::Array(entries.into_iter().map(group_entry).collect());
+                Frame::Array(vec![Frame::Bulk(key), entries])
+            })
+            .collect(),
+    ))
+}
+
+/// Handles `XACK key group id [id ...]`.
+async fn handle_xack(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let group = parse.next_bytes()?;
+    let ids = remaining_bytes(parse, 1, "XACK")?
+        .iter()
+        .map(|id| parse_stream_id(id))
+        .collect::<crate::Result<Vec<_>>>()?;
+
+    let acked = session.db().xack(session.namespace(), &key, &group, &ids)?;
+    Ok(Frame::Integer(acked as i64))
+}
+
+/// Handles `XPENDING key group` and its extended form `XPENDING key group
+/// [IDLE min-idle-time] start end count [consumer]`.
+async fn handle_xpending(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let group = parse.next_bytes()?;
+    let db = session.db();
+
+    let first = match parse.next_bytes() {
+        Ok(first) => first,
+        Err(ParseError::EndOfStream) => {
+            let summary = db.xpending_summary(session.namespace(), &key, &group)?;
+            let (first, last) = match summary.range {
+                Some((first, last)) => (Frame::Bulk(Bytes::from(first.to_string())), Frame::Bulk(Bytes::from(last.to_string()))),
+                None => (Frame::Null, Frame::Null),
+            };
+            let consumers = if summary.consumers.is_empty() {
+                Frame::Null
+            } else {
+                Frame::Array(
+                    summary
+                        .consumers
+                        .into_iter()
+                        .map(|(name, count)| Frame::Array(vec![Frame::Bulk(name), Frame::Bulk(Bytes::from(count.to_string()))]))
+                        .collect(),
+                )
+            };
+            return Ok(Frame::Array(vec![Frame::Integer(summary.count as i64), first, last, consumers]));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let (min_idle, start) = if first.eq_ignore_ascii_case(b"IDLE") {
+        (parse_millis(parse)?, parse.next_bytes()?)
+    } else {
+        (Duration::ZERO, first)
+    };
+    let start = parse_range_id(&start, false)?;
+    let end = parse_range_id(&parse.next_bytes()?, true)?;
+    let count = usize::try_from(parse.next_signed()?).unwrap_or(0);
+    let consumer = match parse.next_bytes() {
+        Ok(consumer) => Some(consumer),
+        Err(ParseError::EndOfStream) => None,
+        Err(e) => return Err(e.into()),
+    };
+    parse.finish()?;
+
+    let pending = db.xpending(session.namespace(), &key, &group, start, end, count, min_idle, consumer.as_deref())?;
+    Ok(Frame::Array(
+        pending
+            .into_iter()
+            .map(|entry| {
+                Frame::Array(vec![
+                    Frame::Bulk(Bytes::from(entry.id.to_string())),
+                    Frame::Bulk(entry.consumer),
+                    Frame::Integer(entry.idle.as_millis() as i64),
+                    Frame::Integer(entry.deliveries as i64),
+                ])
+            })
+            .collect(),
+    ))
+}
+
+/// Handles `XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms]
+/// [TIME unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID]
+/// [LASTID id]`.
+async fn handle_xclaim(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let group = parse.next_bytes()?;
+    let consumer = parse.next_bytes()?;
+    let min_idle = parse_millis(parse)?;
+
+    let mut args = remaining_bytes(parse, 1, "XCLAIM")?.into_iter().peekable();
+    let mut ids = Vec::new();
+    while let Some(id) = args.next_if(|arg| parse_stream_id(arg).is_ok()) {
+        ids.push(parse_stream_id(&id)?);
+    }
+    if ids.is_empty() {
+        return Err("ERR Invalid stream ID specified as stream command argument".into());
+    }
+
+    let integer = |arg: Option<Bytes>| -> crate::Result<i64> {
+        let arg = arg.ok_or("ERR syntax error")?;
+        Ok(std::str::from_utf8(&arg)
+            .ok()
+            .and_then(|arg| arg.parse().ok())
+            .ok_or("ERR value is not an integer or out of range")?)
+    };
+
+    let mut options = ClaimOptions::default();
+    while let Some(option) = args.next() {
+        match option.to_ascii_uppercase().as_slice() {
+            b"IDLE" => options.idle = Some(Duration::from_millis(integer(args.next())?.max(0) as u64)),
+            b"TIME" => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_millis() as i64)
+                    .unwrap_or(0);
+                let idle = now.saturating_sub(integer(args.next())?).max(0);
+                options.idle = Some(Duration::from_millis(idle as u64));
+            }
+            b"RETRYCOUNT" => {
+                let count = integer(args.next())?;
+                options.retry_count = Some(u64::try_from(count).map_err(|_| "ERR Invalid RETRYCOUNT option argument for XCLAIM")?);
+            }
+            b"FORCE" => options.force = true,
+            b"JUSTID" => options.just_id = true,
+            b"LASTID" => {
+                // Only meaningful to replicas; accepted and ignored.
+                parse_stream_id(&args.next().ok_or("ERR syntax error")?)?;
+            }
+            _ => return Err(format!("ERR Unrecognized XCLAIM option '{}'", String::from_utf8_lossy(&option)).into()),
+        }
+    }
+
+    let claimed = session.db().xclaim(session.namespace(), &key, &group, &consumer, min_idle, &ids, options)?;
+    if options.just_id {
+        return Ok(stream_ids(claimed.into_iter().map(|(id, _)| id)));
+    }
+    Ok(stream_entries(claimed))
+}
+
+/// Handles `XAUTOCLAIM key group consumer min-idle-time start [COUNT count]
+/// [JUSTID]`. Replies with the ID to continue scanning from, the claimed
+/// entries and the IDs of pending entries found deleted.
+async fn handle_xautoclaim(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let key = parse.next_bytes()?;
+    let group = parse.next_bytes()?;
+    let consumer = parse.next_bytes()?;
+    let min_idle = parse_millis(parse)?;
+    let start = parse_range_id(&parse.next_bytes()?, false)?;
+
+    let mut count = 100;
+    let mut just_id = false;
+    loop {
+        let option = match parse.next_string() {
+            Ok(option) => option.to_uppercase(),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        match option.as_str() {
+            "COUNT" => {
+                count = usize::try_from(parse.next_signed()?)
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or("ERR COUNT must be > 0")?;
+            }
+            "JUSTID" => just_id = true,
+            _ => return Err("ERR syntax error".into()),
+        }
+    }
+
+    let (next, claimed, deleted) = session
+        .db()
+        .xautoclaim(session.namespace(), &key, &group, &consumer, min_idle, start, count, just_id)?;
+    let claimed = if just_id {
+        stream_ids(claimed.into_iter().map(|(id, _)| id))
+    } else {
+        stream_entries(claimed)
+    };
+    Ok(Frame::Array(vec![Frame::Bulk(Bytes::from(next.to_string())), claimed, stream_ids(deleted)]))
+}
+
+/// A field name followed by its value, for the flat maps replied by `XINFO`.
+fn info_field(name: &'static str, value: Frame) -> [Frame; 2] {
+    [Frame::Bulk(Bytes::from_static(name.as_bytes())), value]
+}
+
+/// Handles `XINFO STREAM key`, `XINFO GROUPS key` and `XINFO CONSUMERS key
+/// group`.
+async fn handle_xinfo(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let subcommand = parse.next_string()?.to_uppercase();
+    let key = parse.next_bytes()?;
+    let db = session.db();
+
+    match subcommand.as_str() {
+        "STREAM" => {
+            parse.finish()?;
+
+            let info = db.xinfo_stream(session.namespace(), &key)?.ok_or("ERR no such key")?;
+            let entry = |entry: Option<StreamEntry>| entry.map_or(Frame::Null, stream_entry);
+            let fields = [
+                info_field("length", Frame::Integer(info.length as i64)),
+                info_field("last-generated-id", Frame::Bulk(Bytes::from(info.last_id.to_string()))),
+                info_field("groups", Frame::Integer(info.groups as i64)),
+                info_field("first-entry", entry(info.first)),
+                info_field("last-entry", entry(info.last)),
+            ];
+            Ok(Frame::Array(fields.into_iter().flatten().collect()))
+        }
+        "GROUPS" => {
+            parse.finish()?;
+
+            let groups = db.xinfo_groups(session.namespace(), &key)?.ok_or("ERR no such key")?;
+            Ok(Frame::Array(
+                groups
+                    .into_iter()
+                    .map(|group| {
+                        let fields = [
+                            info_field("name", Frame::Bulk(group.name)),
+                            info_field("consumers", Frame::Integer(group.consumers as i64)),
+                            info_field("pending", Frame::Integer(group.pending as i64)),
+                            info_field("last-delivered-id", Frame::Bulk(Bytes::from(group.last_delivered.to_string()))),
+                        ];
+                        Frame::Array(fields.into_iter().flatten().collect())
+                    })
+                    .collect(),
+            ))
+        }
+        "CONSUMERS" => {
+            let group = parse.next_bytes()?;
+            parse.finish()?;
+
+            let consumers = db.xinfo_consumers(session.namespace(), &key, &group)?;
+            Ok(Frame::Array(
+                consumers
+                    .into_iter()
+                    .map(|consumer| {
+                        let inactive = consumer.inactive.map_or(-1, |inactive| inactive.as_millis() as i64);
+                        let fields = [
+                            info_field("name", Frame::Bulk(consumer.name)),
+                            info_field("pending", Frame::Integer(consumer.pending as i64)),
+                            info_field("idle", Frame::Integer(consumer.idle.as_millis() as i64)),
+                            info_field("inactive", Frame::Integer(inactive)),
+                        ];
+                        Frame::Array(fields.into_iter().flatten().collect())
+                    })
+                    .collect(),
+            ))
+        }
+        _ => Err(format!("ERR unknown subcommand '{}'. Try XINFO HELP.", subcommand.to_lowercase()).into()),
+    }
+}
+
+/// Handles `PUBLISH channel message`: replies with the number of
+/// subscribers that received it.
+async fn handle_publish(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let channel = parse.next_bytes()?;
+    let message = parse.next_bytes()?;
+    parse.finish()?;
+
+    Ok(Frame::Integer(session.db().pubsub().publish(&channel, &message) as i64))
+}
+
+/// Handles `PUBSUB CHANNELS [pattern]`, `PUBSUB NUMSUB [channel ...]` and
+/// `PUBSUB NUMPAT`.
+async fn handle_pubsub(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let subcommand = parse.next_string()?.to_uppercase();
+    let hub = session.db().pubsub();
+
+    match subcommand.as_str() {
+        "CHANNELS" => {
+            let pattern = match parse.next_bytes() {
+                Ok(pattern) => Some(pattern),
+                Err(ParseError::EndOfStream) => None,
+                Err(e) => return Err(e.into()),
+            };
+            parse.finish()?;
+            Ok(Frame::Array(hub.channels(pattern.as_deref()).into_iter().map(Frame::Bulk).collect()))
+        }
+        "NUMSUB" => {
+            let channels = remaining_bytes(parse, 0, "pubsub|numsub")?;
+            let counts = channels.into_iter().flat_map(|channel| {
+                let count = hub.subscriber_count(&channel) as i64;
+                [Frame::Bulk(channel), Frame::Integer(count)]
+            });
+            Ok(Frame::Array(counts.collect()))
+        }
+        "NUMPAT" => {
+            parse.finish()?;
+            Ok(Frame::Integer(hub.pattern_count() as i64))
+        }
+        _ => Err(format!("ERR unknown subcommand '{}'. Try PUBSUB HELP.", subcommand.to_lowercase()).into()),
+    }
+}
+
+/// Handles `CLIENT ID`, `CLIENT INFO`, `CLIENT LIST [TYPE normal|pubsub]
+/// [ID id [id ...]]`, `CLIENT SETNAME name`, `CLIENT GETNAME`, `CLIENT KILL`,
+/// `CLIENT PAUSE timeout [WRITE|ALL]`, `CLIENT UNPAUSE` and `CLIENT NO-EVICT
+/// ON|OFF`. Outside of transactions this runs without the exec lock.
+///
+/// `CLIENT KILL` takes either the address of a client, or filters: `ID id`,
+/// `ADDR ip:port`, `LADDR ip:port`, `TYPE normal|pubsub`, `MAXAGE seconds`
+/// and `SKIPME yes|no`, the calling client being skipped by default.
+async fn handle_client(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let subcommand = parse.next_string()?.to_uppercase();
+    let clients = session.db().clients();
+    let me = || session.client().ok_or("ERR CLIENT is only available to connected clients");
+
+    match subcommand.as_str() {
+        "ID" => {
+            parse.finish()?;
+            Ok(Frame::Integer(me()?.id() as i64))
+        }
+        "INFO" => {
+            parse.finish()?;
+            Ok(Frame::Bulk(format!("{}\n", me()?.describe()).into()))
+        }
+        "LIST" => {
+            let mut filter = ClientFilter::default();
+            loop {
+                match parse.next_string() {
+                    Ok(option) if option.eq_ignore_ascii_case("TYPE") => filter.pubsub = Some(parse_client_type(&parse.next_string()?)?),
+                    Ok(option) if option.eq_ignore_ascii_case("ID") => {
+                        let ids = remaining_bytes(parse, 1, "client|list")?;
+                        let ids = ids.iter().map(|id| parse_client_id(id)).collect::<crate::Result<_>>()?;
+                        filter.ids = Some(ids);
+                    }
+                    Ok(_) => return Err("ERR syntax error".into()),
+                    Err(ParseError::EndOfStream) => break,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            let list: String = clients.list(&filter).iter().map(|client| format!("{}\n", client.describe())).collect();
+            Ok(Frame::Bulk(list.into()))
+        }
+        "SETNAME" => {
+            let name = parse.next_bytes()?;
+            parse.finish()?;
+            if name.iter().any(|c| !(b'!'..=b'~').contains(c)) {
+                return Err("ERR Client names cannot contain spaces, newlines or special characters.".into());
+            }
+            me()?.set_name(name);
+            Ok(Frame::Simple("OK".to_string()))
+        }
+        "GETNAME" => {
+            parse.finish()?;
+            Ok(me()?.name().map_or(Frame::Null, Frame::Bulk))
+        }
+        "KILL" => {
+            let args = remaining_bytes(parse, 1, "client|kill")?;
+            if let [addr] = args.as_slice() {
+                let filter = ClientFilter { addr: Some(String::from_utf8_lossy(addr).into_owned()), ..ClientFilter::default() };
+                if clients.kill(&filter) == 0 {
+                    return Err("ERR No such client".into());
+                }
+                return Ok(Frame::Simple("OK".to_string()));
+            }
+            if args.len() % 2 != 0 {
+                return Err("ERR syntax error".into());
+            }
+
+            let mut filter = ClientFilter { skip: session.client().map(|client| client.id()), ..ClientFilter::default() };
+            for pair in args.chunks(2) {
+                let value = String::from_utf8_lossy(&pair[1]).into_owned();
+                match String::from_utf8_lossy(&pair[0]).to_uppercase().as_str() {
+                    "ID" => filter.ids = Some(vec![parse_client_id(&pair[1])?]),
+                    "ADDR" => filter.addr = Some(value),
+                    "LADDR" => filter.laddr = Some(value),
+                    "TYPE" => filter.pubsub = Some(parse_client_type(&value)?),
+                    "MAXAGE" => {
+                        let seconds = value.parse().map_err(|_| "ERR syntax error")?;
+                        filter.max_age = Some(Duration::from_secs(seconds));
+                    }
+                    "SKIPME" => match value.to_lowercase().as_str() {
+                        "yes" => filter.skip = session.client().map(|client| client.id()),
+                        "no" => filter.skip = None,
+                        _ => return Err("ERR syntax error".into()),
+                    },
+                    _ => return Err("ERR syntax error".into()),
+                }
+            }
+            Ok(Frame::Integer(clients.kill(&filter) as i64))
+        }
+        "PAUSE" => {
+            let timeout = parse.next_signed().map_err(|_| "ERR timeout is not an integer or out of range")?;
+            if timeout < 0 {
+                return Err("ERR timeout is negative".into());
+            }
+            let writes_only = match parse.next_string() {
+                Ok(mode) if mode.eq_ignore_ascii_case("WRITE") => true,
+                Ok(mode) if mode.eq_ignore_ascii_case("ALL") => false,
+                Ok(_) => return Err("ERR syntax error".into()),
+                Err(ParseError::EndOfStream) => false,
+                Err(e) => return Err(e.into()),
+            };
+            parse.finish()?;
+            clients.pause(Duration::from_millis(timeout as u64), writes_only);
+            Ok(Frame::Simple("OK".to_string()))
+        }
+        "UNPAUSE" => {
+            parse.finish()?;
+            clients.unpause();
+            Ok(Frame::Simple("OK".to_string()))
+        }
+        "NO-EVICT" => {
+            let no_evict = match parse.next_string()?.to_uppercase().as_str() {
+                "ON" => true,
+                "OFF" => false,
+                _ => return Err("ERR syntax error".into()),
+            };
+            parse.finish()?;
+            me()?.set_no_evict(no_evict);
+            Ok(Frame::Simple("OK".to_string()))
+        }
+        _ => Err(format!("ERR unknown subcommand '{}'. Try CLIENT HELP.", subcommand.to_lowercase()).into()),
+    }
+}
+
+/// Parse a client type of `CLIENT LIST` or `CLIENT KILL`: whether it stands
+/// for subscribers.
+fn parse_client_type(kind: &str) -> crate::Result<bool> {
+    match kind.to_lowercase().as_str() {
+        "normal" => Ok(false),
+        "pubsub" => Ok(true),
+        _ => Err(format!("ERR Unknown client type '{}'", kind).into()),
+    }
+}
+
+fn parse_client_id(id: &[u8]) -> crate::Result<u64> {
+    std::str::from_utf8(id)
+        .ok()
+        .and_then(|id| id.parse().ok())
+        .filter(|id| *id > 0)
+        .ok_or_else(|| "ERR Invalid client ID".into())
+}
+
+/// Parameters exposed through `CONFIG GET` and `CONFIG SET`.
+const CONFIG_PARAMETERS: &[&str] = &[
+    "databases", "namespace-aliases", "maxmemory", "maxmemory-policy", "dbfilename", "save", "appendonly", "appendfilename",
+    "appendfsync", "pubsub-queue-limit", "notify-keyspace-events",
+];
+
+/// Parameters that are fixed once the server has started.
+const IMMUTABLE_PARAMETERS: &[&str] = &["databases", "namespace-aliases", "dbfilename", "appendonly", "appendfilename"];
+
+/// Handles `CONFIG GET pattern [pattern ...]` and `CONFIG SET parameter value
+/// [parameter value ...]`.
+async fn handle_config(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let subcommand = parse.next_string()?.to_uppercase();
+    let db = session.db();
+
+    match subcommand.as_str() {
+        "GET" => {
+            let patterns = remaining_bytes(parse, 1, "config|get")?;
+            let reply = CONFIG_PARAMETERS
+                .iter()
+                .filter(|name| patterns.iter().any(|pattern| glob::matches(&pattern.to_ascii_lowercase(), name.as_bytes())))
+                .flat_map(|name| {
+                    let value = match *name {
+                        "databases" => db.namespace_count().to_string(),
+                        "namespace-aliases" => {
+                            let aliases: Vec<String> = db.aliases().iter().map(|(name, index)| format!("{}:{}", name, index)).collect();
+                            aliases.join(" ")
+                        }
+                        "maxmemory" => db.max_memory().to_string(),
+                        "maxmemory-policy" => db.eviction_policy().to_string(),
+                        "dbfilename" => db.dbfilename().display().to_string(),
+                        "save" => SaveRules(&db.save_rules()).to_string(),
+                        "appendonly" => if db.append_only() { "yes" } else { "no" }.to_string(),
+                        "appendfilename" => db.appendfilename().display().to_string(),
+                        "pubsub-queue-limit" => db.pubsub_queue_limit().to_string(),
+                        "notify-keyspace-events" => db.keyspace_events().to_string(),
+                        _ => db.append_fsync().to_string(),
+                    };
+                    [Frame::Bulk(Bytes::from_static(name.as_bytes())), Frame::Bulk(value.into())]
+                })
+                .collect();
+            Ok(Frame::Array(reply))
+        }
+        "SET" => {
+            let args = remaining_bytes(parse, 2, "config|set")?;
+            if args.len() % 2 != 0 {
+                return Err(wrong_arity("config|set"));
+            }
+
+            // Validate everything before applying anything.
+            let mut maxmemory = None;
+            let mut policy = None;
+            let mut save = None;
+            let mut fsync = None;
+            let mut queue_limit = None;
+            let mut keyspace_events = None;
+            for pair in args.chunks(2) {
+                let name = String::from_utf8_lossy(&pair[0]).to_lowercase();
+                let value = String::from_utf8_lossy(&pair[1]);
+                let invalid = |reason: &str| format!("ERR CONFIG SET failed (possibly related to argument '{}') - {}", name, reason);
+                match name.as_str() {
+                    name if IMMUTABLE_PARAMETERS.contains(&name) => return Err(invalid("can't set immutable config").into()),
+                    "maxmemory" => {
+                        maxmemory = Some(parse_memory(&value).ok_or_else(|| invalid("argument must be a memory value"))?);
+                    }
+                    "maxmemory-policy" => {
+                        policy = Some(value.parse::<EvictionPolicy>().map_err(|_| invalid("argument must be an eviction policy"))?);
+                    }
+                    "save" => {
+                        save = Some(parse_save(&value).ok_or_else(|| invalid("argument must be pairs of seconds and changes"))?);
+                    }
+                    "appendfsync" => {
+                        fsync = Some(value.parse::<AppendFsync>().map_err(|_| invalid("argument must be 'always', 'everysec' or 'no'"))?);
+                    }
+                    "pubsub-queue-limit" => {
+                        queue_limit = Some(value.parse::<usize>().map_err(|_| invalid("argument couldn't be parsed into an integer"))?);
+                    }
+                    "notify-keyspace-events" => {
+                        keyspace_events = Some(value.parse::<KeyspaceEvents>().map_err(|_| invalid("Invalid event class character. Use 'Ag$lshzxet'."))?);
+                    }
+                    _ => return Err(format!("ERR Unknown option or number of arguments for CONFIG SET - '{}'", name).into()),
+                }
+            }
+
+            if let Some(policy) = policy {
+                db.set_eviction_policy(policy);
+            }
+            if let Some(save) = save {
+                db.set_save_rules(save);
+            }
+            if let Some(fsync) = fsync {
+                db.set_append_fsync(fsync);
+            }
+            if let Some(limit) = queue_limit {
+                db.set_pubsub_queue_limit(limit);
+            }
+            if let Some(events) = keyspace_events {
+                db.set_keyspace_events(events);
+            }
+            if let Some(maxmemory) = maxmemory {
+                db.set_max_memory(maxmemory);
+                // Lowering the limit takes effect right away.
+                if db.free_memory().is_ok_and(|evicted| evicted > 0) {
+                    db.aof().write_pending().await;
+                }
+            }
+            Ok(Frame::Simple("OK".to_string()))
+        }
+        _ => Err(format!("ERR unknown subcommand '{}'. Try CONFIG HELP.", subcommand.to_lowercase()).into()),
+    }
+}
+
+/// Handles `INFO [section ...]`. Sections are `clients`, `memory`,
+/// `persistence` and `stats`; with no
+/// argument, or `all` or `everything`, every section is returned.
+async fn handle_info(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let mut sections = Vec::new();
+    loop {
+        match parse.next_string() {
+            Ok(section) => sections.push(section.to_lowercase()),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let wanted = |section: &str| {
+        sections.is_empty() || sections.iter().any(|wanted| wanted == section || wanted == "all" || wanted == "everything")
+    };
+
+    let db = session.db();
+    let mut info = String::new();
+    if wanted("clients") {
+        info.push_str("# Clients\r\n");
+        info.push_str(&format!("connected_clients:{}\r\n", db.clients().len()));
+    }
+    if wanted("memory") {
+        if !info.is_empty() {
+            info.push_str("\r\n");
+        }
+        info.push_str("# Memory\r\n");
+        info.push_str(&format!("used_memory:{}\r\n", db.used_memory()));
+        info.push_str(&format!("maxmemory:{}\r\n", db.max_memory()));
+        info.push_str(&format!("maxmemory_policy:{}\r\n", db.eviction_policy()));
+    }
+    if wanted("persistence") {
+        if !info.is_empty() {
+            info.push_str("\r\n");
+        }
+        info.push_str("# Persistence\r\n");
+        info.push_str(&format!("rdb_changes_since_last_save:{}\r\n", db.changes_since_save()));
+        info.push_str(&format!("rdb_bgsave_in_progress:{}\r\n", db.save_in_progress() as u8));
+        info.push_str(&format!("rdb_last_save_time:{}\r\n", db.last_save()));
+        info.push_str(&format!("rdb_last_bgsave_status:{}\r\n", if db.last_save_ok() { "ok" } else { "err" }));
+        info.push_str(&format!("aof_enabled:{}\r\n", db.append_only() as u8));
+        info.push_str(&format!("aof_rewrite_in_progress:{}\r\n", db.aof_rewrite_in_progress() as u8));
+        info.push_str(&format!("aof_last_bgrewrite_status:{}\r\n", if db.aof_last_rewrite_ok() { "ok" } else { "err" }));
+        info.push_str(&format!("aof_last_write_status:{}\r\n", if db.aof_last_write_ok() { "ok" } else { "err" }));
+        info.push_str(&format!("aof_current_size:{}\r\n", db.aof_size()));
+    }
+    if wanted("stats") {
+        if !info.is_empty() {
+            info.push_str("\r\n");
+        }
+        info.push_str("# Stats\r\n");
+        info.push_str(&format!("evicted_keys:{}\r\n", db.evicted_keys()));
+    }
+    Ok(Frame::Bulk(info.into()))
+}
+
+/// Handles `SAVE`, which writes a snapshot before replying and holds up
+/// this connection in the meantime.
+async fn handle_save(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    parse.finish()?;
+
+    session.db().save()?;
+    Ok(Frame::Simple("OK".to_string()))
+}
+
+/// Handles `BGSAVE [SCHEDULE]`. A save is never queued behind another, so
+/// `SCHEDULE` is accepted but fails like a plain `BGSAVE` would.
+async fn handle_bgsave(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    match parse.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("SCHEDULE") => parse.finish()?,
+        Ok(_) => return Err("ERR syntax error".into()),
+        Err(ParseError::EndOfStream) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    session.db().bgsave()?;
+    Ok(Frame::Simple("Background saving started".to_string()))
+}
+
+/// Handles `LASTSAVE`: the unix time of the last successful save.
+async fn handle_lastsave(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    parse.finish()?;
+
+    Ok(Frame::Integer(session.db().last_save() as i64))
+}
+
+/// Handles `BGREWRITEAOF`, which compacts the append-only file in the
+/// background.
+async fn handle_bgrewriteaof(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    parse.finish()?;
+
+    session.db().bgrewriteaof().await?;
+    Ok(Frame::Simple("Background append only file rewriting started".to_string()))
+}
+
+/// Handles `MEMORY USAGE key [SAMPLES count]`. Sizes are always estimated
+/// from a fixed sample, so `SAMPLES` is accepted but ignored.
+async fn handle_memory(parse: &mut Parse, session: &Session) -> crate::Result<Frame> {
+    let subcommand = parse.next_string()?.to_uppercase();
+    if subcommand != "USAGE" {
+        return Err(format!("ERR unknown subcommand '{}'. Try MEMORY HELP.", subcommand.to_lowercase()).into());
+    }
+
+    let key = parse.next_bytes()?;
+    match parse.next_string() {
+        Ok(option) if option.eq_ignore_ascii_case("SAMPLES") => {
+            parse.next_int()?;
+            parse.finish()?;
+        }
+        Ok(_) => return Err("ERR syntax error".into()),
+        Err(ParseError::EndOfStream) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let usage = session.db().memory_usage(session.namespace(), &key);
+    Ok(usage.map_or(Frame::Null, |bytes| Frame::Integer(bytes as i64)))
+}
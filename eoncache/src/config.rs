@@ -0,0 +1,143 @@
+//! Server configuration.
+
+use crate::aof::AppendFsync;
+use crate::memory::EvictionPolicy;
+use crate::pubsub::KeyspaceEvents;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Number of namespaces unless configured otherwise.
+pub const DEFAULT_DATABASES: usize = 16;
+
+/// Messages a subscriber may fall behind by unless configured otherwise.
+pub const DEFAULT_PUBSUB_QUEUE_LIMIT: usize = 10_000;
+
+/// Settings a `Db` is created with. All of them are exposed through
+/// `CONFIG GET`; those that can change at runtime through `CONFIG SET` too.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Number of namespaces.
+    pub databases: usize,
+
+    /// Names clients may `SELECT` instead of a namespace index.
+    pub aliases: BTreeMap<String, usize>,
+
+    /// Memory limit in bytes; `0` means unlimited.
+    pub maxmemory: usize,
+
+    /// What to do once `maxmemory` is reached.
+    pub maxmemory_policy: EvictionPolicy,
+
+    /// File snapshots are saved to and loaded from.
+    pub dbfilename: PathBuf,
+
+    /// When to save a snapshot in the background; none means only on
+    /// `SAVE` and `BGSAVE`.
+    pub save: Vec<SaveRule>,
+
+    /// Whether every write is logged to `appendfilename`. The log is then
+    /// loaded at startup instead of the snapshot.
+    pub appendonly: bool,
+
+    /// File writes are logged to.
+    pub appendfilename: PathBuf,
+
+    /// When the log is flushed to disk.
+    pub appendfsync: AppendFsync,
+
+    /// Messages a subscriber may have waiting to be written before it is
+    /// disconnected; `0` means unlimited.
+    pub pubsub_queue_limit: usize,
+
+    /// Which keyspace events are published.
+    pub notify_keyspace_events: KeyspaceEvents,
+}
+
+/// Save a snapshot once `seconds` have passed since the last one and at
+/// least `changes` writes happened in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveRule {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            databases: DEFAULT_DATABASES,
+            aliases: BTreeMap::new(),
+            maxmemory: 0,
+            maxmemory_policy: EvictionPolicy::default(),
+            dbfilename: PathBuf::from("dump.ecs"),
+            save: Vec::new(),
+            appendonly: false,
+            appendfilename: PathBuf::from("appendonly.aof"),
+            appendfsync: AppendFsync::default(),
+            pubsub_queue_limit: DEFAULT_PUBSUB_QUEUE_LIMIT,
+            notify_keyspace_events: KeyspaceEvents::NONE,
+        }
+    }
+}
+
+impl Config {
+    /// Checks that the settings are consistent with each other.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.databases == 0 {
+            return Err("there must be at least one namespace".into());
+        }
+        for (name, &index) in &self.aliases {
+            if name.parse::<i64>().is_ok() {
+                return Err(format!("alias '{}' would hide a namespace index", name));
+            }
+            if index >= self.databases {
+                return Err(format!("alias '{}' refers to namespace {}, but there are only {}", name, index, self.databases));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a memory size such as `100`, `64kb` or `2gb`, as Redis does: `kb`,
+/// `mb` and `gb` are powers of 1024, `k`, `m` and `g` powers of 1000.
+pub fn parse_memory(value: &str) -> Option<usize> {
+    let value = value.to_lowercase();
+    let digits = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits);
+    let multiplier: usize = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    number.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+/// Parse save rules written as in `CONFIG SET save`: pairs of seconds and
+/// changes, such as `3600 1 300 100`. An empty string means no rules.
+pub fn parse_save(value: &str) -> Option<Vec<SaveRule>> {
+    let numbers: Vec<u64> = value.split_whitespace().map(|number| number.parse().ok()).collect::<Option<_>>()?;
+    if !numbers.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(numbers.chunks(2).map(|pair| SaveRule { seconds: pair[0], changes: pair[1] }).collect())
+}
+
+/// Save rules in the form `parse_save` accepts.
+pub struct SaveRules<'a>(pub &'a [SaveRule]);
+
+impl fmt::Display for SaveRules<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, rule) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{} {}", rule.seconds, rule.changes)?;
+        }
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use bytes::{Buf, BytesMut};
 use std::io::{self, Cursor};
 use std::sync::Arc;
 use crate::db::Db;
+use crate::session::Session;
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
 use tokio::net::TcpStream;
 
@@ -82,6 +83,21 @@ impl Connection {
         }
     }
 
+    /// Wait until the peer closes the connection.
+    ///
+    /// Any data received in the meantime is kept in the read buffer for the
+    /// next call to `read_frame`. This lets the server notice a client that
+    /// disconnects while its command is still blocked.
+    pub async fn closed(&mut self) -> io::Result<()> {
+        while 0 != self.stream.read_buf(&mut self.buffer).await? {}
+        Ok(())
+    }
+
+    /// Number of bytes received but not yet parsed into a frame.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
     /// Tries to parse a frame from the buffer. If the buffer contains enough
     /// data, the frame is returned and the data removed from the buffer. If not
     /// enough data has been buffered yet, `Ok(None)` is returned. If the
@@ -156,25 +172,7 @@ impl Connection {
     /// write stream. The data will be written to the buffer. Once the buffer is
     /// full, it is flushed to the underlying socket.
     pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
-        // Arrays are encoded by encoding each entry. All other frame types are
-        // considered literals. For now, mini-redis is not able to encode
-        // recursive frame structures. See below for more details.
-        match frame {
-            Frame::Array(val) => {
-                // Encode the frame type prefix. For an array, it is `*`.
-                self.stream.write_u8(b'*').await?;
-
-                // Encode the length of the array.
-                self.write_decimal(val.len() as u64).await?;
-
-                // Iterate and encode each entry in the array.
-                for entry in &**val {
-                    self.write_value(entry).await?;
-                }
-            }
-            // The frame type is a literal. Encode the value directly.
-            _ => self.write_value(frame).await?,
-        }
+        self.write_value(frame).await?;
 
         // Ensure the encoded frame is written to the socket. The calls above
         // are to the buffered stream and writes. Calling `flush` writes the
@@ -182,7 +180,7 @@ impl Connection {
         self.stream.flush().await
     }
 
-    /// Write a frame literal to the stream
+    /// Write a frame to the stream
     async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
         match frame {
             Frame::Simple(val) => {
@@ -206,22 +204,28 @@ impl Connection {
                 let len = val.len();
 
                 self.stream.write_u8(b'$').await?;
-                self.write_decimal(len as u64).await?;
+                self.write_decimal(len as i64).await?;
                 self.stream.write_all(val).await?;
                 self.stream.write_all(b"\r\n").await?;
             }
-            // Encoding an `Array` from within a value cannot be done using a
-            // recursive strategy. In general, async fns do not support
-            // recursion. Mini-redis has not needed to encode nested arrays yet,
-            // so for now it is skipped.
-            Frame::Array(_val) => unreachable!(),
+            // Arrays are encoded by encoding each entry. Entries may be
+            // arrays themselves (for instance the reply to a SCAN), so the
+            // recursive call has to be boxed.
+            Frame::Array(val) => {
+                self.stream.write_u8(b'*').await?;
+                self.write_decimal(val.len() as i64).await?;
+
+                for entry in val {
+                    Box::pin(self.write_value(entry)).await?;
+                }
+            }
         }
 
         Ok(())
     }
 
     /// Write a decimal frame to the stream
-    async fn write_decimal(&mut self, val: u64) -> io::Result<()> {
+    async fn write_decimal(&mut self, val: i64) -> io::Result<()> {
         use std::io::Write;
 
         // Convert the value to a string
@@ -237,9 +241,12 @@ impl Connection {
     }
 
     pub async fn process_command(&mut self, db: Arc<Db>) -> crate::Result<()> {
+        let mut session = Session::new(db);
         while let Some(frame) = self.read_frame().await? {
             let mut parse = Parse::new(frame)?;
-            let response = crate::command::handle_command(&mut parse, &db).await?;
+            let response = crate::command::handle_command(&mut parse, &mut session)
+                .await
+                .unwrap_or_else(|e| Frame::Error(e.to_string()));
             self.write_frame(&response).await?;
         }
         Ok(())
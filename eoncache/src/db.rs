@@ -1,140 +1,3890 @@
+use crate::aof::{self, AppendFsync, AppendOnly};
+use crate::blocking::{Handoff, Pop, Served, Waiters, Watchers};
+use crate::clients::Clients;
+use crate::config::{Config, SaveRule};
+use crate::gate::Gate;
+use crate::glob;
+use crate::memory::{self, EvictionPolicy, Memory, ENTRY_OVERHEAD, EVICTION_SAMPLES, LFU_INIT, OOM};
+use crate::pubsub::{Hub, KeyspaceEvents};
+use crate::scripting::Scripts;
+use crate::set::Set;
+use crate::snapshot::{self, Persistence, Record};
+use crate::stream::Stream;
+use crate::zset::SortedSet;
 use bytes::Bytes;
-use std::collections::{HashMap, VecDeque};
-use std::sync::Mutex;
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::Rng;
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use tokio::sync::{oneshot, Notify};
 use tokio::time::{Duration, Instant};
 
-/// Represents a single key-value database with optional key expiration.
+pub use crate::blocking::End;
+pub use crate::stream::{
+    ConsumerInfo, GroupEntry, GroupInfo, PendingEntry, PendingSummary, StreamEntry, StreamId, StreamInfo,
+};
+
+/// How often the active expiry task wakes up to purge expired keys.
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often the save rules are checked.
+const SAVE_RULES_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often the append-only file is flushed under the `everysec` policy.
+const AOF_FSYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of shards each namespace is split into. Commands on keys of
+/// different shards run in parallel.
+const SHARDS: usize = 16;
+
+/// Maximum number of keys purged per shard lock acquisition. Keeping the
+/// batch small bounds how long the active expiry task can block clients.
+const ACTIVE_EXPIRE_BATCH: usize = 20;
+
+/// Error returned when a command is applied to a key holding another type.
+pub const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+/// Error returned when a stream or one of its consumer groups does not exist.
+pub const NOGROUP: &str = "NOGROUP No such key or consumer group";
+
+/// One call's worth of a cursor-based scan: the cursor to continue from,
+/// `0` once the scan is complete, and the elements visited.
+pub type ScanPage<T> = (u64, Vec<T>);
+
+/// One partition of a namespace, with its own lock. Keys are spread over
+/// the shards of their namespace by `shard_of`.
 #[derive(Debug)]
-struct Namespace {
-    entries: HashMap<String, Entry>,
-    lists: HashMap<String, VecDeque<Bytes>>, 
+struct Shard {
+    /// Every key in the shard, whatever the type of its value.
+    keys: HashMap<Bytes, Entry>,
+
+    /// Keys that have a deadline, ordered by when they expire. Lets the
+    /// active expiry task find the next keys to purge without scanning the
+    /// whole shard.
+    expirations: BTreeSet<(Instant, Bytes)>,
+
+    /// Every key, ordered by `scan_hash`. `SCAN` walks this order with the
+    /// hash as its cursor, so a key present for a whole scan is returned no
+    /// matter what is added or removed in between.
+    scan_order: BTreeSet<(u64, Bytes)>,
+
+    /// Sum of the `size` of every entry.
+    used_memory: usize,
+
+    /// Value of `used_memory` last folded into the `Db` total.
+    reported_memory: usize,
+
+    /// Keys handed out for writing since the last `settle`, whose size may
+    /// have changed.
+    dirty: Vec<Bytes>,
+
+    /// Writes since they were last folded into `Persistence::changes`.
+    changes: u64,
+
+    /// Keys watched by `WATCH`, with the number of watches on each and its
+    /// version, bumped whenever the key changes.
+    watched: HashMap<Bytes, (usize, u64)>,
 }
 
 /// Entry in the key-value store.
 #[derive(Debug, Clone)]
 struct Entry {
-    data: Bytes,
+    value: Value,
+
+    /// Instant at which the entry expires and should be removed.
+    expires_at: Option<Instant>,
+
+    /// Approximate bytes used by the entry, key included, as accounted for
+    /// in `Shard::used_memory`.
+    size: usize,
+
+    /// `memory::clock` reading of the last access, for LRU eviction.
+    accessed: Cell<u32>,
+
+    /// Logarithmic access counter, for LFU eviction.
+    frequency: Cell<u8>,
+}
+
+impl Entry {
+    fn new(value: Value, expires_at: Option<Instant>) -> Entry {
+        Entry {
+            value,
+            expires_at,
+            size: 0,
+            accessed: Cell::new(memory::clock()),
+            frequency: Cell::new(LFU_INIT),
+        }
+    }
+
+    /// Record an access to the entry.
+    fn touch(&self) {
+        let frequency = memory::lfu_decay(self.frequency.get(), self.accessed.get());
+        self.frequency.set(memory::lfu_increment(frequency));
+        self.accessed.set(memory::clock());
+    }
+
+    /// How strongly the entry should be evicted under `policy`; higher
+    /// scores go first.
+    fn eviction_score(&self, policy: EvictionPolicy) -> u64 {
+        let idle = memory::idle_ms(self.accessed.get()) as u64;
+        match policy {
+            EvictionPolicy::AllKeysLru | EvictionPolicy::VolatileLru => idle,
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => {
+                let frequency = memory::lfu_decay(self.frequency.get(), self.accessed.get());
+                ((u8::MAX - frequency) as u64) << 32 | idle
+            }
+            EvictionPolicy::VolatileTtl => {
+                let remaining = self.expires_at.map_or(Duration::MAX, |when| when.saturating_duration_since(Instant::now()));
+                u64::MAX - remaining.as_millis().min(u64::MAX as u128) as u64
+            }
+            EvictionPolicy::AllKeysRandom | EvictionPolicy::VolatileRandom | EvictionPolicy::NoEviction => {
+                rand::thread_rng().gen()
+            }
+        }
+    }
+}
+
+/// A value stored under a key.
+///
+/// Each variant is one data type. Commands only operate on the variant they
+/// expect and fail with `WRONGTYPE` on any other.
+#[derive(Debug, Clone)]
+pub(crate) enum Value {
+    String(Bytes),
+    List(VecDeque<Bytes>),
+    Hash(HashMap<Bytes, Bytes>),
+    Set(Set),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl Value {
+    /// Name of the value's type, as reported by the `TYPE` command.
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// Collections are removed from the keyspace once they become empty.
+    /// Streams are the exception: an empty stream still remembers its last
+    /// ID, so it stays.
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::String(_) | Value::Stream(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::SortedSet(zset) => zset.is_empty(),
+        }
+    }
+}
+
+/// Conversion between a `Value` variant and the data it wraps.
+///
+/// Implemented once per data type so that `Namespace` can offer typed
+/// accessors that check the type of a key in a single place.
+trait Typed: Sized {
+    fn wrap(self) -> Value;
+    fn view(value: &Value) -> Option<&Self>;
+    fn view_mut(value: &mut Value) -> Option<&mut Self>;
+}
+
+macro_rules! typed {
+    ($ty:ty, $variant:ident) => {
+        impl Typed for $ty {
+            fn wrap(self) -> Value {
+                Value::$variant(self)
+            }
+
+            fn view(value: &Value) -> Option<&Self> {
+                match value {
+                    Value::$variant(data) => Some(data),
+                    _ => None,
+                }
+            }
+
+            fn view_mut(value: &mut Value) -> Option<&mut Self> {
+                match value {
+                    Value::$variant(data) => Some(data),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+typed!(Bytes, String);
+typed!(VecDeque<Bytes>, List);
+typed!(HashMap<Bytes, Bytes>, Hash);
+typed!(Set, Set);
+typed!(SortedSet, SortedSet);
+typed!(Stream, Stream);
+
+/// Format a float the way replies and stored values expect: the shortest
+/// representation that round-trips, without a trailing `.0`.
+pub(crate) fn format_float(value: f64) -> String {
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    format!("{}", value)
+}
+
+fn pop_end(list: &mut VecDeque<Bytes>, end: End) -> Option<Bytes> {
+    match end {
+        End::Front => list.pop_front(),
+        End::Back => list.pop_back(),
+    }
+}
+
+fn push_end(list: &mut VecDeque<Bytes>, end: End, value: Bytes) {
+    match end {
+        End::Front => list.push_front(value),
+        End::Back => list.push_back(value),
+    }
+}
+
+/// Copy the entry at `source` in `from` to `destination` in `into`, or in
+/// `from` itself if `into` is `None`. Returns `false` if there was nothing
+/// to copy or `destination` exists and `replace` is not set.
+fn copy_entry<'a>(from: &mut Namespace<'a>, source: &[u8], into: Option<&mut Namespace<'a>>, destination: &[u8], replace: bool) -> bool {
+    from.expire_if_needed(source);
+    let entry = match from.entry(source) {
+        Some(entry) => entry.clone(),
+        None => return false,
+    };
+
+    let into = into.unwrap_or(from);
+    into.expire_if_needed(destination);
+    if into.contains(destination) && !replace {
+        return false;
+    }
+    into.put(Bytes::copy_from_slice(destination), entry);
+    true
+}
+
+/// Index of the shard holding `key`. Keys sharing a `scan_hash` always share
+/// a shard.
+fn shard_of(key: &[u8]) -> usize {
+    (scan_hash(key) % SHARDS as u64) as usize
+}
+
+/// Position of `key` in the order `SCAN` visits keys in. Must not change
+/// while the server runs, since clients hold on to cursors derived from it.
+fn scan_hash(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Resolve a possibly negative list index against a list of `len`
+/// elements.
+fn resolve_index(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+/// Resolve the inclusive, possibly negative index range `start..=stop`
+/// against a list of `len` elements. Returns `None` if it selects nothing.
+fn index_range(len: usize, start: i64, stop: i64) -> Option<std::ops::Range<usize>> {
+    let resolve = |index: i64| if index < 0 { index + len as i64 } else { index };
+    let start = resolve(start).max(0);
+    let stop = resolve(stop).min(len as i64 - 1);
+    (start <= stop).then(|| start as usize..stop as usize + 1)
+}
+
+/// The largest string `SETRANGE` and `APPEND` may produce, 512MB like
+/// Redis' default `proto-max-bulk-len`.
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/// The most elements `random_with_repeats` allocates room for up front.
+const MAX_RANDOM_PREALLOCATION: u64 = 1024;
+
+/// `count` elements picked at random from `pool`, possibly repeating some,
+/// as `HRANDFIELD` and `SRANDMEMBER` reply to a negative count.
+///
+/// The count comes from the client, so the reply grows as it is filled
+/// rather than being allocated for the whole count at once.
+fn random_with_repeats<T: Clone>(pool: &[T], count: u64) -> Vec<T> {
+    if pool.is_empty() {
+        return Vec::new();
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut picked = Vec::with_capacity(count.min(MAX_RANDOM_PREALLOCATION) as usize);
+    for _ in 0..count {
+        picked.push(pool[rng.gen_range(0..pool.len())].clone());
+    }
+    picked
+}
+
+/// Parse a stored value as a 64-bit signed integer.
+fn parse_int(value: &[u8]) -> Option<i64> {
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+/// Parse a stored value as a finite float.
+fn parse_float(value: &[u8]) -> Option<f64> {
+    std::str::from_utf8(value)
+        .ok()?
+        .parse()
+        .ok()
+        .filter(|value: &f64| !value.is_nan())
+}
+
+/// How a write should treat the deadline of the key it replaces.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Expiration {
+    /// Drop any existing deadline; the key persists.
+    #[default]
+    Clear,
+    /// Retain the deadline of the existing key, if any.
+    Keep,
+    /// Expire the key at the given instant.
+    At(Instant),
+}
+
+/// Condition under which `Db::expire_at` updates the deadline of a key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpireCondition {
+    Always,
+    /// Only if the key has no deadline (`NX`).
+    NoExpiry,
+    /// Only if the key already has a deadline (`XX`).
+    HasExpiry,
+    /// Only if the new deadline is later than the current one (`GT`).
+    Greater,
+    /// Only if the new deadline is earlier than the current one (`LT`).
+    Less,
+}
+
+/// Operation combining several sets, used by `Db::set_algebra`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOp {
+    /// Members present in every set (`SINTER`).
+    Inter,
+    /// Members present in any set (`SUNION`).
+    Union,
+    /// Members of the first set that are in none of the others (`SDIFF`).
+    Diff,
+}
+
+/// Conditions and extras for `Db::set_with_options`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SetOptions {
+    pub expiration: Expiration,
+    /// Only set the key if it does not exist (`NX`).
+    pub only_new: bool,
+    /// Only set the key if it already exists (`XX`).
+    pub only_existing: bool,
+    /// Return the previous value, which must be a string (`GET`).
+    pub get: bool,
+}
+
+/// Conditions on how `Db::zadd` may update members.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ZAddOptions {
+    /// Only add new members, never update existing ones (`NX`).
+    pub only_new: bool,
+    /// Only update existing members, never add new ones (`XX`).
+    pub only_existing: bool,
+    /// Only update a member if its new score is greater (`GT`).
+    pub greater: bool,
+    /// Only update a member if its new score is less (`LT`).
+    pub less: bool,
+}
+
+/// One end of a score range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+/// One end of a lexicographic range.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    /// Before every member (`-`).
+    Min,
+    /// After every member (`+`).
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+/// Selection of members of a sorted set.
+///
+/// Bounds are always given low to high; reversing the order of the result is
+/// requested separately.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZRange {
+    /// Ranks `start..=stop`; negative ranks count from the end.
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+/// How `Db::zstore` combines the scores of a member found in several sets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+/// How the ID of a new entry is chosen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XAddId {
+    /// Generate the whole ID from the current time (`*`).
+    Auto,
+    /// Use the given milliseconds and generate the sequence (`ms-*`).
+    AutoSeq(u64),
+    /// Use exactly this ID.
+    Explicit(StreamId),
+}
+
+/// How old entries are evicted from a stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrimStrategy {
+    /// Keep at most this many entries (`MAXLEN`).
+    MaxLen(usize),
+    /// Evict entries with IDs below this one (`MINID`).
+    MinId(StreamId),
+}
+
+/// Trimming requested by `XADD` or `XTRIM`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trim {
+    pub strategy: TrimStrategy,
+    /// Maximum number of entries evicted in one go (`LIMIT`).
+    pub limit: Option<usize>,
+}
+
+/// Where a stream read starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XReadFrom {
+    /// Entries with IDs greater than this one. For a consumer group read,
+    /// the consumer's own pending entries with greater IDs.
+    After(StreamId),
+    /// Only entries added after the read starts (`$`), or, for a consumer
+    /// group read, entries never delivered to the group (`>`).
+    New,
+}
+
+/// How `Db::restore` creates the key.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RestoreOptions {
+    /// Instant at which the key expires, or `None` for no expiration.
+    pub expires_at: Option<Instant>,
+    /// Overwrite the key if it already exists (`REPLACE`).
+    pub replace: bool,
+    /// Record the key as last accessed this long ago (`IDLETIME`).
+    pub idle: Option<Duration>,
+    /// Set the LFU access counter to this value (`FREQ`).
+    pub frequency: Option<u8>,
+}
+
+/// How `Db::xclaim` records the deliveries it makes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClaimOptions {
+    /// Record the entries as delivered this long ago rather than now
+    /// (`IDLE`, `TIME`).
+    pub idle: Option<Duration>,
+    /// Set the delivery counters to this value (`RETRYCOUNT`).
+    pub retry_count: Option<u64>,
+    /// Claim entries that exist in the stream even if they are not pending
+    /// (`FORCE`).
+    pub force: bool,
+    /// Leave the delivery counters alone (`JUSTID`).
+    pub just_id: bool,
+}
+
+/// Remaining time to live of a key, as reported by `Db::ttl`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ttl {
+    /// The key does not exist.
+    Missing,
+    /// The key exists but has no deadline.
+    Persistent,
+    /// The key expires after the given duration.
+    Expires(Duration),
 }
 
 /// A simple multi-namespace key-value store.
+///
+/// `Db` itself holds no notion of a "current" namespace. Every operation takes
+/// the namespace index explicitly; the index a client has selected lives in
+/// its `Session`.
 pub struct Db {
-    namespaces: Vec<Mutex<Namespace>>,
-    current_namespace_index: Mutex<usize>,  // Guarded by a Mutex for interior mutability
+    /// The shards of every namespace.
+    namespaces: Vec<Vec<Mutex<Shard>>>,
+
+    /// Namespace indexes by alias.
+    aliases: BTreeMap<String, usize>,
+
+    /// Memory use across all namespaces, and the limit it is held to.
+    memory: Memory,
+
+    /// Clients blocked on empty lists, served directly by pushes.
+    waiters: Waiters,
+
+    /// Clients blocked reading streams, woken by `XADD`.
+    watchers: Watchers,
+
+    /// Snapshot settings and state.
+    persistence: Persistence,
+
+    /// The append-only file.
+    aof: AppendOnly,
+
+    /// Passed shared by every command and exclusively by `EXEC`, so that a
+    /// transaction never interleaves with the commands of other clients.
+    exec_lock: Gate,
+
+    /// Cached scripts, and the one running.
+    scripts: Scripts,
+
+    /// Subscribers to published messages.
+    pubsub: Hub,
+
+    /// Connected clients.
+    clients: Clients,
 }
 
+impl Shard {
+    fn new() -> Shard {
+        Shard {
+            keys: HashMap::new(),
+            expirations: BTreeSet::new(),
+            scan_order: BTreeSet::new(),
+            used_memory: 0,
+            reported_memory: 0,
+            dirty: Vec::new(),
+            changes: 0,
+            watched: HashMap::new(),
+        }
+    }
 
-impl Db {
-    pub fn new() -> Db {
-        let namespaces = (0..16).map(|_| {
-            Mutex::new(Namespace {
-                entries: HashMap::new(),
-                lists: HashMap::new(),
-            })
-        }).collect();
-        
-        Db {
-            namespaces,
-            current_namespace_index: Mutex::new(0), // Default to namespace 0
+    /// Remove every key. Watched keys that existed count as changed.
+    fn clear(&mut self) {
+        let reported_memory = self.reported_memory;
+        let changes = self.changes + self.keys.len() as u64;
+        let mut watched = std::mem::take(&mut self.watched);
+        for (key, (_, version)) in &mut watched {
+            if self.keys.contains_key(key) {
+                *version += 1;
+            }
         }
+        *self = Shard::new();
+        self.reported_memory = reported_memory;
+        self.changes = changes;
+        self.watched = watched;
     }
 
+    /// Exchange the keys of two shards, for `SWAPDB`. Watches stay where
+    /// they are; watched keys held by either shard count as changed.
+    fn swap(&mut self, other: &mut Shard) {
+        std::mem::swap(self, other);
+        std::mem::swap(&mut self.watched, &mut other.watched);
+        for (key, (_, version)) in &mut self.watched {
+            if self.keys.contains_key(key) || other.keys.contains_key(key) {
+                *version += 1;
+            }
+        }
+        for (key, (_, version)) in &mut other.watched {
+            if self.keys.contains_key(key) || other.keys.contains_key(key) {
+                *version += 1;
+            }
+        }
+    }
 
-    /// Selects the namespace to operate on.
-    pub fn select_namespace(&self, index: usize) -> Result<(), String> {
-        let mut current_index = self.current_namespace_index.lock().unwrap();
-        if index < self.namespaces.len() {
-            *current_index = index;
-            Ok(())
+    /// Count a write to `key`, changing its version if it is watched.
+    fn changed(&mut self, key: &[u8]) {
+        self.changes += 1;
+        if let Some((_, version)) = self.watched.get_mut(key) {
+            *version += 1;
+        }
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.keys.contains_key(key)
+    }
+
+    fn deadline(&self, key: &[u8]) -> Option<Instant> {
+        self.keys.get(key).and_then(|entry| entry.expires_at)
+    }
+
+    /// The value at `key` as a `T`. Fails with `WRONGTYPE` if the key holds
+    /// another type.
+    fn read<T: Typed>(&self, key: &[u8]) -> Result<Option<&T>, &'static str> {
+        match self.keys.get(key) {
+            Some(entry) => {
+                entry.touch();
+                T::view(&entry.value).map(Some).ok_or(WRONGTYPE)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Mutable access to the value at `key` as a `T`.
+    ///
+    /// Callers must call `changed` once they have modified the value, and
+    /// `remove_if_empty` if they may have emptied a collection.
+    fn write<T: Typed>(&mut self, key: &[u8]) -> Result<Option<&mut T>, &'static str> {
+        let Some(entry) = self.keys.get_mut(key) else {
+            return Ok(None);
+        };
+        entry.touch();
+        let value = T::view_mut(&mut entry.value).ok_or(WRONGTYPE)?;
+        self.dirty.push(Bytes::copy_from_slice(key));
+        Ok(Some(value))
+    }
+
+    /// Mutable access to the value at `key`, creating an empty `T` if the key
+    /// does not exist. Callers must call `changed` as for `write`.
+    fn write_or_default<T: Typed + Default>(&mut self, key: &[u8]) -> Result<&mut T, &'static str> {
+        if let Some(entry) = self.keys.get(key) {
+            T::view(&entry.value).ok_or(WRONGTYPE)?;
         } else {
-            Err("Namespace index out of range".into())
-        }
-    }
-    /// Retrieves the value associated with a key in the current namespace.
-    pub fn get(&self, key: &str) -> Option<Bytes> {
-        let ns = self.namespaces[*self.current_namespace_index.lock().unwrap()].lock().unwrap();
-        ns.entries.get(key).map(|entry| entry.data.clone())
-            .or_else(|| {
-                ns.lists.get(key).map(|list| {
-                    // Serialize the list elements into a single Bytes object
-                    let serialized = list.iter()
-                        .map(|bytes| bytes.as_ref())
-                        .collect::<Vec<&[u8]>>()
-                        .join(&b' ');
-                    Bytes::from(serialized)
-                })
+            self.scan_order.insert((scan_hash(key), Bytes::copy_from_slice(key)));
+        }
+        self.dirty.push(Bytes::copy_from_slice(key));
+        let entry = self
+            .keys
+            .entry(Bytes::copy_from_slice(key))
+            .or_insert_with(|| Entry::new(T::default().wrap(), None));
+        entry.touch();
+        Ok(T::view_mut(&mut entry.value).expect("type checked"))
+    }
+
+    /// Replace the string at `key` with `value`, keeping its deadline, or
+    /// create it. The caller must have checked that `key` holds a string.
+    fn update_string(&mut self, key: &[u8], value: Bytes) {
+        match self.keys.get_mut(key) {
+            Some(entry) => {
+                entry.value = Value::String(value);
+                self.dirty.push(Bytes::copy_from_slice(key));
+                self.changed(key);
+            }
+            None => self.put(Bytes::copy_from_slice(key), Entry::new(Value::String(value), None)),
+        }
+    }
+
+    /// Drop `key` if it holds a collection that has become empty. Returns
+    /// whether it did.
+    fn remove_if_empty(&mut self, key: &[u8]) -> bool {
+        self.keys.get(key).is_some_and(|entry| entry.value.is_empty()) && self.remove(key)
+    }
+
+    /// Replace the deadline of an existing key, keeping `expirations` in sync.
+    fn set_deadline(&mut self, key: &[u8], when: Option<Instant>) {
+        let slot = match self.keys.get_mut(key) {
+            Some(entry) => &mut entry.expires_at,
+            None => return,
+        };
+
+        let previous = std::mem::replace(slot, when);
+        self.changed(key);
+        if let Some(previous) = previous {
+            self.expirations.remove(&(previous, Bytes::copy_from_slice(key)));
+        }
+        if let Some(when) = when {
+            self.expirations.insert((when, Bytes::copy_from_slice(key)));
+        }
+    }
+
+    /// Remove `key` regardless of its type. Returns `true` if it existed.
+    fn remove(&mut self, key: &[u8]) -> bool {
+        self.take(key).is_some()
+    }
+
+    /// Remove `key` regardless of its type and return its entry.
+    fn take(&mut self, key: &[u8]) -> Option<Entry> {
+        let entry = self.keys.remove(key)?;
+        if let Some(when) = entry.expires_at {
+            self.expirations.remove(&(when, Bytes::copy_from_slice(key)));
+        }
+        self.scan_order.remove(&(scan_hash(key), Bytes::copy_from_slice(key)));
+        self.used_memory -= entry.size;
+        self.changed(key);
+        Some(entry)
+    }
+
+    /// Store `entry` at `key`, deadline and access history included,
+    /// replacing any existing value.
+    fn put(&mut self, key: Bytes, mut entry: Entry) {
+        self.remove(&key);
+        if let Some(when) = entry.expires_at {
+            self.expirations.insert((when, key.clone()));
+        }
+        self.scan_order.insert((scan_hash(&key), key.clone()));
+        entry.size = ENTRY_OVERHEAD + key.len() + memory::value_size(&entry.value);
+        self.used_memory += entry.size;
+        self.changed(&key);
+        self.keys.insert(key, entry);
+    }
+
+    /// Refresh the size of every entry written since the last call, and
+    /// return how much `used_memory` changed since then.
+    fn settle(&mut self) -> isize {
+        for key in std::mem::take(&mut self.dirty) {
+            if let Some(entry) = self.keys.get_mut(&key) {
+                let size = ENTRY_OVERHEAD + key.len() + memory::value_size(&entry.value);
+                self.used_memory = self.used_memory - entry.size + size;
+                entry.size = size;
+            }
+        }
+
+        let delta = self.used_memory as isize - self.reported_memory as isize;
+        self.reported_memory = self.used_memory;
+        delta
+    }
+
+    /// Lazily expire `key` if its deadline has passed. Returns whether it
+    /// did.
+    fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        self.deadline(key).is_some_and(|when| when <= Instant::now()) && self.remove(key)
+    }
+
+    /// Purge at most `limit` expired keys, earliest deadlines first. Returns
+    /// the keys removed.
+    fn purge_expired(&mut self, limit: usize) -> Vec<Bytes> {
+        let now = Instant::now();
+        let mut purged = Vec::new();
+
+        while purged.len() < limit {
+            let key = match self.expirations.first() {
+                Some((when, key)) if *when <= now => key.clone(),
+                _ => break,
+            };
+
+            self.remove(&key);
+            purged.push(key);
+        }
+
+        purged
+    }
+}
+
+impl Shard {
+    /// A key picked at random, expired or not, or `None` if the namespace is
+    /// empty.
+    fn random_key(&self) -> Option<&Bytes> {
+        let start = rand::thread_rng().gen::<u64>();
+        self.scan_order
+            .range((start, Bytes::new())..)
+            .chain(self.scan_order.iter())
+            .next()
+            .map(|(_, key)| key)
+    }
+
+    /// A key picked at random among those with a deadline.
+    fn random_volatile_key(&self) -> Option<&Bytes> {
+        let first = self.expirations.first()?.0;
+        let last = self.expirations.last()?.0;
+        let span = last.duration_since(first).as_nanos().min(u64::MAX as u128) as u64;
+        let start = first + Duration::from_nanos(rand::thread_rng().gen_range(0..=span));
+        self.expirations
+            .range((start, Bytes::new())..)
+            .next()
+            .map(|(_, key)| key)
+    }
+
+    /// Sample keys that may be evicted under `policy`, each with its
+    /// `Entry::eviction_score`.
+    fn eviction_candidates(&self, policy: EvictionPolicy) -> Vec<(u64, Bytes)> {
+        let keys: Vec<&Bytes> = match policy {
+            EvictionPolicy::NoEviction => Vec::new(),
+            // The nearest deadlines are the best candidates; no need to
+            // sample.
+            EvictionPolicy::VolatileTtl => self.expirations.iter().take(EVICTION_SAMPLES).map(|(_, key)| key).collect(),
+            _ if policy.volatile_only() => (0..EVICTION_SAMPLES).filter_map(|_| self.random_volatile_key()).collect(),
+            _ => (0..EVICTION_SAMPLES).filter_map(|_| self.random_key()).collect(),
+        };
+
+        keys.into_iter()
+            .filter_map(|key| {
+                let entry = self.keys.get(key)?;
+                Some((entry.eviction_score(policy), key.clone()))
             })
+            .collect()
+    }
+
+}
+
+/// The locked shards of one namespace.
+///
+/// Commands lock the shards of every key they touch, and operate on the
+/// namespace through this view. Key operations are routed to the shard of
+/// the key, which must be among those locked.
+struct Namespace<'a> {
+    db: &'a Db,
+    index: usize,
+
+    /// Locked shards, by index. Shards are always locked in increasing
+    /// index, namespace by namespace, so that commands locking several of
+    /// them cannot deadlock.
+    shards: Vec<(usize, MutexGuard<'a, Shard>)>,
+
+    /// Keys with blocked clients that could not be served under the locks
+    /// held. They are served once the locks are released.
+    deferred: Vec<Bytes>,
+
+    /// Keyspace events, published once the locks are released.
+    events: Vec<(KeyspaceEvents, &'static str, Bytes)>,
+}
+
+impl Namespace<'_> {
+    /// Whether the shard of `key` is locked.
+    fn holds(&self, key: &[u8]) -> bool {
+        let index = shard_of(key);
+        self.shards.iter().any(|(shard, _)| *shard == index)
+    }
+
+    fn shard(&self, key: &[u8]) -> &Shard {
+        let index = shard_of(key);
+        match self.shards.iter().find(|(shard, _)| *shard == index) {
+            Some((_, shard)) => shard,
+            None => panic!("shard of key {:?} is not locked", key),
+        }
+    }
+
+    fn shard_mut(&mut self, key: &[u8]) -> &mut Shard {
+        let index = shard_of(key);
+        match self.shards.iter_mut().find(|(shard, _)| *shard == index) {
+            Some((_, shard)) => shard,
+            None => panic!("shard of key {:?} is not locked", key),
+        }
+    }
+
+    /// Every locked shard.
+    fn shards(&self) -> impl Iterator<Item = &Shard> {
+        self.shards.iter().map(|(_, shard)| &**shard)
+    }
+
+    /// Number of keys in the locked shards.
+    fn len(&self) -> usize {
+        self.shards().map(|shard| shard.keys.len()).sum()
+    }
+
+    fn entry(&self, key: &[u8]) -> Option<&Entry> {
+        self.shard(key).keys.get(key)
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.shard(key).contains(key)
+    }
+
+    fn deadline(&self, key: &[u8]) -> Option<Instant> {
+        self.shard(key).deadline(key)
+    }
+
+    /// See `Shard::read`.
+    fn read<T: Typed>(&self, key: &[u8]) -> Result<Option<&T>, &'static str> {
+        self.shard(key).read(key)
+    }
+
+    /// See `Shard::write`.
+    fn write<T: Typed>(&mut self, key: &[u8]) -> Result<Option<&mut T>, &'static str> {
+        self.shard_mut(key).write(key)
+    }
+
+    /// See `Shard::write_or_default`.
+    fn write_or_default<T: Typed + Default>(&mut self, key: &[u8]) -> Result<&mut T, &'static str> {
+        self.shard_mut(key).write_or_default(key)
+    }
+
+    /// See `Shard::changed`.
+    fn changed(&mut self, key: &[u8]) {
+        self.shard_mut(key).changed(key);
+    }
+
+    fn put(&mut self, key: Bytes, entry: Entry) {
+        self.shard_mut(&key).put(key, entry);
     }
 
-    /// Sets the value for a key in the current namespace
-    pub fn set(&self, key: String, value: Bytes) {
-        let mut ns = self.namespaces[*self.current_namespace_index.lock().unwrap()].lock().unwrap();
-        ns.entries.insert(key, Entry { data: value });
+    fn take(&mut self, key: &[u8]) -> Option<Entry> {
+        self.shard_mut(key).take(key)
     }
-    /// Sets the value for a key in the current namespace
-    pub fn exists(&self, key: &str) -> bool {
-        let ns = self.namespaces[*self.current_namespace_index.lock().unwrap()].lock().unwrap();
-        ns.entries.contains_key(key)
+
+    fn remove(&mut self, key: &[u8]) -> bool {
+        self.shard_mut(key).remove(key)
     }
 
-    pub fn lpush(&self, key: String, value: Bytes) -> Result<usize, &'static str>{
-        let mut ns = self.namespaces[*self.current_namespace_index.lock().unwrap()].lock().unwrap();
-        let entry = ns.entries.get(&key);
+    fn update_string(&mut self, key: &[u8], value: Bytes) {
+        self.shard_mut(key).update_string(key, value);
+    }
 
-        if entry.is_some() && ns.lists.get(&key).is_none() {
-            return Err("key holds a different type");
+    /// See `Shard::remove_if_empty`; the removal is a `del` event.
+    fn remove_if_empty(&mut self, key: &[u8]) {
+        if self.shard_mut(key).remove_if_empty(key) {
+            self.notify(KeyspaceEvents::GENERIC, "del", key);
         }
+    }
 
-        let list = ns.lists.entry(key).or_insert_with(VecDeque::new);
-        list.push_front(value);
-        Ok(list.len())
+    fn set_deadline(&mut self, key: &[u8], when: Option<Instant>) {
+        self.shard_mut(key).set_deadline(key, when);
     }
 
-    pub fn rpush(&self, key: String, value: Bytes) -> Result<usize, &'static str> {
-        let mut ns = self.namespaces[*self.current_namespace_index.lock().unwrap()].lock().unwrap();
-        let entry = ns.entries.get(&key);
+    fn expire_if_needed(&mut self, key: &[u8]) {
+        if self.shard_mut(key).expire_if_needed(key) {
+            self.notify(KeyspaceEvents::EXPIRED, "expired", key);
+        }
+    }
 
-        if entry.is_some() && ns.lists.get(&key).is_none() {
-            return Err("key holds a different type");
+    /// Purge at most `limit` expired keys from each locked shard. Returns
+    /// the number of keys removed.
+    fn purge_expired(&mut self, limit: usize) -> usize {
+        let purged: Vec<Bytes> = self.shards.iter_mut().flat_map(|(_, shard)| shard.purge_expired(limit)).collect();
+        for key in &purged {
+            self.notify(KeyspaceEvents::EXPIRED, "expired", key);
         }
+        purged.len()
+    }
 
-        let list = ns.lists.entry(key).or_insert_with(VecDeque::new);
-        list.push_back(value);
-        Ok(list.len())
+    /// Record the keyspace event `event`, of `class`, on `key`.
+    fn notify(&mut self, class: KeyspaceEvents, event: &'static str, key: &[u8]) {
+        if self.db.pubsub.notifies(class) {
+            self.events.push((class, event, Bytes::copy_from_slice(key)));
+        }
     }
 
-    pub async fn blpop(&self, keys: Vec<String>, timeout: Duration) -> Option<(String, Bytes)> {
-        let start = Instant::now();
-        while Instant::now().duration_since(start) < timeout {
-            for key in &keys {
-                let mut ns = self.namespaces[*self.current_namespace_index.lock().unwrap()].lock().unwrap();
-                if let Some(queue) = ns.lists.get_mut(key) {
-                    if let Some(value) = queue.pop_front() {
-                        return Some((key.clone(), value));
-                    }
-                }
-            }
-            // Sleep for a small interval before checking again.
-            tokio::time::sleep(Duration::from_millis(50)).await;
+    /// Record the events of a successful `pop` from `key`.
+    fn notify_pop(&mut self, key: &[u8], pop: &Pop) {
+        match pop {
+            Pop::List(End::Front) => self.notify(KeyspaceEvents::LIST, "lpop", key),
+            Pop::List(End::Back) => self.notify(KeyspaceEvents::LIST, "rpop", key),
+            Pop::Move { from, to, destination } => self.notify_move(key, destination, *from, *to),
+            Pop::Min => self.notify(KeyspaceEvents::ZSET, "zpopmin", key),
+            Pop::Max => self.notify(KeyspaceEvents::ZSET, "zpopmax", key),
         }
-        None
     }
 
-    pub async fn brpop(&self, keys: Vec<String>, timeout: Duration) -> Option<(String, Bytes)> {
-        let start = Instant::now();
-        while Instant::now().duration_since(start) < timeout {
-            for key in &keys {
-                let mut ns = self.namespaces[*self.current_namespace_index.lock().unwrap()].lock().unwrap();
-                if let Some(queue) = ns.lists.get_mut(key) {
-                    if let Some(value) = queue.pop_back() {
-                        return Some((key.clone(), value));
-                    }
-                }
-            }
-            // Sleep for a small interval before checking again.
-            tokio::time::sleep(Duration::from_millis(50)).await;
+    /// Record the events of an element moved from `source` to
+    /// `destination`: a pop from the one and a push onto the other.
+    fn notify_move(&mut self, source: &[u8], destination: &[u8], from: End, to: End) {
+        self.notify(KeyspaceEvents::LIST, if from == End::Front { "lpop" } else { "rpop" }, source);
+        self.notify(KeyspaceEvents::LIST, if to == End::Front { "lpush" } else { "rpush" }, destination);
+    }
+
+    /// Remove every key of the locked shards.
+    fn clear(&mut self) {
+        for (_, shard) in &mut self.shards {
+            shard.clear();
         }
-        None
     }
 
+    /// A key of the locked shards picked at random, expired or not.
+    fn random_key(&self) -> Option<&Bytes> {
+        let start = rand::thread_rng().gen::<u64>();
+        let after = self.shards().filter_map(|shard| shard.scan_order.range((start, Bytes::new())..).next()).min();
+        after
+            .or_else(|| self.shards().filter_map(|shard| shard.scan_order.first()).min())
+            .map(|(_, key)| key)
+    }
+
+    /// Store `value` at `key`, replacing any existing value of any type.
+    fn insert(&mut self, key: Bytes, value: Value, expires_at: Option<Instant>) {
+        self.put(key, Entry::new(value, expires_at));
+    }
+
+    /// Store the string `value` at `key`, applying `expiration` to the new
+    /// entry.
+    fn set_string(&mut self, key: Bytes, value: Bytes, expiration: Expiration) {
+        let expires_at = match expiration {
+            Expiration::Clear => None,
+            Expiration::Keep => self.deadline(&key),
+            Expiration::At(when) => Some(when),
+        };
 
+        if expires_at.is_some_and(|when| when <= Instant::now()) {
+            // Already in the past: the write is immediately expired.
+            if self.remove(&key) {
+                self.notify(KeyspaceEvents::GENERIC, "del", &key);
+            }
+            return;
+        }
+        self.notify(KeyspaceEvents::STRING, "set", &key);
+        if matches!(expiration, Expiration::At(_)) {
+            self.notify(KeyspaceEvents::GENERIC, "expire", &key);
+        }
+        self.insert(key, Value::String(value), expires_at);
+    }
+
+    /// Pop one element from the value at `key` as described by `pop`.
+    ///
+    /// Leaves an emptied collection in place; callers must call
+    /// `remove_if_empty` once done.
+    fn pop(&mut self, key: &[u8], pop: &Pop) -> Result<Option<Handoff>, &'static str> {
+        let popped = match pop {
+            Pop::List(end) => self.write::<VecDeque<Bytes>>(key)?.and_then(|list| pop_end(list, *end))
+            .map(|value| (value, None)),
+            Pop::Move { from, to, destination } => self
+                .move_element(key, destination, *from, *to)?
+                .map(|value| (value, None)),
+            Pop::Min | Pop::Max => self.write::<SortedSet>(key)?.and_then(|zset| {
+                let len = zset.len();
+                let rank = if *pop == Pop::Min { 0 } else { len.checked_sub(1)? };
+                zset.remove_ranks(rank, rank + 1).pop()
+            })
+            .map(|(member, score)| (member, Some(score))),
+        };
+
+        // `move_element` records its own changes.
+        if popped.is_some() && !matches!(pop, Pop::Move { .. }) {
+            self.changed(key);
+        }
+        Ok(popped.map(|(value, score)| Handoff { key: Bytes::copy_from_slice(key), value, score }))
+    }
+
+    /// Undo a `pop` whose value could not be delivered.
+    fn unpop(&mut self, pop: &Pop, handoff: Handoff) {
+        match pop {
+            Pop::List(end) => {
+                if let Ok(list) = self.write_or_default::<VecDeque<Bytes>>(&handoff.key) {
+                    push_end(list, *end, handoff.value);
+                }
+            }
+            Pop::Move { from, to, destination } => {
+                if let Ok(Some(list)) = self.write::<VecDeque<Bytes>>(destination) {
+                    pop_end(list, *to);
+                    self.changed(destination);
+                }
+                self.remove_if_empty(destination);
+                if let Ok(list) = self.write_or_default::<VecDeque<Bytes>>(&handoff.key) {
+                    push_end(list, *from, handoff.value);
+                }
+            }
+            Pop::Min | Pop::Max => {
+                if let Ok(zset) = self.write_or_default::<SortedSet>(&handoff.key) {
+                    zset.insert(handoff.value, handoff.score.unwrap_or_default());
+                }
+            }
+        }
+        self.changed(&handoff.key);
+    }
+
+    /// Pop an element from the `from` end of the list at `source` and push it
+    /// onto the `to` end of the list at `destination`. Returns the element, or
+    /// `None` if `source` is empty.
+    ///
+    /// Both types are checked before anything moves, the destination only if
+    /// there is something to move. Leaves an emptied
+    /// `source` in place; callers must call `remove_if_empty`.
+    fn move_element(&mut self, source: &[u8], destination: &[u8], from: End, to: End) -> Result<Option<Bytes>, &'static str> {
+        if self.read::<VecDeque<Bytes>>(source)?.is_none_or(|list| list.is_empty()) {
+            return Ok(None);
+        }
+        self.read::<VecDeque<Bytes>>(destination)?;
+        let value = match self.write::<VecDeque<Bytes>>(source)?.and_then(|list| pop_end(list, from)) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        push_end(self.write_or_default::<VecDeque<Bytes>>(destination)?, to, value.clone());
+        self.changed(source);
+        self.changed(destination);
+        Ok(Some(value))
+    }
+
+    /// Combine the sets at `keys` with `op`, treating missing keys as empty.
+    fn combine_sets(&mut self, op: SetOp, keys: &[Bytes]) -> Result<Set, &'static str> {
+        for key in keys {
+            self.expire_if_needed(key);
+        }
+
+        let mut sets = Vec::with_capacity(keys.len());
+        for key in keys {
+            sets.push(self.read::<Set>(key)?);
+        }
+
+        let empty = Set::default();
+        let mut sets = sets.into_iter().map(|set| set.unwrap_or(&empty));
+        let first = match sets.next() {
+            Some(first) => first.clone(),
+            None => return Ok(Set::default()),
+        };
+
+        Ok(sets.fold(first, |mut acc, set| {
+            match op {
+                SetOp::Inter => acc.retain(|member| set.contains(member)),
+                SetOp::Union => acc.extend(set.iter().cloned()),
+                SetOp::Diff => acc.retain(|member| !set.contains(member)),
+            }
+            acc
+        }))
+    }
+
+    /// Keys that are live and match `pattern` and `type_name`, if given.
+    fn matching_keys(&mut self, candidates: Vec<Bytes>, pattern: Option<&[u8]>, type_name: Option<&str>) -> Vec<Bytes> {
+        candidates
+            .into_iter()
+            .filter(|key| {
+                self.expire_if_needed(key);
+                let entry = match self.entry(key) {
+                    Some(entry) => entry,
+                    None => return false,
+                };
+                pattern.is_none_or(|pattern| glob::matches(pattern, key))
+                    && type_name.is_none_or(|type_name| entry.value.type_name() == type_name)
+            })
+            .collect()
+    }
+}
+
+impl Drop for Namespace<'_> {
+    fn drop(&mut self) {
+        for (_, shard) in &mut self.shards {
+            self.db.memory.adjust(shard.settle());
+            let changes = std::mem::take(&mut shard.changes);
+            if changes > 0 {
+                self.db.persistence.changes.fetch_add(changes, Ordering::Relaxed);
+                // Still under the locks, so that the write is logged before
+                // any later one to the same keys.
+                self.db.aof.changed();
+            }
+        }
+        self.shards.clear();
+
+        for (class, event, key) in std::mem::take(&mut self.events) {
+            self.db.pubsub.notify_keyspace_event(class, event, self.index, &key);
+        }
+        for key in std::mem::take(&mut self.deferred) {
+            self.db.serve_deferred(self.index, &key);
+        }
+    }
+}
+
+impl Default for Db {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Db {
+    pub fn new() -> Db {
+        Db::with_config(Config::default())
+    }
+
+    /// Creates a store configured by `config`.
+    ///
+    /// Panics if `config` is not valid; see `Config::validate`.
+    pub fn with_config(config: Config) -> Db {
+        if let Err(e) = config.validate() {
+            panic!("invalid configuration: {}", e);
+        }
+
+        let namespaces = (0..config.databases)
+            .map(|_| (0..SHARDS).map(|_| Mutex::new(Shard::new())).collect())
+            .collect();
+        let memory = Memory::default();
+        memory.limit.store(config.maxmemory, Ordering::Relaxed);
+        *memory.policy.lock().unwrap() = config.maxmemory_policy;
+
+        Db {
+            namespaces,
+            aliases: config.aliases,
+            memory,
+            waiters: Waiters::default(),
+            watchers: Watchers::default(),
+            persistence: Persistence::new(config.dbfilename, config.save),
+            aof: AppendOnly::new(config.appendfilename, config.appendfsync),
+            exec_lock: Gate::new(),
+            scripts: Scripts::default(),
+            pubsub: Hub::new(config.pubsub_queue_limit, config.notify_keyspace_events),
+            clients: Clients::new(),
+        }
+    }
+
+    /// Creates a store configured by `config` and loads its data: from the
+    /// append-only file if `config.appendonly` is set, from the snapshot at
+    /// `config.dbfilename` otherwise.
+    ///
+    /// Fails without loading anything if the snapshot cannot be read in
+    /// full, for instance because it is truncated or corrupted. An
+    /// append-only file that ends in the middle of a command is trimmed.
+    pub fn open(config: Config) -> crate::Result<Db> {
+        let append_only = config.appendonly;
+        let db = Db::with_config(config);
+        let db = if append_only {
+            aof::open(db)?
+        } else {
+            db.load_snapshot()?;
+            db
+        };
+
+        db.persistence.changes.store(0, Ordering::Relaxed);
+        Ok(db)
+    }
+
+    /// Loads the snapshot at the configured path, if there is one.
+    pub(crate) fn load_snapshot(&self) -> crate::Result<()> {
+        let path = &self.persistence.path;
+        let data = match snapshot::read_file(path)? {
+            Some(data) => data,
+            None => return Ok(()),
+        };
+
+        let namespaces = snapshot::decode(data).map_err(|e| format!("can't load snapshot '{}': {}", path.display(), e))?;
+        self.install(namespaces).map_err(|e| format!("can't load snapshot '{}': {}", path.display(), e))?;
+        Ok(())
+    }
+
+    /// Adds the keys of a decoded snapshot, skipping those that expired
+    /// since it was taken.
+    pub(crate) fn install(&self, namespaces: Vec<Vec<Record>>) -> Result<(), String> {
+        if namespaces.len() > self.namespaces.len() {
+            return Err(format!(
+                "it holds {} namespaces but only {} are configured",
+                namespaces.len(),
+                self.namespaces.len()
+            ));
+        }
+
+        let now = Instant::now();
+        for (index, records) in namespaces.into_iter().enumerate() {
+            let mut ns = self.lock_all(index);
+            for (key, value, deadline) in records {
+                if deadline.is_none_or(|when| when > now) {
+                    ns.put(key, Entry::new(value, deadline));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of namespaces in the store.
+    pub fn namespace_count(&self) -> usize {
+        self.namespaces.len()
+    }
+
+    /// Checks that `index` refers to an existing namespace.
+    pub fn check_namespace(&self, index: usize) -> Result<(), String> {
+        if index < self.namespaces.len() {
+            Ok(())
+        } else {
+            Err(format!("ERR DB index is out of range (0-{})", self.namespaces.len() - 1))
+        }
+    }
+
+    /// Namespace indexes by alias.
+    pub fn aliases(&self) -> &BTreeMap<String, usize> {
+        &self.aliases
+    }
+
+    /// Resolves `name`, either a namespace index or an alias, to the index
+    /// of an existing namespace.
+    pub fn resolve_namespace(&self, name: &str) -> Result<usize, String> {
+        if let Some(&index) = self.aliases.get(name) {
+            return Ok(index);
+        }
+
+        let index = name.parse::<i64>().map_err(|_| format!("ERR unknown namespace '{}'", name))?;
+        let index = usize::try_from(index).unwrap_or(usize::MAX);
+        self.check_namespace(index)?;
+        Ok(index)
+    }
+
+    /// Locks `shards` of namespace `index`.
+    fn lock_shards(&self, index: usize, mut shards: Vec<usize>) -> Namespace<'_> {
+        shards.sort_unstable();
+        shards.dedup();
+        let shards = shards
+            .into_iter()
+            .map(|shard| (shard, self.namespaces[index][shard].lock().unwrap()))
+            .collect();
+
+        Namespace { db: self, index, shards, deferred: Vec::new(), events: Vec::new() }
+    }
+
+    /// Locks the shard of `key` in namespace `index`.
+    fn lock(&self, index: usize, key: &[u8]) -> Namespace<'_> {
+        self.lock_shards(index, vec![shard_of(key)])
+    }
+
+    /// Locks the shards of every key in `keys`.
+    fn lock_keys<'k>(&self, index: usize, keys: impl IntoIterator<Item = &'k [u8]>) -> Namespace<'_> {
+        self.lock_shards(index, keys.into_iter().map(shard_of).collect())
+    }
+
+    /// Locks every shard of namespace `index`.
+    fn lock_all(&self, index: usize) -> Namespace<'_> {
+        self.lock_shards(index, (0..SHARDS).collect())
+    }
+
+    /// Locks shards of two distinct namespaces, always the lower index first
+    /// so that concurrent cross-namespace commands cannot deadlock.
+    fn lock_pair(&self, a: usize, a_shards: Vec<usize>, b: usize, b_shards: Vec<usize>) -> (Namespace<'_>, Namespace<'_>) {
+        debug_assert_ne!(a, b);
+        if a < b {
+            let first = self.lock_shards(a, a_shards);
+            (first, self.lock_shards(b, b_shards))
+        } else {
+            let second = self.lock_shards(b, b_shards);
+            (self.lock_shards(a, a_shards), second)
+        }
+    }
+
+    /// Serve the clients blocked on `key` that `serve_blocked` deferred,
+    /// locking the destinations of those moving elements elsewhere.
+    fn serve_deferred(&self, index: usize, key: &[u8]) {
+        let destinations = self.waiters.destinations(index, key);
+        let mut ns = self.lock_keys(index, std::iter::once(key).chain(destinations.iter().map(|key| &key[..])));
+        self.serve_blocked(index, &mut ns, key);
+    }
+
+    /// Let clients blocked on `key` know that it may now hold data.
+    ///
+    /// Called with the namespace lock held whenever a key appears other than
+    /// through the command that usually feeds it, such as `RENAME`.
+    fn key_ready(&self, index: usize, ns: &mut Namespace, key: &[u8]) {
+        self.serve_blocked(index, ns, key);
+        self.watchers.notify(index, key);
+    }
+
+    /// Retrieves the string value associated with a key in namespace `ns`.
+    pub fn get(&self, ns: usize, key: &[u8]) -> Result<Option<Bytes>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+        Ok(ns.read::<Bytes>(key)?.cloned())
+    }
+
+    /// Name of the type of the value at `key`, or `None` if it does not exist.
+    pub fn type_of(&self, ns: usize, key: &[u8]) -> Option<&'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+        ns.entry(key).map(|entry| entry.value.type_name())
+    }
+
+    /// Sets the value for a key in namespace `ns`, removing any deadline.
+    pub fn set(&self, ns: usize, key: Bytes, value: Bytes) {
+        self.set_with_expiration(ns, key, value, Expiration::Clear);
+    }
+
+    /// Sets the value for a key in namespace `ns`, applying `expiration` to
+    /// the new entry.
+    pub fn set_with_expiration(&self, ns: usize, key: Bytes, value: Bytes, expiration: Expiration) {
+        let mut ns = self.lock(ns, &key);
+        ns.expire_if_needed(&key);
+        ns.set_string(key, value, expiration);
+    }
+
+    /// Sets the value for a key in namespace `ns`, subject to `options`.
+    ///
+    /// Returns whether the value was written and, with `options.get`, the
+    /// previous value. Fails without writing if `options.get` is set and the
+    /// key holds another type.
+    pub fn set_with_options(&self, ns: usize, key: Bytes, value: Bytes, options: SetOptions) -> Result<(bool, Option<Bytes>), &'static str> {
+        let mut ns = self.lock(ns, &key);
+        ns.expire_if_needed(&key);
+
+        let previous = if options.get { ns.read::<Bytes>(&key)?.cloned() } else { None };
+        let exists = ns.contains(&key);
+        if (options.only_new && exists) || (options.only_existing && !exists) {
+            return Ok((false, previous));
+        }
+
+        ns.set_string(key, value, options.expiration);
+        Ok((true, previous))
+    }
+
+    /// Returns the string values at `keys`, `None` for keys that are missing
+    /// or hold another type.
+    pub fn mget(&self, ns: usize, keys: &[Bytes]) -> Vec<Option<Bytes>> {
+        let mut ns = self.lock_keys(ns, keys.iter().map(|key| &key[..]));
+        keys.iter()
+            .map(|key| {
+                ns.expire_if_needed(key);
+                ns.read::<Bytes>(key).ok().flatten().cloned()
+            })
+            .collect()
+    }
+
+    /// Sets every key in `pairs` to its value, removing any deadlines.
+    pub fn mset(&self, ns: usize, pairs: Vec<(Bytes, Bytes)>) {
+        let mut ns = self.lock_keys(ns, pairs.iter().map(|(key, _)| &key[..]));
+        for (key, value) in pairs {
+            ns.set_string(key, value, Expiration::Clear);
+        }
+    }
+
+    /// Like `mset`, but sets nothing if any of the keys exists. Returns
+    /// `true` if the keys were set.
+    pub fn msetnx(&self, ns: usize, pairs: Vec<(Bytes, Bytes)>) -> bool {
+        let mut ns = self.lock_keys(ns, pairs.iter().map(|(key, _)| &key[..]));
+        for (key, _) in &pairs {
+            ns.expire_if_needed(key);
+            if ns.contains(key) {
+                return false;
+            }
+        }
+
+        for (key, value) in pairs {
+            ns.set_string(key, value, Expiration::Clear);
+        }
+        true
+    }
+
+    /// Removes the string at `key` and returns it.
+    pub fn getdel(&self, ns: usize, key: &[u8]) -> Result<Option<Bytes>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let value = ns.read::<Bytes>(key)?.cloned();
+        if value.is_some() {
+            ns.remove(key);
+            ns.notify(KeyspaceEvents::GENERIC, "del", key);
+        }
+        Ok(value)
+    }
+
+    /// Returns the string at `key`, applying `expiration` to it if given.
+    /// `Expiration::Keep` leaves the deadline as is.
+    pub fn getex(&self, ns: usize, key: &[u8], expiration: Expiration) -> Result<Option<Bytes>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let value = ns.read::<Bytes>(key)?.cloned();
+        if value.is_some() {
+            match expiration {
+                Expiration::Keep => {}
+                Expiration::Clear => {
+                    ns.set_deadline(key, None);
+                    ns.notify(KeyspaceEvents::GENERIC, "persist", key);
+                }
+                Expiration::At(when) if when <= Instant::now() => {
+                    ns.remove(key);
+                    ns.notify(KeyspaceEvents::GENERIC, "del", key);
+                }
+                Expiration::At(when) => {
+                    ns.set_deadline(key, Some(when));
+                    ns.notify(KeyspaceEvents::GENERIC, "expire", key);
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    /// Adds `delta` to the integer stored at `key`, treating a missing key
+    /// as zero. The deadline of the key is kept. Returns the new value.
+    pub fn incr_by(&self, ns: usize, key: &[u8], delta: i64) -> Result<i64, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let current = match ns.read::<Bytes>(key)? {
+            Some(value) => parse_int(value).ok_or("ERR value is not an integer or out of range")?,
+            None => 0,
+        };
+
+        let updated = current
+            .checked_add(delta)
+            .ok_or("ERR increment or decrement would overflow")?;
+        ns.update_string(key, Bytes::from(updated.to_string()));
+        ns.notify(KeyspaceEvents::STRING, "incrby", key);
+        Ok(updated)
+    }
+
+    /// Adds `delta` to the float stored at `key`, treating a missing key as
+    /// zero. The deadline of the key is kept. Returns the new value as
+    /// stored.
+    pub fn incr_by_float(&self, ns: usize, key: &[u8], delta: f64) -> Result<Bytes, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let current = match ns.read::<Bytes>(key)? {
+            Some(value) => parse_float(value).ok_or("ERR value is not a valid float")?,
+            None => 0.0,
+        };
+
+        let updated = current + delta;
+        if !updated.is_finite() {
+            return Err("ERR increment would produce NaN or Infinity");
+        }
+
+        let formatted = Bytes::from(format_float(updated));
+        ns.update_string(key, formatted.clone());
+        ns.notify(KeyspaceEvents::STRING, "incrbyfloat", key);
+        Ok(formatted)
+    }
+
+    /// Appends `value` to the string at `key`, creating it if needed.
+    /// Returns the new length.
+    pub fn append(&self, ns: usize, key: &[u8], value: &[u8]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let current = ns.read::<Bytes>(key)?.map_or(&[][..], |current| &current[..]);
+        if current.len() + value.len() > MAX_STRING_LEN {
+            return Err("ERR string exceeds maximum allowed size (proto-max-bulk-len)");
+        }
+
+        let appended = [current, value].concat();
+        let len = appended.len();
+        ns.update_string(key, appended.into());
+        ns.notify(KeyspaceEvents::STRING, "append", key);
+        Ok(len)
+    }
+
+    /// Returns the length of the string at `key`, `0` if it does not exist.
+    pub fn strlen(&self, ns: usize, key: &[u8]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns.read::<Bytes>(key)?.map_or(0, |value| value.len()))
+    }
+
+    /// Returns the bytes of the string at `key` between offsets `start` and
+    /// `end`, both inclusive. Negative offsets count from the end.
+    pub fn getrange(&self, ns: usize, key: &[u8], start: i64, end: i64) -> Result<Bytes, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let value = match ns.read::<Bytes>(key)? {
+            Some(value) => value,
+            None => return Ok(Bytes::new()),
+        };
+        Ok(index_range(value.len(), start, end).map_or_else(Bytes::new, |range| value.slice(range)))
+    }
+
+    /// Overwrites the string at `key` with `value`, starting at `offset`.
+    /// The string is zero-padded if it is shorter than `offset`, and created
+    /// if needed unless `value` is empty. Returns the new length.
+    pub fn setrange(&self, ns: usize, key: &[u8], offset: usize, value: &[u8]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let current = ns.read::<Bytes>(key)?;
+        if value.is_empty() {
+            return Ok(current.map_or(0, |current| current.len()));
+        }
+
+        let end = offset
+            .checked_add(value.len())
+            .filter(|end| *end <= MAX_STRING_LEN)
+            .ok_or("ERR string exceeds maximum allowed size (proto-max-bulk-len)")?;
+
+        let mut updated = current.map_or_else(Vec::new, |current| current.to_vec());
+        if updated.len() < end {
+            updated.resize(end, 0);
+        }
+        updated[offset..end].copy_from_slice(value);
+
+        let len = updated.len();
+        ns.update_string(key, updated.into());
+        ns.notify(KeyspaceEvents::STRING, "setrange", key);
+        Ok(len)
+    }
+
+    /// Checks whether a key exists in namespace `ns`.
+    pub fn exists(&self, ns: usize, key: &[u8]) -> bool {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+        ns.contains(key)
+    }
+
+    /// Sets the deadline of `key` to `when`, subject to `condition`.
+    ///
+    /// A deadline that is already in the past deletes the key. Returns `true`
+    /// if the key exists and the deadline was applied.
+    pub fn expire_at(&self, ns: usize, key: &[u8], when: Instant, condition: ExpireCondition) -> bool {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        if !ns.contains(key) {
+            return false;
+        }
+
+        // A key without a deadline is considered to live forever, so it is
+        // never "less than" a new deadline and always "greater".
+        let current = ns.deadline(key);
+        let allowed = match condition {
+            ExpireCondition::Always => true,
+            ExpireCondition::NoExpiry => current.is_none(),
+            ExpireCondition::HasExpiry => current.is_some(),
+            ExpireCondition::Greater => current.is_some_and(|current| when > current),
+            ExpireCondition::Less => current.is_none_or(|current| when < current),
+        };
+
+        if !allowed {
+            return false;
+        }
+
+        if when <= Instant::now() {
+            ns.remove(key);
+            ns.notify(KeyspaceEvents::GENERIC, "del", key);
+        } else {
+            ns.set_deadline(key, Some(when));
+            ns.notify(KeyspaceEvents::GENERIC, "expire", key);
+        }
+        true
+    }
+
+    /// Removes the deadline from `key`. Returns `true` if a deadline was
+    /// removed.
+    pub fn persist(&self, ns: usize, key: &[u8]) -> bool {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        if ns.deadline(key).is_none() {
+            return false;
+        }
+
+        ns.set_deadline(key, None);
+        ns.notify(KeyspaceEvents::GENERIC, "persist", key);
+        true
+    }
+
+    /// Returns the remaining time to live of `key`.
+    pub fn ttl(&self, ns: usize, key: &[u8]) -> Ttl {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        if !ns.contains(key) {
+            return Ttl::Missing;
+        }
+
+        match ns.deadline(key) {
+            Some(when) => Ttl::Expires(when.saturating_duration_since(Instant::now())),
+            None => Ttl::Persistent,
+        }
+    }
+
+    /// Deletes `keys`, whatever their type. Returns the number of keys that
+    /// existed.
+    pub fn del(&self, index: usize, keys: &[Bytes]) -> usize {
+        let mut ns = self.lock_keys(index, keys.iter().map(|key| &key[..]));
+        keys.iter()
+            .filter(|key| {
+                ns.expire_if_needed(key);
+                let removed = ns.remove(key);
+                if removed {
+                    // Readers blocked on a deleted stream must notice.
+                    self.watchers.notify(index, key);
+                    ns.notify(KeyspaceEvents::GENERIC, "del", key);
+                }
+                removed
+            })
+            .count()
+    }
+
+    /// Renames `source` to `destination`, replacing any value there unless
+    /// `only_new` is set. The deadline moves with the value.
+    ///
+    /// Returns `false` if `only_new` prevented the rename.
+    pub fn rename(&self, index: usize, source: &[u8], destination: &[u8], only_new: bool) -> Result<bool, &'static str> {
+        let mut ns = self.lock_keys(index, [source, destination]);
+        ns.expire_if_needed(source);
+        ns.expire_if_needed(destination);
+
+        if !ns.contains(source) {
+            return Err("ERR no such key");
+        }
+        if source == destination {
+            return Ok(!only_new);
+        }
+        if only_new && ns.contains(destination) {
+            return Ok(false);
+        }
+
+        let entry = ns.take(source).expect("source exists");
+        ns.put(Bytes::copy_from_slice(destination), entry);
+        ns.notify(KeyspaceEvents::GENERIC, "rename_from", source);
+        ns.notify(KeyspaceEvents::GENERIC, "rename_to", destination);
+        self.watchers.notify(index, source);
+        self.key_ready(index, &mut ns, destination);
+        Ok(true)
+    }
+
+    /// Copies the value at `source` to `destination` in namespace `to`,
+    /// replacing any value there only if `replace` is set. The copy keeps
+    /// the deadline of the original.
+    ///
+    /// Returns `false` if `source` does not exist or `destination` does and
+    /// may not be replaced.
+    pub fn copy(&self, index: usize, source: &[u8], to: usize, destination: &[u8], replace: bool) -> Result<bool, &'static str> {
+        if index == to && source == destination {
+            return Err("ERR source and destination objects are the same");
+        }
+
+        if index == to {
+            let mut ns = self.lock_keys(index, [source, destination]);
+            let copied = copy_entry(&mut ns, source, None, destination, replace);
+            if copied {
+                ns.notify(KeyspaceEvents::GENERIC, "copy_to", destination);
+                self.key_ready(index, &mut ns, destination);
+            }
+            return Ok(copied);
+        }
+
+        let (mut from, mut into) = self.lock_pair(index, vec![shard_of(source)], to, vec![shard_of(destination)]);
+        let copied = copy_entry(&mut from, source, Some(&mut into), destination, replace);
+        // Serving blocked clients may lock more shards of `to`, which must
+        // not happen while holding a shard of a higher namespace.
+        drop(from);
+        if copied {
+            into.notify(KeyspaceEvents::GENERIC, "copy_to", destination);
+            self.key_ready(to, &mut into, destination);
+        }
+        Ok(copied)
+    }
+
+    /// Moves `key` from namespace `index` to namespace `to`, keeping its
+    /// deadline. Returns `false` if `key` does not exist or `to` already
+    /// holds it.
+    pub fn move_key(&self, index: usize, key: &[u8], to: usize) -> Result<bool, &'static str> {
+        if index == to {
+            return Err("ERR source and destination objects are the same");
+        }
+
+        let (mut from, mut into) = self.lock_pair(index, vec![shard_of(key)], to, vec![shard_of(key)]);
+        from.expire_if_needed(key);
+        into.expire_if_needed(key);
+        if !from.contains(key) || into.contains(key) {
+            return Ok(false);
+        }
+
+        let entry = from.take(key).expect("key exists");
+        into.put(Bytes::copy_from_slice(key), entry);
+        from.notify(KeyspaceEvents::GENERIC, "move_from", key);
+        into.notify(KeyspaceEvents::GENERIC, "move_to", key);
+        drop(from);
+        self.watchers.notify(index, key);
+        self.key_ready(to, &mut into, key);
+        Ok(true)
+    }
+
+    /// Serializes the value at `key` in namespace `ns` into an opaque
+    /// payload for `restore`. Returns `None` if the key does not exist.
+    pub fn dump(&self, ns: usize, key: &[u8]) -> Option<Bytes> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+        ns.entry(key).map(|entry| snapshot::dump(&entry.value).into())
+    }
+
+    /// Creates `key` in namespace `index` from a payload produced by `dump`.
+    /// A deadline that has already passed leaves the key deleted.
+    pub fn restore(&self, index: usize, key: Bytes, payload: Bytes, options: RestoreOptions) -> Result<(), &'static str> {
+        let value = snapshot::restore(payload)?;
+
+        let mut ns = self.lock(index, &key);
+        ns.expire_if_needed(&key);
+        if ns.contains(&key) {
+            if !options.replace {
+                return Err("BUSYKEY Target key name already exists.");
+            }
+            ns.remove(&key);
+            self.watchers.notify(index, &key);
+        }
+        if options.expires_at.is_some_and(|when| when <= Instant::now()) {
+            if options.replace {
+                ns.notify(KeyspaceEvents::GENERIC, "del", &key);
+            }
+            return Ok(());
+        }
+
+        let entry = Entry::new(value, options.expires_at);
+        if let Some(idle) = options.idle {
+            entry.accessed.set(memory::clock().wrapping_sub(idle.as_millis().min(u32::MAX as u128) as u32));
+        }
+        if let Some(frequency) = options.frequency {
+            entry.frequency.set(frequency);
+        }
+        ns.put(key.clone(), entry);
+        ns.notify(KeyspaceEvents::GENERIC, "restore", &key);
+        self.key_ready(index, &mut ns, &key);
+        Ok(())
+    }
+
+    /// Returns the number of keys in namespace `ns`, including expired keys
+    /// that have not been purged yet.
+    pub fn dbsize(&self, ns: usize) -> usize {
+        self.lock_all(ns).len()
+    }
+
+    /// Deletes every key in namespace `index`.
+    pub fn flushdb(&self, index: usize) {
+        let mut ns = self.lock_all(index);
+        ns.clear();
+        self.watchers.notify_all(index);
+    }
+
+    /// Deletes every key in every namespace.
+    pub fn flushall(&self) {
+        for index in 0..self.namespaces.len() {
+            self.flushdb(index);
+        }
+    }
+
+    /// Atomically exchanges the contents of namespaces `a` and `b`. Clients
+    /// connected to one see the data of the other from then on.
+    pub fn swapdb(&self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+
+        let (mut first, mut second) = self.lock_pair(a, (0..SHARDS).collect(), b, (0..SHARDS).collect());
+        for ((_, first), (_, second)) in first.shards.iter_mut().zip(&mut second.shards) {
+            first.swap(second);
+        }
+        // Counted on a shard like any other write, so that it is logged in
+        // order with the writes around it.
+        first.shards[0].1.changes += 1;
+
+        // Clients blocked in either namespace may now find data.
+        for (index, ns) in [(a, &mut first), (b, &mut second)] {
+            for key in self.waiters.keys(index) {
+                self.serve_blocked(index, ns, &key);
+            }
+            self.watchers.notify_all(index);
+        }
+    }
+
+    /// See `exec_lock`.
+    pub(crate) fn exec_lock(&self) -> &Gate {
+        &self.exec_lock
+    }
+
+    /// The script cache.
+    pub(crate) fn scripts(&self) -> &Scripts {
+        &self.scripts
+    }
+
+    /// The publish/subscribe hub.
+    pub(crate) fn pubsub(&self) -> &Hub {
+        &self.pubsub
+    }
+
+    /// The registry of connected clients.
+    pub(crate) fn clients(&self) -> &Clients {
+        &self.clients
+    }
+
+    /// Starts watching `key` in namespace `index`, for `WATCH`. Returns the
+    /// current version of the key, which changes whenever the key does
+    /// until every watch is undone with `unwatch`.
+    pub fn watch(&self, index: usize, key: Bytes) -> u64 {
+        let mut ns = self.lock(index, &key);
+        ns.expire_if_needed(&key);
+        let (watches, version) = ns.shard_mut(&key).watched.entry(key).or_default();
+        *watches += 1;
+        *version
+    }
+
+    /// Undoes one `watch` of `key` in namespace `index`.
+    pub fn unwatch(&self, index: usize, key: &[u8]) {
+        let mut ns = self.lock(index, key);
+        let watched = &mut ns.shard_mut(key).watched;
+        if let Some((watches, _)) = watched.get_mut(key) {
+            *watches -= 1;
+            if *watches == 0 {
+                watched.remove(key);
+            }
+        }
+    }
+
+    /// Current version of `key` in namespace `index`, which must be watched.
+    /// A key whose deadline has passed is expired first, which changes it.
+    pub fn watched_version(&self, index: usize, key: &[u8]) -> Option<u64> {
+        let mut ns = self.lock(index, key);
+        ns.expire_if_needed(key);
+        ns.shard(key).watched.get(key).map(|(_, version)| *version)
+    }
+
+    /// Returns a random key of namespace `ns`, or `None` if it is empty.
+    pub fn randomkey(&self, ns: usize) -> Option<Bytes> {
+        let mut ns = self.lock_all(ns);
+        loop {
+            let key = ns.random_key()?.clone();
+            ns.expire_if_needed(&key);
+            if ns.contains(&key) {
+                return Some(key);
+            }
+        }
+    }
+
+    /// Returns the keys of namespace `ns` matching `pattern`, or all of them.
+    pub fn keys(&self, ns: usize, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        let mut ns = self.lock_all(ns);
+        let candidates = ns.shards().flat_map(|shard| shard.keys.keys().cloned()).collect();
+        ns.matching_keys(candidates, pattern, None)
+    }
+
+    /// Visits about `count` keys of namespace `ns`, starting at `cursor`, and
+    /// returns the ones matching `pattern` and `type_name` along with the
+    /// cursor to continue from. A returned cursor of `0` ends the scan.
+    ///
+    /// Every key present for the whole scan is returned exactly once. Keys
+    /// added or removed while it runs may or may not be.
+    pub fn scan(&self, ns: usize, cursor: u64, count: usize, pattern: Option<&[u8]>, type_name: Option<&str>) -> (u64, Vec<Bytes>) {
+        let mut ns = self.lock_all(ns);
+
+        // Merge the scan order of every shard. Each shard contributes at
+        // most `count` keys plus those sharing a hash with its last one;
+        // `limit` is the first hash a shard left out, which the scan must
+        // not go past.
+        let mut order: Vec<(u64, Bytes)> = Vec::new();
+        let mut limit: Option<u64> = None;
+        for shard in ns.shards() {
+            let mut last = None;
+            for (taken, (hash, key)) in shard.scan_order.range((cursor, Bytes::new())..).enumerate() {
+                if taken >= count && last != Some(*hash) {
+                    limit = Some(limit.map_or(*hash, |limit| limit.min(*hash)));
+                    break;
+                }
+                order.push((*hash, key.clone()));
+                last = Some(*hash);
+            }
+        }
+        order.sort_unstable();
+
+        let mut candidates: Vec<Bytes> = Vec::new();
+        let mut last = None;
+        let mut next = limit.unwrap_or(0);
+        for (hash, key) in &order {
+            if limit.is_some_and(|limit| *hash >= limit) {
+                break;
+            }
+            // Keys sharing a hash also share a cursor, so never stop between
+            // them.
+            if candidates.len() >= count && last != Some(*hash) {
+                next = *hash;
+                break;
+            }
+            candidates.push(key.clone());
+            last = Some(*hash);
+        }
+
+        (next, ns.matching_keys(candidates, pattern, type_name))
+    }
+
+    /// Pushes `values` onto the head of the list at `key`, one after the
+    /// other, creating the list if needed. Returns the new length.
+    pub fn lpush(&self, ns: usize, key: Bytes, values: Vec<Bytes>) -> Result<usize, &'static str> {
+        self.push(ns, key, values, End::Front, false)
+    }
+
+    /// Pushes `values` onto the tail of the list at `key`, creating the list
+    /// if needed. Returns the new length.
+    pub fn rpush(&self, ns: usize, key: Bytes, values: Vec<Bytes>) -> Result<usize, &'static str> {
+        self.push(ns, key, values, End::Back, false)
+    }
+
+    /// Like `lpush`, but only if the list already exists. Returns `0`
+    /// otherwise.
+    pub fn lpushx(&self, ns: usize, key: Bytes, values: Vec<Bytes>) -> Result<usize, &'static str> {
+        self.push(ns, key, values, End::Front, true)
+    }
+
+    /// Like `rpush`, but only if the list already exists. Returns `0`
+    /// otherwise.
+    pub fn rpushx(&self, ns: usize, key: Bytes, values: Vec<Bytes>) -> Result<usize, &'static str> {
+        self.push(ns, key, values, End::Back, true)
+    }
+
+    fn push(&self, index: usize, key: Bytes, values: Vec<Bytes>, end: End, only_existing: bool) -> Result<usize, &'static str> {
+        let mut ns = self.lock(index, &key);
+        ns.expire_if_needed(&key);
+
+        if only_existing && ns.read::<VecDeque<Bytes>>(&key)?.is_none() {
+            return Ok(0);
+        }
+
+        let list = ns.write_or_default::<VecDeque<Bytes>>(&key)?;
+        for value in values {
+            push_end(list, end, value);
+        }
+        let len = list.len();
+        ns.changed(&key);
+        ns.notify(KeyspaceEvents::LIST, if end == End::Front { "lpush" } else { "rpush" }, &key);
+
+        self.serve_blocked(index, &mut ns, &key);
+        Ok(len)
+    }
+
+    /// Removes and returns up to `count` elements from the head of the list
+    /// at `key`. Returns `None` if the key does not exist.
+    pub fn lpop(&self, ns: usize, key: &[u8], count: usize) -> Result<Option<Vec<Bytes>>, &'static str> {
+        self.list_pop(ns, key, count, End::Front)
+    }
+
+    /// Removes and returns up to `count` elements from the tail of the list
+    /// at `key`. Returns `None` if the key does not exist.
+    pub fn rpop(&self, ns: usize, key: &[u8], count: usize) -> Result<Option<Vec<Bytes>>, &'static str> {
+        self.list_pop(ns, key, count, End::Back)
+    }
+
+    fn list_pop(&self, ns: usize, key: &[u8], count: usize, end: End) -> Result<Option<Vec<Bytes>>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let popped = ns
+            .write::<VecDeque<Bytes>>(key)?
+            .map(|list| (0..count).map_while(|_| pop_end(list, end)).collect::<Vec<_>>());
+        if popped.as_ref().is_some_and(|popped| !popped.is_empty()) {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::LIST, if end == End::Front { "lpop" } else { "rpop" }, key);
+        }
+        ns.remove_if_empty(key);
+        Ok(popped)
+    }
+
+    /// Returns the length of the list at `key`, `0` if it does not exist.
+    pub fn llen(&self, ns: usize, key: &[u8]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns.read::<VecDeque<Bytes>>(key)?.map_or(0, |list| list.len()))
+    }
+
+    /// Returns the elements of the list at `key` between indexes `start` and
+    /// `stop`, both inclusive. Negative indexes count from the tail.
+    pub fn lrange(&self, ns: usize, key: &[u8], start: i64, stop: i64) -> Result<Vec<Bytes>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let list = match ns.read::<VecDeque<Bytes>>(key)? {
+            Some(list) => list,
+            None => return Ok(Vec::new()),
+        };
+        Ok(index_range(list.len(), start, stop).map_or_else(Vec::new, |range| list.range(range).cloned().collect()))
+    }
+
+    /// Returns the element at `index` in the list at `key`. Negative indexes
+    /// count from the tail.
+    pub fn lindex(&self, ns: usize, key: &[u8], index: i64) -> Result<Option<Bytes>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns
+            .read::<VecDeque<Bytes>>(key)?
+            .and_then(|list| list.get(resolve_index(list.len(), index)?).cloned()))
+    }
+
+    /// Replaces the element at `index` in the list at `key`.
+    pub fn lset(&self, ns: usize, key: &[u8], index: i64, value: Bytes) -> Result<(), &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let list = ns.write::<VecDeque<Bytes>>(key)?.ok_or("ERR no such key")?;
+        let index = resolve_index(list.len(), index).ok_or("ERR index out of range")?;
+        list[index] = value;
+        ns.changed(key);
+        ns.notify(KeyspaceEvents::LIST, "lset", key);
+        Ok(())
+    }
+
+    /// Removes elements equal to `element` from the list at `key`: the first
+    /// `count` from the head if `count` is positive, the first `-count` from
+    /// the tail if it is negative, and all of them if it is zero. Returns the
+    /// number of elements removed.
+    pub fn lrem(&self, ns: usize, key: &[u8], count: i64, element: &[u8]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let list = match ns.write::<VecDeque<Bytes>>(key)? {
+            Some(list) => list,
+            None => return Ok(0),
+        };
+
+        let limit = if count == 0 { usize::MAX } else { count.unsigned_abs() as usize };
+        let mut matches: Vec<usize> = list.iter().enumerate().filter(|(_, value)| *value == element).map(|(index, _)| index).collect();
+        if count < 0 {
+            matches.reverse();
+        }
+        matches.truncate(limit);
+        matches.sort_unstable();
+
+        // Remove from the back so earlier indexes stay valid.
+        for index in matches.iter().rev() {
+            list.remove(*index);
+        }
+        if !matches.is_empty() {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::LIST, "lrem", key);
+        }
+
+        ns.remove_if_empty(key);
+        Ok(matches.len())
+    }
+
+    /// Trims the list at `key` to the elements between `start` and `stop`,
+    /// both inclusive. Negative indexes count from the tail.
+    pub fn ltrim(&self, ns: usize, key: &[u8], start: i64, stop: i64) -> Result<(), &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        if let Some(list) = ns.write::<VecDeque<Bytes>>(key)? {
+            match index_range(list.len(), start, stop) {
+                Some(range) => {
+                    list.truncate(range.end);
+                    list.drain(..range.start);
+                }
+                None => list.clear(),
+            }
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::LIST, "ltrim", key);
+        }
+        ns.remove_if_empty(key);
+        Ok(())
+    }
+
+    /// Inserts `element` before (or, with `after`, after) the first element
+    /// equal to `pivot` in the list at `key`. Returns the new length, `-1` if
+    /// `pivot` was not found and `0` if the key does not exist.
+    pub fn linsert(&self, ns: usize, key: &[u8], after: bool, pivot: &[u8], element: Bytes) -> Result<i64, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let list = match ns.write::<VecDeque<Bytes>>(key)? {
+            Some(list) => list,
+            None => return Ok(0),
+        };
+        match list.iter().position(|value| value == pivot) {
+            Some(index) => {
+                list.insert(index + after as usize, element);
+                let len = list.len() as i64;
+                ns.changed(key);
+                ns.notify(KeyspaceEvents::LIST, "linsert", key);
+                Ok(len)
+            }
+            None => Ok(-1),
+        }
+    }
+
+    /// Returns the indexes of elements equal to `element` in the list at
+    /// `key`.
+    ///
+    /// Matching starts at the `rank`-th match, scanning from the tail if
+    /// `rank` is negative, and stops after `count` matches (all of them if
+    /// zero) or `max_len` compared elements (all of them if zero).
+    pub fn lpos(&self, ns: usize, key: &[u8], element: &[u8], rank: i64, count: usize, max_len: usize) -> Result<Vec<usize>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let list = match ns.read::<VecDeque<Bytes>>(key)? {
+            Some(list) => list,
+            None => return Ok(Vec::new()),
+        };
+
+        let len = list.len();
+        let scanned = if max_len == 0 { len } else { max_len.min(len) };
+        let indexes: Box<dyn Iterator<Item = usize>> = if rank < 0 {
+            Box::new((len - scanned..len).rev())
+        } else {
+            Box::new(0..scanned)
+        };
+
+        let count = if count == 0 { usize::MAX } else { count };
+        Ok(indexes
+            .filter(|index| list[*index] == element)
+            .skip(rank.unsigned_abs() as usize - 1)
+            .take(count)
+            .collect())
+    }
+
+    /// Atomically pops an element from the `from` end of the list at
+    /// `source` and pushes it onto the `to` end of the list at
+    /// `destination`. Returns the element, or `None` if `source` is empty.
+    pub fn lmove(&self, index: usize, source: &[u8], destination: &[u8], from: End, to: End) -> Result<Option<Bytes>, &'static str> {
+        let mut ns = self.lock_keys(index, [source, destination]);
+        ns.expire_if_needed(source);
+        ns.expire_if_needed(destination);
+
+        let moved = ns.move_element(source, destination, from, to)?;
+        if moved.is_some() {
+            ns.notify_move(source, destination, from, to);
+        }
+        ns.remove_if_empty(source);
+        if moved.is_some() {
+            self.serve_blocked(index, &mut ns, destination);
+        }
+        Ok(moved)
+    }
+
+    /// Like `lmove`, but blocks until an element is pushed if `source` is
+    /// empty.
+    ///
+    /// A zero `timeout` blocks indefinitely.
+    pub async fn blmove(&self, ns: usize, source: Bytes, destination: Bytes, from: End, to: End, timeout: Duration) -> Result<Option<Bytes>, &'static str> {
+        let pop = Pop::Move { from, to, destination };
+        let handoff = self.blocking_pop(ns, vec![source], timeout, pop).await?;
+        Ok(handoff.map(|handoff| handoff.value))
+    }
+
+    /// Hand elements of the value at `key` to clients blocked on it, oldest
+    /// first, until either the value or the queue of waiters runs dry.
+    ///
+    /// Called with the namespace lock held, right after data was added.
+    fn serve_blocked(&self, index: usize, ns: &mut Namespace, key: &[u8]) {
+        // Serving a blocked `BLMOVE` takes the lock of its destination. If it
+        // is not held, serve the key once the locks are released instead.
+        // No waiter can register on the key in the meantime, since that
+        // takes the lock of the key.
+        if self.waiters.destinations(index, key).iter().any(|destination| !ns.holds(destination)) {
+            ns.deferred.push(Bytes::copy_from_slice(key));
+            return;
+        }
+
+        // Lists that received an element through a blocked `BLMOVE`; their
+        // own waiters are served once this key is done.
+        let mut destinations = Vec::new();
+
+        while let Some(entry) = ns.entry(key).filter(|entry| !entry.value.is_empty()) {
+            let type_name = entry.value.type_name();
+            let (pop, sender) = match self.waiters.next(index, key, type_name) {
+                Some(waiter) => waiter,
+                None => break,
+            };
+
+            let handoff = match ns.pop(key, &pop) {
+                Ok(Some(handoff)) => handoff,
+                // The destination of a move holds another type. Fail the
+                // waiter and leave the element for the next one.
+                Err(e) => {
+                    let _ = sender.send(Err(e));
+                    continue;
+                }
+                Ok(None) => break,
+            };
+
+            // The client may have gone away since it was dequeued. Put the
+            // element back where it came from and try the next waiter.
+            match sender.send(Ok(handoff)) {
+                Ok(()) => {
+                    self.aof.record(index, pop.command(&Bytes::copy_from_slice(key)));
+                    ns.notify_pop(key, &pop);
+                    if let Pop::Move { destination, .. } = pop {
+                        if destination != key {
+                            destinations.push(destination);
+                        }
+                    }
+                }
+                Err(served) => ns.unpop(&pop, served.expect("sent a handoff")),
+            }
+        }
+
+        ns.remove_if_empty(key);
+        for destination in destinations {
+            self.serve_blocked(index, ns, &destination);
+        }
+    }
+
+    /// Pop from the head of the first non-empty list among `keys`, blocking
+    /// until an element is pushed if all of them are empty.
+    ///
+    /// A zero `timeout` blocks indefinitely.
+    pub async fn blpop(&self, ns: usize, keys: Vec<Bytes>, timeout: Duration) -> Result<Option<(Bytes, Bytes)>, &'static str> {
+        let handoff = self.blocking_pop(ns, keys, timeout, Pop::List(End::Front)).await?;
+        Ok(handoff.map(|handoff| (handoff.key, handoff.value)))
+    }
+
+    /// Pop from the tail of the first non-empty list among `keys`, blocking
+    /// until an element is pushed if all of them are empty.
+    ///
+    /// A zero `timeout` blocks indefinitely.
+    pub async fn brpop(&self, ns: usize, keys: Vec<Bytes>, timeout: Duration) -> Result<Option<(Bytes, Bytes)>, &'static str> {
+        let handoff = self.blocking_pop(ns, keys, timeout, Pop::List(End::Back)).await?;
+        Ok(handoff.map(|handoff| (handoff.key, handoff.value)))
+    }
+
+    /// Pop the lowest (or, with `max`, the highest) scored member of the first
+    /// non-empty sorted set among `keys`, blocking until a member is added if
+    /// all of them are empty.
+    ///
+    /// A zero `timeout` blocks indefinitely.
+    pub async fn bzpop(&self, ns: usize, keys: Vec<Bytes>, timeout: Duration, max: bool) -> Result<Option<(Bytes, Bytes, f64)>, &'static str> {
+        let pop = if max { Pop::Max } else { Pop::Min };
+        let handoff = self.blocking_pop(ns, keys, timeout, pop).await?;
+        Ok(handoff.map(|handoff| (handoff.key, handoff.value, handoff.score.unwrap_or_default())))
+    }
+
+    async fn blocking_pop(&self, index: usize, keys: Vec<Bytes>, timeout: Duration, pop: Pop) -> Result<Option<Handoff>, &'static str> {
+        let (id, receiver) = {
+            let destination = match &pop {
+                Pop::Move { destination, .. } => Some(&destination[..]),
+                _ => None,
+            };
+            let mut ns = self.lock_keys(index, keys.iter().map(|key| &key[..]).chain(destination));
+
+            for key in &keys {
+                ns.expire_if_needed(key);
+                if let Some(handoff) = ns.pop(key, &pop)? {
+                    ns.notify_pop(key, &pop);
+                    ns.remove_if_empty(key);
+                    if let Pop::Move { destination, .. } = &pop {
+                        self.serve_blocked(index, &mut ns, destination);
+                    }
+                    return Ok(Some(handoff));
+                }
+            }
+
+            // Every key is empty. Register while still holding the lock so
+            // that no write can slip in unnoticed.
+            self.waiters.register(index, &keys, pop.clone())
+        };
+
+        // Dropping the future (the client disconnected) or timing out must
+        // take the waiter out of the queues, and put back anything handed
+        // over that was not taken.
+        let mut registration = Registration { db: self, index, keys: &keys, id, pop, receiver };
+
+        if timeout.is_zero() {
+            return (&mut registration.receiver).await.ok().transpose();
+        }
+
+        match tokio::time::timeout(timeout, &mut registration.receiver).await {
+            Ok(served) => served.ok().transpose(),
+            Err(_) => {
+                // An element may have been handed over right as the timeout
+                // fired; close the channel and keep it rather than losing it.
+                registration.receiver.close();
+                registration.receiver.try_recv().ok().transpose()
+            }
+        }
+    }
+
+    /// Put back `handoff`, popped for a blocked client that went away before
+    /// taking it, and serve the next client waiting on its key.
+    ///
+    /// A moved element is left in its destination, as if `LMOVE` had run.
+    fn requeue(&self, index: usize, pop: &Pop, handoff: Handoff) {
+        let Some(command) = pop.undo_command(&handoff) else { return };
+        let key = handoff.key.clone();
+        let mut ns = self.lock(index, &key);
+        ns.expire_if_needed(&key);
+
+        self.aof.record(index, command);
+        match pop {
+            Pop::List(End::Front) => ns.notify(KeyspaceEvents::LIST, "lpush", &key),
+            Pop::List(End::Back) => ns.notify(KeyspaceEvents::LIST, "rpush", &key),
+            _ => ns.notify(KeyspaceEvents::ZSET, "zadd", &key),
+        }
+        ns.unpop(pop, handoff);
+        self.serve_blocked(index, &mut ns, &key);
+    }
+
+    /// Sets `fields` in the hash at `key`, creating the hash if needed.
+    /// Returns the number of fields that were newly added.
+    pub fn hset(&self, ns: usize, key: &[u8], fields: Vec<(Bytes, Bytes)>) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let hash = ns.write_or_default::<HashMap<Bytes, Bytes>>(key)?;
+        let added = fields
+            .into_iter()
+            .filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none())
+            .count();
+        ns.changed(key);
+        ns.notify(KeyspaceEvents::HASH, "hset", key);
+        Ok(added)
+    }
+
+    /// Sets `field` in the hash at `key` only if it does not exist yet.
+    /// Returns `true` if the field was set.
+    pub fn hsetnx(&self, ns: usize, key: &[u8], field: Bytes, value: Bytes) -> Result<bool, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let hash = ns.write_or_default::<HashMap<Bytes, Bytes>>(key)?;
+        if hash.contains_key(&field) {
+            return Ok(false);
+        }
+        hash.insert(field, value);
+        ns.changed(key);
+        ns.notify(KeyspaceEvents::HASH, "hset", key);
+        Ok(true)
+    }
+
+    /// Returns the value of `field` in the hash at `key`.
+    pub fn hget(&self, ns: usize, key: &[u8], field: &[u8]) -> Result<Option<Bytes>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let hash = ns.read::<HashMap<Bytes, Bytes>>(key)?;
+        Ok(hash.and_then(|hash| hash.get(field).cloned()))
+    }
+
+    /// Returns the values of `fields` in the hash at `key`, in order.
+    pub fn hmget(&self, ns: usize, key: &[u8], fields: &[Bytes]) -> Result<Vec<Option<Bytes>>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let hash = ns.read::<HashMap<Bytes, Bytes>>(key)?;
+        Ok(fields
+            .iter()
+            .map(|field| hash.and_then(|hash| hash.get(field).cloned()))
+            .collect())
+    }
+
+    /// Returns every field and value in the hash at `key`.
+    pub fn hgetall(&self, ns: usize, key: &[u8]) -> Result<Vec<(Bytes, Bytes)>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let hash = ns.read::<HashMap<Bytes, Bytes>>(key)?;
+        Ok(hash
+            .map(|hash| hash.iter().map(|(field, value)| (field.clone(), value.clone())).collect())
+            .unwrap_or_default())
+    }
+
+    /// Visits about `count` fields of the hash at `key`, starting at
+    /// `cursor`, and returns them with their values along with the cursor to
+    /// continue from. A returned cursor of `0` ends the scan.
+    ///
+    /// Fields are visited in the order `SCAN` visits keys in, with the same
+    /// guarantees. Finding the next ones walks the whole hash, but only
+    /// those returned are copied.
+    pub fn hscan(&self, ns: usize, key: &[u8], cursor: u64, count: usize) -> Result<ScanPage<(Bytes, Bytes)>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let hash = match ns.read::<HashMap<Bytes, Bytes>>(key)? {
+            Some(hash) => hash,
+            None => return Ok((0, Vec::new())),
+        };
+
+        let mut order: Vec<(u64, &Bytes, &Bytes)> = hash
+            .iter()
+            .map(|(field, value)| (scan_hash(field), field, value))
+            .filter(|(hash, _, _)| *hash >= cursor)
+            .collect();
+        let mut next = 0;
+        if order.len() > count {
+            // Fields sharing a hash also share a cursor, so return every
+            // field up to the `count`th one's hash.
+            let (_, &mut (last, _, _), _) = order.select_nth_unstable_by_key(count - 1, |(hash, _, _)| *hash);
+            next = order.iter().map(|(hash, _, _)| *hash).filter(|hash| *hash > last).min().unwrap_or(0);
+            order.retain(|(hash, _, _)| *hash <= last);
+        }
+        order.sort_unstable_by_key(|(hash, _, _)| *hash);
+
+        Ok((next, order.into_iter().map(|(_, field, value)| (field.clone(), value.clone())).collect()))
+    }
+
+    /// Removes `fields` from the hash at `key`, deleting the key once the
+    /// hash is empty. Returns the number of fields removed.
+    pub fn hdel(&self, ns: usize, key: &[u8], fields: &[Bytes]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let removed = match ns.write::<HashMap<Bytes, Bytes>>(key)? {
+            Some(hash) => fields.iter().filter(|field| hash.remove(*field).is_some()).count(),
+            None => 0,
+        };
+        if removed > 0 {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::HASH, "hdel", key);
+        }
+        ns.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    /// Returns the number of fields in the hash at `key`.
+    pub fn hlen(&self, ns: usize, key: &[u8]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns.read::<HashMap<Bytes, Bytes>>(key)?.map_or(0, |hash| hash.len()))
+    }
+
+    /// Adds `delta` to the integer stored in `field`, treating a missing
+    /// field as zero. Returns the new value.
+    pub fn hincrby(&self, ns: usize, key: &[u8], field: Bytes, delta: i64) -> Result<i64, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let hash = ns.write_or_default::<HashMap<Bytes, Bytes>>(key)?;
+        let current = match hash.get(&field) {
+            Some(value) => parse_int(value).ok_or("ERR hash value is not an integer")?,
+            None => 0,
+        };
+
+        let updated = current
+            .checked_add(delta)
+            .ok_or("ERR increment or decrement would overflow")?;
+        hash.insert(field, Bytes::from(updated.to_string()));
+        ns.changed(key);
+        ns.notify(KeyspaceEvents::HASH, "hincrby", key);
+        Ok(updated)
+    }
+
+    /// Adds `delta` to the float stored in `field`, treating a missing field
+    /// as zero. Returns the new value as stored.
+    pub fn hincrbyfloat(&self, ns: usize, key: &[u8], field: Bytes, delta: f64) -> Result<Bytes, &'static str> {
+        if !delta.is_finite() {
+            return Err("ERR increment would produce NaN or Infinity");
+        }
+
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let hash = ns.write_or_default::<HashMap<Bytes, Bytes>>(key)?;
+        let current = match hash.get(&field) {
+            Some(value) => parse_float(value).ok_or("ERR hash value is not a float")?,
+            None => 0.0,
+        };
+
+        let updated = current + delta;
+        if !updated.is_finite() {
+            return Err("ERR increment would produce NaN or Infinity");
+        }
+
+        let updated = Bytes::from(format_float(updated));
+        hash.insert(field, updated.clone());
+        ns.changed(key);
+        ns.notify(KeyspaceEvents::HASH, "hincrbyfloat", key);
+        Ok(updated)
+    }
+
+    /// Returns random fields from the hash at `key`.
+    ///
+    /// A positive `count` returns distinct fields, at most as many as the hash
+    /// holds. A negative `count` returns exactly `-count` fields, possibly
+    /// repeating some.
+    pub fn hrandfield(&self, ns: usize, key: &[u8], count: i64) -> Result<Vec<(Bytes, Bytes)>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let hash = match ns.read::<HashMap<Bytes, Bytes>>(key)? {
+            Some(hash) => hash,
+            None => return Ok(Vec::new()),
+        };
+
+        let pair = |(field, value): (&Bytes, &Bytes)| (field.clone(), value.clone());
+        if count >= 0 {
+            let count = (count as usize).min(hash.len());
+            return Ok(hash.iter().choose_multiple(&mut rand::thread_rng(), count).into_iter().map(pair).collect());
+        }
+
+        // Repeats are picked once the lock is released, however many are
+        // asked for.
+        let pairs: Vec<_> = hash.iter().map(pair).collect();
+        drop(ns);
+        Ok(random_with_repeats(&pairs, count.unsigned_abs()))
+    }
+
+    /// Adds `members` to the set at `key`, creating it if needed. Returns the
+    /// number of members that were not already present.
+    pub fn sadd(&self, ns: usize, key: &[u8], members: Vec<Bytes>) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let set = ns.write_or_default::<Set>(key)?;
+        let added = members.into_iter().filter(|member| set.insert(member.clone())).count();
+        if added > 0 {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::SET, "sadd", key);
+        }
+        Ok(added)
+    }
+
+    /// Removes `members` from the set at `key`, deleting the key once the set
+    /// is empty. Returns the number of members removed.
+    pub fn srem(&self, ns: usize, key: &[u8], members: &[Bytes]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let removed = match ns.write::<Set>(key)? {
+            Some(set) => members.iter().filter(|member| set.remove(member)).count(),
+            None => 0,
+        };
+        if removed > 0 {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::SET, "srem", key);
+        }
+        ns.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    /// Reports, for each of `members`, whether it belongs to the set at `key`.
+    pub fn smismember(&self, ns: usize, key: &[u8], members: &[Bytes]) -> Result<Vec<bool>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let set = ns.read::<Set>(key)?;
+        Ok(members
+            .iter()
+            .map(|member| set.is_some_and(|set| set.contains(member)))
+            .collect())
+    }
+
+    /// Returns every member of the set at `key`.
+    pub fn smembers(&self, ns: usize, key: &[u8]) -> Result<Vec<Bytes>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let set = ns.read::<Set>(key)?;
+        Ok(set.map(|set| set.iter().cloned().collect()).unwrap_or_default())
+    }
+
+    /// Visits about `count` members of the set at `key`, starting at
+    /// `cursor`, and returns them along with the cursor to continue from. A
+    /// returned cursor of `0` ends the scan. See `Set::scan`.
+    pub fn sscan(&self, ns: usize, key: &[u8], cursor: u64, count: usize) -> Result<ScanPage<Bytes>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns
+            .read::<Set>(key)?
+            .map(|set| {
+                let (next, members) = set.scan(cursor, count);
+                (next, members.to_vec())
+            })
+            .unwrap_or_default())
+    }
+
+    /// Returns the number of members of the set at `key`.
+    pub fn scard(&self, ns: usize, key: &[u8]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns.read::<Set>(key)?.map_or(0, |set| set.len()))
+    }
+
+    /// Removes and returns up to `count` random members of the set at `key`.
+    pub fn spop(&self, ns: usize, key: &[u8], count: usize) -> Result<Vec<Bytes>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let popped = match ns.write::<Set>(key)? {
+            Some(set) => {
+                let mut rng = rand::thread_rng();
+                (0..count.min(set.len())).filter_map(|_| set.pop_random(&mut rng)).collect()
+            }
+            None => Vec::new(),
+        };
+        if !popped.is_empty() {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::SET, "spop", key);
+        }
+        ns.remove_if_empty(key);
+        Ok(popped)
+    }
+
+    /// Returns random members of the set at `key` without removing them.
+    ///
+    /// A positive `count` returns distinct members, at most as many as the set
+    /// holds. A negative `count` returns exactly `-count` members, possibly
+    /// repeating some.
+    pub fn srandmember(&self, ns: usize, key: &[u8], count: i64) -> Result<Vec<Bytes>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let set = match ns.read::<Set>(key)? {
+            Some(set) => set,
+            None => return Ok(Vec::new()),
+        };
+
+        if count >= 0 {
+            let count = (count as usize).min(set.len());
+            return Ok(set.as_slice().choose_multiple(&mut rand::thread_rng(), count).cloned().collect());
+        }
+
+        // Repeats are picked once the lock is released, however many are
+        // asked for.
+        let members = set.as_slice().to_vec();
+        drop(ns);
+        Ok(random_with_repeats(&members, count.unsigned_abs()))
+    }
+
+    /// Moves `member` from the set at `source` to the set at `destination`.
+    /// Returns `true` if the member was moved.
+    ///
+    /// Both keys live in the same namespace, so the move happens under a
+    /// single lock and is atomic.
+    pub fn smove(&self, ns: usize, source: &[u8], destination: &[u8], member: Bytes) -> Result<bool, &'static str> {
+        let mut ns = self.lock_keys(ns, [source, destination]);
+        ns.expire_if_needed(source);
+        ns.expire_if_needed(destination);
+
+        // Check both types up front so a failed move leaves no trace.
+        ns.read::<Set>(destination)?;
+        if source == destination {
+            return Ok(ns.read::<Set>(source)?.is_some_and(|set| set.contains(&member)));
+        }
+
+        let removed = match ns.write::<Set>(source)? {
+            Some(set) => set.remove(&member),
+            None => false,
+        };
+        if !removed {
+            return Ok(false);
+        }
+
+        ns.changed(source);
+        ns.notify(KeyspaceEvents::SET, "srem", source);
+        ns.remove_if_empty(source);
+        if ns.write_or_default::<Set>(destination)?.insert(member) {
+            ns.changed(destination);
+        }
+        ns.notify(KeyspaceEvents::SET, "sadd", destination);
+        Ok(true)
+    }
+
+    /// Combines the sets at `keys` with `op`. Missing keys count as empty
+    /// sets.
+    ///
+    /// Every key is read with the shards holding them locked. Shards are
+    /// always locked in the same order, so concurrent set algebra commands
+    /// cannot deadlock, and all are held at once, so they always see a
+    /// consistent snapshot.
+    pub fn set_algebra(&self, ns: usize, op: SetOp, keys: &[Bytes]) -> Result<Vec<Bytes>, &'static str> {
+        let mut ns = self.lock_keys(ns, keys.iter().map(|key| &key[..]));
+        Ok(ns.combine_sets(op, keys)?.into_iter().collect())
+    }
+
+    /// Returns the number of members the sets at `keys` have in common, up
+    /// to `limit` if it is not 0. Missing keys count as empty sets.
+    ///
+    /// Only the smallest set is walked, and only until `limit` members are
+    /// found.
+    pub fn sintercard(&self, ns: usize, keys: &[Bytes], limit: usize) -> Result<usize, &'static str> {
+        let mut ns = self.lock_keys(ns, keys.iter().map(|key| &key[..]));
+        for key in keys {
+            ns.expire_if_needed(key);
+        }
+
+        // Every key is type checked, even once one is known to be missing.
+        let mut sets = Vec::with_capacity(keys.len());
+        for key in keys {
+            sets.push(ns.read::<Set>(key)?);
+        }
+        let Some(mut sets) = sets.into_iter().collect::<Option<Vec<_>>>() else {
+            return Ok(0);
+        };
+        sets.sort_by_key(|set| set.len());
+
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let Some((smallest, others)) = sets.split_first() else { return Ok(0) };
+        Ok(smallest
+            .iter()
+            .filter(|member| others.iter().all(|set| set.contains(member)))
+            .take(limit)
+            .count())
+    }
+
+    /// Like `set_algebra`, but stores the result at `destination`, replacing
+    /// whatever it held. An empty result deletes `destination`. Returns the
+    /// size of the result.
+    pub fn set_algebra_store(&self, ns: usize, op: SetOp, destination: Bytes, keys: &[Bytes]) -> Result<usize, &'static str> {
+        let mut ns = self.lock_keys(ns, keys.iter().chain([&destination]).map(|key| &key[..]));
+        let result = ns.combine_sets(op, keys)?;
+        let len = result.len();
+
+        if result.is_empty() {
+            if ns.remove(&destination) {
+                ns.notify(KeyspaceEvents::GENERIC, "del", &destination);
+            }
+        } else {
+            let event = match op {
+                SetOp::Inter => "sinterstore",
+                SetOp::Union => "sunionstore",
+                SetOp::Diff => "sdiffstore",
+            };
+            ns.notify(KeyspaceEvents::SET, event, &destination);
+            ns.insert(destination, Value::Set(result), None);
+        }
+        Ok(len)
+    }
+
+    /// Adds or updates `members` of the sorted set at `key`, subject to
+    /// `options`. Returns the number of members added and the number of
+    /// existing members whose score changed.
+    pub fn zadd(&self, index: usize, key: &[u8], options: ZAddOptions, members: Vec<(f64, Bytes)>) -> Result<(usize, usize), &'static str> {
+        let mut ns = self.lock(index, key);
+        ns.expire_if_needed(key);
+
+        // Nothing to update and nothing allowed to be added: leave a missing
+        // key missing.
+        if options.only_existing && ns.read::<SortedSet>(key)?.is_none() {
+            return Ok((0, 0));
+        }
+
+        let zset = ns.write_or_default::<SortedSet>(key)?;
+        let mut added = 0;
+        let mut changed = 0;
+
+        for (score, member) in members {
+            match zset.score(&member) {
+                Some(current) => {
+                    if options.only_new
+                        || current == score
+                        || (options.greater && score <= current)
+                        || (options.less && score >= current)
+                    {
+                        continue;
+                    }
+                    zset.insert(member, score);
+                    changed += 1;
+                }
+                None if options.only_existing => {}
+                None => {
+                    zset.insert(member, score);
+                    added += 1;
+                }
+            }
+        }
+        if added + changed > 0 {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::ZSET, "zadd", key);
+        }
+
+        self.serve_blocked(index, &mut ns, key);
+        Ok((added, changed))
+    }
+
+    /// Adds `delta` to the score of `member`, adding the member with score
+    /// `delta` if it does not exist, subject to `options`. Returns the new
+    /// score, or `None` if `options` prevented the update.
+    pub fn zincrby(&self, index: usize, key: &[u8], options: ZAddOptions, delta: f64, member: Bytes) -> Result<Option<f64>, &'static str> {
+        let mut ns = self.lock(index, key);
+        ns.expire_if_needed(key);
+
+        let current = ns.read::<SortedSet>(key)?.and_then(|zset| zset.score(&member));
+        let score = current.unwrap_or(0.0) + delta;
+
+        let skip = match current {
+            Some(current) => options.only_new || (options.greater && score <= current) || (options.less && score >= current),
+            None => options.only_existing,
+        };
+        if skip {
+            return Ok(None);
+        }
+        if score.is_nan() {
+            return Err("ERR resulting score is not a number (NaN)");
+        }
+
+        ns.write_or_default::<SortedSet>(key)?.insert(member, score);
+        ns.changed(key);
+        ns.notify(KeyspaceEvents::ZSET, "zincr", key);
+        self.serve_blocked(index, &mut ns, key);
+        Ok(Some(score))
+    }
+
+    /// Removes `members` from the sorted set at `key`. Returns the number of
+    /// members removed.
+    pub fn zrem(&self, ns: usize, key: &[u8], members: &[Bytes]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let removed = match ns.write::<SortedSet>(key)? {
+            Some(zset) => members.iter().filter(|member| zset.remove(member)).count(),
+            None => 0,
+        };
+        if removed > 0 {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::ZSET, "zrem", key);
+        }
+        ns.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    /// Returns the number of members of the sorted set at `key`.
+    pub fn zcard(&self, ns: usize, key: &[u8]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns.read::<SortedSet>(key)?.map_or(0, |zset| zset.len()))
+    }
+
+    /// Returns the scores of `members` in the sorted set at `key`, in order.
+    pub fn zmscore(&self, ns: usize, key: &[u8], members: &[Bytes]) -> Result<Vec<Option<f64>>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let zset = ns.read::<SortedSet>(key)?;
+        Ok(members
+            .iter()
+            .map(|member| zset.and_then(|zset| zset.score(member)))
+            .collect())
+    }
+
+    /// Returns the rank of `member` and its score. Ranks count from the
+    /// highest score when `reverse` is set.
+    pub fn zrank(&self, ns: usize, key: &[u8], member: &[u8], reverse: bool) -> Result<Option<(usize, f64)>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let zset = match ns.read::<SortedSet>(key)? {
+            Some(zset) => zset,
+            None => return Ok(None),
+        };
+
+        Ok(zset.rank(member).map(|rank| {
+            let rank = if reverse { zset.len() - 1 - rank } else { rank };
+            (rank, zset.score(member).unwrap_or_default())
+        }))
+    }
+
+    /// Visits `count` members of the sorted set at `key`, starting at rank
+    /// `cursor`, and returns them with their scores along with the cursor to
+    /// continue from. A returned cursor of `0` ends the scan.
+    ///
+    /// Members added or removed below the cursor while the scan runs shift
+    /// the others' ranks, so those may be skipped or visited twice.
+    pub fn zscan(&self, ns: usize, key: &[u8], cursor: u64, count: usize) -> Result<ScanPage<(Bytes, f64)>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let zset = match ns.read::<SortedSet>(key)? {
+            Some(zset) => zset,
+            None => return Ok((0, Vec::new())),
+        };
+
+        let start = usize::try_from(cursor).unwrap_or(usize::MAX).min(zset.len());
+        let members: Vec<_> = zset.iter_from(start, false).take(count).collect();
+        let next = start + members.len();
+        Ok((if next < zset.len() { next as u64 } else { 0 }, members))
+    }
+
+    /// Returns the members selected by `range`, with their scores.
+    ///
+    /// With `reverse` the members are returned from highest to lowest.
+    /// `limit` is an offset and count applied after ordering; a negative
+    /// count returns everything past the offset.
+    pub fn zrange(&self, ns: usize, key: &[u8], range: &ZRange, reverse: bool, limit: Option<(usize, i64)>) -> Result<Vec<(Bytes, f64)>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let zset = match ns.read::<SortedSet>(key)? {
+            Some(zset) => zset,
+            None => return Ok(Vec::new()),
+        };
+
+        let (start, end) = zset.rank_interval(range, reverse);
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        let (offset, count) = limit.unwrap_or((0, -1));
+        let count = usize::try_from(count).unwrap_or(usize::MAX);
+        let members = if reverse {
+            zset.iter_from(end - 1, true)
+        } else {
+            zset.iter_from(start, false)
+        };
+
+        Ok(members.take(end - start).skip(offset).take(count).collect())
+    }
+
+    /// Returns the number of members selected by `range`.
+    pub fn zcount(&self, ns: usize, key: &[u8], range: &ZRange) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns.read::<SortedSet>(key)?.map_or(0, |zset| {
+            let (start, end) = zset.rank_interval(range, false);
+            end.saturating_sub(start)
+        }))
+    }
+
+    /// Removes the members selected by `range`. Returns the number removed.
+    pub fn zremrange(&self, ns: usize, key: &[u8], range: &ZRange) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let removed = match ns.write::<SortedSet>(key)? {
+            Some(zset) => {
+                let (start, end) = zset.rank_interval(range, false);
+                zset.remove_ranks(start, end).len()
+            }
+            None => 0,
+        };
+        if removed > 0 {
+            ns.changed(key);
+            let event = match range {
+                ZRange::Rank(..) => "zremrangebyrank",
+                ZRange::Score(..) => "zremrangebyscore",
+                ZRange::Lex(..) => "zremrangebylex",
+            };
+            ns.notify(KeyspaceEvents::ZSET, event, key);
+        }
+        ns.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    /// Removes and returns up to `count` members with the lowest (or, with
+    /// `max`, the highest) scores, in pop order.
+    pub fn zpop(&self, ns: usize, key: &[u8], count: usize, max: bool) -> Result<Vec<(Bytes, f64)>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let popped = match ns.write::<SortedSet>(key)? {
+            Some(zset) if max => {
+                let len = zset.len();
+                let mut popped = zset.remove_ranks(len.saturating_sub(count), len);
+                popped.reverse();
+                popped
+            }
+            Some(zset) => zset.remove_ranks(0, count),
+            None => Vec::new(),
+        };
+        if !popped.is_empty() {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::ZSET, if max { "zpopmax" } else { "zpopmin" }, key);
+        }
+        ns.remove_if_empty(key);
+        Ok(popped)
+    }
+
+    /// Combines the sorted sets at `keys` and stores the result at
+    /// `destination`, replacing whatever it held. Plain sets are accepted as
+    /// input, with every member scoring 1.
+    ///
+    /// Each input's scores are multiplied by the matching entry of `weights`
+    /// (1 if absent) and combined with `aggregate`. An empty result deletes
+    /// `destination`. Returns the size of the result.
+    pub fn zstore(&self, index: usize, op: SetOp, destination: Bytes, keys: &[Bytes], weights: &[f64], aggregate: Aggregate) -> Result<usize, &'static str> {
+        let mut ns = self.lock_keys(index, keys.iter().chain([&destination]).map(|key| &key[..]));
+
+        let mut inputs = Vec::with_capacity(keys.len());
+        for (i, key) in keys.iter().enumerate() {
+            ns.expire_if_needed(key);
+            let weight = weights.get(i).copied().unwrap_or(1.0);
+            let members: HashMap<Bytes, f64> = match ns.entry(key).map(|entry| &entry.value) {
+                Some(Value::SortedSet(zset)) => zset.iter_from(0, false).map(|(member, score)| (member, score * weight)).collect(),
+                Some(Value::Set(set)) => set.iter().map(|member| (member.clone(), weight)).collect(),
+                Some(_) => return Err(WRONGTYPE),
+                None => HashMap::new(),
+            };
+            inputs.push(members);
+        }
+
+        let combine = |a: f64, b: f64| {
+            let combined = match aggregate {
+                Aggregate::Sum => a + b,
+                Aggregate::Min => a.min(b),
+                Aggregate::Max => a.max(b),
+            };
+            // inf + -inf is defined as zero.
+            if combined.is_nan() { 0.0 } else { combined }
+        };
+
+        let mut inputs = inputs.into_iter();
+        let mut result = inputs.next().unwrap_or_default();
+        for input in inputs {
+            match op {
+                SetOp::Union => {
+                    for (member, score) in input {
+                        result
+                            .entry(member)
+                            .and_modify(|current| *current = combine(*current, score))
+                            .or_insert(score);
+                    }
+                }
+                SetOp::Inter => {
+                    result.retain(|member, _| input.contains_key(member));
+                    for (member, current) in result.iter_mut() {
+                        *current = combine(*current, input[member]);
+                    }
+                }
+                SetOp::Diff => result.retain(|member, _| !input.contains_key(member)),
+            }
+        }
+
+        let mut zset = SortedSet::default();
+        for (member, score) in result {
+            zset.insert(member, if score.is_nan() { 0.0 } else { score });
+        }
+
+        let len = zset.len();
+        if zset.is_empty() {
+            if ns.remove(&destination) {
+                ns.notify(KeyspaceEvents::GENERIC, "del", &destination);
+            }
+        } else {
+            let event = match op {
+                SetOp::Inter => "zinterstore",
+                SetOp::Union => "zunionstore",
+                SetOp::Diff => "zdiffstore",
+            };
+            ns.notify(KeyspaceEvents::ZSET, event, &destination);
+            ns.insert(destination.clone(), Value::SortedSet(zset), None);
+            self.serve_blocked(index, &mut ns, &destination);
+        }
+        Ok(len)
+    }
+
+    /// Appends an entry with `fields` to the stream at `key`, then applies
+    /// `trim`. The stream is created if needed, unless `no_create` is set.
+    /// Returns the ID of the new entry, or `None` if the stream does not exist
+    /// and `no_create` is set.
+    pub fn xadd(&self, index: usize, key: &[u8], id: XAddId, fields: Vec<(Bytes, Bytes)>, no_create: bool, trim: Option<Trim>) -> Result<Option<StreamId>, &'static str> {
+        let mut ns = self.lock(index, key);
+        ns.expire_if_needed(key);
+
+        let existed = ns.read::<Stream>(key)?.is_some();
+        if !existed && no_create {
+            return Ok(None);
+        }
+
+        // A new stream is only stored once its first entry is accepted, so
+        // that a rejected ID leaves no trace.
+        let mut created = Stream::default();
+        let stream = match ns.write::<Stream>(key)? {
+            Some(stream) => stream,
+            None => &mut created,
+        };
+        let id = stream.add(id, fields)?;
+        let trimmed = trim.is_some_and(|trim| stream.trim(trim) > 0);
+        if existed {
+            ns.changed(key);
+        } else {
+            ns.insert(Bytes::copy_from_slice(key), Value::Stream(created), None);
+        }
+        ns.notify(KeyspaceEvents::STREAM, "xadd", key);
+        if trimmed {
+            ns.notify(KeyspaceEvents::STREAM, "xtrim", key);
+        }
+
+        self.watchers.notify(index, key);
+        Ok(Some(id))
+    }
+
+    /// Returns the number of entries in the stream at `key`.
+    pub fn xlen(&self, ns: usize, key: &[u8]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns.read::<Stream>(key)?.map_or(0, |stream| stream.len()))
+    }
+
+    /// Returns at most `count` entries of the stream at `key` with IDs in
+    /// `start..=end`, oldest first, or newest first with `reverse`.
+    pub fn xrange(&self, ns: usize, key: &[u8], start: StreamId, end: StreamId, count: usize, reverse: bool) -> Result<Vec<StreamEntry>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns
+            .read::<Stream>(key)?
+            .map(|stream| stream.range(start, end, count, reverse))
+            .unwrap_or_default())
+    }
+
+    /// Removes the entries with the given `ids` from the stream at `key`.
+    /// Returns the number of entries removed.
+    pub fn xdel(&self, ns: usize, key: &[u8], ids: &[StreamId]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let deleted = ns.write::<Stream>(key)?.map_or(0, |stream| stream.delete(ids));
+        if deleted > 0 {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::STREAM, "xdel", key);
+        }
+        Ok(deleted)
+    }
+
+    /// Evicts the oldest entries of the stream at `key` according to `trim`.
+    /// Returns the number of entries evicted.
+    pub fn xtrim(&self, ns: usize, key: &[u8], trim: Trim) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let trimmed = ns.write::<Stream>(key)?.map_or(0, |stream| stream.trim(trim));
+        if trimmed > 0 {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::STREAM, "xtrim", key);
+        }
+        Ok(trimmed)
+    }
+
+    /// Reads at most `count` entries from each of `streams`, starting after
+    /// the given position. Only streams with entries to report appear in the
+    /// result.
+    ///
+    /// With `block`, waits until at least one of the streams is appended to
+    /// if none has entries yet, and returns an empty result on timeout. A
+    /// zero `block` waits indefinitely.
+    pub async fn xread(&self, index: usize, streams: Vec<(Bytes, XReadFrom)>, count: usize, block: Option<Duration>) -> Result<Vec<(Bytes, Vec<StreamEntry>)>, &'static str> {
+        let keys: Vec<Bytes> = streams.iter().map(|(key, _)| key.clone()).collect();
+
+        // Resolve `$` once, so that entries appended while blocked are
+        // reported rather than moving the starting point.
+        let positions = {
+            let mut ns = self.lock_keys(index, keys.iter().map(|key| &key[..]));
+            let mut positions = Vec::with_capacity(streams.len());
+            for (key, from) in &streams {
+                ns.expire_if_needed(key);
+                positions.push(match from {
+                    XReadFrom::After(id) => *id,
+                    XReadFrom::New => ns.read::<Stream>(key)?.map_or(StreamId::MIN, |stream| stream.last_id()),
+                });
+            }
+            positions
+        };
+
+        let result = self.wait_for_streams(index, &keys, block, |ns| {
+            let mut result = Vec::new();
+            for (key, after) in keys.iter().zip(&positions) {
+                ns.expire_if_needed(key);
+                let entries = match (ns.read::<Stream>(key)?, after.next()) {
+                    (Some(stream), Some(start)) => stream.range(start, StreamId::MAX, count, false),
+                    _ => continue,
+                };
+                if !entries.is_empty() {
+                    result.push((key.clone(), entries));
+                }
+            }
+            Ok(Some(result).filter(|result| !result.is_empty()))
+        });
+        Ok(result.await?.unwrap_or_default())
+    }
+
+    /// Run `poll` under the namespace lock until it returns a result. If it
+    /// returns `None` and `block` is set, wait for one of `keys` to be
+    /// appended to and poll again, giving up with `None` once `block`
+    /// elapses. A zero `block` waits indefinitely.
+    async fn wait_for_streams<T>(&self, index: usize, keys: &[Bytes], block: Option<Duration>, mut poll: impl FnMut(&mut Namespace) -> Result<Option<T>, &'static str>) -> Result<Option<T>, &'static str> {
+        let deadline = block.filter(|block| !block.is_zero()).map(|block| Instant::now() + block);
+
+        let mut watch = None;
+        loop {
+            let notify = {
+                let mut ns = self.lock_keys(index, keys.iter().map(|key| &key[..]));
+
+                if let Some(result) = poll(&mut ns)? {
+                    return Ok(Some(result));
+                }
+                if block.is_none() {
+                    return Ok(None);
+                }
+
+                // Register while still holding the lock so that no append can
+                // slip in unnoticed. The registration is kept across wakeups
+                // that turn out to bring nothing new.
+                let watch = watch.get_or_insert_with(|| {
+                    let (id, notify) = self.watchers.register(index, keys);
+                    Watch { db: self, index, keys, id, notify }
+                });
+                watch.notify.clone()
+            };
+
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, notify.notified()).await.is_err() {
+                        return Ok(None);
+                    }
+                }
+                None => notify.notified().await,
+            }
+        }
+    }
+
+    /// Creates consumer group `group` on the stream at `key`, which considers
+    /// entries after `start` new. A missing stream is created if `create` is
+    /// set.
+    pub fn xgroup_create(&self, ns: usize, key: &[u8], group: Bytes, start: XReadFrom, create: bool) -> Result<(), &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        if ns.read::<Stream>(key)?.is_none() && !create {
+            return Err("ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.");
+        }
+
+        let start = match start {
+            XReadFrom::After(id) => Some(id),
+            XReadFrom::New => None,
+        };
+        ns.write_or_default::<Stream>(key)?.create_group(group, start)?;
+        ns.changed(key);
+        ns.notify(KeyspaceEvents::STREAM, "xgroup-create", key);
+        Ok(())
+    }
+
+    /// Moves the point after which entries are new to consumer group `group`.
+    pub fn xgroup_setid(&self, ns: usize, key: &[u8], group: &[u8], start: XReadFrom) -> Result<(), &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let start = match start {
+            XReadFrom::After(id) => Some(id),
+            XReadFrom::New => None,
+        };
+        ns.write::<Stream>(key)?.ok_or(NOGROUP)?.set_group_id(group, start)?;
+        ns.changed(key);
+        ns.notify(KeyspaceEvents::STREAM, "xgroup-setid", key);
+        Ok(())
+    }
+
+    /// Destroys consumer group `group`. Returns `true` if it existed.
+    ///
+    /// Clients blocked reading the group are woken up and fail.
+    pub fn xgroup_destroy(&self, index: usize, key: &[u8], group: &[u8]) -> Result<bool, &'static str> {
+        let mut ns = self.lock(index, key);
+        ns.expire_if_needed(key);
+
+        let destroyed = ns.write::<Stream>(key)?.is_some_and(|stream| stream.destroy_group(group));
+        if destroyed {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::STREAM, "xgroup-destroy", key);
+            self.watchers.notify(index, key);
+        }
+        Ok(destroyed)
+    }
+
+    /// Creates `consumer` in consumer group `group`. Returns `false` if it
+    /// already existed.
+    pub fn xgroup_createconsumer(&self, ns: usize, key: &[u8], group: &[u8], consumer: &Bytes) -> Result<bool, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let created = ns.write::<Stream>(key)?.ok_or(NOGROUP)?.create_consumer(group, consumer)?;
+        if created {
+            ns.changed(key);
+            ns.notify(KeyspaceEvents::STREAM, "xgroup-createconsumer", key);
+        }
+        Ok(created)
+    }
+
+    /// Deletes `consumer` from consumer group `group`, discarding the entries
+    /// it had pending. Returns the number of entries discarded.
+    pub fn xgroup_delconsumer(&self, ns: usize, key: &[u8], group: &[u8], consumer: &[u8]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let discarded = ns.write::<Stream>(key)?.ok_or(NOGROUP)?.delete_consumer(group, consumer)?;
+        ns.changed(key);
+        ns.notify(KeyspaceEvents::STREAM, "xgroup-delconsumer", key);
+        Ok(discarded)
+    }
+
+    /// Reads from each of `streams` on behalf of `consumer` in consumer group
+    /// `group`, at most `count` entries per stream.
+    ///
+    /// `XReadFrom::New` reads entries never delivered to the group and, unless
+    /// `no_ack` is set, records them as pending for `consumer`. Only streams
+    /// with such entries appear in the result, and with `block` the read waits
+    /// for them like `xread` does. `XReadFrom::After` reads the consumer's
+    /// pending entries instead; those streams always appear and never block.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn xreadgroup(&self, index: usize, group: &Bytes, consumer: &Bytes, streams: Vec<(Bytes, XReadFrom)>, count: usize, no_ack: bool, block: Option<Duration>) -> Result<Vec<(Bytes, Vec<GroupEntry>)>, &'static str> {
+        let keys: Vec<Bytes> = streams.iter().map(|(key, _)| key.clone()).collect();
+
+        let result = self.wait_for_streams(index, &keys, block, |ns| {
+            // Check every stream before reading any, so that a missing group
+            // leaves no half-done deliveries behind.
+            for key in &keys {
+                ns.expire_if_needed(key);
+                if !ns.read::<Stream>(key)?.is_some_and(|stream| stream.has_group(group)) {
+                    return Err(NOGROUP);
+                }
+            }
+
+            let mut result = Vec::new();
+            let mut history = false;
+            for (key, from) in &streams {
+                let stream = ns.write::<Stream>(key)?.ok_or(NOGROUP)?;
+                let after = match from {
+                    XReadFrom::After(id) => Some(*id),
+                    XReadFrom::New => None,
+                };
+                let entries = stream.read_group(group, consumer, after, count, no_ack)?;
+
+                if !entries.is_empty() {
+                    ns.changed(key);
+                }
+                history |= after.is_some();
+                if after.is_some() || !entries.is_empty() {
+                    result.push((key.clone(), entries));
+                }
+            }
+            Ok(Some(result).filter(|result| history || !result.is_empty()))
+        });
+        Ok(result.await?.unwrap_or_default())
+    }
+
+    /// Acknowledges `ids` in consumer group `group`, removing them from its
+    /// pending entries. Returns the number of entries that were pending.
+    pub fn xack(&self, ns: usize, key: &[u8], group: &[u8], ids: &[StreamId]) -> Result<usize, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let acked = ns.write::<Stream>(key)?.map_or(0, |stream| stream.ack(group, ids));
+        if acked > 0 {
+            ns.changed(key);
+        }
+        Ok(acked)
+    }
+
+    /// Summarizes the pending entries of consumer group `group`.
+    pub fn xpending_summary(&self, ns: usize, key: &[u8], group: &[u8]) -> Result<PendingSummary, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        ns.read::<Stream>(key)?.ok_or(NOGROUP)?.pending_summary(group)
+    }
+
+    /// Lists up to `count` pending entries of consumer group `group` with IDs
+    /// in `start..=end` that have been idle for at least `min_idle`,
+    /// optionally only those held by `consumer`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn xpending(&self, ns: usize, key: &[u8], group: &[u8], start: StreamId, end: StreamId, count: usize, min_idle: Duration, consumer: Option<&[u8]>) -> Result<Vec<PendingEntry>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        ns.read::<Stream>(key)?.ok_or(NOGROUP)?.pending(group, start, end, count, min_idle, consumer)
+    }
+
+    /// Transfers the pending entries `ids` of consumer group `group` that have
+    /// been idle for at least `min_idle` to `consumer`. Returns the entries
+    /// claimed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn xclaim(&self, ns: usize, key: &[u8], group: &[u8], consumer: &Bytes, min_idle: Duration, ids: &[StreamId], options: ClaimOptions) -> Result<Vec<StreamEntry>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let claimed = ns.write::<Stream>(key)?.ok_or(NOGROUP)?.claim(group, consumer, min_idle, ids, options)?;
+        if !claimed.is_empty() {
+            ns.changed(key);
+        }
+        Ok(claimed)
+    }
+
+    /// Claims for `consumer` up to `count` entries of consumer group `group`
+    /// that have been idle for at least `min_idle`, scanning the pending
+    /// entries from `start`.
+    ///
+    /// Returns the ID the next scan should start from (`0-0` when the scan is
+    /// complete), the entries claimed, and the IDs of pending entries that no
+    /// longer exist in the stream and were dropped.
+    #[allow(clippy::too_many_arguments)]
+    pub fn xautoclaim(&self, ns: usize, key: &[u8], group: &[u8], consumer: &Bytes, min_idle: Duration, start: StreamId, count: usize, just_id: bool) -> Result<(StreamId, Vec<StreamEntry>, Vec<StreamId>), &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        let (next, claimed, deleted) = ns.write::<Stream>(key)?.ok_or(NOGROUP)?.autoclaim(group, consumer, min_idle, start, count, just_id)?;
+        if !claimed.is_empty() || !deleted.is_empty() {
+            ns.changed(key);
+        }
+        Ok((next, claimed, deleted))
+    }
+
+    /// Describes the stream at `key`, or `None` if it does not exist.
+    pub fn xinfo_stream(&self, ns: usize, key: &[u8]) -> Result<Option<StreamInfo>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns.read::<Stream>(key)?.map(|stream| stream.info()))
+    }
+
+    /// Describes the consumer groups of the stream at `key`, or `None` if it
+    /// does not exist.
+    pub fn xinfo_groups(&self, ns: usize, key: &[u8]) -> Result<Option<Vec<GroupInfo>>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        Ok(ns.read::<Stream>(key)?.map(|stream| stream.group_info()))
+    }
+
+    /// Describes the consumers of consumer group `group`.
+    pub fn xinfo_consumers(&self, ns: usize, key: &[u8], group: &[u8]) -> Result<Vec<ConsumerInfo>, &'static str> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+
+        ns.read::<Stream>(key)?.ok_or(NOGROUP)?.consumer_info(group)
+    }
+
+    /// Purge expired keys from every namespace.
+    ///
+    /// Each shard is processed in batches of `ACTIVE_EXPIRE_BATCH` keys,
+    /// releasing the lock between batches so that clients are never blocked
+    /// for long, even when many keys expire at once. Returns the number of
+    /// keys removed.
+    pub fn purge_expired(&self) -> usize {
+        let mut total = 0;
+
+        for index in 0..self.namespaces.len() {
+            for shard in 0..SHARDS {
+                loop {
+                    let purged = self.lock_shards(index, vec![shard]).purge_expired(ACTIVE_EXPIRE_BATCH);
+                    total += purged;
+
+                    if purged < ACTIVE_EXPIRE_BATCH {
+                        break;
+                    }
+                }
+            }
+        }
+
+        total
+    }
+
+    /// Approximate bytes used by every namespace.
+    pub fn used_memory(&self) -> usize {
+        self.memory.used.load(Ordering::Relaxed)
+    }
+
+    /// The `maxmemory` limit in bytes; `0` means unlimited.
+    pub fn max_memory(&self) -> usize {
+        self.memory.limit.load(Ordering::Relaxed)
+    }
+
+    pub fn set_max_memory(&self, bytes: usize) {
+        self.memory.limit.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        *self.memory.policy.lock().unwrap()
+    }
+
+    pub fn set_eviction_policy(&self, policy: EvictionPolicy) {
+        *self.memory.policy.lock().unwrap() = policy;
+    }
+
+    /// Number of keys evicted to stay under `maxmemory` so far.
+    pub fn evicted_keys(&self) -> u64 {
+        self.memory.evicted.load(Ordering::Relaxed)
+    }
+
+    /// Saves a snapshot of every namespace to the configured file, and
+    /// returns once it is on disk. Fails if another save is running.
+    pub fn save(&self) -> crate::Result<()> {
+        if self.persistence.saving.swap(true, Ordering::AcqRel) {
+            return Err("ERR Background save already in progress".into());
+        }
+        let result = self.write_snapshot();
+        self.persistence.saving.store(false, Ordering::Release);
+        result
+    }
+
+    /// Like `save`, but writes the snapshot from a background thread. Only
+    /// copying the data holds up other clients.
+    pub fn bgsave(self: &Arc<Self>) -> Result<(), &'static str> {
+        if self.persistence.saving.swap(true, Ordering::AcqRel) {
+            return Err("ERR Background save already in progress");
+        }
+
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = db.write_snapshot() {
+                tracing::error!("Background save failed: {}", e);
+            }
+            db.persistence.saving.store(false, Ordering::Release);
+        });
+        Ok(())
+    }
+
+    fn write_snapshot(&self) -> crate::Result<()> {
+        let (namespaces, changes) = self.records();
+        let result = snapshot::write_file(&self.persistence.path, &snapshot::encode(&namespaces));
+        self.persistence.record(changes, result.is_ok());
+        result.map_err(|e| format!("ERR can't save snapshot '{}': {}", self.persistence.path.display(), e).into())
+    }
+
+    /// A copy of every live key of every namespace, taken with all shards
+    /// locked so that it reflects a single point in time, along with the
+    /// number of changes it accounts for.
+    pub(crate) fn records(&self) -> (Vec<Vec<Record>>, u64) {
+        let namespaces: Vec<Namespace> = (0..self.namespaces.len()).map(|index| self.lock_all(index)).collect();
+        let changes = self.persistence.changes.load(Ordering::Relaxed);
+
+        let now = Instant::now();
+        let records = namespaces
+            .iter()
+            .map(|ns| {
+                ns.shards()
+                    .flat_map(|shard| &shard.keys)
+                    .filter(|(_, entry)| entry.expires_at.is_none_or(|when| when > now))
+                    .map(|(key, entry)| (key.clone(), entry.value.clone(), entry.expires_at))
+                    .collect()
+            })
+            .collect();
+        (records, changes)
+    }
+
+    /// Unix time in seconds of the last successful save, or of startup if
+    /// there was none.
+    pub fn last_save(&self) -> u64 {
+        self.persistence.last_save.load(Ordering::Relaxed)
+    }
+
+    /// Approximate number of writes since the last successful save.
+    pub fn changes_since_save(&self) -> u64 {
+        self.persistence.changes.load(Ordering::Relaxed)
+    }
+
+    pub fn save_in_progress(&self) -> bool {
+        self.persistence.saving.load(Ordering::Relaxed)
+    }
+
+    /// Whether the last save attempt succeeded.
+    pub fn last_save_ok(&self) -> bool {
+        self.persistence.last_ok.load(Ordering::Relaxed)
+    }
+
+    /// File snapshots are saved to and loaded from.
+    pub fn dbfilename(&self) -> &Path {
+        &self.persistence.path
+    }
+
+    pub fn save_rules(&self) -> Vec<SaveRule> {
+        self.persistence.rules.lock().unwrap().clone()
+    }
+
+    pub fn set_save_rules(&self, rules: Vec<SaveRule>) {
+        *self.persistence.rules.lock().unwrap() = rules;
+    }
+
+    pub(crate) fn aof(&self) -> &AppendOnly {
+        &self.aof
+    }
+
+    /// Replaces the append-only file with a compact one built from the
+    /// current data, in the background. Writes made in the meantime are
+    /// logged to both files.
+    pub async fn bgrewriteaof(self: &Arc<Self>) -> Result<(), &'static str> {
+        // No command runs while the gate is held, and every write that ran
+        // is logged below, so the copy matches the point from which writes
+        // are buffered.
+        let gate = self.exec_lock.exclusive().await;
+        let mut writer = self.aof.lock().await.ok_or("ERR Append only file is disabled")?;
+        writer.flush();
+        if !writer.start_rewrite() {
+            return Err("ERR Background append only file rewriting already in progress");
+        }
+        let (namespaces, _) = self.records();
+        drop(writer);
+        drop(gate);
+
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || db.aof.rewrite(&namespaces));
+        Ok(())
+    }
+
+    pub fn append_only(&self) -> bool {
+        self.aof.enabled()
+    }
+
+    /// File writes are logged to.
+    pub fn appendfilename(&self) -> &Path {
+        &self.aof.path
+    }
+
+    pub fn append_fsync(&self) -> AppendFsync {
+        *self.aof.fsync.lock().unwrap()
+    }
+
+    pub fn set_append_fsync(&self, policy: AppendFsync) {
+        *self.aof.fsync.lock().unwrap() = policy;
+    }
+
+    /// Messages a subscriber may have waiting before it is disconnected.
+    pub fn pubsub_queue_limit(&self) -> usize {
+        self.pubsub.queue_limit()
+    }
+
+    pub fn set_pubsub_queue_limit(&self, limit: usize) {
+        self.pubsub.set_queue_limit(limit);
+    }
+
+    /// Which keyspace events are published.
+    pub fn keyspace_events(&self) -> KeyspaceEvents {
+        self.pubsub.keyspace_events()
+    }
+
+    pub fn set_keyspace_events(&self, events: KeyspaceEvents) {
+        self.pubsub.set_keyspace_events(events);
+    }
+
+    /// Size of the append-only file in bytes.
+    pub fn aof_size(&self) -> u64 {
+        self.aof.size.load(Ordering::Relaxed)
+    }
+
+    pub fn aof_rewrite_in_progress(&self) -> bool {
+        self.aof.rewriting.load(Ordering::Acquire)
+    }
+
+    /// Whether the last append-only file rewrite succeeded.
+    pub fn aof_last_rewrite_ok(&self) -> bool {
+        self.aof.last_rewrite_ok.load(Ordering::Relaxed)
+    }
+
+    /// Whether the last write to the append-only file succeeded.
+    pub fn aof_last_write_ok(&self) -> bool {
+        self.aof.last_write_ok.load(Ordering::Relaxed)
+    }
+
+    /// Approximate bytes used by `key` in namespace `ns`.
+    pub fn memory_usage(&self, ns: usize, key: &[u8]) -> Option<usize> {
+        let mut ns = self.lock(ns, key);
+        ns.expire_if_needed(key);
+        ns.entry(key).map(|entry| entry.size)
+    }
+
+    /// Evict keys until memory use is back under `maxmemory`.
+    ///
+    /// A few keys are sampled from every shard, then evicted from the best
+    /// candidate for the eviction policy down. Each evicted key is logged
+    /// as a `DEL`. Returns the number of keys evicted. Fails with an OOM
+    /// error if the policy is `noeviction` or no key can be evicted, in
+    /// which case commands that may grow memory must be refused.
+    pub fn free_memory(&self) -> Result<usize, &'static str> {
+        // Evicting a candidate only locks its shard; every shard is sampled
+        // again once the pool runs out.
+        let mut pool = Vec::new();
+        let mut evicted = 0;
+        while self.memory.over_limit() {
+            let policy = self.eviction_policy();
+            if policy == EvictionPolicy::NoEviction {
+                return Err(OOM);
+            }
+
+            let (_, index, key) = match pool.pop() {
+                Some(candidate) => candidate,
+                None => {
+                    pool = self.eviction_pool(policy);
+                    pool.pop().ok_or(OOM)?
+                }
+            };
+            let mut ns = self.lock(index, &key);
+            // The key may have been removed, or lost its deadline, since it
+            // was sampled.
+            let evictable = ns.entry(&key).is_some_and(|entry| !policy.volatile_only() || entry.expires_at.is_some());
+            if evictable && ns.remove(&key) {
+                evicted += 1;
+                self.memory.evicted.fetch_add(1, Ordering::Relaxed);
+                self.aof.record(index, vec![Bytes::from_static(b"DEL"), key.clone()]);
+                self.watchers.notify(index, &key);
+                ns.notify(KeyspaceEvents::EVICTED, "evicted", &key);
+            }
+        }
+
+        Ok(evicted)
+    }
+
+    /// Sample keys that may be evicted under `policy` from every shard of
+    /// every namespace, locking one shard at a time. Returns them with their
+    /// namespace, the best candidate last.
+    fn eviction_pool(&self, policy: EvictionPolicy) -> Vec<(u64, usize, Bytes)> {
+        let mut pool = Vec::new();
+        for (index, shard) in (0..self.namespaces.len()).flat_map(|index| (0..SHARDS).map(move |shard| (index, shard))) {
+            let ns = self.lock_shards(index, vec![shard]);
+            pool.extend(ns.shards().flat_map(|shard| shard.eviction_candidates(policy)).map(|(score, key)| (score, index, key)));
+        }
+        pool.sort_unstable();
+        // A key sampled twice has the same score both times.
+        pool.dedup();
+        pool
+    }
+}
+
+/// Removes a blocked client from the waiter queues when dropped, and puts
+/// back a value handed to it that it did not take.
+struct Registration<'a> {
+    db: &'a Db,
+    index: usize,
+    keys: &'a [Bytes],
+    id: u64,
+    pop: Pop,
+    receiver: oneshot::Receiver<Served>,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.db.waiters.unregister(self.index, self.keys, self.id);
+
+        // Once closed, nothing more can be handed over; whatever already was
+        // is still in the channel.
+        self.receiver.close();
+        if let Ok(Ok(handoff)) = self.receiver.try_recv() {
+            self.db.requeue(self.index, &self.pop, handoff);
+        }
+    }
+}
+
+/// Removes a client blocked on streams from the watchers when dropped.
+struct Watch<'a> {
+    db: &'a Db,
+    index: usize,
+    keys: &'a [Bytes],
+    id: u64,
+    notify: Arc<Notify>,
+}
+
+impl Drop for Watch<'_> {
+    fn drop(&mut self) {
+        self.db.watchers.unregister(self.index, self.keys, self.id);
+    }
+}
+
+/// Spawn the background task that saves a snapshot whenever a save rule
+/// calls for one. Like the active expiry task, it exits once the `Db` is
+/// dropped.
+pub fn spawn_save_rules(db: &Arc<Db>) {
+    let db: Weak<Db> = Arc::downgrade(db);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SAVE_RULES_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match db.upgrade() {
+                Some(db) => {
+                    if db.persistence.due() {
+                        let _ = db.bgsave();
+                    }
+                }
+                None => break,
+            }
+        }
+    });
+}
+
+/// Spawn the background task that flushes the append-only file to disk every
+/// second under the `everysec` policy.
+pub fn spawn_aof_fsync(db: &Arc<Db>) {
+    let db: Weak<Db> = Arc::downgrade(db);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(AOF_FSYNC_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match db.upgrade() {
+                Some(db) => db.aof.sync().await,
+                None => break,
+            }
+        }
+    });
+}
+
+/// Spawn the background task that actively purges expired keys.
+///
+/// Keys are also expired lazily whenever they are accessed; this task makes
+/// sure keys that are never touched again still release their memory. The
+/// task holds only a weak reference and exits once the `Db` is dropped.
+pub fn spawn_active_expiry(db: &Arc<Db>) {
+    let db: Weak<Db> = Arc::downgrade(db);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ACTIVE_EXPIRE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match db.upgrade() {
+                // Keys are left alone while `CLIENT PAUSE` holds writes.
+                Some(db) => {
+                    if !db.clients().writes_paused() {
+                        db.purge_expired();
+                    }
+                }
+                None => break,
+            }
+        }
+    });
 }
@@ -13,7 +13,7 @@ use std::string::FromUtf8Error;
 pub enum Frame {
     Simple(String),
     Error(String),
-    Integer(u64),
+    Integer(i64),
     Bulk(Bytes),
     Null,
     Array(Vec<Frame>),
@@ -135,6 +135,19 @@ impl Frame {
         }
     }
 
+    /// Number of bytes the frame takes once encoded.
+    pub fn encoded_len(&self) -> usize {
+        // Type byte, then a line of `len` digits or characters.
+        let line = |len: usize| 1 + len + 2;
+        match self {
+            Frame::Simple(val) | Frame::Error(val) => line(val.len()),
+            Frame::Integer(val) => line(val.to_string().len()),
+            Frame::Null => 5,
+            Frame::Bulk(val) => line(val.len().to_string().len()) + val.len() + 2,
+            Frame::Array(val) => line(val.len().to_string().len()) + val.iter().map(Frame::encoded_len).sum::<usize>(),
+        }
+    }
+
     // pub fn as_bytes(&self) -> Result<Bytes, Error> {
     //     match self {
     //         Frame::Bulk(data) => Ok(data.clone()),
@@ -224,13 +237,14 @@ fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
     Ok(())
 }
 
-/// Read a new-line terminated decimal
-fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
+/// Read a new-line terminated decimal. The value may be negative, as
+/// integer frames can carry signed values.
+fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
     use atoi::atoi;
 
     let line = get_line(src)?;
 
-    atoi::<u64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
+    atoi::<i64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
 }
 
 /// Find a line
@@ -0,0 +1,123 @@
+//! Gate that lets a transaction or script run alone.
+//!
+//! Every command passes the gate shared, while `EXEC`, scripts and
+//! `BGREWRITEAOF` pass it exclusively. A shared pass only bumps a counter,
+//! one of several so that commands on different threads do not contend on
+//! the same one. Only while an exclusive pass is waiting or held do shared
+//! passes fall back to a lock, behind it.
+
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{Notify, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Number of counters shared passes are spread over.
+const STRIPES: usize = 16;
+
+thread_local! {
+    /// The counter shared passes taken on this thread use.
+    static STRIPE: usize = {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        NEXT.fetch_add(1, Ordering::Relaxed) % STRIPES
+    };
+}
+
+#[derive(Debug)]
+pub(crate) struct Gate {
+    /// Commands that passed without the lock and have not left yet.
+    running: [Counter; STRIPES],
+
+    /// Exclusive passes waiting or held.
+    exclusive: AtomicUsize,
+
+    /// Held by exclusive passes, and by shared passes while there are any.
+    lock: RwLock<()>,
+
+    /// Notified when the last command that passed without the lock leaves.
+    drained: Notify,
+}
+
+/// A counter on a cache line of its own.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Counter(AtomicUsize);
+
+/// A shared pass; leaves the gate when dropped.
+pub(crate) struct Shared<'a> {
+    gate: &'a Gate,
+    /// The counter the pass was taken on, or `None` if it holds the lock.
+    stripe: Option<usize>,
+    _guard: Option<RwLockReadGuard<'a, ()>>,
+}
+
+/// An exclusive pass; leaves the gate when dropped.
+pub(crate) struct Exclusive<'a> {
+    _guard: RwLockWriteGuard<'a, ()>,
+    _raised: Raised<'a>,
+}
+
+/// Counts an exclusive pass as waiting or held until dropped.
+struct Raised<'a>(&'a Gate);
+
+impl Gate {
+    pub(crate) fn new() -> Gate {
+        Gate {
+            running: Default::default(),
+            exclusive: AtomicUsize::new(0),
+            lock: RwLock::new(()),
+            drained: Notify::new(),
+        }
+    }
+
+    /// Pass the gate alongside other commands.
+    pub(crate) async fn shared(&self) -> Shared<'_> {
+        let stripe = STRIPE.with(|stripe| *stripe);
+        self.running[stripe].0.fetch_add(1, Ordering::SeqCst);
+        if self.exclusive.load(Ordering::SeqCst) == 0 {
+            return Shared { gate: self, stripe: Some(stripe), _guard: None };
+        }
+
+        // A transaction is about to run or running: step aside and wait
+        // for it to finish.
+        self.leave(stripe);
+        let guard = self.lock.read().await;
+        Shared { gate: self, stripe: None, _guard: Some(guard) }
+    }
+
+    /// Pass the gate alone, once every command that passed before has left.
+    pub(crate) async fn exclusive(&self) -> Exclusive<'_> {
+        // From here on, commands wait behind the lock.
+        self.exclusive.fetch_add(1, Ordering::SeqCst);
+        let raised = Raised(self);
+        let guard = self.lock.write().await;
+
+        loop {
+            let mut drained = pin!(self.drained.notified());
+            drained.as_mut().enable();
+            if self.running.iter().all(|counter| counter.0.load(Ordering::SeqCst) == 0) {
+                break;
+            }
+            drained.await;
+        }
+        Exclusive { _guard: guard, _raised: raised }
+    }
+
+    fn leave(&self, stripe: usize) {
+        if self.running[stripe].0.fetch_sub(1, Ordering::SeqCst) == 1 && self.exclusive.load(Ordering::SeqCst) > 0 {
+            self.drained.notify_waiters();
+        }
+    }
+}
+
+impl Drop for Shared<'_> {
+    fn drop(&mut self) {
+        if let Some(stripe) = self.stripe {
+            self.gate.leave(stripe);
+        }
+    }
+}
+
+impl Drop for Raised<'_> {
+    fn drop(&mut self) {
+        self.0.exclusive.fetch_sub(1, Ordering::SeqCst);
+    }
+}
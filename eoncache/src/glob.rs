@@ -0,0 +1,82 @@
+//! Glob-style pattern matching for `KEYS`, `SCAN` and the collection scans.
+//!
+//! Supports the same syntax as Redis:
+//!
+//! * `*` matches any sequence of bytes, including none.
+//! * `?` matches exactly one byte.
+//! * `[abc]` matches one of the listed bytes, `[a-z]` one byte in a range and
+//!   `[^abc]` any byte not listed.
+//! * `\` matches the following byte literally, both inside and outside of
+//!   brackets.
+//!
+//! Matching works on raw bytes and is case-sensitive.
+
+/// Whether `text` matches the glob `pattern`.
+///
+/// Runs in `O(pattern.len() * text.len())` at worst: on a mismatch only the
+/// most recent `*` is retried, which is enough because an earlier `*` could
+/// only ever match a prefix the later one can absorb as well.
+pub(crate) fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    let mut p = 0;
+    let mut t = 0;
+    // Position in the pattern right after the last `*`, and the position in
+    // the text that `*` currently extends to.
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let next = match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                star = Some((p, t));
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match_class(&pattern[p + 1..], text[t]).map(|len| p + 1 + len),
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(p + 2),
+            Some(byte) => (*byte == text[t]).then_some(p + 1),
+            None => None,
+        };
+
+        match (next, star) {
+            (Some(next), _) => {
+                p = next;
+                t += 1;
+            }
+            // Let the last `*` swallow one more byte and retry from there.
+            (None, Some((after_star, end))) => {
+                star = Some((after_star, end + 1));
+                p = after_star;
+                t = end + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|byte| *byte == b'*')
+}
+
+/// Match `byte` against the bracket expression starting right after a `[`.
+///
+/// Returns the length of the expression, closing `]` included, if `byte`
+/// matches. An unterminated expression extends to the end of the pattern.
+fn match_class(class: &[u8], byte: u8) -> Option<usize> {
+    let negate = class.first() == Some(&b'^');
+    let mut i = negate as usize;
+    let mut matched = false;
+
+    while i < class.len() && class[i] != b']' {
+        if class[i] == b'\\' && i + 1 < class.len() {
+            matched |= class[i + 1] == byte;
+            i += 2;
+        } else if i + 2 < class.len() && class[i + 1] == b'-' {
+            let (low, high) = (class[i].min(class[i + 2]), class[i].max(class[i + 2]));
+            matched |= (low..=high).contains(&byte);
+            i += 3;
+        } else {
+            matched |= class[i] == byte;
+            i += 1;
+        }
+    }
+
+    (matched != negate).then_some((i + 1).min(class.len()))
+}
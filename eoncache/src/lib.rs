@@ -13,11 +13,58 @@ pub use server::run_server;
 pub mod db;
 pub use db::Db;
 
+// blocking
+mod blocking;
+
+// set
+mod set;
+
+// zset
+mod zset;
+
+// stream
+mod stream;
+
+// glob
+mod glob;
+
+// config
+pub mod config;
+pub use config::Config;
+
+// memory
+mod memory;
+pub use memory::EvictionPolicy;
+
+// snapshot
+mod snapshot;
+
+// aof
+mod aof;
+pub use aof::AppendFsync;
+
+// gate
+mod gate;
+
+// scripting
+mod scripting;
+
+// pubsub
+mod pubsub;
+pub use pubsub::KeyspaceEvents;
+
+// clients
+mod clients;
+
+// session
+pub mod session;
+pub use session::Session;
+
 
 // parse 
 
 mod parse;
-use parse::Parse;
+pub use parse::Parse;
 
 // frame
 pub mod frame;
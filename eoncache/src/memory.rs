@@ -0,0 +1,206 @@
+//! Approximate memory accounting and eviction under `maxmemory`.
+//!
+//! Every entry records an estimate of the memory it uses, refreshed whenever
+//! it is written, along with the time of its last access and a logarithmic
+//! access counter. Once the total of all namespaces exceeds the configured
+//! limit, keys are evicted according to the `EvictionPolicy`. As in Redis,
+//! LRU and LFU are approximated: a few keys are sampled from every shard and
+//! the best candidates among them are evicted, best first.
+
+use crate::db::Value;
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tokio::time::Instant;
+
+/// Error returned when a command that may grow memory is refused.
+pub const OOM: &str = "OOM command not allowed when used memory > 'maxmemory'.";
+
+/// Number of keys sampled from each shard when looking for keys to evict.
+pub(crate) const EVICTION_SAMPLES: usize = 5;
+
+/// Fixed cost of an entry in a namespace on top of its key and value: the
+/// hash table slot, the entry itself and its position in `SCAN` order.
+pub(crate) const ENTRY_OVERHEAD: usize = 96;
+
+/// Fixed cost of one element of a collection on top of its bytes.
+const ELEMENT_OVERHEAD: usize = 24;
+
+/// Number of elements of a collection looked at to estimate its size.
+const SIZE_SAMPLES: usize = 8;
+
+/// Access counter of a new key, so that it is not evicted right away for
+/// lack of history.
+pub(crate) const LFU_INIT: u8 = 5;
+
+/// How much harder each increment of the access counter gets.
+const LFU_LOG_FACTOR: f64 = 10.0;
+
+/// The access counter drops by one for every this many milliseconds a key
+/// goes unused.
+const LFU_DECAY_MS: u32 = 60_000;
+
+/// Which keys are evicted once memory use exceeds `maxmemory`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Never evict; refuse commands that may grow memory instead.
+    #[default]
+    NoEviction,
+    /// Evict the least recently used key.
+    AllKeysLru,
+    /// Evict the least frequently used key.
+    AllKeysLfu,
+    /// Evict a random key.
+    AllKeysRandom,
+    /// Evict the least recently used key among keys with a deadline.
+    VolatileLru,
+    /// Evict the least frequently used key among keys with a deadline.
+    VolatileLfu,
+    /// Evict a random key among keys with a deadline.
+    VolatileRandom,
+    /// Evict the key with the nearest deadline.
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    /// Name of the policy, as used by `maxmemory-policy`.
+    pub fn name(self) -> &'static str {
+        match self {
+            EvictionPolicy::NoEviction => "noeviction",
+            EvictionPolicy::AllKeysLru => "allkeys-lru",
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu",
+            EvictionPolicy::AllKeysRandom => "allkeys-random",
+            EvictionPolicy::VolatileLru => "volatile-lru",
+            EvictionPolicy::VolatileLfu => "volatile-lfu",
+            EvictionPolicy::VolatileRandom => "volatile-random",
+            EvictionPolicy::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    /// Whether only keys with a deadline may be evicted.
+    pub(crate) fn volatile_only(self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::VolatileLru | EvictionPolicy::VolatileLfu | EvictionPolicy::VolatileRandom | EvictionPolicy::VolatileTtl
+        )
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let policy = match name.to_lowercase().as_str() {
+            "noeviction" => EvictionPolicy::NoEviction,
+            "allkeys-lru" => EvictionPolicy::AllKeysLru,
+            "allkeys-lfu" => EvictionPolicy::AllKeysLfu,
+            "allkeys-random" => EvictionPolicy::AllKeysRandom,
+            "volatile-lru" => EvictionPolicy::VolatileLru,
+            "volatile-lfu" => EvictionPolicy::VolatileLfu,
+            "volatile-random" => EvictionPolicy::VolatileRandom,
+            "volatile-ttl" => EvictionPolicy::VolatileTtl,
+            _ => return Err(format!("invalid eviction policy '{}'", name)),
+        };
+        Ok(policy)
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Memory use and limits, shared by every namespace of a `Db`.
+#[derive(Debug, Default)]
+pub(crate) struct Memory {
+    /// Approximate bytes used by all namespaces.
+    pub(crate) used: AtomicUsize,
+    /// The `maxmemory` limit in bytes; `0` means unlimited.
+    pub(crate) limit: AtomicUsize,
+    pub(crate) policy: Mutex<EvictionPolicy>,
+    /// Number of keys evicted so far.
+    pub(crate) evicted: AtomicU64,
+}
+
+impl Memory {
+    /// Fold a change in the memory used by one namespace into the total.
+    pub(crate) fn adjust(&self, delta: isize) {
+        if delta >= 0 {
+            self.used.fetch_add(delta as usize, Ordering::Relaxed);
+        } else {
+            self.used.fetch_sub(delta.unsigned_abs(), Ordering::Relaxed);
+        }
+    }
+
+    /// Whether memory use is above a configured limit.
+    pub(crate) fn over_limit(&self) -> bool {
+        let limit = self.limit.load(Ordering::Relaxed);
+        limit != 0 && self.used.load(Ordering::Relaxed) > limit
+    }
+}
+
+/// Milliseconds since the server started, wrapping around every 49 days.
+/// Entries store this rather than an `Instant` to stay small; idle times are
+/// computed with wrapping arithmetic.
+pub(crate) fn clock() -> u32 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u32
+}
+
+/// Milliseconds since the `clock` reading `accessed`.
+pub(crate) fn idle_ms(accessed: u32) -> u32 {
+    clock().wrapping_sub(accessed)
+}
+
+/// The access counter `counter`, decayed for the time since `accessed`.
+pub(crate) fn lfu_decay(counter: u8, accessed: u32) -> u8 {
+    let periods = idle_ms(accessed) / LFU_DECAY_MS;
+    counter.saturating_sub(periods.min(u8::MAX as u32) as u8)
+}
+
+/// Count one more access. The counter grows logarithmically, so it takes
+/// about a million accesses to saturate it.
+pub(crate) fn lfu_increment(counter: u8) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+
+    let base = counter.saturating_sub(LFU_INIT) as f64;
+    let probability = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+    if rand::thread_rng().gen::<f64>() < probability {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+/// Approximate number of bytes used by `value`.
+///
+/// Strings are measured exactly. Collections are measured by sampling a few
+/// elements and scaling by their length, so that the estimate stays cheap to
+/// refresh after every write.
+pub(crate) fn value_size(value: &Value) -> usize {
+    match value {
+        Value::String(value) => value.len(),
+        Value::List(list) => sampled(list.len(), list.iter().map(|value| value.len())),
+        Value::Hash(hash) => sampled(hash.len(), hash.iter().map(|(field, value)| field.len() + value.len())),
+        Value::Set(set) => sampled(set.len(), set.iter().map(|member| member.len())),
+        Value::SortedSet(zset) => sampled(zset.len(), zset.iter_from(0, false).map(|(member, _)| member.len() * 2 + 8)),
+        Value::Stream(stream) => sampled(stream.len(), stream.entry_sizes()),
+    }
+}
+
+/// Scale the average of the first `SIZE_SAMPLES` of `sizes` to `len`
+/// elements.
+fn sampled(len: usize, sizes: impl Iterator<Item = usize>) -> usize {
+    let (count, total) = sizes
+        .take(SIZE_SAMPLES)
+        .fold((0, 0), |(count, total), size| (count + 1, total + size));
+    if count == 0 {
+        return 0;
+    }
+    len * (total / count + ELEMENT_OVERHEAD)
+}
@@ -15,7 +15,7 @@ pub enum ParseError {
 }
 
 impl Parse {
-    pub(crate) fn new(frame: Frame) -> Result<Parse, ParseError> {
+    pub fn new(frame: Frame) -> Result<Parse, ParseError> {
         match frame {
             Frame::Array(array) => Ok(Parse {
                 parts: array.into_iter(),
@@ -24,7 +24,7 @@ impl Parse {
         }
     }
 
-    pub fn next(&mut self) -> Result<Frame, ParseError> {
+    pub(crate) fn next(&mut self) -> Result<Frame, ParseError> {
         self.parts.next().ok_or(ParseError::EndOfStream)
     }
 
@@ -36,7 +36,7 @@ impl Parse {
         };
 
         // Attempt to convert bytes to a UTF-8 string
-        String::from_utf8(bytes).map_err(|_| ParseError::Other("Invalid UTF-8".to_string()))
+        String::from_utf8(bytes).map_err(|_| invalid_utf8())
     }
 
     pub fn next_bytes(&mut self) -> Result<Bytes, ParseError> {
@@ -52,6 +52,33 @@ impl Parse {
         s.parse::<u64>().map_err(|_| ParseError::Other("Invalid integer".to_string()))
     }
 
+    pub fn next_signed(&mut self) -> Result<i64, ParseError> {
+        let s = self.next_string()?;
+        s.parse::<i64>().map_err(|_| ParseError::Other("ERR value is not an integer or out of range".to_string()))
+    }
+
+    pub fn next_float(&mut self) -> Result<f64, ParseError> {
+        let s = self.next_string()?;
+        s.parse::<f64>()
+            .ok()
+            .filter(|value| !value.is_nan())
+            .ok_or_else(|| ParseError::Other("ERR value is not a valid float".to_string()))
+    }
+
+    /// The arguments not taken yet, as bytes.
+    pub(crate) fn remaining(&self) -> Vec<Bytes> {
+        self.parts
+            .as_slice()
+            .iter()
+            .filter_map(|frame| match frame {
+                Frame::Simple(s) => Some(Bytes::from(s.clone().into_bytes())),
+                Frame::Bulk(b) => Some(b.clone()),
+                Frame::Integer(n) => Some(Bytes::from(n.to_string())),
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn finish(&mut self) -> Result<(), ParseError> {
         if self.parts.next().is_some() {
             Err(ParseError::Other("Extra data in frame".to_string()))
@@ -61,6 +88,16 @@ impl Parse {
     }
 }
 
+/// Read an argument taken with `next_bytes` as text. Used where whether an
+/// argument is a key or, say, a timeout depends on how many follow it.
+pub(crate) fn text(bytes: &[u8]) -> Result<&str, ParseError> {
+    std::str::from_utf8(bytes).map_err(|_| invalid_utf8())
+}
+
+fn invalid_utf8() -> ParseError {
+    ParseError::Other("Invalid UTF-8".to_string())
+}
+
 impl From<String> for ParseError {
     fn from(src: String) -> Self {
         ParseError::Other(src)
@@ -0,0 +1,506 @@
+//! Publish/subscribe messaging.
+//!
+//! The `Hub`, shared by every connection through the `Db`, maps channels
+//! and glob patterns to their subscribers. `PUBLISH` hands each subscriber
+//! its own copy of the message frame, ready to be written out.
+//!
+//! A connection enters subscriber mode with its first `SUBSCRIBE` or
+//! `PSUBSCRIBE` and leaves it once it has no subscriptions left. In between
+//! it is no longer request/response: `subscriber_mode` writes messages as
+//! they arrive, while accepting only the subscribe family of commands and
+//! `PING`.
+//!
+//! Messages wait in a queue per subscriber until the connection writes
+//! them. A subscriber that falls more than `pubsub-queue-limit` messages
+//! behind is disconnected rather than left to hold on to ever more memory.
+//!
+//! The `Db` also publishes keyspace notifications through the hub, as in
+//! Redis: for each change to a key, `__keyspace@<ns>__:<key>` receives the
+//! name of the event and `__keyevent@<ns>__:<event>` the key. Which classes
+//! of events are published is set by `notify-keyspace-events`.
+
+use crate::connection::Connection;
+use crate::glob;
+use crate::{Frame, Parse, Session};
+use bytes::Bytes;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU16, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc, Notify};
+
+/// Why a subscriber over the queue limit is disconnected.
+const OVERFLOWED: &str = "subscriber went over pubsub-queue-limit";
+
+/// Why a killed subscriber is disconnected.
+const KILLED: &str = "subscriber killed by CLIENT KILL";
+
+/// Commands that enter subscriber mode.
+const SUBSCRIBE_COMMANDS: &[&str] = &["SUBSCRIBE", "PSUBSCRIBE", "UNSUBSCRIBE", "PUNSUBSCRIBE"];
+
+/// Subscribers by channel and by pattern.
+#[derive(Debug)]
+pub(crate) struct Hub {
+    subscriptions: Mutex<Subscriptions>,
+
+    /// Identifies the next subscriber.
+    next_id: AtomicU64,
+
+    /// Messages a subscriber may have waiting before it is disconnected;
+    /// 0 means unlimited.
+    queue_limit: AtomicUsize,
+
+    /// The keyspace events published, as `KeyspaceEvents` bits.
+    keyspace_events: AtomicU16,
+}
+
+/// Which keyspace events are published, as set by `notify-keyspace-events`:
+/// whether to publish on keyspace channels (`K`), keyevent channels (`E`) or
+/// both, and for which classes of events. The classes are generic commands
+/// such as `DEL` and `EXPIRE` (`g`), commands on strings (`$`), lists (`l`),
+/// sets (`s`), hashes (`h`), sorted sets (`z`) and streams (`t`), expired
+/// keys (`x`) and evicted keys (`e`); `A` stands for all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyspaceEvents(u16);
+
+impl KeyspaceEvents {
+    pub const NONE: KeyspaceEvents = KeyspaceEvents(0);
+    pub const KEYSPACE: KeyspaceEvents = KeyspaceEvents(1 << 0);
+    pub const KEYEVENT: KeyspaceEvents = KeyspaceEvents(1 << 1);
+    pub const GENERIC: KeyspaceEvents = KeyspaceEvents(1 << 2);
+    pub const STRING: KeyspaceEvents = KeyspaceEvents(1 << 3);
+    pub const LIST: KeyspaceEvents = KeyspaceEvents(1 << 4);
+    pub const SET: KeyspaceEvents = KeyspaceEvents(1 << 5);
+    pub const HASH: KeyspaceEvents = KeyspaceEvents(1 << 6);
+    pub const ZSET: KeyspaceEvents = KeyspaceEvents(1 << 7);
+    pub const EXPIRED: KeyspaceEvents = KeyspaceEvents(1 << 8);
+    pub const EVICTED: KeyspaceEvents = KeyspaceEvents(1 << 9);
+    pub const STREAM: KeyspaceEvents = KeyspaceEvents(1 << 10);
+
+    /// Every class of events, `A`.
+    const ALL: KeyspaceEvents = KeyspaceEvents(0b111_1111_1100);
+
+    /// Flags by letter, in the order they are displayed.
+    const LETTERS: [(char, KeyspaceEvents); 11] = [
+        ('g', Self::GENERIC),
+        ('$', Self::STRING),
+        ('l', Self::LIST),
+        ('s', Self::SET),
+        ('h', Self::HASH),
+        ('z', Self::ZSET),
+        ('x', Self::EXPIRED),
+        ('e', Self::EVICTED),
+        ('t', Self::STREAM),
+        ('K', Self::KEYSPACE),
+        ('E', Self::KEYEVENT),
+    ];
+
+    /// Whether every flag of `other` is set.
+    pub fn contains(self, other: KeyspaceEvents) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether events of `class` are published at all.
+    fn publishes(self, class: KeyspaceEvents) -> bool {
+        self.0 & class.0 != 0 && self.0 & (Self::KEYSPACE.0 | Self::KEYEVENT.0) != 0
+    }
+}
+
+impl std::ops::BitOr for KeyspaceEvents {
+    type Output = KeyspaceEvents;
+
+    fn bitor(self, other: KeyspaceEvents) -> KeyspaceEvents {
+        KeyspaceEvents(self.0 | other.0)
+    }
+}
+
+impl FromStr for KeyspaceEvents {
+    type Err = String;
+
+    fn from_str(flags: &str) -> Result<Self, Self::Err> {
+        flags.chars().try_fold(KeyspaceEvents::NONE, |events, letter| {
+            let flag = match letter {
+                'A' => KeyspaceEvents::ALL,
+                letter => match KeyspaceEvents::LETTERS.iter().find(|(known, _)| *known == letter) {
+                    Some(&(_, flag)) => flag,
+                    None => return Err(format!("invalid keyspace event class '{}'", letter)),
+                },
+            };
+            Ok(events | flag)
+        })
+    }
+}
+
+impl fmt::Display for KeyspaceEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut shown = *self;
+        if self.contains(KeyspaceEvents::ALL) {
+            f.write_str("A")?;
+            shown = KeyspaceEvents(self.0 & !KeyspaceEvents::ALL.0);
+        }
+        for (letter, flag) in KeyspaceEvents::LETTERS {
+            if shown.contains(flag) {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Subscriptions {
+    channels: HashMap<Bytes, HashMap<u64, Arc<Subscriber>>>,
+    patterns: HashMap<Bytes, HashMap<u64, Arc<Subscriber>>>,
+}
+
+/// The sending half of a subscribed connection.
+#[derive(Debug)]
+pub(crate) struct Subscriber {
+    id: u64,
+    sender: mpsc::UnboundedSender<Frame>,
+
+    /// Messages sent but not written out yet.
+    queued: AtomicUsize,
+
+    /// Notified once the queue went over the limit.
+    overflowed: Notify,
+}
+
+impl Hub {
+    pub(crate) fn new(queue_limit: usize, keyspace_events: KeyspaceEvents) -> Hub {
+        Hub {
+            subscriptions: Mutex::new(Subscriptions::default()),
+            next_id: AtomicU64::new(0),
+            queue_limit: AtomicUsize::new(queue_limit),
+            keyspace_events: AtomicU16::new(keyspace_events.0),
+        }
+    }
+
+    pub(crate) fn queue_limit(&self) -> usize {
+        self.queue_limit.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_queue_limit(&self, limit: usize) {
+        self.queue_limit.store(limit, Ordering::Relaxed);
+    }
+
+    pub(crate) fn keyspace_events(&self) -> KeyspaceEvents {
+        KeyspaceEvents(self.keyspace_events.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_keyspace_events(&self, events: KeyspaceEvents) {
+        self.keyspace_events.store(events.0, Ordering::Relaxed);
+    }
+
+    /// Whether events of `class` are published.
+    pub(crate) fn notifies(&self, class: KeyspaceEvents) -> bool {
+        self.keyspace_events().publishes(class)
+    }
+
+    /// Publish `event`, of `class`, on `key` of namespace `index`, if
+    /// events of that class are published.
+    pub(crate) fn notify_keyspace_event(&self, class: KeyspaceEvents, event: &str, index: usize, key: &[u8]) {
+        let events = self.keyspace_events();
+        if !events.publishes(class) {
+            return;
+        }
+        if events.contains(KeyspaceEvents::KEYSPACE) {
+            let channel = [format!("__keyspace@{}__:", index).as_bytes(), key].concat();
+            self.publish(&Bytes::from(channel), &Bytes::copy_from_slice(event.as_bytes()));
+        }
+        if events.contains(KeyspaceEvents::KEYEVENT) {
+            let channel = format!("__keyevent@{}__:{}", index, event);
+            self.publish(&Bytes::from(channel), &Bytes::copy_from_slice(key));
+        }
+    }
+
+    /// A new subscriber, and the receiver its messages arrive on.
+    fn subscriber(&self) -> (Arc<Subscriber>, mpsc::UnboundedReceiver<Frame>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let subscriber = Subscriber {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            sender,
+            queued: AtomicUsize::new(0),
+            overflowed: Notify::new(),
+        };
+        (Arc::new(subscriber), receiver)
+    }
+
+    /// Subscribe `subscriber` to `channel`, or to the channels matching it
+    /// if `pattern` is set.
+    fn subscribe(&self, subscriber: &Arc<Subscriber>, channel: Bytes, pattern: bool) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let map = if pattern { &mut subscriptions.patterns } else { &mut subscriptions.channels };
+        map.entry(channel).or_default().insert(subscriber.id, subscriber.clone());
+    }
+
+    /// Undo `subscribe`.
+    fn unsubscribe(&self, id: u64, channel: &[u8], pattern: bool) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let map = if pattern { &mut subscriptions.patterns } else { &mut subscriptions.channels };
+        if let Some(subscribers) = map.get_mut(channel) {
+            subscribers.remove(&id);
+            if subscribers.is_empty() {
+                map.remove(channel);
+            }
+        }
+    }
+
+    /// Send `message` to the subscribers of `channel` and of the patterns
+    /// matching it. Returns how many received it. Subscribers over the
+    /// queue limit are dropped from every subscription.
+    pub(crate) fn publish(&self, channel: &Bytes, message: &Bytes) -> usize {
+        let limit = self.queue_limit();
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let mut received = 0;
+        let mut overflowed = Vec::new();
+        let mut deliver = |subscribers: &HashMap<u64, Arc<Subscriber>>, frame: Frame| {
+            for subscriber in subscribers.values() {
+                match subscriber.send(frame.clone(), limit) {
+                    Ok(()) => received += 1,
+                    Err(()) => overflowed.push(subscriber.id),
+                }
+            }
+        };
+
+        if let Some(subscribers) = subscriptions.channels.get(channel) {
+            deliver(subscribers, push("message", [channel.clone(), message.clone()]));
+        }
+        for (pattern, subscribers) in &subscriptions.patterns {
+            if glob::matches(pattern, channel) {
+                deliver(subscribers, push("pmessage", [pattern.clone(), channel.clone(), message.clone()]));
+            }
+        }
+
+        if !overflowed.is_empty() {
+            let Subscriptions { channels, patterns } = &mut *subscriptions;
+            for map in [channels, patterns] {
+                map.retain(|_, subscribers| {
+                    subscribers.retain(|id, _| !overflowed.contains(id));
+                    !subscribers.is_empty()
+                });
+            }
+        }
+        received
+    }
+
+    /// Channels with at least one subscriber, those matching `pattern` if
+    /// given. Pattern subscriptions are not counted.
+    pub(crate) fn channels(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        let subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions
+            .channels
+            .keys()
+            .filter(|channel| pattern.is_none_or(|pattern| glob::matches(pattern, channel)))
+            .cloned()
+            .collect()
+    }
+
+    /// Number of subscribers of `channel`, pattern subscriptions aside.
+    pub(crate) fn subscriber_count(&self, channel: &[u8]) -> usize {
+        let subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.channels.get(channel).map_or(0, HashMap::len)
+    }
+
+    /// Number of patterns subscribed to, by any client.
+    pub(crate) fn pattern_count(&self) -> usize {
+        self.subscriptions.lock().unwrap().patterns.len()
+    }
+}
+
+impl Subscriber {
+    /// Queue `frame` for writing, unless `limit` messages wait already, in
+    /// which case the subscriber is told to disconnect. A subscriber whose
+    /// connection is gone counts as over the limit.
+    fn send(&self, frame: Frame, limit: usize) -> Result<(), ()> {
+        if limit > 0 && self.queued.load(Ordering::Relaxed) >= limit {
+            self.overflowed.notify_one();
+            return Err(());
+        }
+        self.sender.send(frame).map_err(|_| ())?;
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// A push frame: `kind` followed by `parts`.
+fn push<const N: usize>(kind: &'static str, parts: [Bytes; N]) -> Frame {
+    let kind = Frame::Bulk(Bytes::from_static(kind.as_bytes()));
+    Frame::Array(std::iter::once(kind).chain(parts.into_iter().map(Frame::Bulk)).collect())
+}
+
+/// Whether `frame` is a command that starts subscriber mode.
+pub(crate) fn enters_subscriber_mode(frame: &Frame) -> bool {
+    let name = match frame {
+        Frame::Array(parts) => match parts.first() {
+            Some(Frame::Bulk(name)) => &name[..],
+            Some(Frame::Simple(name)) => name.as_bytes(),
+            _ => return false,
+        },
+        _ => return false,
+    };
+    SUBSCRIBE_COMMANDS.iter().any(|command| command.as_bytes().eq_ignore_ascii_case(name))
+}
+
+/// The subscriptions of one connection, dropped once it leaves subscriber
+/// mode.
+struct Listener<'a> {
+    hub: &'a Hub,
+    subscriber: Arc<Subscriber>,
+    receiver: mpsc::UnboundedReceiver<Frame>,
+    channels: BTreeSet<Bytes>,
+    patterns: BTreeSet<Bytes>,
+}
+
+impl Listener<'_> {
+    fn count(&self) -> i64 {
+        (self.channels.len() + self.patterns.len()) as i64
+    }
+
+    fn subscribe(&mut self, channel: Bytes, pattern: bool) -> Frame {
+        let subscribed = if pattern { &mut self.patterns } else { &mut self.channels };
+        if subscribed.insert(channel.clone()) {
+            self.hub.subscribe(&self.subscriber, channel.clone(), pattern);
+        }
+        let kind = if pattern { "psubscribe" } else { "subscribe" };
+        self.confirmation(kind, Frame::Bulk(channel))
+    }
+
+    fn unsubscribe(&mut self, channel: Bytes, pattern: bool) -> Frame {
+        let subscribed = if pattern { &mut self.patterns } else { &mut self.channels };
+        if subscribed.remove(&channel) {
+            self.hub.unsubscribe(self.subscriber.id, &channel, pattern);
+        }
+        let kind = if pattern { "punsubscribe" } else { "unsubscribe" };
+        self.confirmation(kind, Frame::Bulk(channel))
+    }
+
+    fn confirmation(&self, kind: &'static str, channel: Frame) -> Frame {
+        Frame::Array(vec![Frame::Bulk(Bytes::from_static(kind.as_bytes())), channel, Frame::Integer(self.count())])
+    }
+
+    /// The replies to `command`, one per channel.
+    fn apply(&mut self, frame: Frame) -> crate::Result<Vec<Frame>> {
+        let mut parse = Parse::new(frame)?;
+        let command = parse.next_string()?.to_uppercase();
+        let args = parse.remaining();
+
+        let replies = match command.as_str() {
+            "SUBSCRIBE" | "PSUBSCRIBE" => {
+                if args.is_empty() {
+                    return Err(format!("ERR wrong number of arguments for '{}' command", command.to_lowercase()).into());
+                }
+                let pattern = command == "PSUBSCRIBE";
+                args.into_iter().map(|channel| self.subscribe(channel, pattern)).collect()
+            }
+            "UNSUBSCRIBE" | "PUNSUBSCRIBE" => {
+                let pattern = command == "PUNSUBSCRIBE";
+                let channels = match args.is_empty() {
+                    true if pattern => self.patterns.iter().cloned().collect(),
+                    true => self.channels.iter().cloned().collect(),
+                    false => args,
+                };
+                if channels.is_empty() {
+                    let kind = if pattern { "punsubscribe" } else { "unsubscribe" };
+                    vec![self.confirmation(kind, Frame::Null)]
+                } else {
+                    channels.into_iter().map(|channel| self.unsubscribe(channel, pattern)).collect()
+                }
+            }
+            "PING" => {
+                if args.len() > 1 {
+                    return Err("ERR wrong number of arguments for 'ping' command".into());
+                }
+                let message = args.into_iter().next().unwrap_or_default();
+                vec![push("pong", [message])]
+            }
+            _ => {
+                return Err(format!(
+                    "ERR Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING are allowed in this context",
+                    command.to_lowercase()
+                )
+                .into())
+            }
+        };
+        Ok(replies)
+    }
+}
+
+impl Drop for Listener<'_> {
+    fn drop(&mut self) {
+        for channel in &self.channels {
+            self.hub.unsubscribe(self.subscriber.id, channel, false);
+        }
+        for pattern in &self.patterns {
+            self.hub.unsubscribe(self.subscriber.id, pattern, true);
+        }
+    }
+}
+
+/// Run `connection` in subscriber mode, starting with the subscribe command
+/// `frame`, until it has no subscriptions left. Fails once the connection
+/// should be closed: when the client goes away, falls too far behind, is
+/// killed, or the server shuts down.
+pub(crate) async fn subscriber_mode(
+    connection: &mut Connection,
+    session: &Session,
+    frame: Frame,
+    shutdown: &mut broadcast::Receiver<()>,
+) -> crate::Result<()> {
+    let hub = session.db().pubsub();
+    let client = session.client();
+    let (subscriber, receiver) = hub.subscriber();
+    let mut listener = Listener { hub, subscriber, receiver, channels: BTreeSet::new(), patterns: BTreeSet::new() };
+    let mut command = Some(frame);
+
+    loop {
+        if let Some(frame) = command.take() {
+            match listener.apply(frame) {
+                Ok(replies) => {
+                    for reply in replies {
+                        connection.write_frame(&reply).await?;
+                    }
+                }
+                Err(e) => connection.write_frame(&Frame::Error(e.to_string())).await?,
+            }
+            if let Some(client) = client {
+                client.subscribed(listener.channels.len(), listener.patterns.len());
+            }
+            if listener.count() == 0 {
+                return Ok(());
+            }
+        }
+
+        tokio::select! {
+            frame = connection.read_frame() => match frame? {
+                Some(frame) => {
+                    if let Some(client) = client {
+                        client.received(&frame, connection.buffered());
+                    }
+                    command = Some(frame);
+                }
+                None => return Err("connection closed by the subscriber".into()),
+            },
+            Some(message) = listener.receiver.recv() => {
+                listener.subscriber.queued.fetch_sub(1, Ordering::Relaxed);
+                // Writing blocks while the client does not read, which is
+                // when its queue may go over the limit.
+                if let Some(client) = client {
+                    client.writing(message.encoded_len());
+                }
+                tokio::select! {
+                    written = connection.write_frame(&message) => written?,
+                    _ = listener.subscriber.overflowed.notified() => return Err(OVERFLOWED.into()),
+                    _ = session.killed() => return Err(KILLED.into()),
+                }
+                if let Some(client) = client {
+                    client.writing(0);
+                }
+            }
+            _ = listener.subscriber.overflowed.notified() => return Err(OVERFLOWED.into()),
+            _ = session.killed() => return Err(KILLED.into()),
+            _ = shutdown.recv() => return Err("server shutting down".into()),
+        }
+    }
+}
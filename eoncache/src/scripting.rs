@@ -0,0 +1,288 @@
+//! Lua scripting: `EVAL`, `EVALSHA` and the `SCRIPT` commands.
+//!
+//! Every script runs in a fresh Lua 5.1 state holding only the `table`,
+//! `string` and `math` libraries and the base functions that do not reach
+//! the filesystem, along with `KEYS`, `ARGV` and the `redis` table. Commands
+//! called through `redis.call` and `redis.pcall` go through the same
+//! dispatcher as those of clients, under the exec lock held for the whole
+//! script, so that a script runs as one command. Its writes are logged to
+//! the append-only file as a transaction.
+//!
+//! Replies are converted as in Redis. Integers become Lua numbers, bulk
+//! strings strings, null replies `false`, arrays tables, and status and
+//! error replies tables with an `ok` or `err` field; the other way around,
+//! `true` becomes 1 and numbers are truncated to integers.
+//!
+//! `SCRIPT KILL` stops a script from a hook run every few thousand
+//! instructions, unless the script has already written, in which case
+//! stopping it would leave the data half changed.
+
+use crate::command;
+use crate::{Db, Frame, Session};
+use bytes::Bytes;
+use mlua::{HookTriggers, Lua, LuaOptions, MultiValue, StdLib, Table, Value};
+use sha1::{Digest, Sha1};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Number of instructions between checks for `SCRIPT KILL`.
+const KILL_CHECK_INSTRUCTIONS: u32 = 10_000;
+
+/// Commands that scripts may not call.
+const NOT_FROM_SCRIPTS: &[&str] = &["EVAL", "EVALSHA", "SCRIPT", "MULTI", "EXEC", "DISCARD", "WATCH", "UNWATCH", "CLIENT"];
+
+/// A script to run, with its `KEYS` and `ARGV`.
+pub(crate) struct Script {
+    pub(crate) body: Bytes,
+    pub(crate) keys: Vec<Bytes>,
+    pub(crate) args: Vec<Bytes>,
+}
+
+/// The script cache, and the state of the running script.
+#[derive(Debug, Default)]
+pub(crate) struct Scripts {
+    /// Script bodies by the hex SHA1 of their source.
+    cache: Mutex<HashMap<String, Bytes>>,
+
+    /// Whether a script is running.
+    running: AtomicBool,
+
+    /// Whether the running script has written.
+    wrote: AtomicBool,
+
+    /// Set by `SCRIPT KILL` to stop the running script.
+    kill: AtomicBool,
+}
+
+impl Scripts {
+    /// Cache `body` and return its SHA1.
+    pub(crate) fn load(&self, body: Bytes) -> String {
+        let sha = sha1_hex(&body);
+        self.cache.lock().unwrap().insert(sha.clone(), body);
+        sha
+    }
+
+    /// The cached script with SHA1 `sha`, in either case.
+    pub(crate) fn get(&self, sha: &[u8]) -> Option<Bytes> {
+        let sha = String::from_utf8_lossy(sha).to_lowercase();
+        self.cache.lock().unwrap().get(&sha).cloned()
+    }
+
+    /// Empty the cache.
+    pub(crate) fn flush(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Stop the running script.
+    pub(crate) fn kill(&self) -> Result<(), &'static str> {
+        if !self.running.load(Ordering::Acquire) {
+            return Err("NOTBUSY No scripts in execution right now.");
+        }
+        if self.wrote.load(Ordering::Acquire) {
+            return Err("UNKILLABLE Sorry the script already executed write commands against the dataset. \
+                You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command.");
+        }
+        self.kill.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Hex SHA1 of `data`, as used to name scripts.
+pub(crate) fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Run `script` in namespace `namespace`. The caller must hold the exec lock
+/// exclusively. The writes it makes are added to `logged` along with the
+/// namespace they ran in.
+pub(crate) fn eval(db: &Arc<Db>, namespace: usize, script: Script, logged: &mut Vec<(usize, Vec<Bytes>)>) -> crate::Result<Frame> {
+    let scripts = db.scripts();
+    scripts.kill.store(false, Ordering::Release);
+    scripts.wrote.store(false, Ordering::Release);
+    scripts.running.store(true, Ordering::Release);
+    let result = run(db, namespace, script, logged);
+    scripts.running.store(false, Ordering::Release);
+    result
+}
+
+fn run(db: &Arc<Db>, namespace: usize, script: Script, logged: &mut Vec<(usize, Vec<Bytes>)>) -> crate::Result<Frame> {
+    let lua = Lua::new_with(StdLib::TABLE | StdLib::STRING | StdLib::MATH, LuaOptions::default()).map_err(script_error)?;
+    let globals = lua.globals();
+    for unsafe_function in ["dofile", "loadfile"] {
+        globals.set(unsafe_function, Value::Nil).map_err(script_error)?;
+    }
+    globals.set("KEYS", strings(&lua, &script.keys)?).map_err(script_error)?;
+    globals.set("ARGV", strings(&lua, &script.args)?).map_err(script_error)?;
+
+    let killer = db.clone();
+    lua.set_hook(HookTriggers::new().every_nth_instruction(KILL_CHECK_INSTRUCTIONS), move |_, _| {
+        if killer.scripts().kill.load(Ordering::Acquire) {
+            return Err(mlua::Error::RuntimeError("ERR Script killed by user with SCRIPT KILL...".to_string()));
+        }
+        Ok(())
+    });
+
+    // A script gets its own session, so that a `SELECT` in it does not
+    // change the namespace of the client.
+    let mut session = Session::new(db.clone());
+    session.select(namespace)?;
+    let state = RefCell::new((session, logged));
+    let call = |args: MultiValue| -> Result<Frame, String> {
+        let args = command_args(args)?;
+        let mut state = state.borrow_mut();
+        let (session, logged) = &mut *state;
+        let written = logged.len();
+        let reply = command::call(session, args, logged).map_err(|e| e.to_string());
+        if logged.len() > written {
+            db.scripts().wrote.store(true, Ordering::Release);
+        }
+        reply
+    };
+
+    let reply = lua.scope(|scope| {
+        let redis = redis_table(&lua)?;
+        redis.set(
+            "call",
+            scope.create_function(|lua, args: MultiValue| match call(args) {
+                Ok(reply) => to_lua(lua, reply),
+                Err(e) => Err(mlua::Error::RuntimeError(e)),
+            })?,
+        )?;
+        redis.set(
+            "pcall",
+            scope.create_function(|lua, args: MultiValue| match call(args) {
+                Ok(reply) => to_lua(lua, reply),
+                Err(e) => to_lua(lua, Frame::Error(e)),
+            })?,
+        )?;
+        lua.globals().set("redis", redis)?;
+
+        let value: Value = lua.load(&script.body[..]).set_name("@user_script").eval()?;
+        Ok(from_lua(value))
+    });
+    reply.map_err(script_error)
+}
+
+/// The `redis` table, but for `call` and `pcall`.
+fn redis_table(lua: &Lua) -> mlua::Result<Table<'_>> {
+    let redis = lua.create_table()?;
+    redis.set("sha1hex", lua.create_function(|_, data: mlua::String| Ok(sha1_hex(data.as_bytes())))?)?;
+    redis.set(
+        "status_reply",
+        lua.create_function(|lua, status: mlua::String| to_lua(lua, Frame::Simple(status.to_string_lossy().into_owned())))?,
+    )?;
+    redis.set(
+        "error_reply",
+        lua.create_function(|lua, error: mlua::String| to_lua(lua, Frame::Error(error.to_string_lossy().into_owned())))?,
+    )?;
+    redis.set(
+        "log",
+        lua.create_function(|_, (_level, message): (i64, mlua::String)| {
+            tracing::info!("script: {}", message.to_string_lossy());
+            Ok(())
+        })?,
+    )?;
+    for (i, level) in ["LOG_DEBUG", "LOG_VERBOSE", "LOG_NOTICE", "LOG_WARNING"].into_iter().enumerate() {
+        redis.set(level, i)?;
+    }
+    Ok(redis)
+}
+
+/// A Lua array of `values`.
+fn strings<'lua>(lua: &'lua Lua, values: &[Bytes]) -> crate::Result<Table<'lua>> {
+    let strings = values.iter().map(|value| lua.create_string(value));
+    let table = lua.create_sequence_from(strings.collect::<mlua::Result<Vec<_>>>().map_err(script_error)?);
+    table.map_err(script_error)
+}
+
+/// The arguments of `redis.call` as a command, name first.
+fn command_args(args: MultiValue) -> Result<Vec<Bytes>, String> {
+    if args.is_empty() {
+        return Err("ERR Please specify at least one argument for this redis lib call".to_string());
+    }
+
+    let args = args
+        .into_iter()
+        .map(|arg| match arg {
+            Value::String(string) => Ok(Bytes::copy_from_slice(string.as_bytes())),
+            Value::Integer(n) => Ok(Bytes::from(n.to_string())),
+            Value::Number(n) => Ok(Bytes::from(n.to_string())),
+            _ => Err("ERR Lua redis lib command arguments must be strings or integers".to_string()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let name = String::from_utf8_lossy(&args[0]).to_uppercase();
+    if NOT_FROM_SCRIPTS.contains(&name.as_str()) {
+        return Err("ERR This Redis command is not allowed from script".to_string());
+    }
+    Ok(args)
+}
+
+/// `reply` as a Lua value.
+fn to_lua(lua: &Lua, reply: Frame) -> mlua::Result<Value<'_>> {
+    let value = match reply {
+        Frame::Integer(n) => Value::Integer(n),
+        Frame::Bulk(data) => Value::String(lua.create_string(&data)?),
+        Frame::Null => Value::Boolean(false),
+        Frame::Simple(status) => {
+            let table = lua.create_table()?;
+            table.set("ok", status)?;
+            Value::Table(table)
+        }
+        Frame::Error(error) => {
+            let table = lua.create_table()?;
+            table.set("err", error)?;
+            Value::Table(table)
+        }
+        Frame::Array(frames) => {
+            let table = lua.create_table()?;
+            for (i, frame) in frames.into_iter().enumerate() {
+                table.raw_set(i + 1, to_lua(lua, frame)?)?;
+            }
+            Value::Table(table)
+        }
+    };
+    Ok(value)
+}
+
+/// The reply for `value`, returned by a script.
+fn from_lua(value: Value) -> Frame {
+    match value {
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Integer(n) => Frame::Integer(n),
+        Value::Number(n) => Frame::Integer(n as i64),
+        Value::String(string) => Frame::Bulk(Bytes::copy_from_slice(string.as_bytes())),
+        Value::Table(table) => {
+            if let Ok(mlua::Value::String(status)) = table.raw_get("ok") {
+                return Frame::Simple(status.to_string_lossy().into_owned());
+            }
+            if let Ok(mlua::Value::String(error)) = table.raw_get("err") {
+                return Frame::Error(error.to_string_lossy().into_owned());
+            }
+            // Arrays stop at the first nil, as in Redis.
+            let elements = table.sequence_values::<Value>().map_while(Result::ok);
+            Frame::Array(elements.map(from_lua).collect())
+        }
+        _ => Frame::Null,
+    }
+}
+
+/// The error reply for a script that failed with `error`. Errors of the
+/// commands it called, and of `SCRIPT KILL`, are passed on as they are.
+fn script_error(error: mlua::Error) -> crate::Error {
+    match error {
+        mlua::Error::CallbackError { cause, .. } => script_error((*cause).clone()),
+        mlua::Error::SyntaxError { message, .. } => format!("ERR Error compiling script: {}", message).into(),
+        mlua::Error::RuntimeError(message) if has_error_code(&message) => message.into(),
+        mlua::Error::RuntimeError(message) => format!("ERR Error running script: {}", message).into(),
+        error => format!("ERR Error running script: {}", error).into(),
+    }
+}
+
+/// Whether `message` starts with an error code such as `ERR` or `WRONGTYPE`.
+fn has_error_code(message: &str) -> bool {
+    let code = message.split(' ').next().unwrap_or_default();
+    message.len() > code.len() && !code.is_empty() && code.bytes().all(|byte| byte.is_ascii_uppercase())
+}
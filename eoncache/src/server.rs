@@ -1,11 +1,12 @@
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::broadcast;
 use std::sync::Arc;
-use crate::Db;
+use crate::{db, Db, Frame, Session};
 use crate::connection::Connection;
 use crate::shutdown::Shutdown;
 use crate::parse::Parse;
 use crate::command::handle_command;
+use crate::pubsub;
 
 pub async fn run_server(listener: TcpListener, db: Arc<Db>, shutdown: Shutdown) -> crate::Result<()> {
     // Listen for CTRL+C in a separate task
@@ -16,6 +17,15 @@ pub async fn run_server(listener: TcpListener, db: Arc<Db>, shutdown: Shutdown)
 
     let mut receiver = shutdown.subscribe();  // Get a subscriber for the main loop
 
+    // Purge expired keys in the background, in addition to lazy expiry.
+    db::spawn_active_expiry(&db);
+
+    // Save a snapshot whenever a save rule calls for one.
+    db::spawn_save_rules(&db);
+
+    // Flush the append-only file every second if so configured.
+    db::spawn_aof_fsync(&db);
+
     loop {
         tokio::select! {
             Ok((socket, _)) = listener.accept() => {
@@ -35,23 +45,64 @@ pub async fn run_server(listener: TcpListener, db: Arc<Db>, shutdown: Shutdown)
     Ok(())
 }
 async fn process_connection(socket: TcpStream, db: Arc<Db>, mut shutdown_recv: broadcast::Receiver<()>) {
+    let (addr, laddr) = match (socket.peer_addr(), socket.local_addr()) {
+        (Ok(addr), Ok(laddr)) => (addr, laddr),
+        _ => return,
+    };
+    let client = db.clients().register(addr, laddr);
     let mut connection = Connection::new(socket);
+    let mut session = Session::connected(db, client.clone());
 
-    while let Ok(Some(frame)) = connection.read_frame().await {
+    loop {
+        let frame = tokio::select! {
+            frame = connection.read_frame() => frame,
+            _ = client.killed() => break,
+        };
+        let frame = match frame {
+            Ok(Some(frame)) => frame,
+            _ => break,
+        };
         tracing::debug!("Received frame: {:?}", frame);
+        client.received(&frame, connection.buffered());
+
+        // Subscribing takes the connection over until every subscription
+        // is gone.
+        if !session.in_transaction() && pubsub::enters_subscriber_mode(&frame) {
+            if let Err(e) = pubsub::subscriber_mode(&mut connection, &session, frame, &mut shutdown_recv).await {
+                tracing::debug!("Closing subscriber connection: {}", e);
+                break;
+            }
+            continue;
+        }
+
         match Parse::new(frame) {
             Ok(mut parse) => {
-                match handle_command(&mut parse, &db).await {
-                    Ok(response) => {
-                        if connection.write_frame(&response).await.is_err() {
-                            tracing::error!("Error sending response");
-                            break;
-                        }
-                    },
-                    Err(e) => {
-                        tracing::error!("Error handling command: {}", e);
-                        break;
-                    }
+                // A blocking command may wait for a long time. Stop waiting
+                // if the client goes away in the meantime, so it no longer
+                // counts as a blocked client.
+                let result = tokio::select! {
+                    biased;
+                    result = handle_command(&mut parse, &mut session) => result,
+                    _ = connection.closed() => break,
+                    _ = client.killed() => break,
+                };
+                session.update_client();
+
+                // Command errors are reported to the client; the connection
+                // itself stays usable.
+                let response = result.unwrap_or_else(|e| {
+                    tracing::debug!("Error handling command: {}", e);
+                    Frame::Error(e.to_string())
+                });
+                client.writing(response.encoded_len());
+                let written = tokio::select! {
+                    written = connection.write_frame(&response) => written,
+                    _ = client.killed() => break,
+                };
+                client.writing(0);
+                if written.is_err() {
+                    tracing::error!("Error sending response");
+                    break;
                 }
             },
             Err(e) => {
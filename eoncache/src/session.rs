@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use bytes::Bytes;
+use crate::clients::ClientInfo;
+use crate::Db;
+
+/// Per-client handle around the shared `Db`.
+///
+/// Every connection owns its own `Session`, so state such as the selected
+/// namespace is never shared between clients. Commands are always executed
+/// against the namespace recorded here.
+///
+/// The session also holds the client's transaction, if one is open, and
+/// the keys it watches. Dropping the session stops watching them, and
+/// takes the client out of the registry of connected clients.
+pub struct Session {
+    db: Arc<Db>,
+    namespace: usize,
+
+    /// The client's entry in the registry, for sessions of connections.
+    client: Option<Arc<ClientInfo>>,
+
+    /// The transaction opened by `MULTI`, if any.
+    transaction: Option<Transaction>,
+
+    /// Keys watched by `WATCH`: namespace, key and version when watched.
+    watched: Vec<(usize, Bytes, u64)>,
+}
+
+/// Commands queued between `MULTI` and `EXEC`.
+#[derive(Debug, Default)]
+pub(crate) struct Transaction {
+    /// Each queued command, name first.
+    pub(crate) commands: Vec<Vec<Bytes>>,
+
+    /// Whether a command was refused while queueing, so that `EXEC` must
+    /// discard the transaction.
+    pub(crate) aborted: bool,
+}
+
+impl Session {
+    /// Create a new session on `db`, starting in namespace 0.
+    pub fn new(db: Arc<Db>) -> Session {
+        Session { db, namespace: 0, client: None, transaction: None, watched: Vec::new() }
+    }
+
+    /// Create a new session for the connected `client`.
+    pub(crate) fn connected(db: Arc<Db>, client: Arc<ClientInfo>) -> Session {
+        Session { db, namespace: 0, client: Some(client), transaction: None, watched: Vec::new() }
+    }
+
+    /// The shared database this session operates on.
+    pub fn db(&self) -> &Arc<Db> {
+        &self.db
+    }
+
+    /// Index of the namespace currently selected by this session.
+    pub fn namespace(&self) -> usize {
+        self.namespace
+    }
+
+    /// Switch this session to namespace `index`.
+    ///
+    /// Only this session is affected; other clients keep their selection.
+    pub fn select(&mut self, index: usize) -> Result<(), String> {
+        self.db.check_namespace(index)?;
+        self.namespace = index;
+        Ok(())
+    }
+
+    /// The client's entry in the registry, if the session belongs to a
+    /// connection.
+    pub(crate) fn client(&self) -> Option<&Arc<ClientInfo>> {
+        self.client.as_ref()
+    }
+
+    /// Wait until the client is killed by `CLIENT KILL`. Never returns for
+    /// sessions without a client.
+    pub(crate) async fn killed(&self) {
+        match &self.client {
+            Some(client) => client.killed().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Record the selected namespace and the open transaction in the
+    /// client's entry, once a command has run.
+    pub(crate) fn update_client(&self) {
+        if let Some(client) = &self.client {
+            let queued = self.transaction.as_ref().map(|transaction| transaction.commands.len());
+            client.ran(self.namespace, queued);
+        }
+    }
+
+    /// Whether commands are being queued for `EXEC`.
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    /// Open a transaction: commands are queued from now on.
+    pub(crate) fn multi(&mut self) -> Result<(), &'static str> {
+        if self.transaction.is_some() {
+            return Err("ERR MULTI calls can not be nested");
+        }
+        self.transaction = Some(Transaction::default());
+        Ok(())
+    }
+
+    /// The open transaction, if any.
+    pub(crate) fn transaction_mut(&mut self) -> Option<&mut Transaction> {
+        self.transaction.as_mut()
+    }
+
+    /// Close the open transaction and return it.
+    pub(crate) fn take_transaction(&mut self) -> Option<Transaction> {
+        self.transaction.take()
+    }
+
+    /// Watch `key` in the selected namespace until `unwatch`.
+    pub(crate) fn watch(&mut self, key: Bytes) {
+        let version = self.db.watch(self.namespace, key.clone());
+        self.watched.push((self.namespace, key, version));
+    }
+
+    /// Whether any watched key changed since it was watched.
+    pub(crate) fn watched_changed(&self) -> bool {
+        self.watched
+            .iter()
+            .any(|(index, key, version)| self.db.watched_version(*index, key) != Some(*version))
+    }
+
+    /// Stop watching every key.
+    pub(crate) fn unwatch(&mut self) {
+        for (index, key, _) in std::mem::take(&mut self.watched) {
+            self.db.unwatch(index, &key);
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.unwatch();
+        if let Some(client) = &self.client {
+            self.db.clients().unregister(client.id());
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Set value type.
+//!
+//! Members are kept in a vector, with a hash map from each member to its
+//! position in it. Removing a member moves the last one into its place, so
+//! adding, removing and looking up a member take O(1), and so does picking a
+//! random member for `SPOP` and `SRANDMEMBER`.
+
+use bytes::Bytes;
+use rand::Rng;
+use std::collections::HashMap;
+
+/// A set of unique members, in no particular order.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Set {
+    members: Vec<Bytes>,
+    positions: HashMap<Bytes, usize>,
+}
+
+impl Set {
+    pub(crate) fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub(crate) fn contains(&self, member: &[u8]) -> bool {
+        self.positions.contains_key(member)
+    }
+
+    /// Add `member`. Returns `true` if it is new.
+    pub(crate) fn insert(&mut self, member: Bytes) -> bool {
+        if self.positions.contains_key(&member) {
+            return false;
+        }
+        self.positions.insert(member.clone(), self.members.len());
+        self.members.push(member);
+        true
+    }
+
+    /// Remove `member`. Returns `true` if it was present.
+    pub(crate) fn remove(&mut self, member: &[u8]) -> bool {
+        match self.positions.remove(member) {
+            Some(position) => {
+                self.take(position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove and return a random member.
+    pub(crate) fn pop_random(&mut self, rng: &mut impl Rng) -> Option<Bytes> {
+        if self.is_empty() {
+            return None;
+        }
+        let member = self.take(rng.gen_range(0..self.members.len()));
+        self.positions.remove(&member);
+        Some(member)
+    }
+
+    /// Keep only the members `keep` returns `true` for.
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&Bytes) -> bool) {
+        let mut position = 0;
+        while position < self.members.len() {
+            if keep(&self.members[position]) {
+                position += 1;
+            } else {
+                // The last member moves into `position`, which is checked
+                // next.
+                let member = self.take(position);
+                self.positions.remove(&member);
+            }
+        }
+    }
+
+    /// Every member, in no particular order.
+    pub(crate) fn as_slice(&self) -> &[Bytes] {
+        &self.members
+    }
+
+    pub(crate) fn iter(&self) -> std::slice::Iter<'_, Bytes> {
+        self.members.iter()
+    }
+
+    /// Up to `count` members for `SSCAN` to visit from `cursor`, and the
+    /// cursor to continue from, `0` once the scan is complete.
+    ///
+    /// Members are visited from the end of the vector towards the start, the
+    /// cursor being the position the next call stops before. Removing a
+    /// member only moves the last one into its place, so a member left to
+    /// visit either stays put or, being the last, moves to a position still
+    /// left to visit. A member may be visited twice, but none present for
+    /// the whole scan is missed.
+    pub(crate) fn scan(&self, cursor: u64, count: usize) -> (u64, &[Bytes]) {
+        let end = match cursor {
+            0 => self.members.len(),
+            cursor => usize::try_from(cursor).map_or(self.members.len(), |cursor| cursor.min(self.members.len())),
+        };
+        let start = end.saturating_sub(count);
+        (start as u64, &self.members[start..end])
+    }
+
+    /// Take the member at `position` out of the vector, moving the last
+    /// member into its place. Its entry in `positions` is left to the
+    /// caller.
+    fn take(&mut self, position: usize) -> Bytes {
+        let member = self.members.swap_remove(position);
+        if let Some(moved) = self.members.get(position) {
+            *self.positions.get_mut(moved).unwrap() = position;
+        }
+        member
+    }
+}
+
+impl Extend<Bytes> for Set {
+    fn extend<I: IntoIterator<Item = Bytes>>(&mut self, members: I) {
+        for member in members {
+            self.insert(member);
+        }
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(members: I) -> Set {
+        let mut set = Set::default();
+        set.extend(members);
+        set
+    }
+}
+
+impl IntoIterator for Set {
+    type Item = Bytes;
+    type IntoIter = std::vec::IntoIter<Bytes>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.members.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Set {
+    type Item = &'a Bytes;
+    type IntoIter = std::slice::Iter<'a, Bytes>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.members.iter()
+    }
+}
@@ -13,6 +13,12 @@ impl Clone for Shutdown {
     }
 }
 
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Shutdown {
     pub fn new() -> Self {
         let (sender, _) = broadcast::channel(1);
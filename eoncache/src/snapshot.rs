@@ -0,0 +1,492 @@
+//! Point-in-time snapshots of the whole store.
+//!
+//! A snapshot file starts with `MAGIC` and the format `VERSION`, followed by
+//! the number of namespaces and then each namespace: its number of keys and
+//! every key with its deadline and value. The file ends with a CRC-32 of
+//! everything before it. Integers are little-endian and byte strings are
+//! prefixed with their length. Deadlines and other points in time are stored
+//! as Unix times in milliseconds, so that they survive a restart.
+//!
+//! A snapshot is written to a temporary file that is then renamed over the
+//! previous one, so that a crash while saving never leaves a partial file
+//! behind. Loading checks the checksum before decoding anything, and decodes
+//! the whole file before any of it is installed.
+//!
+//! `DUMP` payloads use the same encoding for a single value, preceded by the
+//! format version and followed by a CRC-32.
+
+use crate::config::SaveRule;
+use crate::db::Value;
+use crate::set::Set;
+use crate::stream::Stream;
+use crate::zset::SortedSet;
+use bytes::{Buf, Bytes};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{Duration, Instant};
+
+/// First bytes of every snapshot file.
+const MAGIC: &[u8] = b"EONCACHE";
+
+/// Version of the format written. Files of other versions are refused.
+const VERSION: u16 = 1;
+
+/// Seconds to wait after a failed save before a save rule tries again.
+const RETRY_DELAY: u64 = 5;
+
+/// Type tags of encoded values.
+const STRING: u8 = 0;
+const LIST: u8 = 1;
+const HASH: u8 = 2;
+const SET: u8 = 3;
+const SORTED_SET: u8 = 4;
+const STREAM: u8 = 5;
+
+const TRUNCATED: &str = "unexpected end of data";
+
+/// A key with its value and deadline.
+pub(crate) type Record = (Bytes, Value, Option<Instant>);
+
+/// Converts points in time between `Instant`s and Unix times, relative to a
+/// single reading of both clocks.
+#[derive(Debug, Clone, Copy)]
+struct Clock {
+    instant: Instant,
+    unix_ms: i64,
+}
+
+impl Clock {
+    fn now() -> Clock {
+        Clock {
+            instant: Instant::now(),
+            unix_ms: unix_time().as_millis() as i64,
+        }
+    }
+
+    fn to_unix_ms(self, when: Instant) -> i64 {
+        if when >= self.instant {
+            self.unix_ms.saturating_add((when - self.instant).as_millis() as i64)
+        } else {
+            self.unix_ms.saturating_sub((self.instant - when).as_millis() as i64)
+        }
+    }
+
+    /// The `Instant` of `unix_ms`. Points too far in the past to be
+    /// represented are clamped to now; points too far in the future give
+    /// `None`.
+    fn to_instant(self, unix_ms: i64) -> Option<Instant> {
+        let offset = Duration::from_millis(unix_ms.abs_diff(self.unix_ms));
+        if unix_ms >= self.unix_ms {
+            self.instant.checked_add(offset)
+        } else {
+            Some(self.instant.checked_sub(offset).unwrap_or(self.instant))
+        }
+    }
+}
+
+/// Time elapsed since the Unix epoch.
+pub(crate) fn unix_time() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Writes values in the snapshot encoding.
+pub(crate) struct Encoder {
+    buf: Vec<u8>,
+    clock: Clock,
+}
+
+impl Encoder {
+    pub(crate) fn new() -> Encoder {
+        Encoder {
+            buf: Vec::new(),
+            clock: Clock::now(),
+        }
+    }
+
+    pub(crate) fn raw(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.raw(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.raw(&value.to_le_bytes());
+    }
+
+    pub(crate) fn i64(&mut self, value: i64) {
+        self.raw(&value.to_le_bytes());
+    }
+
+    pub(crate) fn f64(&mut self, value: f64) {
+        self.raw(&value.to_le_bytes());
+    }
+
+    pub(crate) fn len(&mut self, len: usize) {
+        self.u64(len as u64);
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.raw(bytes);
+    }
+
+    pub(crate) fn instant(&mut self, when: Instant) {
+        let unix_ms = self.clock.to_unix_ms(when);
+        self.i64(unix_ms);
+    }
+
+    pub(crate) fn optional_instant(&mut self, when: Option<Instant>) {
+        match when {
+            Some(when) => {
+                self.u8(1);
+                self.instant(when);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    pub(crate) fn value(&mut self, value: &Value) {
+        match value {
+            Value::String(value) => {
+                self.u8(STRING);
+                self.bytes(value);
+            }
+            Value::List(list) => {
+                self.u8(LIST);
+                self.len(list.len());
+                for element in list {
+                    self.bytes(element);
+                }
+            }
+            Value::Hash(hash) => {
+                self.u8(HASH);
+                self.len(hash.len());
+                for (field, value) in hash {
+                    self.bytes(field);
+                    self.bytes(value);
+                }
+            }
+            Value::Set(set) => {
+                self.u8(SET);
+                self.len(set.len());
+                for member in set {
+                    self.bytes(member);
+                }
+            }
+            Value::SortedSet(zset) => {
+                self.u8(SORTED_SET);
+                self.len(zset.len());
+                for (member, score) in zset.iter_from(0, false) {
+                    self.bytes(&member);
+                    self.f64(score);
+                }
+            }
+            Value::Stream(stream) => {
+                self.u8(STREAM);
+                stream.encode(self);
+            }
+        }
+    }
+
+    /// Append a CRC-32 of everything written so far and return the result.
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        let checksum = crc32fast::hash(&self.buf);
+        self.raw(&checksum.to_le_bytes());
+        self.buf
+    }
+}
+
+/// Reads values in the snapshot encoding.
+pub(crate) struct Decoder {
+    data: Bytes,
+    clock: Clock,
+}
+
+impl Decoder {
+    /// A decoder over `data`, once the CRC-32 at its end is checked and
+    /// stripped.
+    pub(crate) fn new(mut data: Bytes) -> Result<Decoder, &'static str> {
+        if data.len() < 4 {
+            return Err(TRUNCATED);
+        }
+        let checksum = data.split_off(data.len() - 4).get_u32_le();
+        if crc32fast::hash(&data) != checksum {
+            return Err("checksum mismatch");
+        }
+
+        Ok(Decoder { data, clock: Clock::now() })
+    }
+
+    pub(crate) fn raw(&mut self, len: usize) -> Result<Bytes, &'static str> {
+        if self.data.len() < len {
+            return Err(TRUNCATED);
+        }
+        Ok(self.data.split_to(len))
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, &'static str> {
+        Ok(self.raw(1)?.get_u8())
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, &'static str> {
+        Ok(self.raw(2)?.get_u16_le())
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, &'static str> {
+        Ok(self.raw(8)?.get_u64_le())
+    }
+
+    pub(crate) fn i64(&mut self) -> Result<i64, &'static str> {
+        Ok(self.raw(8)?.get_i64_le())
+    }
+
+    pub(crate) fn f64(&mut self) -> Result<f64, &'static str> {
+        Ok(self.raw(8)?.get_f64_le())
+    }
+
+    /// A length or element count. Every element takes at least one byte, so
+    /// a count larger than what is left is corrupt.
+    pub(crate) fn len(&mut self) -> Result<usize, &'static str> {
+        let len = self.u64()?;
+        if len > self.data.len() as u64 {
+            return Err(TRUNCATED);
+        }
+        Ok(len as usize)
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<Bytes, &'static str> {
+        let len = self.len()?;
+        self.raw(len)
+    }
+
+    pub(crate) fn instant(&mut self) -> Result<Instant, &'static str> {
+        let unix_ms = self.i64()?;
+        self.clock.to_instant(unix_ms).ok_or("time out of range")
+    }
+
+    pub(crate) fn optional_instant(&mut self) -> Result<Option<Instant>, &'static str> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => self.instant().map(Some),
+        }
+    }
+
+    pub(crate) fn value(&mut self) -> Result<Value, &'static str> {
+        let value = match self.u8()? {
+            STRING => Value::String(self.bytes()?),
+            LIST => {
+                let len = self.len()?;
+                let mut list = VecDeque::new();
+                for _ in 0..len {
+                    list.push_back(self.bytes()?);
+                }
+                Value::List(list)
+            }
+            HASH => {
+                let len = self.len()?;
+                let mut hash = HashMap::new();
+                for _ in 0..len {
+                    hash.insert(self.bytes()?, self.bytes()?);
+                }
+                Value::Hash(hash)
+            }
+            SET => {
+                let len = self.len()?;
+                let mut set = Set::default();
+                for _ in 0..len {
+                    set.insert(self.bytes()?);
+                }
+                Value::Set(set)
+            }
+            SORTED_SET => {
+                let len = self.len()?;
+                let mut zset = SortedSet::default();
+                for _ in 0..len {
+                    let member = self.bytes()?;
+                    let score = self.f64()?;
+                    if score.is_nan() {
+                        return Err("invalid sorted set score");
+                    }
+                    zset.insert(member, score);
+                }
+                Value::SortedSet(zset)
+            }
+            STREAM => Value::Stream(Stream::decode(self)?),
+            _ => return Err("unknown value type"),
+        };
+        Ok(value)
+    }
+
+    /// Fails unless everything was read.
+    pub(crate) fn finish(&self) -> Result<(), &'static str> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err("unexpected data after the end")
+        }
+    }
+}
+
+/// Encode a snapshot of every namespace, given as the records of each in
+/// order of index.
+pub(crate) fn encode(namespaces: &[Vec<Record>]) -> Vec<u8> {
+    let mut out = Encoder::new();
+    out.raw(MAGIC);
+    out.u16(VERSION);
+
+    out.len(namespaces.len());
+    for records in namespaces {
+        out.len(records.len());
+        for (key, value, deadline) in records {
+            out.bytes(key);
+            out.optional_instant(*deadline);
+            out.value(value);
+        }
+    }
+
+    out.finish()
+}
+
+/// Decode a snapshot written by `encode`.
+pub(crate) fn decode(data: Bytes) -> Result<Vec<Vec<Record>>, &'static str> {
+    if !data.starts_with(MAGIC) {
+        return Err("not a snapshot file");
+    }
+
+    let mut input = Decoder::new(data)?;
+    input.raw(MAGIC.len())?;
+    if input.u16()? != VERSION {
+        return Err("unsupported format version");
+    }
+
+    let count = input.len()?;
+    let mut namespaces = Vec::new();
+    for _ in 0..count {
+        let len = input.len()?;
+        let mut records = Vec::new();
+        for _ in 0..len {
+            let key = input.bytes()?;
+            let deadline = input.optional_instant()?;
+            records.push((key, input.value()?, deadline));
+        }
+        namespaces.push(records);
+    }
+
+    input.finish()?;
+    Ok(namespaces)
+}
+
+/// Encode `value` as a `DUMP` payload.
+pub(crate) fn dump(value: &Value) -> Vec<u8> {
+    let mut out = Encoder::new();
+    out.u16(VERSION);
+    out.value(value);
+    out.finish()
+}
+
+/// Decode a `DUMP` payload, refusing those of other format versions.
+pub(crate) fn restore(payload: Bytes) -> Result<Value, &'static str> {
+    const INVALID: &str = "ERR DUMP payload version or checksum are wrong";
+
+    let mut input = Decoder::new(payload).map_err(|_| INVALID)?;
+    if input.u16().map_err(|_| INVALID)? != VERSION {
+        return Err(INVALID);
+    }
+    let value = input.value().map_err(|_| "ERR Bad data format")?;
+    input.finish().map_err(|_| "ERR Bad data format")?;
+    Ok(value)
+}
+
+/// Write `data` to `path`. The previous file is only replaced once the new
+/// one is entirely on disk.
+pub(crate) fn write_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+
+    let mut file = File::create(&temporary)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+/// The contents of `path`, or `None` if there is no such file.
+pub(crate) fn read_file(path: &Path) -> io::Result<Option<Bytes>> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data.into())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Where snapshots go, when they are taken, and how the last one went.
+#[derive(Debug)]
+pub(crate) struct Persistence {
+    pub(crate) path: PathBuf,
+    pub(crate) rules: Mutex<Vec<SaveRule>>,
+
+    /// Approximate number of writes since the last successful save.
+    pub(crate) changes: AtomicU64,
+
+    /// Unix time in seconds of the last successful save, or of startup.
+    pub(crate) last_save: AtomicU64,
+
+    /// Unix time in seconds of the last save attempt.
+    last_attempt: AtomicU64,
+
+    /// Whether the last save attempt succeeded.
+    pub(crate) last_ok: AtomicBool,
+
+    /// Set while a save is running; only one runs at a time.
+    pub(crate) saving: AtomicBool,
+}
+
+impl Persistence {
+    pub(crate) fn new(path: PathBuf, rules: Vec<SaveRule>) -> Persistence {
+        let now = unix_time().as_secs();
+        Persistence {
+            path,
+            rules: Mutex::new(rules),
+            changes: AtomicU64::new(0),
+            last_save: AtomicU64::new(now),
+            last_attempt: AtomicU64::new(now),
+            last_ok: AtomicBool::new(true),
+            saving: AtomicBool::new(false),
+        }
+    }
+
+    /// Record the outcome of a save that captured `changes` writes.
+    pub(crate) fn record(&self, changes: u64, ok: bool) {
+        let now = unix_time().as_secs();
+        if ok {
+            self.changes.fetch_sub(changes, Ordering::Relaxed);
+            self.last_save.store(now, Ordering::Relaxed);
+        }
+        self.last_attempt.store(now, Ordering::Relaxed);
+        self.last_ok.store(ok, Ordering::Relaxed);
+    }
+
+    /// Whether a save rule calls for a snapshot now. After a failed save,
+    /// waits `RETRY_DELAY` before trying again.
+    pub(crate) fn due(&self) -> bool {
+        let now = unix_time().as_secs();
+        if !self.last_ok.load(Ordering::Relaxed) && now.saturating_sub(self.last_attempt.load(Ordering::Relaxed)) < RETRY_DELAY {
+            return false;
+        }
+
+        let elapsed = now.saturating_sub(self.last_save.load(Ordering::Relaxed));
+        let changes = self.changes.load(Ordering::Relaxed);
+        let rules = self.rules.lock().unwrap();
+        rules.iter().any(|rule| changes >= rule.changes && elapsed >= rule.seconds)
+    }
+}
@@ -0,0 +1,727 @@
+//! Stream value type.
+//!
+//! A stream is an append-only log of entries, each holding a list of
+//! field/value pairs and addressed by a `StreamId`. IDs are strictly
+//! increasing, so entries live in a `BTreeMap` and range reads are plain
+//! ordered range scans.
+//!
+//! Consumer groups track, per group, the last entry handed out and a pending
+//! entries list (PEL) of entries delivered but not yet acknowledged, so that
+//! entries held by a consumer that went away can be claimed by another.
+
+use crate::db::{ClaimOptions, Trim, TrimStrategy, XAddId, NOGROUP};
+use crate::snapshot::{Decoder, Encoder};
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{Duration, Instant};
+
+/// ID of a stream entry: a millisecond timestamp and a sequence number for
+/// entries added within the same millisecond.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    /// The smallest ID greater than this one.
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    /// The largest ID smaller than this one.
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+
+    /// Parse an ID where the sequence number may be omitted, in which case it
+    /// defaults to `missing_seq`. Used for range bounds, where `5` means
+    /// `5-0` as a start and `5-18446744073709551615` as an end.
+    pub fn parse_with_default(id: &str, missing_seq: u64) -> Option<StreamId> {
+        match id.split_once('-') {
+            Some((ms, seq)) => Some(StreamId::new(ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some(StreamId::new(id.parse().ok()?, missing_seq)),
+        }
+    }
+}
+
+impl FromStr for StreamId {
+    type Err = &'static str;
+
+    fn from_str(id: &str) -> Result<StreamId, Self::Err> {
+        StreamId::parse_with_default(id, 0).ok_or("ERR Invalid stream ID specified as stream command argument")
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// A stream entry: its ID and field/value pairs.
+pub type StreamEntry = (StreamId, Vec<(Bytes, Bytes)>);
+
+/// An entry read from a consumer's pending entries. The fields are `None` if
+/// the entry was deleted from the stream after it was delivered.
+pub type GroupEntry = (StreamId, Option<Vec<(Bytes, Bytes)>>);
+
+/// Summary of a group's pending entries, as reported by `XPENDING key group`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingSummary {
+    pub count: usize,
+    /// Smallest and greatest pending IDs.
+    pub range: Option<(StreamId, StreamId)>,
+    /// Number of pending entries per consumer that has any.
+    pub consumers: Vec<(Bytes, usize)>,
+}
+
+/// A single pending entry, as reported by the extended form of `XPENDING`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    pub id: StreamId,
+    pub consumer: Bytes,
+    /// Time since the entry was last delivered.
+    pub idle: Duration,
+    /// Number of times the entry was delivered.
+    pub deliveries: u64,
+}
+
+/// Reply of `XINFO STREAM`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamInfo {
+    pub length: usize,
+    pub last_id: StreamId,
+    pub groups: usize,
+    pub first: Option<StreamEntry>,
+    pub last: Option<StreamEntry>,
+}
+
+/// One group in the reply of `XINFO GROUPS`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupInfo {
+    pub name: Bytes,
+    pub consumers: usize,
+    pub pending: usize,
+    pub last_delivered: StreamId,
+}
+
+/// One consumer in the reply of `XINFO CONSUMERS`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsumerInfo {
+    pub name: Bytes,
+    pub pending: usize,
+    /// Time since the consumer last interacted with the group.
+    pub idle: Duration,
+    /// Time since the consumer last read or claimed an entry, if it ever did.
+    pub inactive: Option<Duration>,
+}
+
+/// An entry delivered to a consumer and not acknowledged yet.
+#[derive(Debug, Clone)]
+struct Delivery {
+    consumer: Bytes,
+    delivered_at: Instant,
+    count: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Consumer {
+    /// IDs of the entries this consumer holds in the group's PEL.
+    pending: BTreeSet<StreamId>,
+    /// Last time the consumer interacted with the group at all.
+    seen_at: Instant,
+    /// Last time the consumer was handed entries.
+    active_at: Option<Instant>,
+}
+
+impl Consumer {
+    fn new(now: Instant) -> Consumer {
+        Consumer {
+            pending: BTreeSet::new(),
+            seen_at: now,
+            active_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Group {
+    /// Last entry handed out with `>`; later entries are new to the group.
+    last_delivered: StreamId,
+    /// Every entry delivered and not acknowledged, whichever consumer holds it.
+    pending: BTreeMap<StreamId, Delivery>,
+    consumers: BTreeMap<Bytes, Consumer>,
+}
+
+impl Group {
+    fn new(last_delivered: StreamId) -> Group {
+        Group {
+            last_delivered,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    /// The consumer called `name`, created if it does not exist, marked as
+    /// seen now.
+    fn touch(&mut self, name: &Bytes, now: Instant) -> &mut Consumer {
+        let consumer = self
+            .consumers
+            .entry(name.clone())
+            .or_insert_with(|| Consumer::new(now));
+        consumer.seen_at = now;
+        consumer
+    }
+
+    /// Record `id` as pending for `consumer`, taking it from whichever
+    /// consumer held it before.
+    fn assign(&mut self, id: StreamId, consumer: &Bytes, delivered_at: Instant, count: u64) {
+        let previous = self.pending.insert(
+            id,
+            Delivery {
+                consumer: consumer.clone(),
+                delivered_at,
+                count,
+            },
+        );
+        if let Some(previous) = previous {
+            if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        self.touch(consumer, Instant::now()).pending.insert(id);
+    }
+
+    /// Drop `id` from the PEL. Returns `true` if it was pending.
+    fn release(&mut self, id: StreamId) -> bool {
+        match self.pending.remove(&id) {
+            Some(delivery) => {
+                if let Some(owner) = self.consumers.get_mut(&delivery.consumer) {
+                    owner.pending.remove(&id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Stream {
+    entries: BTreeMap<StreamId, Vec<(Bytes, Bytes)>>,
+
+    /// ID of the last entry ever added, even if it has since been deleted.
+    /// New IDs must be greater.
+    last_id: StreamId,
+
+    groups: BTreeMap<Bytes, Group>,
+}
+
+impl Stream {
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Approximate number of bytes used by each entry, in ID order.
+    pub(crate) fn entry_sizes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entries.values().map(|fields| {
+            16 + fields.iter().map(|(field, value)| field.len() + value.len()).sum::<usize>()
+        })
+    }
+
+    pub(crate) fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Append an entry. Fails if the requested ID is not greater than the
+    /// last one.
+    pub(crate) fn add(&mut self, id: XAddId, fields: Vec<(Bytes, Bytes)>) -> Result<StreamId, &'static str> {
+        let id = match id {
+            XAddId::Auto => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_millis() as u64)
+                    .unwrap_or(0);
+                if now > self.last_id.ms {
+                    StreamId::new(now, 0)
+                } else {
+                    self.last_id.next().ok_or("ERR The stream has exhausted the last possible ID, unable to add more items")?
+                }
+            }
+            XAddId::AutoSeq(ms) if ms == self.last_id.ms => self
+                .last_id
+                .next()
+                .filter(|id| id.ms == ms)
+                .ok_or("ERR The ID specified in XADD is equal or smaller than the target stream top item")?,
+            XAddId::AutoSeq(ms) => StreamId::new(ms, if ms == 0 { 1 } else { 0 }),
+            XAddId::Explicit(id) => id,
+        };
+
+        if id == StreamId::MIN {
+            return Err("ERR The ID specified in XADD must be greater than 0-0");
+        }
+        if id <= self.last_id {
+            return Err("ERR The ID specified in XADD is equal or smaller than the target stream top item");
+        }
+
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        Ok(id)
+    }
+
+    /// Remove the entries with the given IDs. Returns how many existed.
+    pub(crate) fn delete(&mut self, ids: &[StreamId]) -> usize {
+        ids.iter().filter(|id| self.entries.remove(id).is_some()).count()
+    }
+
+    /// Evict the oldest entries according to `trim`. Returns how many were
+    /// evicted.
+    pub(crate) fn trim(&mut self, trim: Trim) -> usize {
+        let limit = trim.limit.unwrap_or(usize::MAX);
+        let mut evicted = 0;
+
+        while evicted < limit {
+            let oldest = match self.entries.first_key_value() {
+                Some((id, _)) => *id,
+                None => break,
+            };
+
+            let evict = match trim.strategy {
+                TrimStrategy::MaxLen(max) => self.entries.len() > max,
+                TrimStrategy::MinId(min) => oldest < min,
+            };
+            if !evict {
+                break;
+            }
+
+            self.entries.remove(&oldest);
+            evicted += 1;
+        }
+
+        evicted
+    }
+
+    /// Entries with IDs in `start..=end`, oldest first, or newest first if
+    /// `reverse` is set. At most `count` entries are returned.
+    pub(crate) fn range(&self, start: StreamId, end: StreamId, count: usize, reverse: bool) -> Vec<StreamEntry> {
+        if start > end {
+            return Vec::new();
+        }
+
+        let range = self.entries.range(start..=end);
+        let entry = |(id, fields): (&StreamId, &Vec<(Bytes, Bytes)>)| (*id, fields.clone());
+        if reverse {
+            range.rev().take(count).map(entry).collect()
+        } else {
+            range.take(count).map(entry).collect()
+        }
+    }
+
+    /// Create the consumer group `name`, which considers entries after
+    /// `start` new. `None` starts after the last entry.
+    pub(crate) fn create_group(&mut self, name: Bytes, start: Option<StreamId>) -> Result<(), &'static str> {
+        if self.groups.contains_key(&name) {
+            return Err("BUSYGROUP Consumer Group name already exists");
+        }
+        self.groups.insert(name, Group::new(start.unwrap_or(self.last_id)));
+        Ok(())
+    }
+
+    pub(crate) fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Move the point after which entries are new to group `name`. `None`
+    /// moves it to the last entry.
+    pub(crate) fn set_group_id(&mut self, name: &[u8], start: Option<StreamId>) -> Result<(), &'static str> {
+        let last_id = self.last_id;
+        let group = self.groups.get_mut(name).ok_or(NOGROUP)?;
+        group.last_delivered = start.unwrap_or(last_id);
+        Ok(())
+    }
+
+    pub(crate) fn has_group(&self, name: &[u8]) -> bool {
+        self.groups.contains_key(name)
+    }
+
+    /// Create `consumer` in group `name`. Returns `false` if it existed.
+    pub(crate) fn create_consumer(&mut self, name: &[u8], consumer: &Bytes) -> Result<bool, &'static str> {
+        let group = self.groups.get_mut(name).ok_or(NOGROUP)?;
+        if group.consumers.contains_key(consumer) {
+            return Ok(false);
+        }
+        group.consumers.insert(consumer.clone(), Consumer::new(Instant::now()));
+        Ok(true)
+    }
+
+    /// Delete `consumer` from group `name`, dropping the entries it held
+    /// from the PEL. Returns how many entries it held.
+    pub(crate) fn delete_consumer(&mut self, name: &[u8], consumer: &[u8]) -> Result<usize, &'static str> {
+        let group = self.groups.get_mut(name).ok_or(NOGROUP)?;
+        let removed = match group.consumers.remove(consumer) {
+            Some(removed) => removed,
+            None => return Ok(0),
+        };
+        for id in &removed.pending {
+            group.pending.remove(id);
+        }
+        Ok(removed.pending.len())
+    }
+
+    /// Read on behalf of `consumer` in group `name`.
+    ///
+    /// With `after` unset, returns up to `count` entries never delivered to
+    /// the group and, unless `no_ack` is set, adds them to the PEL. With
+    /// `after` set, returns the consumer's own pending entries with greater
+    /// IDs instead, counting each as delivered again.
+    pub(crate) fn read_group(&mut self, name: &[u8], consumer: &Bytes, after: Option<StreamId>, count: usize, no_ack: bool) -> Result<Vec<GroupEntry>, &'static str> {
+        let now = Instant::now();
+        let group = self.groups.get_mut(name).ok_or(NOGROUP)?;
+        group.touch(consumer, now);
+
+        let after = match after {
+            Some(after) => after,
+            None => {
+                let entries: Vec<StreamEntry> = match group.last_delivered.next() {
+                    Some(start) => self
+                        .entries
+                        .range(start..)
+                        .take(count)
+                        .map(|(id, fields)| (*id, fields.clone()))
+                        .collect(),
+                    None => Vec::new(),
+                };
+
+                if let Some((last, _)) = entries.last() {
+                    group.last_delivered = *last;
+                    group.touch(consumer, now).active_at = Some(now);
+                }
+                if !no_ack {
+                    for (id, _) in &entries {
+                        group.assign(*id, consumer, now, 1);
+                    }
+                }
+                return Ok(entries.into_iter().map(|(id, fields)| (id, Some(fields))).collect());
+            }
+        };
+
+        let ids: Vec<StreamId> = match after.next() {
+            Some(start) => group.touch(consumer, now).pending.range(start..).take(count).copied().collect(),
+            None => Vec::new(),
+        };
+
+        Ok(ids
+            .into_iter()
+            .map(|id| {
+                if let Some(delivery) = group.pending.get_mut(&id) {
+                    delivery.delivered_at = now;
+                    delivery.count += 1;
+                }
+                (id, self.entries.get(&id).cloned())
+            })
+            .collect())
+    }
+
+    /// Acknowledge `ids` in group `name`. Returns how many were pending.
+    pub(crate) fn ack(&mut self, name: &[u8], ids: &[StreamId]) -> usize {
+        match self.groups.get_mut(name) {
+            Some(group) => ids.iter().filter(|id| group.release(**id)).count(),
+            None => 0,
+        }
+    }
+
+    pub(crate) fn pending_summary(&self, name: &[u8]) -> Result<PendingSummary, &'static str> {
+        let group = self.groups.get(name).ok_or(NOGROUP)?;
+
+        let range = match (group.pending.first_key_value(), group.pending.last_key_value()) {
+            (Some((first, _)), Some((last, _))) => Some((*first, *last)),
+            _ => None,
+        };
+        let consumers = group
+            .consumers
+            .iter()
+            .filter(|(_, consumer)| !consumer.pending.is_empty())
+            .map(|(name, consumer)| (name.clone(), consumer.pending.len()))
+            .collect();
+
+        Ok(PendingSummary {
+            count: group.pending.len(),
+            range,
+            consumers,
+        })
+    }
+
+    /// Up to `count` pending entries of group `name` with IDs in
+    /// `start..=end`, idle for at least `min_idle`, optionally only those
+    /// held by `consumer`.
+    pub(crate) fn pending(&self, name: &[u8], start: StreamId, end: StreamId, count: usize, min_idle: Duration, consumer: Option<&[u8]>) -> Result<Vec<PendingEntry>, &'static str> {
+        let group = self.groups.get(name).ok_or(NOGROUP)?;
+        if start > end {
+            return Ok(Vec::new());
+        }
+
+        let now = Instant::now();
+        Ok(group
+            .pending
+            .range(start..=end)
+            .filter(|(_, delivery)| consumer.is_none_or(|consumer| delivery.consumer.as_ref() == consumer))
+            .map(|(id, delivery)| PendingEntry {
+                id: *id,
+                consumer: delivery.consumer.clone(),
+                idle: now.saturating_duration_since(delivery.delivered_at),
+                deliveries: delivery.count,
+            })
+            .filter(|entry| entry.idle >= min_idle)
+            .take(count)
+            .collect())
+    }
+
+    /// Transfer the pending entries `ids` of group `name` that have been idle
+    /// for at least `min_idle` to `consumer`. Returns the entries claimed.
+    ///
+    /// Pending entries that were deleted from the stream are dropped from
+    /// the PEL instead of being claimed.
+    pub(crate) fn claim(&mut self, name: &[u8], consumer: &Bytes, min_idle: Duration, ids: &[StreamId], options: ClaimOptions) -> Result<Vec<StreamEntry>, &'static str> {
+        let now = Instant::now();
+        let group = self.groups.get_mut(name).ok_or(NOGROUP)?;
+        group.touch(consumer, now);
+
+        let mut claimed = Vec::new();
+        for id in ids {
+            let fields = match self.entries.get(id) {
+                Some(fields) => fields,
+                None => {
+                    group.release(*id);
+                    continue;
+                }
+            };
+
+            let count = match group.pending.get(id) {
+                Some(delivery) if now.saturating_duration_since(delivery.delivered_at) < min_idle => continue,
+                Some(delivery) => delivery.count,
+                None if options.force => 0,
+                None => continue,
+            };
+
+            let count = match options.retry_count {
+                Some(retry_count) => retry_count,
+                None if options.just_id => count,
+                None => count + 1,
+            };
+            let delivered_at = options
+                .idle
+                .and_then(|idle| now.checked_sub(idle))
+                .unwrap_or(now);
+
+            group.assign(*id, consumer, delivered_at, count);
+            claimed.push((*id, fields.clone()));
+        }
+
+        if !claimed.is_empty() {
+            group.touch(consumer, now).active_at = Some(now);
+        }
+        Ok(claimed)
+    }
+
+    /// Claim for `consumer` up to `count` entries of group `name` idle for at
+    /// least `min_idle`, scanning the PEL from `start`.
+    ///
+    /// Returns the ID to resume the scan from (`0-0` once the whole PEL has
+    /// been scanned), the entries claimed and the IDs of pending entries that
+    /// were found deleted from the stream and dropped from the PEL.
+    pub(crate) fn autoclaim(&mut self, name: &[u8], consumer: &Bytes, min_idle: Duration, start: StreamId, count: usize, just_id: bool) -> Result<(StreamId, Vec<StreamEntry>, Vec<StreamId>), &'static str> {
+        let now = Instant::now();
+        let group = self.groups.get_mut(name).ok_or(NOGROUP)?;
+
+        // Bound the work done per call, like Redis does, even if few of the
+        // scanned entries are idle enough.
+        let mut attempts = count.saturating_mul(10);
+        let mut scanned = Vec::new();
+        let mut next = StreamId::MIN;
+        for (id, delivery) in group.pending.range(start..) {
+            if attempts == 0 || scanned.len() == count {
+                next = *id;
+                break;
+            }
+            attempts -= 1;
+            if now.saturating_duration_since(delivery.delivered_at) >= min_idle {
+                scanned.push(*id);
+            }
+        }
+
+        let mut deleted = Vec::new();
+        let mut claimed = Vec::new();
+        for id in scanned {
+            match self.entries.get(&id) {
+                Some(fields) => {
+                    let count = group.pending.get(&id).map_or(0, |delivery| delivery.count);
+                    let count = if just_id { count } else { count + 1 };
+                    group.assign(id, consumer, now, count);
+                    claimed.push((id, fields.clone()));
+                }
+                None => {
+                    group.release(id);
+                    deleted.push(id);
+                }
+            }
+        }
+
+        let consumer = group.touch(consumer, now);
+        if !claimed.is_empty() {
+            consumer.active_at = Some(now);
+        }
+        Ok((next, claimed, deleted))
+    }
+
+    pub(crate) fn info(&self) -> StreamInfo {
+        let entry = |(id, fields): (&StreamId, &Vec<(Bytes, Bytes)>)| (*id, fields.clone());
+        StreamInfo {
+            length: self.entries.len(),
+            last_id: self.last_id,
+            groups: self.groups.len(),
+            first: self.entries.first_key_value().map(entry),
+            last: self.entries.last_key_value().map(entry),
+        }
+    }
+
+    pub(crate) fn group_info(&self) -> Vec<GroupInfo> {
+        self.groups
+            .iter()
+            .map(|(name, group)| GroupInfo {
+                name: name.clone(),
+                consumers: group.consumers.len(),
+                pending: group.pending.len(),
+                last_delivered: group.last_delivered,
+            })
+            .collect()
+    }
+
+    pub(crate) fn consumer_info(&self, name: &[u8]) -> Result<Vec<ConsumerInfo>, &'static str> {
+        let group = self.groups.get(name).ok_or(NOGROUP)?;
+        let now = Instant::now();
+
+        Ok(group
+            .consumers
+            .iter()
+            .map(|(name, consumer)| ConsumerInfo {
+                name: name.clone(),
+                pending: consumer.pending.len(),
+                idle: now.saturating_duration_since(consumer.seen_at),
+                inactive: consumer.active_at.map(|at| now.saturating_duration_since(at)),
+            })
+            .collect())
+    }
+}
+
+impl StreamId {
+    fn encode(self, out: &mut Encoder) {
+        out.u64(self.ms);
+        out.u64(self.seq);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<StreamId, &'static str> {
+        Ok(StreamId::new(input.u64()?, input.u64()?))
+    }
+}
+
+impl Stream {
+    /// Write the stream, consumer groups included, for a snapshot.
+    pub(crate) fn encode(&self, out: &mut Encoder) {
+        self.last_id.encode(out);
+        out.len(self.entries.len());
+        for (id, fields) in &self.entries {
+            id.encode(out);
+            out.len(fields.len());
+            for (field, value) in fields {
+                out.bytes(field);
+                out.bytes(value);
+            }
+        }
+
+        out.len(self.groups.len());
+        for (name, group) in &self.groups {
+            out.bytes(name);
+            group.last_delivered.encode(out);
+
+            out.len(group.consumers.len());
+            for (name, consumer) in &group.consumers {
+                out.bytes(name);
+                out.instant(consumer.seen_at);
+                out.optional_instant(consumer.active_at);
+            }
+
+            // Which entries each consumer holds follows from the PEL.
+            out.len(group.pending.len());
+            for (id, delivery) in &group.pending {
+                id.encode(out);
+                out.bytes(&delivery.consumer);
+                out.instant(delivery.delivered_at);
+                out.u64(delivery.count);
+            }
+        }
+    }
+
+    /// Read a stream written by `encode`.
+    pub(crate) fn decode(input: &mut Decoder) -> Result<Stream, &'static str> {
+        let mut stream = Stream {
+            last_id: StreamId::decode(input)?,
+            ..Stream::default()
+        };
+
+        for _ in 0..input.len()? {
+            let id = StreamId::decode(input)?;
+            let mut fields = Vec::new();
+            for _ in 0..input.len()? {
+                fields.push((input.bytes()?, input.bytes()?));
+            }
+            stream.entries.insert(id, fields);
+        }
+
+        for _ in 0..input.len()? {
+            let name = input.bytes()?;
+            let mut group = Group::new(StreamId::decode(input)?);
+
+            for _ in 0..input.len()? {
+                let name = input.bytes()?;
+                let mut consumer = Consumer::new(input.instant()?);
+                consumer.active_at = input.optional_instant()?;
+                group.consumers.insert(name, consumer);
+            }
+
+            for _ in 0..input.len()? {
+                let id = StreamId::decode(input)?;
+                let consumer = input.bytes()?;
+                let delivered_at = input.instant()?;
+                let count = input.u64()?;
+                group
+                    .consumers
+                    .get_mut(&consumer)
+                    .ok_or("pending entry held by an unknown consumer")?
+                    .pending
+                    .insert(id);
+                group.pending.insert(id, Delivery { consumer, delivered_at, count });
+            }
+
+            stream.groups.insert(name, group);
+        }
+
+        Ok(stream)
+    }
+}
@@ -0,0 +1,341 @@
+//! Sorted set value type.
+//!
+//! Members are kept in a treap ordered by `(score, member)`. Every node
+//! records the size of its subtree, so looking up the rank of a member,
+//! selecting the member at a rank and locating a score or lexicographic bound
+//! all take O(log n). A hash map from member to score answers `ZSCORE` in
+//! constant time.
+
+use crate::db::{LexBound, ScoreBound, ZRange};
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// A finite score with a total order.
+///
+/// Scores are never NaN, and `-0.0` is normalized to `0.0` so that equal
+/// scores always compare equal.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Score(f64);
+
+impl Score {
+    pub(crate) fn new(value: f64) -> Score {
+        debug_assert!(!value.is_nan());
+        Score(value + 0.0)
+    }
+
+    pub(crate) fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Position of a member in the ordering.
+type Key = (Score, Bytes);
+
+#[derive(Debug, Clone)]
+struct Node {
+    key: Key,
+    priority: u64,
+    size: usize,
+    left: Link,
+    right: Link,
+}
+
+type Link = Option<Box<Node>>;
+
+fn size(link: &Link) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+impl Node {
+    fn new(key: Key) -> Box<Node> {
+        Box::new(Node {
+            key,
+            priority: rand::random(),
+            size: 1,
+            left: None,
+            right: None,
+        })
+    }
+
+    fn update(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+}
+
+/// Split `link` into its first `rank` nodes and the rest.
+fn split(link: Link, rank: usize) -> (Link, Link) {
+    let mut node = match link {
+        Some(node) => node,
+        None => return (None, None),
+    };
+
+    let left_size = size(&node.left);
+    if rank <= left_size {
+        let (left, rest) = split(node.left.take(), rank);
+        node.left = rest;
+        node.update();
+        (left, Some(node))
+    } else {
+        let (rest, right) = split(node.right.take(), rank - left_size - 1);
+        node.right = rest;
+        node.update();
+        (Some(node), right)
+    }
+}
+
+/// Concatenate two treaps where every key of `left` precedes those of `right`.
+fn merge(left: Link, right: Link) -> Link {
+    match (left, right) {
+        (None, right) => right,
+        (left, None) => left,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority >= right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.update();
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                right.update();
+                Some(right)
+            }
+        }
+    }
+}
+
+/// A set of unique members, each with a score, ordered by score and then by
+/// member.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SortedSet {
+    scores: HashMap<Bytes, Score>,
+    root: Link,
+}
+
+impl SortedSet {
+    pub(crate) fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub(crate) fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).map(|score| score.get())
+    }
+
+    /// Add `member` with `score`, or move it if it is already present.
+    /// Returns `true` if the member is new.
+    pub(crate) fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        let score = Score::new(score);
+        let previous = self.scores.insert(member.clone(), score);
+
+        if let Some(previous) = previous {
+            if previous == score {
+                return false;
+            }
+            self.unlink(&(previous, member.clone()));
+        }
+
+        let key = (score, member);
+        let rank = self.count_before(|other| *other < key);
+        let (left, right) = split(self.root.take(), rank);
+        self.root = merge(merge(left, Some(Node::new(key))), right);
+
+        previous.is_none()
+    }
+
+    /// Remove `member`. Returns `true` if it was present.
+    pub(crate) fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove_entry(member) {
+            Some((member, score)) => {
+                self.unlink(&(score, member));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn unlink(&mut self, key: &Key) {
+        let rank = self.count_before(|other| other < key);
+        let (left, rest) = split(self.root.take(), rank);
+        let (_, right) = split(rest, 1);
+        self.root = merge(left, right);
+    }
+
+    /// Zero-based rank of `member` in ascending order.
+    pub(crate) fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = *self.scores.get(member)?;
+        Some(self.count_before(|(other_score, other)| {
+            (*other_score, other.as_ref()) < (score, member)
+        }))
+    }
+
+    /// Number of members for which `before` holds. `before` must hold for a
+    /// prefix of the ordering and fail for the rest.
+    pub(crate) fn count_before(&self, before: impl Fn(&Key) -> bool) -> usize {
+        let mut count = 0;
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            if before(&node.key) {
+                count += size(&node.left) + 1;
+                link = &node.right;
+            } else {
+                link = &node.left;
+            }
+        }
+
+        count
+    }
+
+    /// Iterate over the members from rank `start` onwards, ascending, or from
+    /// rank `start` down to rank 0 if `reverse` is set.
+    pub(crate) fn iter_from(&self, start: usize, reverse: bool) -> Iter<'_> {
+        let mut iter = Iter {
+            stack: Vec::new(),
+            reverse,
+        };
+
+        // Walk down to the node at `start`, remembering every ancestor that
+        // comes after it in iteration order.
+        let mut rank = start;
+        let mut link = &self.root;
+        while let Some(node) = link {
+            let left_size = size(&node.left);
+            match rank.cmp(&left_size) {
+                Ordering::Less => {
+                    if !reverse {
+                        iter.stack.push(node);
+                    }
+                    link = &node.left;
+                }
+                Ordering::Equal => {
+                    iter.stack.push(node);
+                    break;
+                }
+                Ordering::Greater => {
+                    if reverse {
+                        iter.stack.push(node);
+                    }
+                    rank -= left_size + 1;
+                    link = &node.right;
+                }
+            }
+        }
+
+        iter
+    }
+
+    /// Ascending ranks `start..end` of the members selected by `range`.
+    ///
+    /// With `reverse`, rank ranges count from the highest score instead; the
+    /// interval returned is still in ascending ranks.
+    pub(crate) fn rank_interval(&self, range: &ZRange, reverse: bool) -> (usize, usize) {
+        let len = self.len();
+
+        match range {
+            ZRange::Rank(start, stop) => {
+                let resolve = |rank: i64| if rank < 0 { rank + len as i64 } else { rank };
+                let start = resolve(*start).max(0);
+                let stop = resolve(*stop).min(len as i64 - 1);
+                if start > stop {
+                    return (0, 0);
+                }
+
+                let (start, stop) = (start as usize, stop as usize);
+                if reverse {
+                    (len - 1 - stop, len - start)
+                } else {
+                    (start, stop + 1)
+                }
+            }
+            ZRange::Score(min, max) => {
+                let start = self.count_before(|(score, _)| match min {
+                    ScoreBound::Inclusive(min) => score.get() < *min,
+                    ScoreBound::Exclusive(min) => score.get() <= *min,
+                });
+                let end = self.count_before(|(score, _)| match max {
+                    ScoreBound::Inclusive(max) => score.get() <= *max,
+                    ScoreBound::Exclusive(max) => score.get() < *max,
+                });
+                (start, end.max(start))
+            }
+            ZRange::Lex(min, max) => {
+                let start = self.count_before(|(_, member)| match min {
+                    LexBound::Min => false,
+                    LexBound::Max => true,
+                    LexBound::Inclusive(min) => member < min,
+                    LexBound::Exclusive(min) => member <= min,
+                });
+                let end = self.count_before(|(_, member)| match max {
+                    LexBound::Min => false,
+                    LexBound::Max => true,
+                    LexBound::Inclusive(max) => member <= max,
+                    LexBound::Exclusive(max) => member < max,
+                });
+                (start, end.max(start))
+            }
+        }
+    }
+
+    /// Remove the members with ranks in `start..end`.
+    pub(crate) fn remove_ranks(&mut self, start: usize, end: usize) -> Vec<(Bytes, f64)> {
+        if start >= end {
+            return Vec::new();
+        }
+
+        let (left, rest) = split(self.root.take(), start);
+        let (removed, right) = split(rest, end - start);
+        self.root = merge(left, right);
+
+        let removed = SortedSet { scores: HashMap::new(), root: removed };
+        let removed: Vec<_> = removed.iter_from(0, false).collect();
+        for (member, _) in &removed {
+            self.scores.remove(member);
+        }
+        removed
+    }
+}
+
+/// In-order iterator over the members of a `SortedSet` and their scores.
+pub(crate) struct Iter<'a> {
+    stack: Vec<&'a Node>,
+    reverse: bool,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = (Bytes, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        let mut link = if self.reverse { &node.left } else { &node.right };
+        while let Some(child) = link {
+            self.stack.push(child);
+            link = if self.reverse { &child.right } else { &child.left };
+        }
+
+        Some((node.key.1.clone(), node.key.0.get()))
+    }
+}
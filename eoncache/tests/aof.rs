@@ -0,0 +1,347 @@
+mod common;
+
+use bytes::Bytes;
+use common::start_server_with;
+use eoncache::{client, AppendFsync, Config, Db, EvictionPolicy};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn config(dir: &Path) -> Config {
+    Config {
+        dbfilename: dir.join("dump.ecs"),
+        appendonly: true,
+        appendfilename: dir.join("appendonly.aof"),
+        appendfsync: AppendFsync::Always,
+        ..Config::default()
+    }
+}
+
+fn aof_path(dir: &Path) -> PathBuf {
+    dir.join("appendonly.aof")
+}
+
+#[tokio::test]
+async fn writes_are_replayed_on_restart() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.set("string", "value").await.unwrap();
+    client.expire("string", 1000).await.unwrap();
+    client.incr_by("counter", 5).await.unwrap();
+    client.rpush("list", "a".into()).await.unwrap();
+    client.rpush("list", "b".into()).await.unwrap();
+    client.hset("hash", &[("field", "value".into())]).await.unwrap();
+    client.sadd("set", &["x", "y"]).await.unwrap();
+    client.zadd("zset", &[(2.5, "member")]).await.unwrap();
+    let id = client.xadd("stream", "*", &[("event", "created".into())]).await.unwrap();
+    client.del(&["set"]).await.unwrap();
+    client.select(3).await.unwrap();
+    client.set("elsewhere", "three").await.unwrap();
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.get("string").await.unwrap().unwrap(), "value");
+    let ttl = client.ttl("string").await.unwrap();
+    assert!(ttl > 990 && ttl <= 1000, "ttl was {}", ttl);
+    assert_eq!(client.incr_by("counter", 0).await.unwrap(), 5);
+    assert_eq!(client.lrange("list", 0, -1).await.unwrap(), vec!["a", "b"]);
+    assert_eq!(client.hgetall("hash").await.unwrap(), vec![("field".into(), "value".into())]);
+    assert_eq!(client.key_type("set").await.unwrap(), "none");
+    assert_eq!(client.zscore("zset", "member").await.unwrap(), Some(2.5));
+    // The generated ID is logged, not regenerated on replay.
+    assert_eq!(client.xrange("stream", "-", "+").await.unwrap()[0].0, id);
+    assert_eq!(client.get("elsewhere").await.unwrap(), None);
+    client.select(3).await.unwrap();
+    assert_eq!(client.get("elsewhere").await.unwrap().unwrap(), "three");
+}
+
+#[tokio::test]
+async fn relative_expirations_are_logged_as_deadlines() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.set("short", "lived").await.unwrap();
+    client.expire("short", 1).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.get("short").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn pops_served_to_blocked_clients_are_replayed() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut waiter = client::connect(addr).await.unwrap();
+    let mut producer = client::connect(addr).await.unwrap();
+
+    let blocked = tokio::spawn(async move { waiter.blpop(&[Bytes::from_static(b"jobs")], 0).await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    producer.lpush("jobs", "first".into()).await.unwrap();
+    assert_eq!(blocked.await.unwrap(), Some(("jobs".into(), "first".into())));
+    producer.lpush("jobs", "second".into()).await.unwrap();
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.lrange("jobs", 0, -1).await.unwrap(), vec!["second"]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_writes_are_logged_in_the_order_they_took_effect() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = || Config { appendfsync: AppendFsync::No, ..config(dir.path()) };
+
+    let addr = start_server_with(Db::open(config()).unwrap()).await;
+    let writers: Vec<_> = (0..4)
+        .map(|writer| {
+            tokio::spawn(async move {
+                let mut client = client::connect(addr).await.unwrap();
+                for i in 0..100 {
+                    client.rpush("shared", format!("{}:{}", writer, i).into()).await.unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.await.unwrap();
+    }
+    let mut client = client::connect(addr).await.unwrap();
+    let before = client.lrange("shared", 0, -1).await.unwrap();
+    assert_eq!(before.len(), 400);
+
+    let addr = start_server_with(Db::open(config()).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.lrange("shared", 0, -1).await.unwrap(), before);
+}
+
+#[tokio::test]
+async fn evicted_keys_stay_evicted_on_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = || Config { maxmemory_policy: EvictionPolicy::AllKeysLru, ..config(dir.path()) };
+
+    let addr = start_server_with(Db::open(config()).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    for i in 0..200 {
+        client.set(&format!("key:{}", i), &"x".repeat(100)).await.unwrap();
+    }
+    client.config_set("maxmemory", "5000").await.unwrap();
+    let left = client.dbsize().await.unwrap();
+    assert!(left < 200, "{}", left);
+
+    // A write that has to make room is logged after the keys it evicted.
+    client.set("fresh", "value").await.unwrap();
+    let left = client.dbsize().await.unwrap();
+
+    let addr = start_server_with(Db::open(config()).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.dbsize().await.unwrap(), left);
+    assert_eq!(client.get("fresh").await.unwrap().unwrap(), "value");
+}
+
+#[tokio::test]
+async fn truncated_tail_is_trimmed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = aof_path(dir.path());
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.set("kept", "value").await.unwrap();
+    let complete = std::fs::metadata(&path).unwrap().len();
+
+    // A crash in the middle of appending a command.
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"*3\r\n$3\r\nSET\r\n$4\r\nlost\r\n$5\r\nva").unwrap();
+    drop(file);
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), complete);
+    assert_eq!(client.get("kept").await.unwrap().unwrap(), "value");
+    assert_eq!(client.get("lost").await.unwrap(), None);
+
+    // New writes follow the last complete command.
+    client.set("after", "restart").await.unwrap();
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.get("after").await.unwrap().unwrap(), "restart");
+}
+
+#[tokio::test]
+async fn corrupted_log_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = aof_path(dir.path());
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.set("a", "1").await.unwrap();
+    client.set("b", "2").await.unwrap();
+
+    let mut data = std::fs::read(&path).unwrap();
+    let end = data.len();
+    data.extend_from_slice(b"garbage\r\n");
+    data.extend_from_within(..end);
+    std::fs::write(&path, &data).unwrap();
+
+    let error = match Db::open(config(dir.path())) {
+        Ok(_) => panic!("corrupted log was loaded"),
+        Err(e) => e.to_string(),
+    };
+    assert!(error.starts_with("can't load append-only file"), "{}", error);
+}
+
+#[tokio::test]
+async fn snapshot_seeds_a_new_log() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let snapshot_only = Config { appendonly: false, ..config(dir.path()) };
+    let addr = start_server_with(Db::open(snapshot_only).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.set("saved", "value").await.unwrap();
+    client.save().await.unwrap();
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.get("saved").await.unwrap().unwrap(), "value");
+    assert!(aof_path(dir.path()).exists());
+
+    // From now on the log, not the snapshot, is loaded.
+    std::fs::remove_file(dir.path().join("dump.ecs")).unwrap();
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.get("saved").await.unwrap().unwrap(), "value");
+}
+
+#[tokio::test]
+async fn rewrite_compacts_the_log_while_writes_continue() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = aof_path(dir.path());
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut writer = client::connect(addr).await.unwrap();
+    for _ in 0..200 {
+        client.incr_by("counter", 1).await.unwrap();
+    }
+    let before = std::fs::metadata(&path).unwrap().len();
+
+    client.bgrewriteaof().await.unwrap();
+    writer.set("during", "rewrite").await.unwrap();
+    let mut info = String::new();
+    for _ in 0..100 {
+        info = client.info(Some("persistence")).await.unwrap();
+        if info.contains("aof_rewrite_in_progress:0") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(info.contains("aof_last_bgrewrite_status:ok\r\n"), "{}", info);
+    writer.set("after", "rewrite").await.unwrap();
+    assert!(std::fs::metadata(&path).unwrap().len() < before);
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.incr_by("counter", 0).await.unwrap(), 200);
+    assert_eq!(client.get("during").await.unwrap().unwrap(), "rewrite");
+    assert_eq!(client.get("after").await.unwrap().unwrap(), "rewrite");
+}
+
+#[tokio::test]
+async fn fsync_policy_can_be_changed() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.config_set("appendfsync", "everysec").await.unwrap();
+    assert!(client.config_set("appendfsync", "sometimes").await.is_err());
+    assert!(client.config_set("appendonly", "no").await.is_err());
+
+    client.set("key", "value").await.unwrap();
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.get("key").await.unwrap().unwrap(), "value");
+}
+
+#[tokio::test]
+async fn restored_keys_are_replayed_with_their_deadline() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.rpush("source", "element".into()).await.unwrap();
+    let payload = client.dump("source").await.unwrap().unwrap();
+    client.restore("copy", 1_000_000, payload.clone(), false).await.unwrap();
+    client.restore("short", 1, payload, false).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.lrange("copy", 0, -1).await.unwrap(), vec!["element"]);
+    let ttl = client.ttl("copy").await.unwrap();
+    assert!(ttl > 990 && ttl <= 1000, "ttl was {}", ttl);
+    assert_eq!(client.key_type("short").await.unwrap(), "none");
+}
+
+#[tokio::test]
+async fn transactions_are_replayed_whole_or_not_at_all() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = aof_path(dir.path());
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.multi().await.unwrap();
+    client.set("first", "1").await.unwrap();
+    client.select_named("2").await.unwrap();
+    client.set("second", "2").await.unwrap();
+    client.exec().await.unwrap().unwrap();
+    let complete = std::fs::metadata(&path).unwrap().len();
+
+    // A crash after the start of a transaction was written.
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"*1\r\n$5\r\nMULTI\r\n*3\r\n$3\r\nSET\r\n$4\r\nlost\r\n$5\r\nvalue\r\n").unwrap();
+    drop(file);
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), complete);
+    assert_eq!(client.get("first").await.unwrap().unwrap(), "1");
+    assert_eq!(client.get("second").await.unwrap(), None);
+    client.select(2).await.unwrap();
+    assert_eq!(client.get("second").await.unwrap().unwrap(), "2");
+    assert_eq!(client.get("lost").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn script_effects_are_replayed() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.sadd("set", &["x", "y", "z"]).await.unwrap();
+    let script = "
+        local popped = redis.call('SPOP', KEYS[1])
+        redis.call('SELECT', '4')
+        redis.call('SET', KEYS[2], popped)
+        return popped
+    ";
+    let popped = match client.eval(script, &["set", "popped"], &[] as &[&str]).await.unwrap() {
+        eoncache::Frame::Bulk(popped) => popped,
+        frame => panic!("unexpected reply {:?}", frame),
+    };
+
+    // The script does not change the namespace of the client.
+    assert_eq!(client.get("popped").await.unwrap(), None);
+
+    let addr = start_server_with(Db::open(config(dir.path())).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut members = client.smembers("set").await.unwrap();
+    members.sort();
+    assert_eq!(members.len(), 2);
+    assert!(!members.contains(&popped));
+    client.select(4).await.unwrap();
+    assert_eq!(client.get("popped").await.unwrap().unwrap(), popped);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use bytes::Bytes;
+use common::start_server;
+use eoncache::{buffer, client};
+
+/// A key that is not valid UTF-8, such as a raw hash digest.
+const DIGEST: &[u8] = b"\xde\xad\xbe\xef\x00\xff";
+
+#[tokio::test]
+async fn keys_need_not_be_utf8() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set(DIGEST, "value").await.unwrap();
+    assert_eq!(client.get(DIGEST).await.unwrap().unwrap(), "value");
+    assert_eq!(client.key_type(DIGEST).await.unwrap(), "string");
+
+    // Keys differing only in a byte that is not valid UTF-8 are distinct.
+    client.set(b"id:\xfe", "first").await.unwrap();
+    client.set(b"id:\xff", "second").await.unwrap();
+    assert_eq!(client.mget(&[&b"id:\xfe"[..], b"id:\xff"]).await.unwrap(), vec![Some("first".into()), Some("second".into())]);
+
+    client.rename(DIGEST, b"renamed:\x80").await.unwrap();
+    assert_eq!(client.get(DIGEST).await.unwrap(), None);
+    assert_eq!(client.del(&[&b"renamed:\x80"[..]]).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn binary_keys_are_listed_verbatim() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.mset(&[(&b"blob:\x01\x02"[..], "a".into()), (b"blob:\xc3\x28", "b".into()), (b"other", "c".into())]).await.unwrap();
+
+    let mut keys = client.keys("blob:*").await.unwrap();
+    keys.sort();
+    assert_eq!(keys, vec![Bytes::from_static(b"blob:\x01\x02"), Bytes::from_static(b"blob:\xc3\x28")]);
+
+    let (cursor, mut scanned) = client.scan(0, Some("blob:*"), 100).await.unwrap();
+    scanned.sort();
+    assert_eq!(cursor, 0);
+    assert_eq!(scanned, keys);
+}
+
+#[tokio::test]
+async fn blocked_pop_reports_binary_key() {
+    let addr = start_server().await;
+    let mut waiter = client::connect(addr).await.unwrap();
+    let mut producer = client::connect(addr).await.unwrap();
+
+    let key = Bytes::from_static(b"queue:\x9f");
+    let blocked = tokio::spawn({
+        let key = key.clone();
+        async move { waiter.blpop(&[key], 0).await.unwrap() }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    producer.rpush(&key, "job".into()).await.unwrap();
+    assert_eq!(blocked.await.unwrap(), Some((key, "job".into())));
+}
+
+#[tokio::test]
+async fn buffer_forwards_binary_keys_and_values() {
+    let addr = start_server().await;
+    let mut buffer = buffer::buffer(client::connect(addr).await.unwrap());
+
+    buffer.set(DIGEST, Bytes::from_static(b"\x00\xff")).await.unwrap();
+    assert_eq!(buffer.get(DIGEST).await.unwrap().unwrap(), &b"\x00\xff"[..]);
+}
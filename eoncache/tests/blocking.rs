@@ -0,0 +1,140 @@
+mod common;
+
+use bytes::Bytes;
+use common::{start_server, Raw};
+use eoncache::db::End;
+use eoncache::{client, Db, Frame};
+use std::time::Duration;
+
+#[tokio::test]
+async fn push_wakes_blocked_client() {
+    let addr = start_server().await;
+
+    let mut waiter = client::connect(addr).await.unwrap();
+    let blocked = tokio::spawn(async move { waiter.blpop(&["jobs".into()], 0).await.unwrap() });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let mut producer = client::connect(addr).await.unwrap();
+    producer.rpush("jobs", "first".into()).await.unwrap();
+
+    let (key, value) = blocked.await.unwrap().unwrap();
+    assert_eq!(key, "jobs");
+    assert_eq!(value, "first");
+
+    // The element was handed over, not left behind in the list.
+    assert_eq!(producer.exists("jobs").await.unwrap().unwrap(), "0");
+}
+
+#[tokio::test]
+async fn waiters_are_served_in_fifo_order() {
+    let addr = start_server().await;
+
+    let mut blocked = Vec::new();
+    for _ in 0..3 {
+        let mut waiter = client::connect(addr).await.unwrap();
+        blocked.push(tokio::spawn(async move { waiter.brpop(&["jobs".into()], 0).await.unwrap() }));
+        // Make sure each waiter registers before the next one.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let mut producer = client::connect(addr).await.unwrap();
+    for job in ["a", "b", "c"] {
+        producer.lpush("jobs", job.into()).await.unwrap();
+    }
+
+    for (waiter, expected) in blocked.into_iter().zip(["a", "b", "c"]) {
+        let (_, value) = waiter.await.unwrap().unwrap();
+        assert_eq!(value, expected);
+    }
+}
+
+#[tokio::test]
+async fn blocked_pop_times_out() {
+    let addr = start_server().await;
+
+    let mut waiter = client::connect(addr).await.unwrap();
+    assert_eq!(waiter.blpop(&["empty".into()], 1).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn oversized_timeouts_are_refused() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+
+    for command in [&["BLPOP", "list", "1e20"][..], &["BZPOPMIN", "zset", "1e20"], &["BLMOVE", "list", "other", "LEFT", "RIGHT", "1e20"]] {
+        let reply = raw.call(command).await;
+        assert!(matches!(&reply, Frame::Error(e) if e == "ERR timeout is out of range"), "{:?}", reply);
+    }
+
+    // The connection is still served.
+    assert!(matches!(raw.call(&["LLEN", "list"]).await, Frame::Integer(0)));
+}
+
+#[tokio::test]
+async fn disconnected_waiter_is_skipped() {
+    let addr = start_server().await;
+
+    let mut gone = client::connect(addr).await.unwrap();
+    let abandoned = tokio::spawn(async move { gone.blpop(&["jobs".into()], 0).await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let mut waiter = client::connect(addr).await.unwrap();
+    let blocked = tokio::spawn(async move { waiter.blpop(&["jobs".into()], 0).await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    // Dropping the first client closes its connection while it is blocked.
+    abandoned.abort();
+    let _ = abandoned.await;
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let mut producer = client::connect(addr).await.unwrap();
+    producer.rpush("jobs", "only".into()).await.unwrap();
+
+    let (_, value) = blocked.await.unwrap().unwrap();
+    assert_eq!(value, "only");
+}
+
+#[tokio::test]
+async fn value_handed_to_a_dropped_waiter_is_put_back() {
+    let db = Db::new();
+    let mut blocked = Box::pin(db.blpop(0, vec![Bytes::from("jobs")], Duration::ZERO));
+    // Poll once so the waiter registers.
+    assert!(tokio::time::timeout(Duration::from_millis(10), &mut blocked).await.is_err());
+
+    // The push hands the element over, but the waiter goes away (as when
+    // its client disconnects) before it takes it.
+    db.rpush(0, Bytes::from("jobs"), vec![Bytes::from("only")]).unwrap();
+    drop(blocked);
+
+    assert_eq!(db.lrange(0, b"jobs", 0, -1).unwrap(), vec![Bytes::from("only")]);
+}
+
+#[tokio::test]
+async fn value_handed_to_a_dropped_waiter_goes_to_the_next_one() {
+    let db = Db::new();
+    let mut gone = Box::pin(db.brpop(0, vec![Bytes::from("jobs")], Duration::ZERO));
+    assert!(tokio::time::timeout(Duration::from_millis(10), &mut gone).await.is_err());
+    let mut next = Box::pin(db.brpop(0, vec![Bytes::from("jobs")], Duration::ZERO));
+    assert!(tokio::time::timeout(Duration::from_millis(10), &mut next).await.is_err());
+
+    db.rpush(0, Bytes::from("jobs"), vec![Bytes::from("only")]).unwrap();
+    drop(gone);
+
+    let (key, value) = tokio::time::timeout(Duration::from_secs(1), next).await.unwrap().unwrap().unwrap();
+    assert_eq!((key, value), (Bytes::from("jobs"), Bytes::from("only")));
+    assert_eq!(db.llen(0, b"jobs").unwrap(), 0);
+}
+
+#[tokio::test]
+async fn blocked_move_to_a_destination_of_another_type_fails() {
+    let db = Db::new();
+    let mut blocked = Box::pin(db.blmove(0, Bytes::from("jobs"), Bytes::from("done"), End::Front, End::Back, Duration::ZERO));
+    assert!(tokio::time::timeout(Duration::from_millis(10), &mut blocked).await.is_err());
+
+    db.set(0, Bytes::from("done"), Bytes::from("not a list"));
+    db.rpush(0, Bytes::from("jobs"), vec![Bytes::from("only")]).unwrap();
+
+    let result = tokio::time::timeout(Duration::from_secs(1), blocked).await.unwrap();
+    assert!(result.is_err_and(|e| e.starts_with("WRONGTYPE")));
+    assert_eq!(db.lrange(0, b"jobs", 0, -1).unwrap(), vec![Bytes::from("only")]);
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::start_server;
+use eoncache::{client, Client};
+use std::time::{Duration, Instant};
+
+/// The value of `field` in a line of `CLIENT LIST`.
+fn field<'a>(line: &'a str, field: &str) -> &'a str {
+    line.split(' ')
+        .find_map(|pair| pair.strip_prefix(field)?.strip_prefix('='))
+        .unwrap_or_else(|| panic!("no {} in {}", field, line))
+}
+
+/// The line of `client` in `CLIENT LIST`.
+async fn line_of(list: &mut Client, client: &mut Client) -> String {
+    let id = client.client_id().await.unwrap().to_string();
+    let lines = list.client_list(&["ID", &id]).await.unwrap();
+    assert_eq!(lines.len(), 1, "{:?}", lines);
+    lines[0].clone()
+}
+
+#[tokio::test]
+async fn clients_have_ids_and_names() {
+    let addr = start_server().await;
+    let mut first = client::connect(addr).await.unwrap();
+    let mut second = client::connect(addr).await.unwrap();
+
+    let id = first.client_id().await.unwrap();
+    assert!(id > 0);
+    assert_ne!(second.client_id().await.unwrap(), id);
+
+    assert_eq!(first.client_getname().await.unwrap(), None);
+    first.client_setname("billing-worker").await.unwrap();
+    assert_eq!(first.client_getname().await.unwrap().unwrap(), "billing-worker");
+    let err = first.client_setname("has space").await.unwrap_err();
+    assert!(err.to_string().starts_with("ERR Client names cannot contain spaces"), "{}", err);
+
+    let info = first.client_info().await.unwrap();
+    assert_eq!(field(&info, "id"), id.to_string());
+    assert_eq!(field(&info, "name"), "billing-worker");
+    assert_eq!(field(&info, "cmd"), "client|info");
+
+    // An empty name removes it.
+    first.client_setname("").await.unwrap();
+    assert_eq!(first.client_getname().await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn client_list_reports_what_clients_do() {
+    let addr = start_server().await;
+    let mut admin = client::connect(addr).await.unwrap();
+    let mut worker = client::connect(addr).await.unwrap();
+
+    worker.select(3).await.unwrap();
+    worker.client_no_evict(true).await.unwrap();
+    let line = line_of(&mut admin, &mut worker).await;
+    assert_eq!(field(&line, "db"), "3");
+    assert_eq!(field(&line, "cmd"), "client|id");
+    assert_eq!(field(&line, "flags"), "e");
+    assert_eq!(field(&line, "no-evict"), "on");
+    assert_eq!(field(&line, "multi"), "-1");
+    assert_eq!(field(&line, "laddr"), addr.to_string());
+
+    let id = worker.client_id().await.unwrap().to_string();
+    worker.multi().await.unwrap();
+    worker.set("key", "value").await.unwrap();
+    let line = admin.client_list(&["ID", &id]).await.unwrap().remove(0);
+    assert_eq!(field(&line, "flags"), "xe");
+    assert_eq!(field(&line, "multi"), "1");
+
+    // Subscribers are listed apart from the other clients.
+    let _subscriber = client::connect(addr).await.unwrap().subscribe(&["news", "sports"]).await.unwrap();
+    let subscribers = admin.client_list(&["TYPE", "pubsub"]).await.unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(field(&subscribers[0], "sub"), "2");
+    assert_eq!(field(&subscribers[0], "flags"), "P");
+    assert_eq!(admin.client_list(&["TYPE", "normal"]).await.unwrap().len(), 2);
+    assert!(admin.client_list(&["TYPE", "bogus"]).await.is_err());
+
+    assert!(admin.info(Some("clients")).await.unwrap().contains("connected_clients:3"));
+}
+
+#[tokio::test]
+async fn client_kill_disconnects_matching_clients() {
+    let addr = start_server().await;
+    let mut admin = client::connect(addr).await.unwrap();
+    let mut victim = client::connect(addr).await.unwrap();
+    let mut survivor = client::connect(addr).await.unwrap();
+
+    let id = victim.client_id().await.unwrap().to_string();
+    assert_eq!(admin.client_kill(&[("ID", &id)]).await.unwrap(), 1);
+    assert!(victim.get("key").await.is_err());
+    assert_eq!(admin.client_kill(&[("ID", &id)]).await.unwrap(), 0);
+
+    let mut subscriber = client::connect(addr).await.unwrap().subscribe(&["news"]).await.unwrap();
+    assert_eq!(admin.client_kill(&[("TYPE", "pubsub")]).await.unwrap(), 1);
+    assert!(!matches!(subscriber.next_message().await, Ok(Some(_))));
+
+    // The caller is skipped unless asked otherwise.
+    assert_eq!(admin.client_kill(&[("TYPE", "normal")]).await.unwrap(), 1);
+    assert!(survivor.get("key").await.is_err());
+    assert_eq!(admin.client_list(&[]).await.unwrap().len(), 1);
+
+    let mut other = client::connect(addr).await.unwrap();
+    let line = line_of(&mut admin, &mut other).await;
+    assert_eq!(admin.client_kill(&[("ADDR", field(&line, "addr")), ("MAXAGE", "100")]).await.unwrap(), 0);
+    assert_eq!(admin.client_kill(&[("ADDR", field(&line, "addr"))]).await.unwrap(), 1);
+    assert!(admin.client_kill(&[("ADDR", "127.0.0.1:1"), ("SKIPME", "maybe")]).await.is_err());
+}
+
+#[tokio::test]
+async fn client_pause_holds_commands() {
+    let addr = start_server().await;
+    let mut admin = client::connect(addr).await.unwrap();
+    let mut writer = client::connect(addr).await.unwrap();
+
+    // Only writes wait for a `WRITE` pause to run out.
+    admin.client_pause(300, true).await.unwrap();
+    let start = Instant::now();
+    assert_eq!(writer.get("key").await.unwrap(), None);
+    assert!(start.elapsed() < Duration::from_millis(200));
+    writer.set("key", "value").await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(250));
+
+    // `UNPAUSE` releases everything at once.
+    admin.client_pause(60_000, false).await.unwrap();
+    let reading = tokio::spawn(async move { writer.get("key").await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!reading.is_finished());
+    admin.client_unpause().await.unwrap();
+    let value = tokio::time::timeout(Duration::from_secs(1), reading).await.unwrap().unwrap();
+    assert_eq!(value.unwrap(), "value");
+}
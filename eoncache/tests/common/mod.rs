@@ -0,0 +1,48 @@
+//! Fixtures shared by the integration tests.
+
+// Each test file is its own crate and uses only some of these.
+#![allow(dead_code)]
+
+use eoncache::{run_server, Connection, Db, Frame, Shutdown};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+
+/// Start a server with an empty store on a free port.
+pub async fn start_server() -> SocketAddr {
+    start_server_with(Db::new()).await
+}
+
+/// Start a server for `db` on a free port.
+pub async fn start_server_with(db: Db) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move { run_server(listener, Arc::new(db), Shutdown::new()).await });
+
+    addr
+}
+
+/// A raw connection, to send commands and options the client does not
+/// expose and see every frame the server replies with.
+pub struct Raw(pub Connection);
+
+impl Raw {
+    pub async fn connect(addr: SocketAddr) -> Raw {
+        Raw(Connection::new(TcpStream::connect(addr).await.unwrap()))
+    }
+
+    pub async fn send(&mut self, args: &[impl AsRef<[u8]>]) {
+        let args = args.iter().map(|arg| Frame::Bulk(bytes::Bytes::copy_from_slice(arg.as_ref()))).collect();
+        self.0.write_frame(&Frame::Array(args)).await.unwrap();
+    }
+
+    pub async fn read(&mut self) -> Frame {
+        self.0.read_frame().await.unwrap().unwrap()
+    }
+
+    pub async fn call(&mut self, args: &[impl AsRef<[u8]>]) -> Frame {
+        self.send(args).await;
+        self.read().await
+    }
+}
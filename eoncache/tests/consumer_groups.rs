@@ -0,0 +1,89 @@
+mod common;
+
+use common::start_server;
+use eoncache::client;
+use std::time::Duration;
+
+fn ids(entries: &[(String, Vec<(bytes::Bytes, bytes::Bytes)>)]) -> Vec<&str> {
+    entries.iter().map(|(id, _)| id.as_str()).collect()
+}
+
+#[tokio::test]
+async fn each_entry_goes_to_one_consumer() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.xgroup_create("jobs", "workers", "$", true).await.unwrap();
+    for i in 1..=4 {
+        client.xadd("jobs", &format!("{}-0", i), &[("job", i.to_string().into())]).await.unwrap();
+    }
+
+    let first = client.xreadgroup("workers", "w1", "jobs", ">", 3, None).await.unwrap();
+    let second = client.xreadgroup("workers", "w2", "jobs", ">", 3, None).await.unwrap();
+    assert_eq!(ids(&first), vec!["1-0", "2-0", "3-0"]);
+    assert_eq!(ids(&second), vec!["4-0"]);
+    assert!(client.xreadgroup("workers", "w2", "jobs", ">", 3, None).await.unwrap().is_empty());
+
+    // Acknowledged entries leave the consumer's pending list.
+    assert_eq!(client.xack("jobs", "workers", &["1-0", "4-0"]).await.unwrap(), 2);
+    let pending = client.xreadgroup("workers", "w1", "jobs", "0", 10, None).await.unwrap();
+    assert_eq!(ids(&pending), vec!["2-0", "3-0"]);
+    assert!(client.xreadgroup("workers", "w2", "jobs", "0", 10, None).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn jobs_of_a_crashed_worker_can_be_claimed() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.xgroup_create("jobs", "workers", "0", true).await.unwrap();
+    client.xadd("jobs", "1-0", &[("job", "resize".into())]).await.unwrap();
+
+    // The worker reads the job and disappears without acknowledging it.
+    {
+        let mut worker = client::connect(addr).await.unwrap();
+        worker.xreadgroup("workers", "crashed", "jobs", ">", 1, None).await.unwrap();
+    }
+
+    // Too recent to be considered abandoned.
+    assert!(client.xclaim("jobs", "workers", "rescuer", 60_000, &["1-0"]).await.unwrap().is_empty());
+
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    let claimed = client.xclaim("jobs", "workers", "rescuer", 20, &["1-0"]).await.unwrap();
+    assert_eq!(claimed, vec![("1-0".to_string(), vec![("job".into(), "resize".into())])]);
+
+    let pending = client.xreadgroup("workers", "rescuer", "jobs", "0", 10, None).await.unwrap();
+    assert_eq!(ids(&pending), vec!["1-0"]);
+    assert!(client.xreadgroup("workers", "crashed", "jobs", "0", 10, None).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn blocked_group_reader_is_woken_by_xadd() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.xgroup_create("jobs", "workers", "$", true).await.unwrap();
+
+    let mut worker = client::connect(addr).await.unwrap();
+    let blocked = tokio::spawn(async move { worker.xreadgroup("workers", "w1", "jobs", ">", 1, Some(0)).await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    client.xadd("jobs", "5-0", &[("job", "send".into())]).await.unwrap();
+    assert_eq!(ids(&blocked.await.unwrap()), vec!["5-0"]);
+}
+
+#[tokio::test]
+async fn missing_groups_are_reported() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    let err = client.xgroup_create("missing", "workers", "$", false).await.unwrap_err();
+    assert!(err.to_string().contains("MKSTREAM"), "{}", err);
+
+    client.xadd("jobs", "1-0", &[("job", "x".into())]).await.unwrap();
+    let err = client.xreadgroup("nobody", "w1", "jobs", ">", 1, None).await.unwrap_err();
+    assert!(err.to_string().starts_with("NOGROUP"), "{}", err);
+
+    client.xgroup_create("jobs", "workers", "$", false).await.unwrap();
+    let err = client.xgroup_create("jobs", "workers", "$", false).await.unwrap_err();
+    assert!(err.to_string().starts_with("BUSYGROUP"), "{}", err);
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use bytes::Bytes;
+use common::{start_server, start_server_with, Raw};
+use eoncache::{client, Config, Db, EvictionPolicy, Frame};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Send `args` over a raw connection and return the reply, for options the
+/// client does not expose.
+async fn command(addr: SocketAddr, args: Vec<Bytes>) -> Frame {
+    Raw::connect(addr).await.call(&args).await
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+#[tokio::test]
+async fn every_type_round_trips() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("string", "value").await.unwrap();
+    client.rpush("list", "a".into()).await.unwrap();
+    client.rpush("list", "b".into()).await.unwrap();
+    client.hset("hash", &[("field", "value".into())]).await.unwrap();
+    client.sadd("set", &["x", "y"]).await.unwrap();
+    client.zadd("zset", &[(1.5, "low"), (7.0, "high")]).await.unwrap();
+    client.xadd("stream", "1-1", &[("event", "created".into())]).await.unwrap();
+    client.xgroup_create("stream", "workers", "0", false).await.unwrap();
+    client.xreadgroup("workers", "alice", "stream", ">", 10, None).await.unwrap();
+
+    for key in ["string", "list", "hash", "set", "zset", "stream"] {
+        let payload = client.dump(key).await.unwrap().unwrap();
+        client.restore(format!("copy:{}", key), 0, payload, false).await.unwrap();
+        assert_eq!(client.key_type(format!("copy:{}", key)).await.unwrap(), client.key_type(key).await.unwrap());
+    }
+
+    assert_eq!(client.get("copy:string").await.unwrap().unwrap(), "value");
+    assert_eq!(client.lrange("copy:list", 0, -1).await.unwrap(), vec!["a", "b"]);
+    assert_eq!(client.hgetall("copy:hash").await.unwrap(), vec![("field".into(), "value".into())]);
+    let mut members = client.smembers("copy:set").await.unwrap();
+    members.sort();
+    assert_eq!(members, vec!["x", "y"]);
+    assert_eq!(client.zrange("copy:zset", 0, -1).await.unwrap(), vec!["low", "high"]);
+    assert_eq!(client.zscore("copy:zset", "high").await.unwrap(), Some(7.0));
+
+    // Consumer groups and their pending entries are part of the payload.
+    let entries = client.xrange("copy:stream", "-", "+").await.unwrap();
+    let pending = client.xreadgroup("workers", "alice", "copy:stream", "0", 10, None).await.unwrap();
+    assert_eq!(pending, entries);
+
+    assert_eq!(client.dump("missing").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn existing_keys_need_replace() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("source", "new").await.unwrap();
+    client.set("target", "old").await.unwrap();
+    let payload = client.dump("source").await.unwrap().unwrap();
+
+    let err = client.restore("target", 0, payload.clone(), false).await.unwrap_err();
+    assert_eq!(err.to_string(), "BUSYKEY Target key name already exists.");
+    assert_eq!(client.get("target").await.unwrap().unwrap(), "old");
+
+    client.restore("target", 0, payload, true).await.unwrap();
+    assert_eq!(client.get("target").await.unwrap().unwrap(), "new");
+}
+
+#[tokio::test]
+async fn ttl_is_relative_or_absolute() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("source", "value").await.unwrap();
+    let payload = client.dump("source").await.unwrap().unwrap();
+
+    client.restore("relative", 5_000, payload.clone(), false).await.unwrap();
+    let ttl = client.ttl("relative").await.unwrap();
+    assert!(ttl > 0 && ttl <= 5, "ttl was {}", ttl);
+
+    let at = Bytes::from((unix_millis() + 10_000).to_string());
+    let reply = command(addr, vec!["RESTORE".into(), "absolute".into(), at, payload.clone(), "ABSTTL".into()]).await;
+    assert!(matches!(reply, Frame::Simple(ref ok) if ok == "OK"), "{:?}", reply);
+    let ttl = client.ttl("absolute").await.unwrap();
+    assert!(ttl > 5 && ttl <= 10, "ttl was {}", ttl);
+
+    // A deadline in the past leaves no key behind, even over an existing one.
+    let past = Bytes::from((unix_millis() - 1_000).to_string());
+    let reply = command(addr, vec!["RESTORE".into(), "absolute".into(), past, payload.clone(), "ABSTTL".into(), "REPLACE".into()]).await;
+    assert!(matches!(reply, Frame::Simple(ref ok) if ok == "OK"), "{:?}", reply);
+    assert_eq!(client.key_type("absolute").await.unwrap(), "none");
+
+    let reply = command(addr, vec!["RESTORE".into(), "negative".into(), "-1".into(), payload]).await;
+    assert!(matches!(reply, Frame::Error(ref e) if e == "ERR Invalid TTL value, must be >= 0"), "{:?}", reply);
+}
+
+#[tokio::test]
+async fn corrupted_payloads_are_rejected() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.rpush("list", "element".into()).await.unwrap();
+    let payload = client.dump("list").await.unwrap().unwrap();
+
+    let mut flipped = payload.to_vec();
+    flipped[3] ^= 0xff;
+    let err = client.restore("copy", 0, flipped.into(), false).await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR DUMP payload version or checksum are wrong");
+
+    let err = client.restore("copy", 0, payload.slice(..payload.len() - 1), false).await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR DUMP payload version or checksum are wrong");
+
+    // A payload from another format version, with a valid checksum.
+    let mut other_version = payload[..payload.len() - 4].to_vec();
+    other_version[0] = other_version[0].wrapping_add(1);
+    let checksum = crc32fast::hash(&other_version);
+    other_version.extend_from_slice(&checksum.to_le_bytes());
+    let err = client.restore("copy", 0, other_version.into(), false).await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR DUMP payload version or checksum are wrong");
+
+    assert_eq!(client.key_type("copy").await.unwrap(), "none");
+}
+
+#[tokio::test]
+async fn idletime_feeds_lru_eviction() {
+    let config = Config {
+        maxmemory_policy: EvictionPolicy::AllKeysLru,
+        ..Config::default()
+    };
+    let addr = start_server_with(Db::with_config(config)).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("fresh", &"x".repeat(1_000)).await.unwrap();
+    client.set("source", &"x".repeat(1_000)).await.unwrap();
+    let payload = client.dump("source").await.unwrap().unwrap();
+    client.del(&["source"]).await.unwrap();
+
+    let reply = command(addr, vec!["RESTORE".into(), "stale".into(), "0".into(), payload.clone(), "IDLETIME".into(), "1000".into()]).await;
+    assert!(matches!(reply, Frame::Simple(ref ok) if ok == "OK"), "{:?}", reply);
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    let info = client.info(Some("memory")).await.unwrap();
+    let used: u64 = info
+        .lines()
+        .find_map(|line| line.strip_prefix("used_memory:"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    client.config_set("maxmemory", &(used - 500).to_string()).await.unwrap();
+    assert_eq!(client.key_type("stale").await.unwrap(), "none");
+    assert!(client.get("fresh").await.unwrap().is_some());
+
+    let reply = command(addr, vec!["RESTORE".into(), "both".into(), "0".into(), payload, "IDLETIME".into(), "1".into(), "FREQ".into(), "1".into()]).await;
+    assert!(matches!(reply, Frame::Error(ref e) if e == "ERR syntax error"), "{:?}", reply);
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::{start_server, start_server_with};
+use eoncache::{client, Config, Db, EvictionPolicy};
+
+/// Value of `field` in an `INFO` reply.
+fn info_field(info: &str, field: &str) -> u64 {
+    info.lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn used_memory_follows_writes_and_deletes() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    let empty = info_field(&client.info(Some("memory")).await.unwrap(), "used_memory");
+    client.set("big", &"x".repeat(10_000)).await.unwrap();
+    let written = info_field(&client.info(Some("memory")).await.unwrap(), "used_memory");
+    assert!(written >= empty + 10_000);
+
+    for i in 0..100 {
+        client.rpush("list", format!("element {}", i).into()).await.unwrap();
+    }
+    let grown = info_field(&client.info(Some("memory")).await.unwrap(), "used_memory");
+    assert!(grown > written);
+
+    client.del(&["big", "list"]).await.unwrap();
+    let freed = info_field(&client.info(Some("memory")).await.unwrap(), "used_memory");
+    assert_eq!(freed, empty);
+}
+
+#[tokio::test]
+async fn noeviction_rejects_writes_but_not_reads() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("key", &"x".repeat(1_000)).await.unwrap();
+    client.config_set("maxmemory", "100").await.unwrap();
+
+    let err = client.set("other", "value").await.unwrap_err();
+    assert_eq!(err.to_string(), "OOM command not allowed when used memory > 'maxmemory'.");
+    assert!(client.get("key").await.unwrap().is_some());
+
+    // Deleting frees memory and is always allowed.
+    client.del(&["key"]).await.unwrap();
+    client.set("other", "value").await.unwrap();
+}
+
+#[tokio::test]
+async fn allkeys_lru_evicts_idle_keys_first() {
+    let config = Config {
+        maxmemory_policy: EvictionPolicy::AllKeysLru,
+        ..Config::default()
+    };
+    let addr = start_server_with(Db::with_config(config)).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    for i in 0..50 {
+        client.set(&format!("key:{}", i), &"x".repeat(100)).await.unwrap();
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    client.get("key:0").await.unwrap();
+
+    let used = info_field(&client.info(Some("memory")).await.unwrap(), "used_memory");
+    client.config_set("maxmemory", &(used / 2).to_string()).await.unwrap();
+
+    let info = client.info(None).await.unwrap();
+    assert!(info_field(&info, "used_memory") <= used / 2);
+    assert!(info_field(&info, "evicted_keys") >= 20);
+    assert!(client.get("key:0").await.unwrap().is_some());
+
+    // Writes keep succeeding by evicting more keys.
+    client.set("fresh", &"x".repeat(100)).await.unwrap();
+    assert!(client.get("fresh").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn volatile_policies_only_evict_keys_with_a_deadline() {
+    let config = Config {
+        maxmemory_policy: EvictionPolicy::VolatileTtl,
+        ..Config::default()
+    };
+    let addr = start_server_with(Db::with_config(config)).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("persistent", &"x".repeat(1_000)).await.unwrap();
+    client.set("soon", &"x".repeat(1_000)).await.unwrap();
+    client.expire("soon", 100).await.unwrap();
+    client.set("later", &"x".repeat(1_000)).await.unwrap();
+    client.expire("later", 1_000).await.unwrap();
+
+    let used = info_field(&client.info(Some("memory")).await.unwrap(), "used_memory");
+    client.config_set("maxmemory", &(used - 500).to_string()).await.unwrap();
+    assert!(client.get("soon").await.unwrap().is_none());
+    assert!(client.get("later").await.unwrap().is_some());
+
+    // With nothing left to evict, writes are refused.
+    client.config_set("maxmemory", "1").await.unwrap();
+    assert!(client.get("persistent").await.unwrap().is_some());
+    assert!(client.set("other", "value").await.is_err());
+}
@@ -0,0 +1,202 @@
+mod common;
+
+use bytes::Bytes;
+use common::{start_server, Raw};
+use eoncache::db::Expiration;
+use eoncache::{client, Db, Frame};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+
+fn int(frame: Frame) -> i64 {
+    match frame {
+        Frame::Integer(n) => n,
+        frame => panic!("expected an integer, got {:?}", frame),
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[tokio::test]
+async fn ttl_reports_missing_and_persistent_keys() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut raw = Raw::connect(addr).await;
+
+    assert_eq!(client.ttl("session").await.unwrap(), -2);
+    assert_eq!(int(raw.call(&["PTTL", "session"]).await), -2);
+
+    client.set("session", "token").await.unwrap();
+    assert_eq!(client.ttl("session").await.unwrap(), -1);
+    assert_eq!(int(raw.call(&["PTTL", "session"]).await), -1);
+
+    assert!(client.expire("session", 100).await.unwrap());
+    assert_eq!(client.ttl("session").await.unwrap(), 100);
+    let pttl = int(raw.call(&["PTTL", "session"]).await);
+    assert!((99_000..=100_000).contains(&pttl), "{}", pttl);
+
+    // Expiring a missing key does nothing.
+    assert!(!client.expire("missing", 100).await.unwrap());
+    assert_eq!(client.ttl("missing").await.unwrap(), -2);
+}
+
+#[tokio::test]
+async fn expiretime_reports_the_deadline() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut raw = Raw::connect(addr).await;
+
+    assert_eq!(int(raw.call(&["EXPIRETIME", "key"]).await), -2);
+    client.set("key", "value").await.unwrap();
+    assert_eq!(int(raw.call(&["PEXPIRETIME", "key"]).await), -1);
+
+    let at = unix_secs() + 100;
+    raw.call(&["EXPIREAT", "key", &at.to_string()]).await;
+    assert_eq!(int(raw.call(&["EXPIRETIME", "key"]).await), at as i64);
+    assert_eq!(int(raw.call(&["PEXPIRETIME", "key"]).await), at as i64 * 1000);
+}
+
+#[tokio::test]
+async fn expire_conditions_guard_the_deadline() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut raw = Raw::connect(addr).await;
+    client.set("key", "value").await.unwrap();
+
+    // NX and XX look at whether there is a deadline at all.
+    assert_eq!(int(raw.call(&["EXPIRE", "key", "100", "XX"]).await), 0);
+    assert_eq!(client.ttl("key").await.unwrap(), -1);
+    assert_eq!(int(raw.call(&["EXPIRE", "key", "100", "NX"]).await), 1);
+    assert_eq!(int(raw.call(&["EXPIRE", "key", "200", "NX"]).await), 0);
+    assert_eq!(client.ttl("key").await.unwrap(), 100);
+    assert_eq!(int(raw.call(&["EXPIRE", "key", "200", "XX"]).await), 1);
+    assert_eq!(client.ttl("key").await.unwrap(), 200);
+
+    // GT and LT compare with the current deadline.
+    assert_eq!(int(raw.call(&["EXPIRE", "key", "150", "GT"]).await), 0);
+    assert_eq!(int(raw.call(&["EXPIRE", "key", "300", "GT"]).await), 1);
+    assert_eq!(client.ttl("key").await.unwrap(), 300);
+    assert_eq!(int(raw.call(&["EXPIRE", "key", "400", "LT"]).await), 0);
+    assert_eq!(int(raw.call(&["PEXPIRE", "key", "50000", "LT"]).await), 1);
+    assert_eq!(client.ttl("key").await.unwrap(), 50);
+
+    // A key without a deadline lives forever: it is never less than a new
+    // deadline, and always greater.
+    client.persist("key").await.unwrap();
+    assert_eq!(int(raw.call(&["EXPIRE", "key", "100", "GT"]).await), 0);
+    assert_eq!(int(raw.call(&["EXPIRE", "key", "100", "LT"]).await), 1);
+    assert_eq!(client.ttl("key").await.unwrap(), 100);
+
+    let reply = raw.call(&["EXPIRE", "key", "100", "SOON"]).await;
+    assert!(matches!(&reply, Frame::Error(e) if e.starts_with("ERR Unsupported option")), "{:?}", reply);
+}
+
+#[tokio::test]
+async fn non_positive_ttls_delete_the_key() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut raw = Raw::connect(addr).await;
+
+    client.set("zero", "value").await.unwrap();
+    assert!(client.expire("zero", 0).await.unwrap());
+    assert_eq!(client.get("zero").await.unwrap(), None);
+    assert_eq!(client.ttl("zero").await.unwrap(), -2);
+
+    client.rpush("negative", "a".into()).await.unwrap();
+    assert_eq!(int(raw.call(&["PEXPIRE", "negative", "-5"]).await), 1);
+    assert_eq!(client.llen("negative").await.unwrap(), 0);
+
+    client.set("past", "value").await.unwrap();
+    let past = (unix_secs() - 10).to_string();
+    assert_eq!(int(raw.call(&["EXPIREAT", "past", &past]).await), 1);
+    assert_eq!(client.exists("past").await.unwrap().unwrap(), "0");
+
+    // An absolute deadline in the future is kept.
+    client.set("future", "value").await.unwrap();
+    let future = ((unix_secs() + 100) * 1000).to_string();
+    assert_eq!(int(raw.call(&["PEXPIREAT", "future", &future]).await), 1);
+    let ttl = client.ttl("future").await.unwrap();
+    assert!((99..=100).contains(&ttl), "{}", ttl);
+}
+
+#[tokio::test]
+async fn persist_removes_the_deadline() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    assert!(!client.persist("missing").await.unwrap());
+
+    client.set("key", "value").await.unwrap();
+    assert!(!client.persist("key").await.unwrap());
+
+    client.expire("key", 100).await.unwrap();
+    assert!(client.persist("key").await.unwrap());
+    assert_eq!(client.ttl("key").await.unwrap(), -1);
+    assert!(!client.persist("key").await.unwrap());
+}
+
+#[tokio::test]
+async fn set_options_set_or_keep_the_deadline() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut raw = Raw::connect(addr).await;
+
+    raw.call(&["SET", "key", "one", "EX", "100"]).await;
+    assert_eq!(client.ttl("key").await.unwrap(), 100);
+
+    // KEEPTTL keeps the deadline; a plain SET drops it.
+    raw.call(&["SET", "key", "two", "KEEPTTL"]).await;
+    assert_eq!(client.get("key").await.unwrap().unwrap(), "two");
+    assert_eq!(client.ttl("key").await.unwrap(), 100);
+    client.set("key", "three").await.unwrap();
+    assert_eq!(client.ttl("key").await.unwrap(), -1);
+
+    raw.call(&["SET", "key", "four", "PX", "50000"]).await;
+    assert_eq!(client.ttl("key").await.unwrap(), 50);
+    let at = (unix_secs() + 200).to_string();
+    raw.call(&["SET", "key", "five", "EXAT", &at]).await;
+    let ttl = client.ttl("key").await.unwrap();
+    assert!((199..=200).contains(&ttl), "{}", ttl);
+
+    let reply = raw.call(&["SET", "key", "six", "EX", "10", "KEEPTTL"]).await;
+    assert!(matches!(&reply, Frame::Error(_)), "{:?}", reply);
+    let reply = raw.call(&["SET", "key", "six", "EX", "0"]).await;
+    assert!(matches!(&reply, Frame::Error(_)), "{:?}", reply);
+}
+
+#[tokio::test]
+async fn expired_keys_are_dropped_when_read() {
+    // No server, so no active expiry: only reading the key removes it.
+    let db = Db::new();
+    let deadline = Instant::now() + Duration::from_millis(50);
+    db.set_with_expiration(0, Bytes::from("key"), Bytes::from("value"), Expiration::At(deadline));
+    assert_eq!(db.get(0, b"key").unwrap().unwrap(), "value");
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(db.dbsize(0), 1);
+    assert_eq!(db.get(0, b"key").unwrap(), None);
+    assert_eq!(db.dbsize(0), 0);
+}
+
+#[tokio::test]
+async fn expired_keys_are_purged_in_the_background() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut raw = Raw::connect(addr).await;
+
+    for i in 0..50 {
+        raw.call(&["SET", &format!("temporary:{}", i), "value", "PX", "50"]).await;
+    }
+    client.set("permanent", "value").await.unwrap();
+    assert_eq!(client.dbsize().await.unwrap(), 51);
+
+    // `DBSIZE` still counts expired keys until they are purged, and nothing
+    // reads them again.
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while client.dbsize().await.unwrap() > 1 {
+        assert!(Instant::now() < deadline, "expired keys were not purged");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(client.get("permanent").await.unwrap().unwrap(), "value");
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::{start_server, Raw};
+use eoncache::{buffer, client, Frame};
+
+#[tokio::test]
+async fn fields_are_updated_individually() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    let added = client
+        .hset("session:1", &[("user", "alice".into()), ("theme", "dark".into())])
+        .await
+        .unwrap();
+    assert_eq!(added, 2);
+
+    // Overwriting an existing field does not count as an addition.
+    assert_eq!(client.hset("session:1", &[("theme", "light".into())]).await.unwrap(), 0);
+    assert!(!client.hsetnx("session:1", "user", "bob".into()).await.unwrap());
+
+    assert_eq!(client.hget("session:1", "theme").await.unwrap().unwrap(), "light");
+    assert_eq!(
+        client.hmget("session:1", &["user", "missing"]).await.unwrap(),
+        vec![Some("alice".into()), None]
+    );
+    assert_eq!(client.hlen("session:1").await.unwrap(), 2);
+
+    let mut all = client.hgetall("session:1").await.unwrap();
+    all.sort();
+    assert_eq!(all, vec![("theme".into(), "light".into()), ("user".into(), "alice".into())]);
+}
+
+#[tokio::test]
+async fn deleting_the_last_field_removes_the_key() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.hset("h", &[("a", "1".into()), ("b", "2".into())]).await.unwrap();
+    assert_eq!(client.hdel("h", &["a", "missing"]).await.unwrap(), 1);
+    assert!(client.hexists("h", "b").await.unwrap());
+
+    assert_eq!(client.hdel("h", &["b"]).await.unwrap(), 1);
+    assert_eq!(client.exists("h").await.unwrap().unwrap(), "0");
+}
+
+#[tokio::test]
+async fn increments_are_atomic() {
+    let addr = start_server().await;
+
+    let mut tasks = Vec::new();
+    for _ in 0..8 {
+        tasks.push(tokio::spawn(async move {
+            let mut client = client::connect(addr).await.unwrap();
+            for _ in 0..25 {
+                client.hincrby("stats", "hits", 1).await.unwrap();
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.hincrby("stats", "hits", 0).await.unwrap(), 200);
+    assert_eq!(client.hincrbyfloat("stats", "load", 0.5).await.unwrap(), 0.5);
+    assert_eq!(client.hincrbyfloat("stats", "load", 2.0).await.unwrap(), 2.5);
+
+    client.hset("stats", &[("name", "web".into())]).await.unwrap();
+    let err = client.hincrby("stats", "name", 1).await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR hash value is not an integer");
+
+    client.hincrby("stats", "max", i64::MAX).await.unwrap();
+    let err = client.hincrby("stats", "max", 1).await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR increment or decrement would overflow");
+}
+
+#[tokio::test]
+async fn hash_commands_check_the_key_type() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("plain", "value").await.unwrap();
+    let err = client.hget("plain", "field").await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+
+    client.hset("h", &[("field", "value".into())]).await.unwrap();
+    let err = client.get("h").await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+}
+
+#[tokio::test]
+async fn hrandfield_counts_are_bounded() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut raw = Raw::connect(addr).await;
+    client.hset("h", &[("a", "1".into()), ("b", "2".into())]).await.unwrap();
+
+    let len = |frame: Frame| match frame {
+        Frame::Array(frames) => frames.len(),
+        frame => panic!("{:?}", frame),
+    };
+    assert_eq!(len(raw.call(&["HRANDFIELD", "h", &i64::MAX.to_string()]).await), 2);
+    assert_eq!(len(raw.call(&["HRANDFIELD", "h", "-3", "WITHVALUES"]).await), 6);
+
+    for args in [vec![i64::MIN.to_string()], vec![(i64::MIN / 2).to_string(), "WITHVALUES".into()]] {
+        let reply = raw.call(&[&["HRANDFIELD".to_string(), "h".into()], &args[..]].concat()).await;
+        assert!(matches!(&reply, Frame::Error(e) if e == "ERR value is out of range"), "{:?}", reply);
+    }
+
+    // The hash is still served afterwards.
+    assert_eq!(client.hget("h", "a").await.unwrap().unwrap(), "1");
+}
+
+#[tokio::test]
+async fn buffer_forwards_hash_commands() {
+    let addr = start_server().await;
+    let mut buffer = buffer::buffer(client::connect(addr).await.unwrap());
+
+    buffer.hset("h", &[("a", "1".into())]).await.unwrap();
+    assert_eq!(buffer.hincrby("h", "a", 4).await.unwrap().unwrap(), "5");
+    assert_eq!(buffer.hget("h", "a").await.unwrap().unwrap(), "5");
+    assert_eq!(buffer.hmget("h", &["a", "b"]).await.unwrap(), vec![Some("5".into()), None]);
+    assert_eq!(buffer.hgetall("h").await.unwrap(), vec![("a".into(), "5".into())]);
+}
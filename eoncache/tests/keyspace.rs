@@ -0,0 +1,71 @@
+mod common;
+
+use common::start_server;
+use eoncache::client;
+use std::time::Duration;
+
+#[tokio::test]
+async fn del_removes_keys_of_any_type() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("string", "value").await.unwrap();
+    client.rpush("list", "a".into()).await.unwrap();
+    client.sadd("set", &["a"]).await.unwrap();
+
+    assert_eq!(client.del(&["string", "list", "set", "missing"]).await.unwrap(), 3);
+    assert_eq!(client.dbsize().await.unwrap(), 0);
+    assert_eq!(client.key_type("list").await.unwrap(), "none");
+}
+
+#[tokio::test]
+async fn rename_keeps_the_value_and_deadline() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.hset("old", &[("field", "value".into())]).await.unwrap();
+    client.expire("old", 100).await.unwrap();
+    client.set("new", "replaced").await.unwrap();
+
+    client.rename("old", "new").await.unwrap();
+    assert_eq!(client.key_type("new").await.unwrap(), "hash");
+    assert!(client.ttl("new").await.unwrap() > 0);
+    assert_eq!(client.exists("old").await.unwrap().unwrap(), "0");
+
+    let err = client.rename("old", "other").await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR no such key");
+}
+
+#[tokio::test]
+async fn rename_serves_blocked_clients() {
+    let addr = start_server().await;
+
+    let mut waiter = client::connect(addr).await.unwrap();
+    let blocked = tokio::spawn(async move { waiter.blpop(&["ready".into()], 0).await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut client = client::connect(addr).await.unwrap();
+    client.rpush("staging", "job".into()).await.unwrap();
+    client.rename("staging", "ready").await.unwrap();
+
+    assert_eq!(blocked.await.unwrap(), Some(("ready".into(), "job".into())));
+}
+
+#[tokio::test]
+async fn swapdb_exchanges_namespaces() {
+    let addr = start_server().await;
+    let mut live = client::connect(addr).await.unwrap();
+    let mut rebuild = client::connect(addr).await.unwrap();
+    rebuild.select(1).await.unwrap();
+
+    live.set("page", "old").await.unwrap();
+    rebuild.set("page", "new").await.unwrap();
+    rebuild.set("extra", "1").await.unwrap();
+
+    live.swapdb(0, 1).await.unwrap();
+
+    assert_eq!(live.get("page").await.unwrap().unwrap(), "new");
+    assert_eq!(live.dbsize().await.unwrap(), 2);
+    assert_eq!(rebuild.get("page").await.unwrap().unwrap(), "old");
+    assert_eq!(rebuild.dbsize().await.unwrap(), 1);
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use bytes::Bytes;
+use common::{start_server, Raw};
+use eoncache::client::{self, Subscriber};
+use eoncache::Frame;
+use std::time::Duration;
+
+/// The next message, as its channel and content.
+async fn next(subscriber: &mut Subscriber) -> (String, String) {
+    let message = tokio::time::timeout(Duration::from_secs(1), subscriber.next_message())
+        .await
+        .expect("no message arrived")
+        .unwrap()
+        .unwrap();
+    let text = |bytes: Bytes| String::from_utf8(bytes.to_vec()).unwrap();
+    (text(message.channel), text(message.content))
+}
+
+/// Asserts no message arrives for a little while.
+async fn assert_quiet(subscriber: &mut Subscriber) {
+    let message = tokio::time::timeout(Duration::from_millis(100), subscriber.next_message()).await;
+    assert!(message.is_err(), "unexpected message {:?}", message);
+}
+
+async fn config(raw: &mut Raw, args: &[&str]) -> Frame {
+    raw.call(&[&["CONFIG"], args].concat()).await
+}
+
+#[tokio::test]
+async fn keyspace_and_keyevent_channels_carry_events() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.config_set("notify-keyspace-events", "KEA").await.unwrap();
+
+    let mut keyspace = client::connect(addr).await.unwrap().psubscribe(&["__keyspace@0__:*"]).await.unwrap();
+    let mut keyevent = client::connect(addr).await.unwrap().psubscribe(&["__keyevent@0__:*"]).await.unwrap();
+
+    client.set("greeting", "hello").await.unwrap();
+    assert_eq!(next(&mut keyspace).await, ("__keyspace@0__:greeting".into(), "set".into()));
+    assert_eq!(next(&mut keyevent).await, ("__keyevent@0__:set".into(), "greeting".into()));
+
+    client.rpush("list", "a".into()).await.unwrap();
+    assert_eq!(next(&mut keyspace).await, ("__keyspace@0__:list".into(), "rpush".into()));
+    client.hset("hash", &[("field", "value".into())]).await.unwrap();
+    assert_eq!(next(&mut keyspace).await, ("__keyspace@0__:hash".into(), "hset".into()));
+
+    client.del(&["greeting", "missing"]).await.unwrap();
+    assert_eq!(next(&mut keyspace).await, ("__keyspace@0__:greeting".into(), "del".into()));
+
+    client.expire("list", 1).await.unwrap();
+    assert_eq!(next(&mut keyspace).await, ("__keyspace@0__:list".into(), "expire".into()));
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(client.llen("list").await.unwrap(), 0);
+    assert_eq!(next(&mut keyspace).await, ("__keyspace@0__:list".into(), "expired".into()));
+
+    // Writes that change nothing are not reported.
+    client.hsetnx("hash", "field", "other".into()).await.unwrap();
+    let mut raw = Raw::connect(addr).await;
+    raw.call(&["ZADD", "zset", "XX", "1", "member"]).await;
+    raw.call(&["ZADD", "zset", "XX", "INCR", "1", "member"]).await;
+    assert_quiet(&mut keyspace).await;
+    assert!(matches!(raw.call(&["EXISTS", "zset"]).await, Frame::Integer(0)));
+}
+
+#[tokio::test]
+async fn only_selected_classes_are_published() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.config_set("notify-keyspace-events", "El").await.unwrap();
+
+    let mut keyevent = client::connect(addr).await.unwrap().psubscribe(&["__key*__:*"]).await.unwrap();
+
+    client.set("string", "value").await.unwrap();
+    client.sadd("set", &["member"]).await.unwrap();
+    client.lpush("list", "a".into()).await.unwrap();
+    assert_eq!(next(&mut keyevent).await, ("__keyevent@0__:lpush".into(), "list".into()));
+
+    // Moving an element pops from one list and pushes onto the other. The
+    // emptied source is deleted too, but generic events are not selected.
+    client.lmove("list", "other", "LEFT", "RIGHT").await.unwrap();
+    assert_eq!(next(&mut keyevent).await, ("__keyevent@0__:lpop".into(), "list".into()));
+    assert_eq!(next(&mut keyevent).await, ("__keyevent@0__:rpush".into(), "other".into()));
+    assert_quiet(&mut keyevent).await;
+
+    // Classes alone publish nothing without K or E.
+    client.config_set("notify-keyspace-events", "A").await.unwrap();
+    client.lpush("list", "b".into()).await.unwrap();
+    assert_quiet(&mut keyevent).await;
+}
+
+#[tokio::test]
+async fn notify_keyspace_events_is_configurable() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+
+    let reply = config(&mut raw, &["GET", "notify-keyspace-events"]).await;
+    assert!(matches!(&reply, Frame::Array(parts) if matches!(&parts[1], Frame::Bulk(value) if value.is_empty())), "{:?}", reply);
+
+    assert!(matches!(config(&mut raw, &["SET", "notify-keyspace-events", "KEA"]).await, Frame::Simple(_)));
+    let reply = config(&mut raw, &["GET", "notify-keyspace-events"]).await;
+    assert!(matches!(&reply, Frame::Array(parts) if matches!(&parts[1], Frame::Bulk(value) if value == "AKE")), "{:?}", reply);
+
+    assert!(matches!(config(&mut raw, &["SET", "notify-keyspace-events", "Kx$"]).await, Frame::Simple(_)));
+    let reply = config(&mut raw, &["GET", "notify-keyspace-events"]).await;
+    assert!(matches!(&reply, Frame::Array(parts) if matches!(&parts[1], Frame::Bulk(value) if value == "$xK")), "{:?}", reply);
+
+    let reply = config(&mut raw, &["SET", "notify-keyspace-events", "KQ"]).await;
+    assert!(matches!(&reply, Frame::Error(e) if e.contains("Invalid event class character")), "{:?}", reply);
+}
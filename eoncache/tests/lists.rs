@@ -0,0 +1,99 @@
+mod common;
+
+use common::start_server;
+use eoncache::client;
+use std::time::Duration;
+
+#[tokio::test]
+async fn lists_keep_insertion_order() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.rpush("letters", "b".into()).await.unwrap();
+    client.rpush("letters", "c".into()).await.unwrap();
+    client.lpush("letters", "a".into()).await.unwrap();
+
+    assert_eq!(client.llen("letters").await.unwrap(), 3);
+    assert_eq!(client.lrange("letters", 0, -1).await.unwrap(), vec!["a", "b", "c"]);
+    assert_eq!(client.lrange("letters", -2, 10).await.unwrap(), vec!["b", "c"]);
+    assert!(client.lrange("letters", 2, 1).await.unwrap().is_empty());
+    assert!(client.lrange("missing", 0, -1).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn lmove_rotates_and_removes_empty_lists() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    for job in ["one", "two"] {
+        client.rpush("pending", job.into()).await.unwrap();
+    }
+
+    // Moving onto the same list rotates it.
+    assert_eq!(client.lmove("pending", "pending", "LEFT", "RIGHT").await.unwrap().unwrap(), "one");
+    assert_eq!(client.lrange("pending", 0, -1).await.unwrap(), vec!["two", "one"]);
+
+    assert_eq!(client.lmove("pending", "done", "RIGHT", "LEFT").await.unwrap().unwrap(), "one");
+    assert_eq!(client.lmove("pending", "done", "RIGHT", "LEFT").await.unwrap().unwrap(), "two");
+    assert_eq!(client.lmove("pending", "done", "RIGHT", "LEFT").await.unwrap(), None);
+
+    // The emptied source list is gone.
+    assert_eq!(client.exists("pending").await.unwrap().unwrap(), "0");
+    assert_eq!(client.lrange("done", 0, -1).await.unwrap(), vec!["two", "one"]);
+}
+
+#[tokio::test]
+async fn lmove_checks_the_destination_type() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.rpush("source", "job".into()).await.unwrap();
+    client.set("plain", "value").await.unwrap();
+
+    let err = client.lmove("source", "plain", "LEFT", "LEFT").await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+    assert_eq!(client.lrange("source", 0, -1).await.unwrap(), vec!["job"]);
+}
+
+#[tokio::test]
+async fn blmove_waits_for_the_source() {
+    let addr = start_server().await;
+
+    let mut worker = client::connect(addr).await.unwrap();
+    let blocked = tokio::spawn(async move { worker.blmove("queue", "processing", "LEFT", "RIGHT", 0).await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut producer = client::connect(addr).await.unwrap();
+    producer.rpush("queue", "job".into()).await.unwrap();
+
+    assert_eq!(blocked.await.unwrap().unwrap(), "job");
+    assert_eq!(producer.llen("queue").await.unwrap(), 0);
+    assert_eq!(producer.lrange("processing", 0, -1).await.unwrap(), vec!["job"]);
+}
+
+#[tokio::test]
+async fn blmove_serves_clients_blocked_on_the_destination() {
+    let addr = start_server().await;
+
+    let mut consumer = client::connect(addr).await.unwrap();
+    let popped = tokio::spawn(async move { consumer.blpop(&["stage2".into()], 0).await.unwrap() });
+    let mut mover = client::connect(addr).await.unwrap();
+    let moved = tokio::spawn(async move { mover.blmove("stage1", "stage2", "LEFT", "RIGHT", 0).await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut producer = client::connect(addr).await.unwrap();
+    producer.rpush("stage1", "job".into()).await.unwrap();
+
+    assert_eq!(moved.await.unwrap().unwrap(), "job");
+    assert_eq!(popped.await.unwrap(), Some(("stage2".into(), "job".into())));
+    assert_eq!(producer.exists("stage2").await.unwrap().unwrap(), "0");
+}
+
+#[tokio::test]
+async fn blmove_times_out() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    assert_eq!(client.blmove("empty", "other", "LEFT", "LEFT", 1).await.unwrap(), None);
+    assert_eq!(client.exists("other").await.unwrap().unwrap(), "0");
+}
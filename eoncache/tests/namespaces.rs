@@ -0,0 +1,100 @@
+mod common;
+
+use common::{start_server, start_server_with};
+use eoncache::{client, Config, Db};
+
+#[tokio::test]
+async fn select_is_per_connection() {
+    let addr = start_server().await;
+
+    let mut first = client::connect(addr).await.unwrap();
+    let mut second = client::connect(addr).await.unwrap();
+
+    first.select(3).await.unwrap();
+    first.set("key", "three").await.unwrap();
+
+    // The second client never selected anything and stays on namespace 0.
+    assert_eq!(second.get("key").await.unwrap(), None);
+    second.set("key", "zero").await.unwrap();
+
+    assert_eq!(first.get("key").await.unwrap().unwrap(), "three");
+    assert_eq!(second.get("key").await.unwrap().unwrap(), "zero");
+}
+
+#[tokio::test]
+async fn concurrent_clients_on_different_namespaces_are_isolated() {
+    let addr = start_server().await;
+
+    let mut tasks = Vec::new();
+    for index in 0..8usize {
+        tasks.push(tokio::spawn(async move {
+            let mut client = client::connect(addr).await.unwrap();
+            client.select(index).await.unwrap();
+
+            for round in 0..50 {
+                let value = format!("ns{}-{}", index, round);
+                client.set("shared", &value).await.unwrap();
+                client.rpush("queue", value.clone().into()).await.unwrap();
+
+                let got = client.get("shared").await.unwrap().unwrap();
+                assert_eq!(got, value.as_str());
+                tokio::task::yield_now().await;
+            }
+        }));
+    }
+
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    // Every namespace ends up holding only its own writes.
+    for index in 0..8usize {
+        let mut client = client::connect(addr).await.unwrap();
+        client.select(index).await.unwrap();
+        let got = client.get("shared").await.unwrap().unwrap();
+        assert_eq!(got, format!("ns{}-49", index).as_str());
+    }
+}
+
+#[tokio::test]
+async fn namespace_count_is_configurable() {
+    let config = Config {
+        databases: 64,
+        ..Config::default()
+    };
+    let addr = start_server_with(Db::with_config(config)).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.select(63).await.unwrap();
+    client.set("key", "last").await.unwrap();
+
+    let err = client.select(64).await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR DB index is out of range (0-63)");
+    assert_eq!(client.get("key").await.unwrap().unwrap(), "last");
+}
+
+#[tokio::test]
+async fn aliases_select_namespaces_by_name() {
+    let config = Config {
+        databases: 4,
+        aliases: [("sessions".to_string(), 1), ("cache".to_string(), 3)].into_iter().collect(),
+        ..Config::default()
+    };
+    let addr = start_server_with(Db::with_config(config)).await;
+
+    let mut by_name = client::connect(addr).await.unwrap();
+    by_name.select_named("sessions").await.unwrap();
+    by_name.set("user:1", "alice").await.unwrap();
+
+    let mut by_index = client::connect(addr).await.unwrap();
+    by_index.select(1).await.unwrap();
+    assert_eq!(by_index.get("user:1").await.unwrap().unwrap(), "alice");
+
+    // Aliases name indexes rather than data, so they follow a swap.
+    by_name.swapdb(1, 3).await.unwrap();
+    by_name.select_named("cache").await.unwrap();
+    assert_eq!(by_name.get("user:1").await.unwrap().unwrap(), "alice");
+
+    let err = by_name.select_named("unknown").await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR unknown namespace 'unknown'");
+}
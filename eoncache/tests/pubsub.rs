@@ -0,0 +1,121 @@
+mod common;
+
+use common::{start_server, Raw};
+use eoncache::{client, Frame};
+use std::time::Duration;
+
+/// Whether `frame` is an array of the bulk strings `parts`, with `count` as
+/// an integer last if given.
+fn is_push(frame: &Frame, parts: &[&str], count: Option<i64>) -> bool {
+    let Frame::Array(frames) = frame else { return false };
+    let strings = frames.iter().take(parts.len());
+    let strings_match = frames.len() == parts.len() + count.is_some() as usize
+        && strings.zip(parts).all(|(frame, part)| matches!(frame, Frame::Bulk(bulk) if bulk == part));
+    strings_match && count.is_none_or(|count| matches!(frames.last(), Some(Frame::Integer(n)) if *n == count))
+}
+
+#[tokio::test]
+async fn messages_reach_channel_and_pattern_subscribers() {
+    let addr = start_server().await;
+    let mut publisher = client::connect(addr).await.unwrap();
+
+    let mut news = client::connect(addr).await.unwrap().subscribe(&["news", "sports"]).await.unwrap();
+    let mut everything = client::connect(addr).await.unwrap().psubscribe(&["n*"]).await.unwrap();
+
+    assert_eq!(publisher.publish("news", "hello").await.unwrap(), 2);
+    assert_eq!(publisher.publish("sports", "goal").await.unwrap(), 1);
+    assert_eq!(publisher.publish("weather", "rain").await.unwrap(), 0);
+
+    let message = news.next_message().await.unwrap().unwrap();
+    assert_eq!((message.channel, message.content, message.pattern), ("news".into(), "hello".into(), None));
+    let message = news.next_message().await.unwrap().unwrap();
+    assert_eq!((message.channel, message.content), ("sports".into(), "goal".into()));
+
+    let message = everything.next_message().await.unwrap().unwrap();
+    assert_eq!(message.pattern, Some("n*".into()));
+    assert_eq!((message.channel, message.content), ("news".into(), "hello".into()));
+
+    // Messages published while subscribing are kept.
+    news.unsubscribe(&["sports"]).await.unwrap();
+    assert_eq!(news.channels(), ["news"]);
+    publisher.publish("news", "again").await.unwrap();
+    assert_eq!(publisher.publish("sports", "missed").await.unwrap(), 0);
+    assert_eq!(news.next_message().await.unwrap().unwrap().content, "again");
+}
+
+#[tokio::test]
+async fn pubsub_introspection() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    let _first = client::connect(addr).await.unwrap().subscribe(&["orders", "orders:eu"]).await.unwrap();
+    let mut second = client::connect(addr).await.unwrap().subscribe(&["orders"]).await.unwrap();
+    let _patterns = client::connect(addr).await.unwrap().psubscribe(&["orders:*", "logs.*"]).await.unwrap();
+
+    let mut channels = client.pubsub_channels(None).await.unwrap();
+    channels.sort();
+    assert_eq!(channels, vec!["orders", "orders:eu"]);
+    assert_eq!(client.pubsub_channels(Some("*:eu")).await.unwrap(), vec!["orders:eu"]);
+    assert_eq!(client.pubsub_numsub(&["orders", "orders:eu", "none"]).await.unwrap(), vec![2, 1, 0]);
+    assert_eq!(client.pubsub_numpat().await.unwrap(), 2);
+
+    // Subscriptions end with the connection.
+    second.unsubscribe(&[]).await.unwrap();
+    drop(second);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(client.pubsub_numsub(&["orders"]).await.unwrap(), vec![1]);
+}
+
+#[tokio::test]
+async fn subscriber_mode_accepts_only_subscribe_commands_and_ping() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+
+    raw.send(&["SUBSCRIBE", "a", "b"]).await;
+    assert!(is_push(&raw.read().await, &["subscribe", "a"], Some(1)));
+    assert!(is_push(&raw.read().await, &["subscribe", "b"], Some(2)));
+
+    let reply = raw.call(&["GET", "key"]).await;
+    assert!(matches!(&reply, Frame::Error(e) if e.starts_with("ERR Can't execute 'get'")), "{:?}", reply);
+    assert!(is_push(&raw.call(&["PING"]).await, &["pong", ""], None));
+    assert!(is_push(&raw.call(&["PING", "hi"]).await, &["pong", "hi"], None));
+
+    assert!(is_push(&raw.call(&["PSUBSCRIBE", "c*"]).await, &["psubscribe", "c*"], Some(3)));
+    assert!(is_push(&raw.call(&["PUNSUBSCRIBE"]).await, &["punsubscribe", "c*"], Some(2)));
+
+    // Leaving the last channel ends subscriber mode.
+    raw.send(&["UNSUBSCRIBE"]).await;
+    assert!(is_push(&raw.read().await, &["unsubscribe", "a"], Some(1)));
+    assert!(is_push(&raw.read().await, &["unsubscribe", "b"], Some(0)));
+    assert!(matches!(raw.call(&["GET", "key"]).await, Frame::Null));
+
+    // Unsubscribing with no subscriptions replies once, with no channel.
+    match raw.call(&["UNSUBSCRIBE"]).await {
+        Frame::Array(parts) => assert!(matches!(parts.as_slice(), [Frame::Bulk(_), Frame::Null, Frame::Integer(0)])),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn slow_subscribers_are_disconnected() {
+    let addr = start_server().await;
+    let mut publisher = client::connect(addr).await.unwrap();
+    publisher.config_set("pubsub-queue-limit", "16").await.unwrap();
+
+    // This subscriber never reads, so once the socket buffers are full its
+    // queue grows until it goes over the limit.
+    let mut slow = Raw::connect(addr).await;
+    assert!(is_push(&slow.call(&["SUBSCRIBE", "firehose"]).await, &["subscribe", "firehose"], Some(1)));
+
+    let message = "x".repeat(64 * 1024);
+    let mut dropped = false;
+    for _ in 0..10_000 {
+        if publisher.publish("firehose", &message).await.unwrap() == 0 {
+            dropped = true;
+            break;
+        }
+    }
+    assert!(dropped, "the subscriber was never disconnected");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(publisher.pubsub_numsub(&["firehose"]).await.unwrap(), vec![0]);
+}
@@ -0,0 +1,189 @@
+mod common;
+
+use common::{start_server, Raw};
+use eoncache::{client, Frame};
+use bytes::Bytes;
+use std::collections::HashSet;
+
+/// One `command key cursor COUNT count` call: the next cursor and the
+/// elements returned.
+async fn scan_page(raw: &mut Raw, command: &str, key: &str, cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+    let cursor = cursor.to_string();
+    let count = count.to_string();
+    match raw.call(&[command, key, &cursor, "COUNT", &count]).await {
+        Frame::Array(mut reply) => {
+            let elements = match reply.pop() {
+                Some(Frame::Array(elements)) => elements,
+                frame => panic!("unexpected elements {:?}", frame),
+            };
+            let next = match reply.pop() {
+                Some(Frame::Bulk(next)) => std::str::from_utf8(&next).unwrap().parse().unwrap(),
+                frame => panic!("unexpected cursor {:?}", frame),
+            };
+            let elements = elements
+                .into_iter()
+                .map(|element| match element {
+                    Frame::Bulk(element) => element,
+                    frame => panic!("unexpected element {:?}", frame),
+                })
+                .collect();
+            (next, elements)
+        }
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn keys_match_glob_patterns() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    for key in ["hello", "hallo", "hxllo", "hllo", "heeeello", "h*llo", "h[a]llo"] {
+        client.set(key, "1").await.unwrap();
+    }
+
+    let keys = |mut keys: Vec<Bytes>| {
+        keys.sort();
+        keys
+    };
+    assert_eq!(keys(client.keys("h?llo").await.unwrap()), vec!["h*llo", "hallo", "hello", "hxllo"]);
+    assert_eq!(keys(client.keys("h*llo").await.unwrap()).len(), 7);
+    assert_eq!(keys(client.keys("h[ae]llo").await.unwrap()), vec!["hallo", "hello"]);
+    assert_eq!(keys(client.keys("h[^e]llo").await.unwrap()), vec!["h*llo", "hallo", "hxllo"]);
+    assert_eq!(keys(client.keys("h[a-f]llo").await.unwrap()), vec!["hallo", "hello"]);
+    assert_eq!(keys(client.keys("h\\*llo").await.unwrap()), vec!["h*llo"]);
+    assert_eq!(keys(client.keys("h\\[a\\]llo").await.unwrap()), vec!["h[a]llo"]);
+    assert!(client.keys("nothing*").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn scan_visits_every_key_once() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    for i in 0..200 {
+        client.set(&format!("key:{}", i), "1").await.unwrap();
+    }
+
+    let mut seen = HashSet::new();
+    let mut cursor = 0;
+    let mut calls = 0;
+    loop {
+        let (next, keys) = client.scan(cursor, None, 15).await.unwrap();
+        for key in keys {
+            assert!(seen.insert(key), "key returned twice");
+        }
+
+        // Churn the keyspace while scanning.
+        client.set(&format!("new:{}", calls), "1").await.unwrap();
+        calls += 1;
+
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+
+    assert!(calls > 1);
+    for i in 0..200 {
+        assert!(seen.contains(format!("key:{}", i).as_bytes()));
+    }
+}
+
+#[tokio::test]
+async fn scan_filters_by_pattern() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    for i in 0..30 {
+        client.set(&format!("user:{}", i), "1").await.unwrap();
+        client.set(&format!("session:{}", i), "1").await.unwrap();
+    }
+
+    let mut found = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let (next, keys) = client.scan(cursor, Some("user:*"), 10).await.unwrap();
+        found.extend(keys);
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+
+    assert_eq!(found.len(), 30);
+    assert!(found.iter().all(|key| key.starts_with(b"user:")));
+}
+
+#[tokio::test]
+async fn collection_scans_page_through_large_collections() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+
+    let mut hset = vec!["HSET".to_string(), "hash".to_string()];
+    let mut sadd = vec!["SADD".to_string(), "set".to_string()];
+    let mut zadd = vec!["ZADD".to_string(), "zset".to_string()];
+    for i in 0..1000 {
+        hset.extend([format!("field:{}", i), i.to_string()]);
+        sadd.push(format!("member:{}", i));
+        zadd.extend([i.to_string(), format!("member:{}", i)]);
+    }
+    raw.call(&hset).await;
+    raw.call(&sadd).await;
+    raw.call(&zadd).await;
+
+    for (command, key, per_element) in [("HSCAN", "hash", 2), ("SSCAN", "set", 1), ("ZSCAN", "zset", 2)] {
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            let (next, elements) = scan_page(&mut raw, command, key, cursor, 50).await;
+            calls += 1;
+            assert!(elements.len() <= 50 * per_element, "{} returned {} elements", command, elements.len() / per_element);
+            for element in elements.into_iter().step_by(per_element) {
+                assert!(seen.insert(element), "{} returned an element twice", command);
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        assert_eq!(seen.len(), 1000, "{}", command);
+        assert!(calls >= 20, "{} took {} calls", command, calls);
+    }
+}
+
+#[tokio::test]
+async fn set_scans_survive_removals() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+
+    let mut sadd = vec!["SADD".to_string(), "set".to_string()];
+    for i in 0..300 {
+        sadd.extend([format!("keep:{}", i), format!("drop:{}", i)]);
+    }
+    raw.call(&sadd).await;
+
+    let mut seen = HashSet::new();
+    let mut cursor = 0;
+    let mut dropped = 0;
+    loop {
+        let (next, members) = scan_page(&mut raw, "SSCAN", "set", cursor, 20).await;
+        seen.extend(members);
+
+        // Remove members while scanning, each one moving another.
+        for _ in 0..10 {
+            raw.call(&["SREM", "set", &format!("drop:{}", dropped)]).await;
+            dropped += 1;
+        }
+
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+
+    for i in 0..300 {
+        assert!(seen.contains(format!("keep:{}", i).as_bytes()));
+    }
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use common::start_server;
+use eoncache::{client, Frame};
+use std::time::Duration;
+
+const NONE: &[&str] = &[];
+
+#[tokio::test]
+async fn replies_convert_between_lua_and_frames() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.rpush("list", "a".into()).await.unwrap();
+    client.rpush("list", "b".into()).await.unwrap();
+
+    let reply = client.eval("return redis.call('LRANGE', KEYS[1], 0, -1)", &["list"], NONE).await.unwrap();
+    assert!(matches!(&reply, Frame::Array(items) if items.len() == 2 && matches!(&items[1], Frame::Bulk(b) if b == "b")));
+
+    // Numbers are truncated, `true` is 1 and `false` is null.
+    assert!(matches!(client.eval("return 3.99", NONE, NONE).await.unwrap(), Frame::Integer(3)));
+    assert!(matches!(client.eval("return true", NONE, NONE).await.unwrap(), Frame::Integer(1)));
+    assert!(matches!(client.eval("return false", NONE, NONE).await.unwrap(), Frame::Null));
+    assert!(matches!(client.eval("return redis.call('GET', 'missing')", NONE, NONE).await.unwrap(), Frame::Null));
+
+    // Arrays stop at the first nil.
+    let reply = client.eval("return {1, 2, nil, 4}", NONE, NONE).await.unwrap();
+    assert!(matches!(&reply, Frame::Array(items) if items.len() == 2));
+
+    let reply = client.eval("return redis.status_reply('FINE')", NONE, NONE).await.unwrap();
+    assert!(matches!(&reply, Frame::Simple(status) if status == "FINE"));
+    let reply = client.eval("return redis.call('SET', 'key', ARGV[1])", NONE, &["value"]).await.unwrap();
+    assert!(matches!(&reply, Frame::Simple(status) if status == "OK"));
+
+    let err = client.eval("return redis.error_reply('MY failure')", NONE, NONE).await.unwrap_err();
+    assert_eq!(err.to_string(), "MY failure");
+}
+
+#[tokio::test]
+async fn call_raises_and_pcall_returns_errors() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("string", "value").await.unwrap();
+
+    let err = client.eval("return redis.call('LPUSH', KEYS[1], 'x')", &["string"], NONE).await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+
+    let script = "local reply = redis.pcall('LPUSH', KEYS[1], 'x'); return reply.err ~= nil";
+    assert!(matches!(client.eval(script, &["string"], NONE).await.unwrap(), Frame::Integer(1)));
+
+    let err = client.eval("return redis.call('EVAL', 'return 1', 0)", NONE, NONE).await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR This Redis command is not allowed from script");
+    let err = client.eval("return redis.call()", NONE, NONE).await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR Please specify at least one argument for this redis lib call");
+
+    let err = client.eval("return +", NONE, NONE).await.unwrap_err();
+    assert!(err.to_string().starts_with("ERR Error compiling script"), "{}", err);
+    let err = client.eval("error('oops')", NONE, NONE).await.unwrap_err();
+    assert!(err.to_string().starts_with("ERR Error running script"), "{}", err);
+}
+
+#[tokio::test]
+async fn scripts_run_atomically() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("stock", "10").await.unwrap();
+
+    let script = "
+        local stock = tonumber(redis.call('GET', KEYS[1]))
+        if stock <= 0 then return 0 end
+        redis.call('DECR', KEYS[1])
+        redis.call('RPUSH', KEYS[2], ARGV[1])
+        return 1
+    ";
+    let mut buyers = Vec::new();
+    for i in 0..20 {
+        buyers.push(tokio::spawn(async move {
+            let mut client = client::connect(addr).await.unwrap();
+            client.eval(script, &["stock", "orders"], &[format!("buyer{}", i)]).await.unwrap()
+        }));
+    }
+    let mut sold = 0;
+    for buyer in buyers {
+        if let Frame::Integer(1) = buyer.await.unwrap() {
+            sold += 1;
+        }
+    }
+
+    assert_eq!(sold, 10);
+    assert_eq!(client.get("stock").await.unwrap().unwrap(), "0");
+    assert_eq!(client.llen("orders").await.unwrap(), 10);
+}
+
+#[tokio::test]
+async fn scripts_are_cached_by_sha() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    let sha = client.script_load("return ARGV[1]").await.unwrap();
+    assert_eq!(sha.len(), 40);
+    let reply = client.evalsha(&sha, NONE, &["echo"]).await.unwrap();
+    assert!(matches!(&reply, Frame::Bulk(value) if value == "echo"));
+    let reply = client.evalsha(&sha.to_uppercase(), NONE, &["echo"]).await.unwrap();
+    assert!(matches!(&reply, Frame::Bulk(value) if value == "echo"));
+
+    // `EVAL` caches its script too.
+    client.eval("return 2", NONE, NONE).await.unwrap();
+    let sha2 = match client.eval("return redis.sha1hex('return 2')", NONE, NONE).await.unwrap() {
+        Frame::Bulk(sha) => String::from_utf8(sha.to_vec()).unwrap(),
+        frame => panic!("unexpected reply {:?}", frame),
+    };
+    let missing = "0".repeat(40);
+    assert_eq!(client.script_exists(&[&sha, &sha2, &missing]).await.unwrap(), vec![true, true, false]);
+
+    client.script_flush().await.unwrap();
+    assert_eq!(client.script_exists(&[&sha]).await.unwrap(), vec![false]);
+    let err = client.evalsha(&sha, NONE, NONE).await.unwrap_err();
+    assert_eq!(err.to_string(), "NOSCRIPT No matching script. Please use EVAL.");
+}
+
+#[tokio::test]
+async fn scripts_cannot_reach_the_system() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    let reply = client.eval("return {type(os), type(io), type(dofile), type(loadfile)}", NONE, NONE).await.unwrap();
+    match reply {
+        Frame::Array(types) => {
+            for kind in types {
+                assert!(matches!(&kind, Frame::Bulk(kind) if kind == "nil"), "{:?}", kind);
+            }
+        }
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+    assert!(client.eval("return require('os')", NONE, NONE).await.is_err());
+}
+
+#[tokio::test]
+async fn script_kill_stops_read_only_scripts() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut other = client::connect(addr).await.unwrap();
+
+    let err = other.script_kill().await.unwrap_err();
+    assert_eq!(err.to_string(), "NOTBUSY No scripts in execution right now.");
+
+    let looping = tokio::spawn(async move { client.eval("while true do end", NONE, NONE).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    other.script_kill().await.unwrap();
+    let err = looping.await.unwrap().unwrap_err();
+    assert!(err.to_string().starts_with("ERR Script killed by user"), "{}", err);
+
+    // Scripts that wrote cannot be killed; this one stops by itself.
+    let mut client = client::connect(addr).await.unwrap();
+    let script = "redis.call('SET', 'key', 'value'); local i = 0; while i < 100000000 do i = i + 1 end; return i";
+    let writing = tokio::spawn(async move { client.eval(script, NONE, NONE).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let err = other.script_kill().await.unwrap_err();
+    assert!(err.to_string().starts_with("UNKILLABLE"), "{}", err);
+    assert!(writing.await.unwrap().is_ok());
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use common::{start_server, Raw};
+use eoncache::{client, Frame};
+
+fn sorted(mut members: Vec<bytes::Bytes>) -> Vec<bytes::Bytes> {
+    members.sort();
+    members
+}
+
+#[tokio::test]
+async fn members_are_deduplicated() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    assert_eq!(client.sadd("seen", &["e1", "e2", "e1"]).await.unwrap(), 2);
+    assert_eq!(client.sadd("seen", &["e2", "e3"]).await.unwrap(), 1);
+    assert_eq!(client.scard("seen").await.unwrap(), 3);
+    assert!(client.sismember("seen", "e3").await.unwrap());
+    assert!(!client.sismember("seen", "e4").await.unwrap());
+
+    assert_eq!(client.srem("seen", &["e1", "e2", "e3", "e4"]).await.unwrap(), 3);
+    assert_eq!(client.exists("seen").await.unwrap().unwrap(), "0");
+}
+
+#[tokio::test]
+async fn smove_is_atomic_in_both_directions() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    let members: Vec<String> = (0..50).map(|i| i.to_string()).collect();
+    let members: Vec<&str> = members.iter().map(String::as_str).collect();
+    client.sadd("left", &members[..25]).await.unwrap();
+    client.sadd("right", &members[25..]).await.unwrap();
+
+    let mut tasks = Vec::new();
+    for (source, destination) in [("left", "right"), ("right", "left")] {
+        tasks.push(tokio::spawn(async move {
+            let mut client = client::connect(addr).await.unwrap();
+            for member in 0..50 {
+                client.smove(source, destination, &member.to_string()).await.unwrap();
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    // Every member ends up in exactly one of the two sets.
+    let mut all = client.smembers("left").await.unwrap();
+    all.extend(client.smembers("right").await.unwrap());
+    let mut expected: Vec<bytes::Bytes> = members.iter().map(|m| bytes::Bytes::from(m.to_string())).collect();
+    expected.sort();
+    assert_eq!(sorted(all), expected);
+}
+
+#[tokio::test]
+async fn set_commands_check_the_key_type() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("plain", "value").await.unwrap();
+    let err = client.sadd("plain", &["member"]).await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+
+    client.sadd("flags", &["beta"]).await.unwrap();
+    let err = client.smove("flags", "plain", "beta").await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+    assert!(client.sismember("flags", "beta").await.unwrap());
+}
+
+#[tokio::test]
+async fn random_member_counts_are_bounded() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut raw = Raw::connect(addr).await;
+    client.sadd("s", &["a", "b"]).await.unwrap();
+
+    let len = |frame: Frame| match frame {
+        Frame::Array(frames) => frames.len(),
+        frame => panic!("{:?}", frame),
+    };
+    assert_eq!(len(raw.call(&["SRANDMEMBER", "s", &i64::MAX.to_string()]).await), 2);
+    assert_eq!(len(raw.call(&["SRANDMEMBER", "s", "-5"]).await), 5);
+
+    let reply = raw.call(&["SRANDMEMBER", "s", &i64::MIN.to_string()]).await;
+    assert!(matches!(&reply, Frame::Error(e) if e == "ERR value is out of range"), "{:?}", reply);
+
+    // The set is still served afterwards.
+    assert!(client.sismember("s", "a").await.unwrap());
+    assert_eq!(len(raw.call(&["SPOP", "s", &i64::MAX.to_string()]).await), 2);
+    assert_eq!(client.scard("s").await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn spop_removes_distinct_members() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut raw = Raw::connect(addr).await;
+
+    let members: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+    let members: Vec<&str> = members.iter().map(String::as_str).collect();
+    client.sadd("s", &members).await.unwrap();
+
+    let Frame::Array(popped) = raw.call(&["SPOP", "s", "30"]).await else { panic!("SPOP did not reply an array") };
+    let popped: std::collections::HashSet<_> = popped
+        .into_iter()
+        .map(|frame| match frame {
+            Frame::Bulk(member) => String::from_utf8(member.to_vec()).unwrap(),
+            frame => panic!("{:?}", frame),
+        })
+        .collect();
+    assert_eq!(popped.len(), 30);
+    assert_eq!(client.scard("s").await.unwrap(), 70);
+    for member in &popped {
+        assert!(!client.sismember("s", member).await.unwrap());
+    }
+
+    // Whatever is left is popped one member at a time, then the key is gone.
+    let mut left = client.smembers("s").await.unwrap();
+    for _ in 0..70 {
+        let reply = raw.call(&["SPOP", "s"]).await;
+        let Frame::Bulk(member) = reply else { panic!("{:?}", reply) };
+        left.retain(|other| *other != member);
+    }
+    assert!(left.is_empty());
+    assert!(matches!(raw.call(&["SPOP", "s"]).await, Frame::Null));
+    assert_eq!(client.exists("s").await.unwrap().unwrap(), "0");
+}
+
+#[tokio::test]
+async fn sintercard_stops_at_the_limit() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut raw = Raw::connect(addr).await;
+
+    let members: Vec<String> = (0..75).map(|i| i.to_string()).collect();
+    let members: Vec<&str> = members.iter().map(String::as_str).collect();
+    client.sadd("a", &members[..50]).await.unwrap();
+    client.sadd("b", &members[25..]).await.unwrap();
+
+    let card = |frame: Frame| match frame {
+        Frame::Integer(n) => n,
+        frame => panic!("{:?}", frame),
+    };
+    assert_eq!(card(raw.call(&["SINTERCARD", "2", "a", "b"]).await), 25);
+    assert_eq!(card(raw.call(&["SINTERCARD", "2", "a", "b", "LIMIT", "10"]).await), 10);
+    assert_eq!(card(raw.call(&["SINTERCARD", "2", "a", "b", "LIMIT", "0"]).await), 25);
+    assert_eq!(card(raw.call(&["SINTERCARD", "1", "a", "LIMIT", "100"]).await), 50);
+    assert_eq!(card(raw.call(&["SINTERCARD", "2", "a", "missing"]).await), 0);
+
+    // Every key is type checked, even past a missing one.
+    client.set("plain", "value").await.unwrap();
+    let reply = raw.call(&["SINTERCARD", "3", "a", "missing", "plain"]).await;
+    assert!(matches!(&reply, Frame::Error(e) if e.starts_with("WRONGTYPE")), "{:?}", reply);
+}
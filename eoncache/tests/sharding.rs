@@ -0,0 +1,95 @@
+mod common;
+
+use common::start_server;
+use eoncache::client;
+use std::collections::HashSet;
+use std::time::Duration;
+
+#[tokio::test]
+async fn concurrent_multi_key_commands_do_not_deadlock() {
+    let addr = start_server().await;
+
+    // Clients move elements and members between the same keys in opposite
+    // directions, so they lock the same shards in every order.
+    let tasks: Vec<_> = (0..8)
+        .map(|client_id| {
+            tokio::spawn(async move {
+                let mut client = client::connect(addr).await.unwrap();
+                for i in 0..100 {
+                    let (a, b) = (format!("key:{}", i % 10), format!("key:{}", (i + 3) % 10));
+                    let (source, destination) = if client_id % 2 == 0 { (&a, &b) } else { (&b, &a) };
+
+                    client.rpush(&format!("list:{}", source), "x".into()).await.unwrap();
+                    client.lmove(&format!("list:{}", source), &format!("list:{}", destination), "LEFT", "RIGHT").await.unwrap();
+                    client.sadd(&format!("set:{}", source), &["member"]).await.unwrap();
+                    client.smove(&format!("set:{}", source), &format!("set:{}", destination), "member").await.unwrap();
+                    client.mset(&[(source.as_str(), "1".into()), (destination.as_str(), "2".into())]).await.unwrap();
+                }
+            })
+        })
+        .collect();
+
+    let all = async {
+        for task in tasks {
+            task.await.unwrap();
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), all).await.expect("clients deadlocked");
+
+    // Every pushed element is still somewhere.
+    let mut client = client::connect(addr).await.unwrap();
+    let mut total = 0;
+    for key in 0..10 {
+        total += client.llen(&format!("list:key:{}", key)).await.unwrap();
+    }
+    assert_eq!(total, 800);
+}
+
+#[tokio::test]
+async fn blmove_to_other_shards_is_served() {
+    let addr = start_server().await;
+
+    // With many destinations, most live in another shard than the source.
+    let mut waiters = Vec::new();
+    for i in 0..20 {
+        let mut waiter = client::connect(addr).await.unwrap();
+        waiters.push(tokio::spawn(async move {
+            waiter.blmove("source", &format!("destination:{}", i), "LEFT", "RIGHT", 0).await.unwrap()
+        }));
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut client = client::connect(addr).await.unwrap();
+    for i in 0..20 {
+        client.rpush("source", format!("job:{}", i).into()).await.unwrap();
+    }
+
+    for (i, waiter) in waiters.into_iter().enumerate() {
+        assert_eq!(waiter.await.unwrap().unwrap(), format!("job:{}", i));
+        assert_eq!(client.llen(&format!("destination:{}", i)).await.unwrap(), 1);
+    }
+    assert_eq!(client.llen("source").await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn scan_visits_every_shard() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    for i in 0..500 {
+        client.set(&format!("key:{}", i), "1").await.unwrap();
+    }
+
+    let mut seen = HashSet::new();
+    let mut cursor = 0;
+    loop {
+        let (next, keys) = client.scan(cursor, None, 7).await.unwrap();
+        seen.extend(keys);
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    assert_eq!(seen.len(), 500);
+    assert_eq!(client.dbsize().await.unwrap(), 500);
+}
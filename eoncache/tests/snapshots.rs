@@ -0,0 +1,197 @@
+mod common;
+
+use common::start_server_with;
+use eoncache::{client, Config, Db};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn config(path: &Path) -> Config {
+    Config { dbfilename: path.to_path_buf(), ..Config::default() }
+}
+
+/// Open a store from `path`, expecting the snapshot to be rejected.
+fn open_error(path: &Path) -> String {
+    match Db::open(config(path)) {
+        Ok(_) => panic!("snapshot at {} was loaded", path.display()),
+        Err(e) => e.to_string(),
+    }
+}
+
+/// Save a small snapshot with `SAVE` and return its path.
+async fn saved_snapshot(dir: &Path) -> PathBuf {
+    let path = dir.join("dump.ecs");
+    let addr = start_server_with(Db::open(config(&path)).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("greeting", "hello").await.unwrap();
+    client.rpush("queue", "job".into()).await.unwrap();
+    client.save().await.unwrap();
+    path
+}
+
+#[tokio::test]
+async fn every_type_survives_a_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dump.ecs");
+
+    let addr = start_server_with(Db::open(config(&path)).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.set("string", "value").await.unwrap();
+    client.expire("string", 1000).await.unwrap();
+    client.rpush("list", "a".into()).await.unwrap();
+    client.rpush("list", "b".into()).await.unwrap();
+    client.hset("hash", &[("field", "value".into())]).await.unwrap();
+    client.sadd("set", &["x", "y"]).await.unwrap();
+    client.zadd("zset", &[(1.5, "low"), (7.0, "high")]).await.unwrap();
+    client.xadd("stream", "1-1", &[("event", "created".into())]).await.unwrap();
+    client.xgroup_create("stream", "workers", "0", false).await.unwrap();
+    client.xreadgroup("workers", "alice", "stream", ">", 10, None).await.unwrap();
+    client.select(2).await.unwrap();
+    client.set("elsewhere", "two").await.unwrap();
+    client.save().await.unwrap();
+
+    let addr = start_server_with(Db::open(config(&path)).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.get("string").await.unwrap().unwrap(), "value");
+    let ttl = client.ttl("string").await.unwrap();
+    assert!(ttl > 990 && ttl <= 1000, "ttl was {}", ttl);
+    assert_eq!(client.lrange("list", 0, -1).await.unwrap(), vec!["a", "b"]);
+    assert_eq!(client.hgetall("hash").await.unwrap(), vec![("field".into(), "value".into())]);
+    let mut members = client.smembers("set").await.unwrap();
+    members.sort();
+    assert_eq!(members, vec!["x", "y"]);
+    assert_eq!(client.zrange("zset", 0, -1).await.unwrap(), vec!["low", "high"]);
+    assert_eq!(client.zscore("zset", "low").await.unwrap(), Some(1.5));
+
+    // The consumer group comes back with its pending entry.
+    let entries = client.xrange("stream", "-", "+").await.unwrap();
+    assert_eq!(entries.len(), 1);
+    let pending = client.xreadgroup("workers", "alice", "stream", "0", 10, None).await.unwrap();
+    assert_eq!(pending, entries);
+    assert!(client.xreadgroup("workers", "bob", "stream", ">", 10, None).await.unwrap().is_empty());
+
+    assert_eq!(client.get("elsewhere").await.unwrap(), None);
+    client.select(2).await.unwrap();
+    assert_eq!(client.get("elsewhere").await.unwrap().unwrap(), "two");
+}
+
+#[tokio::test]
+async fn expired_keys_are_not_loaded() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dump.ecs");
+
+    let addr = start_server_with(Db::open(config(&path)).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.set("short", "lived").await.unwrap();
+    client.expire("short", 1).await.unwrap();
+    client.set("kept", "value").await.unwrap();
+    client.save().await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let addr = start_server_with(Db::open(config(&path)).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.dbsize().await.unwrap(), 1);
+    assert_eq!(client.get("short").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn bgsave_updates_lastsave_and_info() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dump.ecs");
+
+    let addr = start_server_with(Db::open(config(&path)).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    let started = client.lastsave().await.unwrap();
+    client.set("key", "value").await.unwrap();
+    assert!(client.info(Some("persistence")).await.unwrap().contains("rdb_changes_since_last_save:1\r\n"));
+
+    client.bgsave().await.unwrap();
+    let mut info = String::new();
+    for _ in 0..100 {
+        info = client.info(Some("persistence")).await.unwrap();
+        if info.contains("rdb_bgsave_in_progress:0") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(info.contains("rdb_changes_since_last_save:0\r\n"), "{}", info);
+    assert!(info.contains("rdb_last_bgsave_status:ok\r\n"), "{}", info);
+    assert!(client.lastsave().await.unwrap() >= started);
+    assert!(path.exists());
+}
+
+#[tokio::test]
+async fn save_rules_trigger_a_background_save() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dump.ecs");
+
+    let addr = start_server_with(Db::open(config(&path)).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+    client.config_set("save", "0 2").await.unwrap();
+
+    // One change is not enough for the rule.
+    client.set("first", "1").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!path.exists());
+
+    client.set("second", "2").await.unwrap();
+    for _ in 0..100 {
+        if path.exists() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(path.exists());
+
+    assert!(client.config_set("save", "60").await.is_err());
+}
+
+#[tokio::test]
+async fn missing_snapshot_starts_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let addr = start_server_with(Db::open(config(&dir.path().join("absent.ecs"))).unwrap()).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    assert_eq!(client.dbsize().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn corrupted_snapshot_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = saved_snapshot(dir.path()).await;
+
+    let mut data = std::fs::read(&path).unwrap();
+    let middle = data.len() / 2;
+    data[middle] ^= 0xff;
+    std::fs::write(&path, &data).unwrap();
+
+    let error = open_error(&path);
+    assert!(error.contains("checksum mismatch"), "{}", error);
+    assert!(error.contains(&path.display().to_string()), "{}", error);
+}
+
+#[tokio::test]
+async fn truncated_snapshot_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = saved_snapshot(dir.path()).await;
+
+    let data = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &data[..data.len() - 10]).unwrap();
+    let error = open_error(&path);
+    assert!(error.starts_with("can't load snapshot"), "{}", error);
+
+    std::fs::write(&path, &data[..2]).unwrap();
+    let error = open_error(&path);
+    assert!(error.starts_with("can't load snapshot"), "{}", error);
+}
+
+#[tokio::test]
+async fn other_files_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "just some text that is long enough").unwrap();
+
+    let error = open_error(&path);
+    assert!(error.contains("not a snapshot file"), "{}", error);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use common::{start_server, Raw};
+use eoncache::{client, Frame};
+use std::time::Duration;
+
+#[tokio::test]
+async fn leaderboard_ranks_follow_scores() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    assert_eq!(client.zadd("board", &[(30.0, "carol"), (10.0, "alice"), (20.0, "bob")]).await.unwrap(), 3);
+    assert_eq!(client.zrange("board", 0, -1).await.unwrap(), vec!["alice", "bob", "carol"]);
+
+    // Updating a score moves the member instead of adding it again.
+    assert_eq!(client.zadd("board", &[(40.0, "alice")]).await.unwrap(), 0);
+    assert_eq!(client.zrank("board", "alice").await.unwrap(), Some(2));
+    assert_eq!(client.zscore("board", "alice").await.unwrap(), Some(40.0));
+    assert_eq!(client.zrange("board", -1, -1).await.unwrap(), vec!["alice"]);
+    assert_eq!(client.zrank("board", "dave").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn ranks_stay_consistent_across_many_updates() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    let names: Vec<String> = (0..300).map(|i| format!("m{:03}", i)).collect();
+    for (i, name) in names.iter().enumerate() {
+        client.zadd("big", &[((i % 17) as f64, name)]).await.unwrap();
+    }
+    for name in names.iter().step_by(2) {
+        client.zadd("big", &[(100.0, name)]).await.unwrap();
+    }
+
+    let ordered = client.zrange("big", 0, -1).await.unwrap();
+    assert_eq!(ordered.len(), names.len());
+    for (rank, member) in ordered.iter().enumerate().step_by(7) {
+        let member = std::str::from_utf8(member).unwrap();
+        assert_eq!(client.zrank("big", member).await.unwrap(), Some(rank));
+    }
+}
+
+#[tokio::test]
+async fn zadd_wakes_blocked_bzpopmin() {
+    let addr = start_server().await;
+
+    let mut waiter = client::connect(addr).await.unwrap();
+    let blocked = tokio::spawn(async move { waiter.bzpopmin(&["tasks".into()], 0).await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut producer = client::connect(addr).await.unwrap();
+    producer.zadd("tasks", &[(2.0, "later"), (1.0, "sooner")]).await.unwrap();
+
+    let (key, member, score) = blocked.await.unwrap().unwrap();
+    assert_eq!((&key[..], &member[..], score), (&b"tasks"[..], &b"sooner"[..], 1.0));
+    assert_eq!(producer.zrange("tasks", 0, -1).await.unwrap(), vec!["later"]);
+}
+
+#[tokio::test]
+async fn sorted_set_commands_check_the_key_type() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.rpush("queue", "job".into()).await.unwrap();
+    let err = client.zadd("queue", &[(1.0, "member")]).await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+
+    client.zadd("board", &[(1.0, "member")]).await.unwrap();
+    let err = client.blpop(&["board".into()], 1).await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+}
+
+#[tokio::test]
+async fn store_commands_check_numkeys_against_the_arguments() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+    raw.call(&["ZADD", "a", "1", "member"]).await;
+
+    for command in ["ZUNIONSTORE", "ZINTERSTORE", "ZDIFFSTORE"] {
+        let reply = raw.call(&[command, "destination", "1000000000000", "a"]).await;
+        assert!(matches!(&reply, Frame::Error(e) if e == "ERR syntax error"), "{}: {:?}", command, reply);
+    }
+
+    // The server is still up.
+    assert!(matches!(raw.call(&["ZCARD", "a"]).await, Frame::Integer(1)));
+}
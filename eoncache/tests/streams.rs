@@ -0,0 +1,112 @@
+mod common;
+
+use common::start_server;
+use eoncache::client;
+use std::time::Duration;
+
+#[tokio::test]
+async fn entries_are_ordered_by_id() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    assert_eq!(client.xadd("events", "1-1", &[("type", "login".into())]).await.unwrap(), "1-1");
+    assert_eq!(client.xadd("events", "1-*", &[("type", "click".into())]).await.unwrap(), "1-2");
+    assert_eq!(client.xadd("events", "5", &[("type", "logout".into())]).await.unwrap(), "5-0");
+
+    // IDs must keep increasing.
+    let err = client.xadd("events", "3-0", &[("type", "late".into())]).await.unwrap_err();
+    assert!(err.to_string().contains("equal or smaller"), "{}", err);
+
+    // Generated IDs come after explicit ones.
+    let generated = client.xadd("events", "*", &[("type", "view".into())]).await.unwrap();
+    let ms: u64 = generated.split('-').next().unwrap().parse().unwrap();
+    assert!(ms > 5);
+
+    assert_eq!(client.xlen("events").await.unwrap(), 4);
+    let entries = client.xrange("events", "1", "5").await.unwrap();
+    let ids: Vec<_> = entries.iter().map(|(id, _)| id.as_str()).collect();
+    assert_eq!(ids, vec!["1-1", "1-2", "5-0"]);
+    assert_eq!(entries[1].1, vec![("type".into(), "click".into())]);
+}
+
+#[tokio::test]
+async fn readers_replay_without_consuming() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    for i in 1..=3 {
+        client.xadd("log", &format!("{}-0", i), &[("n", i.to_string().into())]).await.unwrap();
+    }
+
+    for _ in 0..2 {
+        let read = client.xread(&[("log", "1-0")], None).await.unwrap();
+        assert_eq!(read.len(), 1);
+        let ids: Vec<_> = read[0].1.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["2-0", "3-0"]);
+    }
+
+    assert!(client.xread(&[("log", "3-0"), ("missing", "0")], None).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn xadd_wakes_every_blocked_reader() {
+    let addr = start_server().await;
+
+    let mut readers = Vec::new();
+    for _ in 0..3 {
+        let mut reader = client::connect(addr).await.unwrap();
+        readers.push(tokio::spawn(async move { reader.xread(&[("feed", "$")], Some(0)).await.unwrap() }));
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut producer = client::connect(addr).await.unwrap();
+    producer.xadd("feed", "7-0", &[("msg", "hello".into())]).await.unwrap();
+
+    for reader in readers {
+        let read = reader.await.unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].0, "feed");
+        assert_eq!(read[0].1, vec![("7-0".to_string(), vec![("msg".into(), "hello".into())])]);
+    }
+}
+
+#[tokio::test]
+async fn blocking_read_times_out() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.xadd("feed", "1-0", &[("a", "1".into())]).await.unwrap();
+    assert!(client.xread(&[("feed", "$")], Some(50)).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn streams_are_trimmed() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    for i in 1..=6 {
+        client.xadd("metrics", &format!("{}-0", i), &[("v", i.to_string().into())]).await.unwrap();
+    }
+
+    assert_eq!(client.xtrim("metrics", "MAXLEN", "4").await.unwrap(), 2);
+    assert_eq!(client.xtrim("metrics", "MINID", "5").await.unwrap(), 2);
+    let ids: Vec<_> = client.xrange("metrics", "-", "+").await.unwrap().into_iter().map(|(id, _)| id).collect();
+    assert_eq!(ids, vec!["5-0", "6-0"]);
+
+    // Trimming everything keeps the stream and its last ID.
+    assert_eq!(client.xtrim("metrics", "MAXLEN", "0").await.unwrap(), 2);
+    assert_eq!(client.exists("metrics").await.unwrap().unwrap(), "1");
+    assert!(client.xadd("metrics", "6-0", &[("v", "again".into())]).await.is_err());
+}
+
+#[tokio::test]
+async fn stream_commands_check_the_key_type() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("plain", "value").await.unwrap();
+    let err = client.xadd("plain", "*", &[("a", "1".into())]).await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+    let err = client.xread(&[("plain", "0")], None).await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::start_server;
+use eoncache::client;
+
+#[tokio::test]
+async fn concurrent_increments_are_not_lost() {
+    let addr = start_server().await;
+
+    let mut tasks = Vec::new();
+    for _ in 0..4 {
+        tasks.push(tokio::spawn(async move {
+            let mut client = client::connect(addr).await.unwrap();
+            for _ in 0..50 {
+                client.incr_by("hits", 1).await.unwrap();
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let mut client = client::connect(addr).await.unwrap();
+    assert_eq!(client.get("hits").await.unwrap().unwrap(), "200");
+}
+
+#[tokio::test]
+async fn increments_report_bad_values_and_overflow() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("name", "eon").await.unwrap();
+    let err = client.incr_by("name", 1).await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR value is not an integer or out of range");
+
+    client.set("big", &i64::MAX.to_string()).await.unwrap();
+    let err = client.incr_by("big", 1).await.unwrap_err();
+    assert_eq!(err.to_string(), "ERR increment or decrement would overflow");
+    assert_eq!(client.get("big").await.unwrap().unwrap(), i64::MAX.to_string());
+}
+
+#[tokio::test]
+async fn increments_keep_the_deadline() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.set("counter", "10").await.unwrap();
+    client.expire("counter", 100).await.unwrap();
+    assert_eq!(client.incr_by("counter", -3).await.unwrap(), 7);
+    assert!(client.ttl("counter").await.unwrap() > 0);
+}
+
+#[tokio::test]
+async fn append_builds_strings() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    assert_eq!(client.append("greeting", "hello".into()).await.unwrap(), 5);
+    assert_eq!(client.append("greeting", " world".into()).await.unwrap(), 11);
+    assert_eq!(client.get("greeting").await.unwrap().unwrap(), "hello world");
+}
+
+#[tokio::test]
+async fn mget_and_mset_cover_many_keys() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.mset(&[("a", "1".into()), ("b", "2".into())]).await.unwrap();
+    client.sadd("set", &["member"]).await.unwrap();
+
+    let values = client.mget(&["a", "missing", "b", "set"]).await.unwrap();
+    assert_eq!(values, vec![Some("1".into()), None, Some("2".into()), None]);
+}
@@ -0,0 +1,260 @@
+mod common;
+
+use common::{start_server, Raw};
+use eoncache::{client, Frame};
+use std::time::Duration;
+
+/// Send `args` inside a transaction, expecting them to be queued.
+async fn queue(raw: &mut Raw, args: &[&str]) {
+    let reply = raw.call(args).await;
+    assert!(matches!(&reply, Frame::Simple(queued) if queued == "QUEUED"), "{:?}", reply);
+}
+
+fn is_ok(frame: &Frame) -> bool {
+    matches!(frame, Frame::Simple(ok) if ok == "OK")
+}
+
+fn is_error(frame: &Frame, message: &str) -> bool {
+    matches!(frame, Frame::Error(e) if e.starts_with(message))
+}
+
+#[tokio::test]
+async fn exec_runs_queued_commands_in_order() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+
+    assert!(is_ok(&raw.call(&["MULTI"]).await));
+    queue(&mut raw, &["SET", "counter", "1"]).await;
+    queue(&mut raw, &["INCR", "counter"]).await;
+    queue(&mut raw, &["GET", "counter"]).await;
+
+    let replies = match raw.call(&["EXEC"]).await {
+        Frame::Array(replies) => replies,
+        frame => panic!("unexpected reply {:?}", frame),
+    };
+    assert_eq!(replies.len(), 3);
+    assert!(is_ok(&replies[0]));
+    assert!(matches!(replies[1], Frame::Integer(2)));
+    assert!(matches!(&replies[2], Frame::Bulk(value) if value == "2"));
+}
+
+#[tokio::test]
+async fn refused_commands_abort_the_transaction() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    raw.call(&["MULTI"]).await;
+    queue(&mut raw, &["SET", "key", "value"]).await;
+    assert!(is_error(&raw.call(&["NOSUCHCOMMAND"]).await, "ERR unknown command"));
+    assert!(is_error(&raw.call(&["GET"]).await, "ERR wrong number of arguments for 'get' command"));
+    assert!(is_error(&raw.call(&["EXEC"]).await, "EXECABORT"));
+    assert_eq!(client.get("key").await.unwrap(), None);
+
+    // Errors while running do not stop the other commands.
+    raw.call(&["MULTI"]).await;
+    queue(&mut raw, &["SET", "string", "value"]).await;
+    queue(&mut raw, &["LPUSH", "string", "element"]).await;
+    queue(&mut raw, &["SET", "other", "value"]).await;
+    match raw.call(&["EXEC"]).await {
+        Frame::Array(replies) => {
+            assert!(is_ok(&replies[0]));
+            assert!(is_error(&replies[1], "WRONGTYPE"));
+            assert!(is_ok(&replies[2]));
+        }
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn discard_and_misplaced_commands() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+
+    assert!(is_error(&raw.call(&["EXEC"]).await, "ERR EXEC without MULTI"));
+    assert!(is_error(&raw.call(&["DISCARD"]).await, "ERR DISCARD without MULTI"));
+
+    raw.call(&["MULTI"]).await;
+    assert!(is_error(&raw.call(&["MULTI"]).await, "ERR MULTI calls can not be nested"));
+    assert!(is_error(&raw.call(&["WATCH", "key"]).await, "ERR WATCH inside MULTI is not allowed"));
+    queue(&mut raw, &["SET", "key", "value"]).await;
+    assert!(is_ok(&raw.call(&["DISCARD"]).await));
+
+    assert!(matches!(raw.call(&["GET", "key"]).await, Frame::Null));
+}
+
+#[tokio::test]
+async fn watched_key_changes_abort_exec() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+    let mut other = client::connect(addr).await.unwrap();
+
+    raw.call(&["SET", "key", "original"]).await;
+    raw.call(&["WATCH", "key", "missing"]).await;
+    other.set("key", "theirs").await.unwrap();
+    raw.call(&["MULTI"]).await;
+    queue(&mut raw, &["SET", "key", "mine"]).await;
+    assert!(matches!(raw.call(&["EXEC"]).await, Frame::Null));
+    assert_eq!(other.get("key").await.unwrap().unwrap(), "theirs");
+
+    // `EXEC` ends the watch, and unchanged keys let the transaction through.
+    raw.call(&["WATCH", "key"]).await;
+    raw.call(&["MULTI"]).await;
+    queue(&mut raw, &["SET", "key", "mine"]).await;
+    assert!(matches!(raw.call(&["EXEC"]).await, Frame::Array(_)));
+    assert_eq!(other.get("key").await.unwrap().unwrap(), "mine");
+
+    // Creating a watched key counts as a change.
+    raw.call(&["WATCH", "missing"]).await;
+    other.set("missing", "now here").await.unwrap();
+    raw.call(&["MULTI"]).await;
+    queue(&mut raw, &["DEL", "missing"]).await;
+    assert!(matches!(raw.call(&["EXEC"]).await, Frame::Null));
+
+    // So does changing a watched key within the same connection.
+    raw.call(&["WATCH", "key"]).await;
+    raw.call(&["SET", "key", "again"]).await;
+    raw.call(&["MULTI"]).await;
+    assert!(matches!(raw.call(&["EXEC"]).await, Frame::Null));
+
+    // `UNWATCH` forgets the watched keys.
+    raw.call(&["WATCH", "key"]).await;
+    other.set("key", "theirs").await.unwrap();
+    assert!(is_ok(&raw.call(&["UNWATCH"]).await));
+    raw.call(&["MULTI"]).await;
+    assert!(matches!(raw.call(&["EXEC"]).await, Frame::Array(_)));
+}
+
+#[tokio::test]
+async fn failed_and_no_op_writes_leave_watched_keys_alone() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+    let mut other = Raw::connect(addr).await;
+
+    // Removing a field the hash does not have changes nothing.
+    raw.call(&["HSET", "h", "field", "value"]).await;
+    raw.call(&["WATCH", "h"]).await;
+    assert!(matches!(other.call(&["HDEL", "h", "missing"]).await, Frame::Integer(0)));
+    raw.call(&["MULTI"]).await;
+    queue(&mut raw, &["HGET", "h", "field"]).await;
+    assert!(matches!(raw.call(&["EXEC"]).await, Frame::Array(_)));
+
+    // Neither does a write refused because the key holds another type.
+    raw.call(&["SET", "s", "string"]).await;
+    raw.call(&["WATCH", "s"]).await;
+    assert!(is_error(&other.call(&["LPUSH", "s", "x"]).await, "WRONGTYPE"));
+    raw.call(&["MULTI"]).await;
+    queue(&mut raw, &["GET", "s"]).await;
+    assert!(matches!(raw.call(&["EXEC"]).await, Frame::Array(_)));
+
+    // Nor does an update-only write to a missing key.
+    raw.call(&["WATCH", "z"]).await;
+    assert!(matches!(other.call(&["ZADD", "z", "XX", "1", "member"]).await, Frame::Integer(0)));
+    raw.call(&["MULTI"]).await;
+    queue(&mut raw, &["ZCARD", "z"]).await;
+    assert!(matches!(raw.call(&["EXEC"]).await, Frame::Array(_)));
+}
+
+#[tokio::test]
+async fn expiry_and_flushes_change_watched_keys() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+    let mut other = client::connect(addr).await.unwrap();
+
+    raw.call(&["SET", "short", "lived", "PX", "50"]).await;
+    raw.call(&["WATCH", "short"]).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    raw.call(&["MULTI"]).await;
+    queue(&mut raw, &["SET", "short", "again"]).await;
+    assert!(matches!(raw.call(&["EXEC"]).await, Frame::Null));
+
+    raw.call(&["SET", "key", "value"]).await;
+    raw.call(&["WATCH", "key"]).await;
+    raw.call(&["SELECT", "1"]).await;
+    other.select(0).await.unwrap();
+    let mut flusher = Raw::connect(addr).await;
+    flusher.call(&["FLUSHALL"]).await;
+    raw.call(&["MULTI"]).await;
+    queue(&mut raw, &["SET", "key", "value"]).await;
+    assert!(matches!(raw.call(&["EXEC"]).await, Frame::Null));
+    assert_eq!(other.get("key").await.unwrap(), None);
+
+    // Watches stay with their namespace across `SWAPDB`.
+    raw.call(&["SELECT", "0"]).await;
+    raw.call(&["SET", "key", "zero"]).await;
+    raw.call(&["WATCH", "key"]).await;
+    flusher.call(&["SWAPDB", "0", "1"]).await;
+    raw.call(&["MULTI"]).await;
+    assert!(matches!(raw.call(&["EXEC"]).await, Frame::Null));
+}
+
+#[tokio::test]
+async fn transactions_are_isolated() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+    let mut reader = client::connect(addr).await.unwrap();
+
+    let reads = tokio::spawn(async move {
+        let mut seen = Vec::new();
+        for _ in 0..200 {
+            let value = reader.get("counter").await.unwrap();
+            seen.push(value.map_or(0, |value| std::str::from_utf8(&value).unwrap().parse::<u64>().unwrap()));
+        }
+        seen
+    });
+
+    for _ in 0..20 {
+        raw.call(&["MULTI"]).await;
+        for _ in 0..50 {
+            queue(&mut raw, &["INCR", "counter"]).await;
+        }
+        raw.call(&["EXEC"]).await;
+    }
+
+    for value in reads.await.unwrap() {
+        assert_eq!(value % 50, 0, "saw a partial transaction: {}", value);
+    }
+    assert!(matches!(&raw.call(&["GET", "counter"]).await, Frame::Bulk(value) if value == "1000"));
+}
+
+#[tokio::test]
+async fn blocking_commands_do_not_block_in_a_transaction() {
+    let addr = start_server().await;
+    let mut raw = Raw::connect(addr).await;
+
+    raw.call(&["MULTI"]).await;
+    queue(&mut raw, &["BLPOP", "empty", "0"]).await;
+    queue(&mut raw, &["RPUSH", "list", "element"]).await;
+    queue(&mut raw, &["BLPOP", "list", "0"]).await;
+    match raw.call(&["EXEC"]).await {
+        Frame::Array(replies) => {
+            assert!(matches!(replies[0], Frame::Null));
+            assert!(matches!(&replies[2], Frame::Array(popped) if popped.len() == 2));
+        }
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn client_transaction_helpers() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+    let mut other = client::connect(addr).await.unwrap();
+
+    client.watch(&["key"]).await.unwrap();
+    client.multi().await.unwrap();
+    client.set("key", "value").await.unwrap();
+    assert_eq!(client.exec().await.unwrap().unwrap().len(), 1);
+
+    client.watch(&["key"]).await.unwrap();
+    other.set("key", "changed").await.unwrap();
+    client.multi().await.unwrap();
+    client.set("key", "value").await.unwrap();
+    assert!(client.exec().await.unwrap().is_none());
+
+    client.multi().await.unwrap();
+    client.set("key", "discarded").await.unwrap();
+    client.discard().await.unwrap();
+    client.unwatch().await.unwrap();
+    assert_eq!(client.get("key").await.unwrap().unwrap(), "changed");
+}
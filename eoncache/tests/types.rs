@@ -0,0 +1,38 @@
+mod common;
+
+use common::start_server;
+use eoncache::client;
+
+#[tokio::test]
+async fn commands_reject_keys_of_another_type() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.rpush("queue", "job".into()).await.unwrap();
+    let err = client.get("queue").await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+
+    client.set("name", "value").await.unwrap();
+    let err = client.lpush("name", "job".into()).await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+
+    let err = client.blpop(&["name".into()], 1).await.unwrap_err();
+    assert!(err.to_string().starts_with("WRONGTYPE"), "{}", err);
+
+    // The connection stays usable after a type error.
+    assert_eq!(client.get("name").await.unwrap().unwrap(), "value");
+}
+
+#[tokio::test]
+async fn lists_share_the_keyspace_with_strings() {
+    let addr = start_server().await;
+    let mut client = client::connect(addr).await.unwrap();
+
+    client.rpush("queue", "job".into()).await.unwrap();
+    assert_eq!(client.exists("queue").await.unwrap().unwrap(), "1");
+
+    // SET replaces a value of any type instead of shadowing it.
+    client.set("queue", "plain").await.unwrap();
+    assert_eq!(client.get("queue").await.unwrap().unwrap(), "plain");
+    assert!(client.blpop(&["queue".into()], 1).await.is_err());
+}